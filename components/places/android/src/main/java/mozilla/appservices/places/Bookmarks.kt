@@ -77,8 +77,8 @@ sealed class BookmarkTreeNode {
 /**
  * A bookmark tree node that represents a bookmarked URL.
  *
- * Its type is always [BookmarkType.Bookmark], and it has a `title `and `url`
- * in addition to the fields defined by [BookmarkTreeNode].
+ * Its type is always [BookmarkType.Bookmark], and it has a `title`, `url`,
+ * `keyword` and `tags` in addition to the fields defined by [BookmarkTreeNode].
  */
 
 data class BookmarkItem(
@@ -99,7 +99,18 @@ data class BookmarkItem(
      * Note that the bookmark storage layer treats NULL and the
      * empty string as equivalent in titles.
      */
-    val title: String
+    val title: String,
+
+    /**
+     * The keyword for this bookmark, if it has one.
+     */
+    val keyword: String? = null,
+
+    /**
+     * The tags for this bookmark's URL. These are shared by all bookmarks
+     * with the same URL.
+     */
+    val tags: List<String> = listOf()
 ) : BookmarkTreeNode() {
     override val type get() = BookmarkType.Bookmark
 }
@@ -253,6 +264,79 @@ interface ReadableBookmarksConnection : InterruptibleConnection {
      * has its `interrupt()` method called on another thread.
      */
     fun getRecentBookmarks(limit: Int): List<BookmarkItem>
+
+    /**
+     * Runs the `place:` query for a bookmark, like a smart folder synced
+     * from desktop.
+     *
+     * @param guid The GUID of a bookmark with a `place:` URL.
+     * @return The pages or tags the query matched.
+     *
+     * @throws UnknownBookmarkItem If `guid` does not refer to a known bookmark.
+     * @throws PlacesException If the bookmark isn't a valid `place:` query, or
+     * isn't a bookmark at all.
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun runBookmarkQuery(guid: String): PlaceQueryResults
+
+    /**
+     * Returns a page of a folder's children, without their own children.
+     * Folders in the list have their `childGUIDs` filled in, but not their
+     * `children`.
+     *
+     * @param guid The GUID of the folder.
+     * @param order How to sort the children. Ties are broken by position.
+     * @param offset How many children to skip.
+     * @param limit The maximum number of children to return.
+     * @return The requested page of children.
+     *
+     * @throws UnknownBookmarkItem If `guid` does not refer to a known bookmark.
+     * @throws InvalidParent If `guid` does not refer to a folder node.
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun listFolderChildren(
+        guid: String,
+        order: FolderChildrenOrder = FolderChildrenOrder.POSITION,
+        offset: Int = 0,
+        limit: Int
+    ): List<BookmarkTreeNode>
+
+    /**
+     * Returns a token for the most recent bookmark change. Fetch it along
+     * with the tree, then pass it to [getBookmarkChangesSince] to find out
+     * what changed since, locally or by syncing.
+     *
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun getBookmarkChangeToken(): Long
+
+    /**
+     * Returns the bookmarks inserted, updated, moved to another folder, or
+     * deleted after `token`, and a new token to pass next time. Changes to
+     * tags aren't included. Deletions are forgotten after a while, so check
+     * [BookmarkChanges.tokenExpired] for tokens that are too old.
+     *
+     * @param token A token from [getBookmarkChangeToken], or from an earlier
+     * call to this method.
+     * @return The changed bookmarks.
+     *
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun getBookmarkChangesSince(token: Long): BookmarkChanges
+
+    /**
+     * Exports all bookmarks, including their tags and keywords, to a file
+     * which desktop Firefox (and most other browsers) can import.
+     *
+     * @param path Where to write the file. Its extension picks the format:
+     * `.html` for a `bookmarks.html` file, `.jsonlz4` for a compressed
+     * desktop backup, and anything else for an uncompressed JSON backup.
+     */
+    fun exportBookmarks(path: String)
 }
 
 /**
@@ -279,6 +363,37 @@ interface WritableBookmarksConnection : ReadableBookmarksConnection {
      */
     fun deleteAllBookmarks()
 
+    /**
+     * Undo the most recent bookmark change made with [createFolder],
+     * [createSeparator], [createBookmarkItem], [updateBookmark] or
+     * [deleteBookmarkNode]. Deleted items are restored with their original
+     * GUIDs, positions and creation dates.
+     *
+     * If the change can't be undone because the items it touched have
+     * changed since, an exception is thrown and the change is forgotten.
+     *
+     * @return Whether or not there was a change to undo.
+     */
+    fun undo(): Boolean
+
+    /**
+     * Make the most recently undone bookmark change again.
+     *
+     * @return Whether or not there was a change to redo.
+     */
+    fun redo(): Boolean
+
+    /**
+     * Imports bookmarks from a `bookmarks.html` file, or a desktop JSON
+     * backup (compressed or not), as written by [exportBookmarks].
+     *
+     * The imported items are added after any existing bookmarks in each
+     * root - nothing is removed.
+     *
+     * @param path Path to the file to import.
+     */
+    fun importBookmarks(path: String)
+
     /**
      * Create a bookmark folder, returning its guid.
      *
@@ -329,6 +444,9 @@ interface WritableBookmarksConnection : ReadableBookmarksConnection {
      * If not provided, this item will be appended. If the position is outside
      * the range of positions currently occupied by children in this folder,
      * it is first constrained to be within that range.
+     * @param keyword The keyword for the new bookmark. If another bookmark
+     * already has this keyword, it's removed from that bookmark.
+     * @param tags Tags to add to `url`. Any existing tags are kept.
      * @return The GUID of the newly inserted bookmark item.
      *
      * @throws CannotUpdateRoot If `parentGUID` is the [BookmarkRoot.Root] (e.g. "root________")
@@ -336,12 +454,15 @@ interface WritableBookmarksConnection : ReadableBookmarksConnection {
      * @throws InvalidParent If `parentGUID` does not refer to a folder node.
      * @throws UrlParseFailed If `url` does not refer to a valid URL.
      * @throws UrlTooLong if `url` exceeds the maximum length of 65536 bytes (when encoded)
+     * @throws PlacesException if `keyword` or one of the `tags` is invalid.
      */
     fun createBookmarkItem(
         parentGUID: String,
         url: String,
         title: String,
-        position: Int? = null
+        position: Int? = null,
+        keyword: String? = null,
+        tags: List<String> = listOf()
     ): String
 
     /**
@@ -360,6 +481,41 @@ interface WritableBookmarksConnection : ReadableBookmarksConnection {
      * folder node.
      */
     fun updateBookmark(guid: String, info: BookmarkUpdateInfo)
+
+    /**
+     * Reorder the children of a folder in one go.
+     *
+     * The children listed in `childGUIDs` are placed first, in the order
+     * given, followed by any children which weren't listed, in their current
+     * order. GUIDs which aren't children of the folder are ignored.
+     *
+     * @param parentGUID The GUID of the folder to reorder.
+     * @param childGUIDs The GUIDs of the folder's children, in their new order.
+     *
+     * @throws CannotUpdateRoot If `parentGUID` is [BookmarkRoot.Root] (e.g. "root________")
+     * @throws UnknownBookmarkItem If `parentGUID` does not refer to a known bookmark.
+     * @throws InvalidParent If `parentGUID` does not refer to a folder node.
+     */
+    fun reorderChildren(parentGUID: String, childGUIDs: List<String>)
+
+    /**
+     * Move several items into a folder in one go, keeping them together in
+     * the order given. The items may come from different folders.
+     *
+     * @param guids The GUIDs of the items to move.
+     * @param parentGUID The GUID of the folder to move them into.
+     * @param position The index of the child the items should be placed
+     * before, counting the folder's current children. If not provided, the
+     * items are appended.
+     *
+     * @throws CannotUpdateRoot If any of `guids` is a bookmark root, or
+     * `parentGUID` is [BookmarkRoot.Root] (e.g. "root________")
+     * @throws UnknownBookmarkItem If `parentGUID` or any of `guids` does not
+     * refer to a known bookmark.
+     * @throws InvalidParent If `parentGUID` does not refer to a folder node,
+     * or is one of the items being moved, or inside one of them.
+     */
+    fun moveItems(guids: List<String>, parentGUID: String, position: Int? = null)
 }
 
 /**
@@ -403,7 +559,20 @@ data class BookmarkUpdateInfo(
      * For nodes of type [BookmarkType.Bookmark], a string specifying
      * the new url of the bookmark node.
      */
-    val url: String? = null
+    val url: String? = null,
+
+    /**
+     * For nodes of type [BookmarkType.Bookmark], the new keyword of the
+     * bookmark node. An empty string removes the keyword.
+     */
+    val keyword: String? = null,
+
+    /**
+     * For nodes of type [BookmarkType.Bookmark], the new tags for the
+     * bookmark's URL, replacing its existing tags. An empty list removes
+     * all of them.
+     */
+    val tags: List<String>? = null
 ) {
 
     internal fun toProtobuf(guid: String): MsgTypes.BookmarkNode {
@@ -413,6 +582,8 @@ data class BookmarkUpdateInfo(
         this.parentGUID?.let { builder.setParentGuid(it) }
         this.title?.let { builder.setTitle(it) }
         this.url?.let { builder.setUrl(it) }
+        this.keyword?.let { builder.setKeyword(it) }
+        this.tags?.let { builder.addAllTags(it).setHaveTags(true) }
         return builder.build()
     }
 }
@@ -491,7 +662,9 @@ internal fun unpackProtobuf(msg: MsgTypes.BookmarkNode): BookmarkTreeNode {
                     dateAdded = dateAdded,
                     lastModified = lastModified,
                     title = title,
-                    url = msg.url
+                    url = msg.url,
+                    keyword = if (msg.hasKeyword()) { msg.keyword } else { null },
+                    tags = msg.tagsList
             )
         }
 
@@ -535,6 +708,58 @@ internal fun unpackProtobuf(msg: MsgTypes.BookmarkNode): BookmarkTreeNode {
     }
 }
 
+/**
+ * How [ReadableBookmarksConnection.listFolderChildren] sorts a folder's children.
+ */
+enum class FolderChildrenOrder(val value: Int) {
+    /** The order the children are stored in. */
+    POSITION(1),
+    /** By title, A to Z, ignoring case. Untitled items come last. */
+    TITLE(2),
+    /** Most recently added first. */
+    DATE_ADDED(3),
+    /** Most recently modified first. */
+    LAST_MODIFIED(4),
+    /** Bookmarks for the most recently visited pages first. */
+    LAST_VISITED(5)
+}
+
+/**
+ * The GUIDs of bookmarks changed since a token, returned by
+ * [ReadableBookmarksConnection.getBookmarkChangesSince].
+ *
+ * Items inserted since the token are only in [inserted], and items deleted
+ * since are only in [deleted]. An item can be both [updated] and [moved].
+ */
+data class BookmarkChanges(
+    /** The token to pass next time. */
+    val token: Long,
+    val inserted: List<String>,
+    /** Items whose title, URL, or keyword changed, and folders whose children were reordered. */
+    val updated: List<String>,
+    /** Items moved to another folder. */
+    val moved: List<String>,
+    val deleted: List<String>,
+    /**
+     * If true, the token was so old that some deletions since have been
+     * forgotten, and the whole tree should be reloaded.
+     */
+    val tokenExpired: Boolean
+) {
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.BookmarkChanges): BookmarkChanges {
+            return BookmarkChanges(
+                token = msg.token,
+                inserted = msg.insertedList,
+                updated = msg.updatedList,
+                moved = msg.movedList,
+                deleted = msg.deletedList,
+                tokenExpired = msg.tokenExpired
+            )
+        }
+    }
+}
+
 // Unpack results from getBookmarksWithURL and searchBookmarks. Both of these can only return
 // BookmarkItems, so we just do the cast inside the mapper.
 internal fun unpackProtobufItemList(msg: MsgTypes.BookmarkNodeList): List<BookmarkItem> {
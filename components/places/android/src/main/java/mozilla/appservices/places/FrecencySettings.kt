@@ -0,0 +1,102 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+package mozilla.appservices.places
+
+/**
+ * Weights and bonuses for the frecency algorithm, used by
+ * [PlacesManager.setFrecencySettings]. These are named after Desktop's
+ * `places.frecency.*` prefs, and default to the same values.
+ */
+data class FrecencySettings(
+    val numVisits: Int = 10,
+    val firstBucketCutoffDays: Int = 4,
+    val secondBucketCutoffDays: Int = 14,
+    val thirdBucketCutoffDays: Int = 31,
+    val fourthBucketCutoffDays: Int = 90,
+    val firstBucketWeight: Int = 100,
+    val secondBucketWeight: Int = 70,
+    val thirdBucketWeight: Int = 50,
+    val fourthBucketWeight: Int = 30,
+    val defaultBucketWeight: Int = 10,
+    val embedVisitBonus: Int = 0,
+    val framedLinkVisitBonus: Int = 0,
+    val linkVisitBonus: Int = 100,
+    val typedVisitBonus: Int = 2000,
+    val bookmarkVisitBonus: Int = 75,
+    val downloadVisitBonus: Int = 0,
+    val permanentRedirectVisitBonus: Int = 0,
+    val temporaryRedirectVisitBonus: Int = 0,
+    val redirectSourceVisitBonus: Int = 25,
+    val defaultVisitBonus: Int = 0,
+    val unvisitedBookmarkBonus: Int = 140,
+    val unvisitedTypedBonus: Int = 200,
+    val reloadVisitBonus: Int = 0,
+    /**
+     * How much to multiply frecencies by each day, when
+     * [WritableHistoryConnection.runMaintenance] is called, so that pages
+     * which haven't been visited in a while rank lower. 1.0 turns decay off.
+     */
+    val decayRate: Double = 0.975
+) {
+    internal fun toProtobuf(): MsgTypes.FrecencySettings {
+        return MsgTypes.FrecencySettings.newBuilder()
+            .setNumVisits(numVisits)
+            .setFirstBucketCutoffDays(firstBucketCutoffDays)
+            .setSecondBucketCutoffDays(secondBucketCutoffDays)
+            .setThirdBucketCutoffDays(thirdBucketCutoffDays)
+            .setFourthBucketCutoffDays(fourthBucketCutoffDays)
+            .setFirstBucketWeight(firstBucketWeight)
+            .setSecondBucketWeight(secondBucketWeight)
+            .setThirdBucketWeight(thirdBucketWeight)
+            .setFourthBucketWeight(fourthBucketWeight)
+            .setDefaultBucketWeight(defaultBucketWeight)
+            .setEmbedVisitBonus(embedVisitBonus)
+            .setFramedLinkVisitBonus(framedLinkVisitBonus)
+            .setLinkVisitBonus(linkVisitBonus)
+            .setTypedVisitBonus(typedVisitBonus)
+            .setBookmarkVisitBonus(bookmarkVisitBonus)
+            .setDownloadVisitBonus(downloadVisitBonus)
+            .setPermanentRedirectVisitBonus(permanentRedirectVisitBonus)
+            .setTemporaryRedirectVisitBonus(temporaryRedirectVisitBonus)
+            .setRedirectSourceVisitBonus(redirectSourceVisitBonus)
+            .setDefaultVisitBonus(defaultVisitBonus)
+            .setUnvisitedBookmarkBonus(unvisitedBookmarkBonus)
+            .setUnvisitedTypedBonus(unvisitedTypedBonus)
+            .setReloadVisitBonus(reloadVisitBonus)
+            .setDecayRate(decayRate)
+            .build()
+    }
+
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.FrecencySettings): FrecencySettings {
+            return FrecencySettings(
+                numVisits = msg.numVisits,
+                firstBucketCutoffDays = msg.firstBucketCutoffDays,
+                secondBucketCutoffDays = msg.secondBucketCutoffDays,
+                thirdBucketCutoffDays = msg.thirdBucketCutoffDays,
+                fourthBucketCutoffDays = msg.fourthBucketCutoffDays,
+                firstBucketWeight = msg.firstBucketWeight,
+                secondBucketWeight = msg.secondBucketWeight,
+                thirdBucketWeight = msg.thirdBucketWeight,
+                fourthBucketWeight = msg.fourthBucketWeight,
+                defaultBucketWeight = msg.defaultBucketWeight,
+                embedVisitBonus = msg.embedVisitBonus,
+                framedLinkVisitBonus = msg.framedLinkVisitBonus,
+                linkVisitBonus = msg.linkVisitBonus,
+                typedVisitBonus = msg.typedVisitBonus,
+                bookmarkVisitBonus = msg.bookmarkVisitBonus,
+                downloadVisitBonus = msg.downloadVisitBonus,
+                permanentRedirectVisitBonus = msg.permanentRedirectVisitBonus,
+                temporaryRedirectVisitBonus = msg.temporaryRedirectVisitBonus,
+                redirectSourceVisitBonus = msg.redirectSourceVisitBonus,
+                defaultVisitBonus = msg.defaultVisitBonus,
+                unvisitedBookmarkBonus = msg.unvisitedBookmarkBonus,
+                unvisitedTypedBonus = msg.unvisitedTypedBonus,
+                reloadVisitBonus = msg.reloadVisitBonus,
+                decayRate = msg.decayRate
+            )
+        }
+    }
+}
@@ -47,7 +47,19 @@ internal interface LibPlacesFFI : Library {
         handle: PlacesApiHandle,
         db_path: String,
         out_err: RustError.ByReference
-    )
+    ): RustBuffer.ByValue
+
+    fun places_history_import_from_chromium(
+        handle: PlacesApiHandle,
+        db_path: String,
+        out_err: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun places_bookmarks_import_from_chromium(
+        handle: PlacesApiHandle,
+        path: String,
+        out_err: RustError.ByReference
+    ): RustBuffer.ByValue
 
     fun places_note_observation(
         handle: PlacesConnectionHandle,
@@ -55,6 +67,29 @@ internal interface LibPlacesFFI : Library {
         out_err: RustError.ByReference
     )
 
+    fun places_note_page_metadata(
+        handle: PlacesConnectionHandle,
+        url: String,
+        description: String?,
+        preview_image_url: String?,
+        out_err: RustError.ByReference
+    )
+
+    fun places_set_favicon_for_page(
+        handle: PlacesConnectionHandle,
+        page_url: String,
+        data: Pointer,
+        len: Int,
+        out_err: RustError.ByReference
+    )
+
+    fun places_get_favicon_for_page(
+        handle: PlacesConnectionHandle,
+        page_url: String,
+        preferred_size: Int,
+        out_err: RustError.ByReference
+    ): RustBuffer.ByValue
+
     /** Returns JSON string, which you need to free with places_destroy_string */
     fun places_query_autocomplete(
         handle: PlacesConnectionHandle,
@@ -70,6 +105,12 @@ internal interface LibPlacesFFI : Library {
         out_err: RustError.ByReference
     ): Pointer?
 
+    fun places_run_query(
+        handle: PlacesConnectionHandle,
+        query: String,
+        out_err: RustError.ByReference
+    ): RustBuffer.ByValue
+
     /** Note: urls_len and buffer_len must be the same length. The argument is somewhat redundant, but
      * is provided for a slight additional amount of sanity checking. These lengths are the number
      * of elements present (and not e.g. the number of bytes allocated). */
@@ -118,6 +159,13 @@ internal interface LibPlacesFFI : Library {
         out_err: RustError.ByReference
     )
 
+    fun places_delete_visits_for_host(
+        handle: PlacesConnectionHandle,
+        host: String,
+        include_subdomains: Byte,
+        out_err: RustError.ByReference
+    )
+
     fun places_delete_visit(
         handle: PlacesConnectionHandle,
         visit_url: String,
@@ -135,6 +183,16 @@ internal interface LibPlacesFFI : Library {
         out_err: RustError.ByReference
     )
 
+    fun places_recalculate_stale_frecencies(
+        handle: PlacesConnectionHandle,
+        out_err: RustError.ByReference
+    )
+
+    fun places_recalculate_all_frecencies(
+        handle: PlacesConnectionHandle,
+        out_err: RustError.ByReference
+    )
+
     fun places_prune_destructively(
         handle: PlacesConnectionHandle,
         out_err: RustError.ByReference
@@ -161,6 +219,25 @@ internal interface LibPlacesFFI : Library {
         error: RustError.ByReference
     ): RustBuffer.ByValue
 
+    fun places_get_top_frecent_site_infos(
+        handle: PlacesConnectionHandle,
+        numItems: Long,
+        frecencyThreshold: Long,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun places_search_history(
+        handle: PlacesConnectionHandle,
+        query: String,
+        start: Long,
+        end: Long,
+        excludeTypes: Int,
+        order: Int,
+        offset: Long,
+        count: Long,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
     fun places_get_visit_count(
         handle: PlacesConnectionHandle,
         excludeTypes: Int,
@@ -192,6 +269,83 @@ internal interface LibPlacesFFI : Library {
         out_err: RustError.ByReference
     )
 
+    fun sync15_reading_list_sync(
+        handle: PlacesApiHandle,
+        key_id: String,
+        access_token: String,
+        sync_key: String,
+        tokenserver_url: String,
+        out_err: RustError.ByReference
+    ): Pointer?
+
+    fun places_api_reset_reading_list(
+        handle: PlacesApiHandle,
+        out_err: RustError.ByReference
+    )
+
+    fun places_api_register_open_tab(
+        handle: PlacesApiHandle,
+        url: String,
+        out_err: RustError.ByReference
+    )
+
+    fun places_api_unregister_open_tab(
+        handle: PlacesApiHandle,
+        url: String,
+        out_err: RustError.ByReference
+    ): Byte
+
+    fun places_api_get_frecency_settings(
+        handle: PlacesApiHandle,
+        out_err: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun places_api_set_frecency_settings(
+        handle: PlacesApiHandle,
+        data: Pointer,
+        len: Int,
+        out_err: RustError.ByReference
+    )
+
+    fun reading_list_add(
+        handle: PlacesConnectionHandle,
+        url: String,
+        title: String?,
+        excerpt: String?,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun reading_list_get_all(
+        handle: PlacesConnectionHandle,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun reading_list_get_by_guid(
+        handle: PlacesConnectionHandle,
+        guid: String,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun reading_list_get_by_url(
+        handle: PlacesConnectionHandle,
+        url: String,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun reading_list_update(
+        handle: PlacesConnectionHandle,
+        guid: String,
+        data: Pointer,
+        len: Int,
+        error: RustError.ByReference
+    )
+
+    fun reading_list_delete(
+        handle: PlacesConnectionHandle,
+        guid: String,
+        error: RustError.ByReference
+    ): Byte
+
     fun bookmarks_get_all_with_url(
         handle: PlacesConnectionHandle,
         url: String,
@@ -224,12 +378,38 @@ internal interface LibPlacesFFI : Library {
         error: RustError.ByReference
     ): RustBuffer.ByValue
 
+    fun bookmarks_run_query(
+        handle: PlacesConnectionHandle,
+        guid: String,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
     fun bookmarks_get_recent(
         handle: PlacesConnectionHandle,
         limit: Int,
         error: RustError.ByReference
     ): RustBuffer.ByValue
 
+    fun bookmarks_list_folder_children(
+        handle: PlacesConnectionHandle,
+        guid: String,
+        order: Int,
+        offset: Int,
+        limit: Int,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun bookmarks_get_change_token(
+        handle: PlacesConnectionHandle,
+        error: RustError.ByReference
+    ): Long
+
+    fun bookmarks_get_changes_since(
+        handle: PlacesConnectionHandle,
+        token: Long,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
     // Returns newly inserted guid
     fun bookmarks_insert(
         handle: PlacesConnectionHandle,
@@ -257,6 +437,48 @@ internal interface LibPlacesFFI : Library {
         error: RustError.ByReference
     )
 
+    // Returns 1 if there was a change to undo.
+    fun bookmarks_undo(
+        handle: PlacesConnectionHandle,
+        error: RustError.ByReference
+    ): Byte
+
+    // Returns 1 if there was a change to redo.
+    fun bookmarks_redo(
+        handle: PlacesConnectionHandle,
+        error: RustError.ByReference
+    ): Byte
+
+    fun bookmarks_reorder_children(
+        handle: PlacesConnectionHandle,
+        parent_guid: String,
+        child_guids: StringArray,
+        child_guids_len: Int,
+        error: RustError.ByReference
+    )
+
+    // A negative position appends the items.
+    fun bookmarks_move_items(
+        handle: PlacesConnectionHandle,
+        guids: StringArray,
+        guids_len: Int,
+        new_parent_guid: String,
+        position: Int,
+        error: RustError.ByReference
+    )
+
+    fun bookmarks_export(
+        handle: PlacesConnectionHandle,
+        path: String,
+        error: RustError.ByReference
+    )
+
+    fun bookmarks_import_backup(
+        handle: PlacesConnectionHandle,
+        path: String,
+        error: RustError.ByReference
+    )
+
     /** Destroy strings returned from libplaces_ffi calls. */
     fun places_destroy_string(s: Pointer)
 
@@ -269,6 +491,20 @@ internal interface LibPlacesFFI : Library {
     /** Destroy connection created using `places_connection_new` */
     fun places_connection_destroy(handle: PlacesConnectionHandle, out_err: RustError.ByReference)
 
+    fun places_api_register_event_queue(
+        apiHandle: PlacesApiHandle,
+        error: RustError.ByReference
+    ): PlacesEventQueueHandle
+
+    /** Returns JSON string, which you need to free with places_destroy_string */
+    fun places_event_queue_drain(
+        handle: PlacesEventQueueHandle,
+        error: RustError.ByReference
+    ): Pointer?
+
+    /** Destroy queue created using `places_api_register_event_queue` */
+    fun places_event_queue_destroy(handle: PlacesEventQueueHandle, out_err: RustError.ByReference)
+
     /** Destroy api created using `places_api_new` */
     fun places_api_destroy(handle: PlacesApiHandle, out_err: RustError.ByReference)
 
@@ -283,10 +519,23 @@ internal interface LibPlacesFFI : Library {
         url: String,
         out_err: RustError.ByReference
     )
+
+    fun places_remove_adaptive_entries(
+        handle: PlacesConnectionHandle,
+        url: String?,
+        input: String?,
+        out_err: RustError.ByReference
+    ): Long
+
+    fun places_remove_all_adaptive_entries(
+        handle: PlacesConnectionHandle,
+        out_err: RustError.ByReference
+    ): Long
 }
 
 internal typealias PlacesConnectionHandle = Long
 internal typealias PlacesApiHandle = Long
+internal typealias PlacesEventQueueHandle = Long
 
 // This doesn't use a handle to avoid unnecessary locking and
 // because the type is panic safe, sync, and send.
@@ -4,6 +4,7 @@
 
 package mozilla.appservices.places
 
+import com.google.protobuf.ByteString
 import com.sun.jna.Native
 import com.sun.jna.Pointer
 import com.sun.jna.StringArray
@@ -115,26 +116,137 @@ class PlacesApi(path: String) : PlacesManager, AutoCloseable {
         return SyncTelemetryPing.fromJSONString(pingJSONString)
     }
 
-    override fun importBookmarksFromFennec(path: String): List<BookmarkItem> {
+    override fun syncReadingList(syncInfo: SyncAuthInfo): SyncTelemetryPing {
+        val pingJSONString = rustCallForString(this) { error ->
+            LibPlacesFFI.INSTANCE.sync15_reading_list_sync(
+                    this.handle.get(),
+                    syncInfo.kid,
+                    syncInfo.fxaAccessToken,
+                    syncInfo.syncKey,
+                    syncInfo.tokenserverURL,
+                    error
+            )
+        }
+        return SyncTelemetryPing.fromJSONString(pingJSONString)
+    }
+
+    override fun importBookmarksFromFennec(path: String): FennecBookmarksImport {
         val rustBuf = rustCall(this) { error ->
             LibPlacesFFI.INSTANCE.places_bookmarks_import_from_fennec(
                 this.handle.get(), path, error)
         }
 
         try {
-            val message = MsgTypes.BookmarkNodeList.parseFrom(rustBuf.asCodedInputStream()!!)
-            return unpackProtobufItemList(message)
+            val message = MsgTypes.FennecBookmarksImport.parseFrom(rustBuf.asCodedInputStream()!!)
+            return FennecBookmarksImport(
+                pinned = message.pinnedList.map { unpackProtobuf(it) as BookmarkItem },
+                report = ImportReport.fromMessage(message.report)
+            )
         } finally {
             LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
         }
     }
 
-    override fun importVisitsFromFennec(path: String) {
-        rustCall(this) { error ->
+    override fun importVisitsFromFennec(path: String): ImportReport {
+        return importWithReport { error ->
             LibPlacesFFI.INSTANCE.places_history_import_from_fennec(
                 this.handle.get(), path, error)
         }
     }
+
+    override fun importVisitsFromChromium(path: String): ImportReport {
+        return importWithReport { error ->
+            LibPlacesFFI.INSTANCE.places_history_import_from_chromium(
+                this.handle.get(), path, error)
+        }
+    }
+
+    override fun importBookmarksFromChromium(path: String): ImportReport {
+        return importWithReport { error ->
+            LibPlacesFFI.INSTANCE.places_bookmarks_import_from_chromium(
+                this.handle.get(), path, error)
+        }
+    }
+
+    private inline fun importWithReport(callback: (RustError.ByReference) -> RustBuffer.ByValue): ImportReport {
+        val rustBuf = rustCall(this, callback)
+        try {
+            return ImportReport.fromMessage(MsgTypes.ImportReport.parseFrom(rustBuf.asCodedInputStream()!!))
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+        }
+    }
+
+    override fun registerOpenTab(url: String) {
+        rustCall(this) { error ->
+            LibPlacesFFI.INSTANCE.places_api_register_open_tab(this.handle.get(), url, error)
+        }
+    }
+
+    override fun unregisterOpenTab(url: String): Boolean {
+        val existed = rustCall(this) { error ->
+            LibPlacesFFI.INSTANCE.places_api_unregister_open_tab(this.handle.get(), url, error)
+        }
+        return existed.toInt() != 0
+    }
+
+    override fun getFrecencySettings(): FrecencySettings {
+        val rustBuf = rustCall(this) { error ->
+            LibPlacesFFI.INSTANCE.places_api_get_frecency_settings(this.handle.get(), error)
+        }
+        try {
+            val message = MsgTypes.FrecencySettings.parseFrom(rustBuf.asCodedInputStream()!!)
+            return FrecencySettings.fromMessage(message)
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+        }
+    }
+
+    override fun setFrecencySettings(settings: FrecencySettings) {
+        val (nioBuf, len) = settings.toProtobuf().toNioDirectBuffer()
+        rustCall(this) { error ->
+            val ptr = Native.getDirectBufferPointer(nioBuf)
+            LibPlacesFFI.INSTANCE.places_api_set_frecency_settings(this.handle.get(), ptr, len, error)
+        }
+    }
+
+    override fun registerEventQueue(): PlacesEventQueue {
+        val queueHandle = rustCall(this) { error ->
+            LibPlacesFFI.INSTANCE.places_api_register_event_queue(this.handle.get(), error)
+        }
+        return PlacesEventQueue(queueHandle)
+    }
+}
+
+/**
+ * A queue of [PlacesEvent]s, created by [PlacesManager.registerEventQueue].
+ *
+ * Events are queued until [drain] is called, so it should be polled regularly
+ * (for example, when the UI showing history or bookmarks becomes visible).
+ * Close it when you no longer need it.
+ */
+class PlacesEventQueue internal constructor(queueHandle: Long) : AutoCloseable {
+    private val handle: AtomicLong = AtomicLong(queueHandle)
+
+    /**
+     * Returns the events which happened since the last call, oldest first.
+     */
+    fun drain(): List<PlacesEvent> {
+        val json = rustCallForString(this) { error ->
+            LibPlacesFFI.INSTANCE.places_event_queue_drain(this.handle.get(), error)
+        }
+        return PlacesEvent.fromJSONArray(json)
+    }
+
+    @Synchronized
+    override fun close() {
+        val handle = this.handle.getAndSet(0L)
+        if (handle != 0L) {
+            rustCall(this) { error ->
+                LibPlacesFFI.INSTANCE.places_event_queue_destroy(handle, error)
+            }
+        }
+    }
 }
 
 internal inline fun <U> rustCall(syncOn: Any, callback: (RustError.ByReference) -> U): U {
@@ -231,7 +343,8 @@ open class PlacesConnection internal constructor(connHandle: Long) : Interruptib
 open class PlacesReaderConnection internal constructor(connHandle: Long) :
         PlacesConnection(connHandle),
         ReadableHistoryConnection,
-        ReadableBookmarksConnection {
+        ReadableBookmarksConnection,
+        ReadableReadingListConnection {
     override fun queryAutocomplete(query: String, limit: Int): List<SearchResult> {
         val json = rustCallForString { error ->
             LibPlacesFFI.INSTANCE.places_query_autocomplete(this.handle.get(), query, limit, error)
@@ -245,6 +358,32 @@ open class PlacesReaderConnection internal constructor(connHandle: Long) :
         }
     }
 
+    override fun runPlaceQuery(query: String): PlaceQueryResults {
+        val rustBuf = rustCall { error ->
+            LibPlacesFFI.INSTANCE.places_run_query(this.handle.get(), query, error)
+        }
+
+        try {
+            val message = MsgTypes.QueryResults.parseFrom(rustBuf.asCodedInputStream()!!)
+            return PlaceQueryResults.fromMessage(message)
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+        }
+    }
+
+    override fun runBookmarkQuery(guid: String): PlaceQueryResults {
+        val rustBuf = rustCall { error ->
+            LibPlacesFFI.INSTANCE.bookmarks_run_query(this.handle.get(), guid, error)
+        }
+
+        try {
+            val message = MsgTypes.QueryResults.parseFrom(rustBuf.asCodedInputStream()!!)
+            return PlaceQueryResults.fromMessage(message)
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+        }
+    }
+
     override fun getVisited(urls: List<String>): List<Boolean> {
         // Note urlStrings has a potential footgun in that StringArray has a `size()` method
         // which returns the size *in bytes*. Hence us using urls.size (which is an element count)
@@ -313,6 +452,55 @@ open class PlacesReaderConnection internal constructor(connHandle: Long) :
         }
     }
 
+    override fun getFaviconForPage(url: String, preferredSize: Int): Favicon? {
+        val rustBuf = rustCall { error ->
+            LibPlacesFFI.INSTANCE.places_get_favicon_for_page(
+                    this.handle.get(), url, preferredSize, error)
+        }
+        try {
+            return rustBuf.asCodedInputStream()?.let { stream ->
+                Favicon.fromMessage(MsgTypes.FaviconInfo.parseFrom(stream))
+            }
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+        }
+    }
+
+    override fun getTopFrecentSiteInfos(numItems: Long, frecencyThreshold: Long): List<TopFrecentSiteInfo> {
+        val infoBuffer = rustCall { error ->
+            LibPlacesFFI.INSTANCE.places_get_top_frecent_site_infos(
+                    this.handle.get(), numItems, frecencyThreshold, error)
+        }
+        try {
+            val infos = MsgTypes.TopFrecentSiteInfos.parseFrom(infoBuffer.asCodedInputStream()!!)
+            return TopFrecentSiteInfo.fromMessage(infos)
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(infoBuffer)
+        }
+    }
+
+    override fun searchHistory(
+        query: String,
+        start: Long,
+        end: Long,
+        excludeTypes: List<VisitType>,
+        order: HistorySearchOrder,
+        offset: Long,
+        count: Long
+    ): List<VisitInfo> {
+        val infoBuffer = rustCall { error ->
+            LibPlacesFFI.INSTANCE.places_search_history(
+                    this.handle.get(), query, start, end, visitTransitionSet(excludeTypes),
+                    order.value, offset, count, error)
+        }
+        try {
+            val infos = MsgTypes.HistoryVisitInfos.parseFrom(infoBuffer.asCodedInputStream()!!)
+            return VisitInfo.fromMessage(infos)
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(infoBuffer)
+        }
+    }
+
     override fun getVisitCount(excludeTypes: List<VisitType>): Long {
         return rustCall { error ->
             LibPlacesFFI.INSTANCE.places_get_visit_count(
@@ -369,6 +557,12 @@ open class PlacesReaderConnection internal constructor(connHandle: Long) :
         }
     }
 
+    override fun exportBookmarks(path: String) {
+        rustCall { error ->
+            LibPlacesFFI.INSTANCE.bookmarks_export(this.handle.get(), path, error)
+        }
+    }
+
     override fun searchBookmarks(query: String, limit: Int): List<BookmarkItem> {
         val rustBuf = rustCall { err ->
             LibPlacesFFI.INSTANCE.bookmarks_search(this.handle.get(), query, limit, err)
@@ -394,6 +588,83 @@ open class PlacesReaderConnection internal constructor(connHandle: Long) :
             LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
         }
     }
+
+    override fun listFolderChildren(
+        guid: String,
+        order: FolderChildrenOrder,
+        offset: Int,
+        limit: Int
+    ): List<BookmarkTreeNode> {
+        val rustBuf = rustCall { err ->
+            LibPlacesFFI.INSTANCE.bookmarks_list_folder_children(
+                    this.handle.get(), guid, order.value, offset, limit, err)
+        }
+
+        try {
+            val message = MsgTypes.BookmarkNodeList.parseFrom(rustBuf.asCodedInputStream()!!)
+            return message.nodesList.map { unpackProtobuf(it) }
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+        }
+    }
+
+    override fun getBookmarkChangeToken(): Long {
+        return rustCall { error ->
+            LibPlacesFFI.INSTANCE.bookmarks_get_change_token(this.handle.get(), error)
+        }
+    }
+
+    override fun getBookmarkChangesSince(token: Long): BookmarkChanges {
+        val rustBuf = rustCall { err ->
+            LibPlacesFFI.INSTANCE.bookmarks_get_changes_since(this.handle.get(), token, err)
+        }
+
+        try {
+            val message = MsgTypes.BookmarkChanges.parseFrom(rustBuf.asCodedInputStream()!!)
+            return BookmarkChanges.fromMessage(message)
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+        }
+    }
+
+    override fun getReadingList(): List<ReadingListItem> {
+        val rustBuf = rustCall { error ->
+            LibPlacesFFI.INSTANCE.reading_list_get_all(this.handle.get(), error)
+        }
+
+        try {
+            val message = MsgTypes.ReadingListItems.parseFrom(rustBuf.asCodedInputStream()!!)
+            return ReadingListItem.fromCollectionMessage(message)
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+        }
+    }
+
+    override fun getReadingListItem(guid: String): ReadingListItem? {
+        val rustBuf = rustCall { error ->
+            LibPlacesFFI.INSTANCE.reading_list_get_by_guid(this.handle.get(), guid, error)
+        }
+        try {
+            return rustBuf.asCodedInputStream()?.let { stream ->
+                ReadingListItem.fromMessage(MsgTypes.ReadingListItem.parseFrom(stream))
+            }
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+        }
+    }
+
+    override fun getReadingListItemForUrl(url: String): ReadingListItem? {
+        val rustBuf = rustCall { error ->
+            LibPlacesFFI.INSTANCE.reading_list_get_by_url(this.handle.get(), url, error)
+        }
+        try {
+            return rustBuf.asCodedInputStream()?.let { stream ->
+                ReadingListItem.fromMessage(MsgTypes.ReadingListItem.parseFrom(stream))
+            }
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+        }
+    }
 }
 
 fun visitTransitionSet(l: List<VisitType>): Int {
@@ -413,7 +684,8 @@ fun visitTransitionSet(l: List<VisitType>): Int {
 class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesApi) :
         PlacesReaderConnection(connHandle),
         WritableHistoryConnection,
-        WritableBookmarksConnection {
+        WritableBookmarksConnection,
+        WritableReadingListConnection {
     // The reference to our PlacesAPI. Mostly used to know how to handle getting closed.
     val apiRef = WeakReference(api)
     override fun noteObservation(data: VisitObservation) {
@@ -423,6 +695,22 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         }
     }
 
+    override fun notePageMetadata(url: String, description: String?, previewImageUrl: String?) {
+        rustCall { error ->
+            LibPlacesFFI.INSTANCE.places_note_page_metadata(
+                    this.handle.get(), url, description, previewImageUrl, error)
+        }
+    }
+
+    override fun setFaviconForPage(url: String, favicon: Favicon) {
+        val (nioBuf, len) = favicon.toMessage().toNioDirectBuffer()
+        rustCall { error ->
+            val ptr = Native.getDirectBufferPointer(nioBuf)
+            LibPlacesFFI.INSTANCE.places_set_favicon_for_page(
+                    this.handle.get(), url, ptr, len, error)
+        }
+    }
+
     override fun deletePlace(url: String) {
         rustCall { error ->
             LibPlacesFFI.INSTANCE.places_delete_place(
@@ -448,6 +736,13 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         }
     }
 
+    override fun deleteVisitsForHost(host: String, includeSubdomains: Boolean) {
+        rustCall { error ->
+            LibPlacesFFI.INSTANCE.places_delete_visits_for_host(
+                    this.handle.get(), host, if (includeSubdomains) 1 else 0, error)
+        }
+    }
+
     override fun wipeLocal() {
         rustCall { error ->
             LibPlacesFFI.INSTANCE.places_wipe_local(this.handle.get(), error)
@@ -460,6 +755,18 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         }
     }
 
+    override fun recalculateStaleFrecencies() {
+        rustCall { error ->
+            LibPlacesFFI.INSTANCE.places_recalculate_stale_frecencies(this.handle.get(), error)
+        }
+    }
+
+    override fun recalculateAllFrecencies() {
+        rustCall { error ->
+            LibPlacesFFI.INSTANCE.places_recalculate_all_frecencies(this.handle.get(), error)
+        }
+    }
+
     override fun pruneDestructively() {
         rustCall { error ->
             LibPlacesFFI.INSTANCE.places_prune_destructively(this.handle.get(), error)
@@ -478,6 +785,12 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         }
     }
 
+    override fun importBookmarks(path: String) {
+        rustCall { error ->
+            LibPlacesFFI.INSTANCE.bookmarks_import_backup(this.handle.get(), path, error)
+        }
+    }
+
     override fun deleteBookmarkNode(guid: String): Boolean {
         val existedByte = rustCall { error ->
             LibPlacesFFI.INSTANCE.bookmarks_delete(this.handle.get(), guid, error)
@@ -485,6 +798,48 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         return existedByte.toInt() != 0
     }
 
+    override fun addToReadingList(url: String, title: String?, excerpt: String?): ReadingListItem {
+        val rustBuf = rustCall { error ->
+            LibPlacesFFI.INSTANCE.reading_list_add(this.handle.get(), url, title, excerpt, error)
+        }
+
+        try {
+            val message = MsgTypes.ReadingListItem.parseFrom(rustBuf.asCodedInputStream()!!)
+            return ReadingListItem.fromMessage(message)
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+        }
+    }
+
+    override fun updateReadingListItem(guid: String, info: ReadingListItemUpdateInfo) {
+        val (nioBuf, len) = info.toProtobuf().toNioDirectBuffer()
+        rustCall { error ->
+            val ptr = Native.getDirectBufferPointer(nioBuf)
+            LibPlacesFFI.INSTANCE.reading_list_update(this.handle.get(), guid, ptr, len, error)
+        }
+    }
+
+    override fun deleteReadingListItem(guid: String): Boolean {
+        val existedByte = rustCall { error ->
+            LibPlacesFFI.INSTANCE.reading_list_delete(this.handle.get(), guid, error)
+        }
+        return existedByte.toInt() != 0
+    }
+
+    override fun undo(): Boolean {
+        val undidByte = rustCall { error ->
+            LibPlacesFFI.INSTANCE.bookmarks_undo(this.handle.get(), error)
+        }
+        return undidByte.toInt() != 0
+    }
+
+    override fun redo(): Boolean {
+        val redidByte = rustCall { error ->
+            LibPlacesFFI.INSTANCE.bookmarks_redo(this.handle.get(), error)
+        }
+        return redidByte.toInt() != 0
+    }
+
     // Does the shared insert work, takes the position just because
     // its a little tedious to type out setting it
     private fun doInsert(builder: MsgTypes.BookmarkNode.Builder, position: Int?): String {
@@ -512,12 +867,21 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         return this.doInsert(builder, position)
     }
 
-    override fun createBookmarkItem(parentGUID: String, url: String, title: String, position: Int?): String {
+    override fun createBookmarkItem(
+        parentGUID: String,
+        url: String,
+        title: String,
+        position: Int?,
+        keyword: String?,
+        tags: List<String>
+    ): String {
         val builder = MsgTypes.BookmarkNode.newBuilder()
                 .setNodeType(BookmarkType.Bookmark.value)
                 .setParentGuid(parentGUID)
                 .setUrl(url)
                 .setTitle(title)
+                .addAllTags(tags)
+        keyword?.let { builder.setKeyword(it) }
         return this.doInsert(builder, position)
     }
 
@@ -530,6 +894,24 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         }
     }
 
+    override fun reorderChildren(parentGUID: String, childGUIDs: List<String>) {
+        // As in `getVisited`, StringArray's `size()` is in bytes, so pass the
+        // element count from the list.
+        val guidStrings = StringArray(childGUIDs.toTypedArray(), "utf8")
+        rustCall { error ->
+            LibPlacesFFI.INSTANCE.bookmarks_reorder_children(
+                    this.handle.get(), parentGUID, guidStrings, childGUIDs.size, error)
+        }
+    }
+
+    override fun moveItems(guids: List<String>, parentGUID: String, position: Int?) {
+        val guidStrings = StringArray(guids.toTypedArray(), "utf8")
+        rustCall { error ->
+            LibPlacesFFI.INSTANCE.bookmarks_move_items(
+                    this.handle.get(), guidStrings, guids.size, parentGUID, position ?: -1, error)
+        }
+    }
+
     override fun acceptResult(searchString: String, url: String) {
         rustCall { error ->
             LibPlacesFFI.INSTANCE.places_accept_result(
@@ -537,6 +919,19 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         }
     }
 
+    override fun removeAdaptiveEntries(url: String?, input: String?): Long {
+        return rustCall { error ->
+            LibPlacesFFI.INSTANCE.places_remove_adaptive_entries(
+                    this.handle.get(), url, input, error)
+        }
+    }
+
+    override fun removeAllAdaptiveEntries(): Long {
+        return rustCall { error ->
+            LibPlacesFFI.INSTANCE.places_remove_all_adaptive_entries(this.handle.get(), error)
+        }
+    }
+
     @Synchronized
     override fun close() {
         // If our API is still around, do nothing.
@@ -605,6 +1000,16 @@ interface PlacesManager {
      */
     fun syncBookmarks(syncInfo: SyncAuthInfo): SyncTelemetryPing
 
+    /**
+     * Syncs the reading list, returning a telemetry ping.
+     *
+     * Note that this function blocks until the sync is complete, which may
+     * take some time due to the network etc. Because only 1 thread can be
+     * using a PlacesAPI at a time, it is recommended, but not enforced, that
+     * you have all connections you intend using open before calling this.
+     */
+    fun syncReadingList(syncInfo: SyncAuthInfo): SyncTelemetryPing
+
     /**
      * Imports bookmarks from a Fennec `browser.db` database.
      * Fennec used to store "pinned websites" as normal bookmarks
@@ -616,9 +1021,9 @@ interface PlacesManager {
      * It has been designed exclusively for non-sync users.
      *
      * @param path Path to the `browser.db` file database.
-     * @return A list of pinned websites.
+     * @return The pinned websites, and a report of what was imported.
      */
-    fun importBookmarksFromFennec(path: String): List<BookmarkItem>
+    fun importBookmarksFromFennec(path: String): FennecBookmarksImport
 
     /**
      * Imports visits from a Fennec `browser.db` database.
@@ -627,8 +1032,72 @@ interface PlacesManager {
      * be called before bookmarks import.
      *
      * @param path Path to the `browser.db` file database.
+     * @return A report of what was imported.
+     */
+    fun importVisitsFromFennec(path: String): ImportReport
+
+    /**
+     * Imports history from a Chrome or Chromium `History` database.
+     *
+     * Visits we already have aren't imported again, so this can be
+     * called more than once for the same profile.
+     *
+     * @param path Path to the `History` file database.
+     * @return A report of what was imported.
+     */
+    fun importVisitsFromChromium(path: String): ImportReport
+
+    /**
+     * Imports bookmarks from a Chrome or Chromium `Bookmarks` file.
+     * The bookmarks bar, "Other bookmarks" and "Mobile bookmarks" are
+     * added to the toolbar, unfiled and mobile roots.
+     *
+     * @param path Path to the `Bookmarks` JSON file.
+     * @return A report of what was imported.
+     */
+    fun importBookmarksFromChromium(path: String): ImportReport
+
+    /**
+     * Note that a page was opened in a tab, so that [ReadableHistoryConnection.queryAutocomplete]
+     * can offer to switch to it. A page can be open in more than one tab, so
+     * each call should be matched by a call to [unregisterOpenTab].
+     *
+     * @throws UrlParseFailed If `url` is not a valid URL.
+     */
+    fun registerOpenTab(url: String)
+
+    /**
+     * Note that a tab showing a page was closed, or navigated away from it.
+     *
+     * @return Whether or not the page was open.
+     * @throws UrlParseFailed If `url` is not a valid URL.
      */
-    fun importVisitsFromFennec(path: String)
+    fun unregisterOpenTab(url: String): Boolean
+
+    /**
+     * Get the weights and bonuses used to calculate frecencies.
+     */
+    fun getFrecencySettings(): FrecencySettings
+
+    /**
+     * Change the weights and bonuses used to calculate frecencies. The new
+     * settings are saved, and used by every connection from now on.
+     *
+     * This marks every page's frecency as stale, but doesn't recalculate
+     * them. Call [WritableHistoryConnection.recalculateStaleFrecencies] to
+     * do that, ideally in the background.
+     *
+     * @throws PlacesException If a sync is in progress, or if the settings
+     * are invalid: for example, if `numVisits` isn't positive, the cutoffs
+     * are negative or out of order, or `decayRate` isn't in (0, 1].
+     */
+    fun setFrecencySettings(settings: FrecencySettings)
+
+    /**
+     * Start queueing change notifications for history and bookmarks. Changes
+     * made by any connection are queued once they have been committed.
+     */
+    fun registerEventQueue(): PlacesEventQueue
 }
 
 interface InterruptibleConnection : AutoCloseable {
@@ -642,9 +1111,20 @@ interface ReadableHistoryConnection : InterruptibleConnection {
     /**
      * A way to search the internal database tailored for autocompletion purposes.
      *
+     * The query is split into words, and each result must match all of them.
+     * The words `*`, `^`, `+`, and `%` restrict the search to bookmarks,
+     * history, tagged pages, and open tabs, respectively.
+     *
+     * Keywords come first, then matching origins and URLs, then everything
+     * else by frecency, including pages registered with
+     * [PlacesManager.registerOpenTab].
+     *
      * @param query a string to match results against.
      * @param limit a maximum number of results to retrieve.
-     * @return a list of [SearchResult] matching the [query], in arbitrary order.
+     * @return a list of [SearchResult] matching the [query], best match first.
+     *
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
      */
     fun queryAutocomplete(query: String, limit: Int): List<SearchResult>
 
@@ -659,6 +1139,22 @@ interface ReadableHistoryConnection : InterruptibleConnection {
      */
     fun matchUrl(query: String): String?
 
+    /**
+     * Runs one of desktop's `place:` queries, like the ones behind its
+     * "Most Visited" and "Recent Tags" smart folders. Only some of its
+     * parameters are supported; see `places::query` for the list.
+     *
+     * @param query A `place:` URL.
+     * @return The pages or tags the query matched.
+     *
+     * @throws UrlParseFailed If `query` is not a valid URL.
+     * @throws PlacesException If `query` isn't a `place:` URL, or uses an
+     * unsupported parameter value.
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun runPlaceQuery(query: String): PlaceQueryResults
+
     /**
      * Maps a list of page URLs to a list of booleans indicating if each URL was visited.
      * @param urls a list of page URLs about which "visited" information is being requested.
@@ -704,6 +1200,52 @@ interface ReadableHistoryConnection : InterruptibleConnection {
      */
     fun getVisitPage(offset: Long, count: Long, excludeTypes: List<VisitType> = listOf()): List<VisitInfo>
 
+    /**
+     * Returns the most frecent sites, to show as "top sites". Each site is
+     * represented by its most frecent page, and redirects, error pages and hidden
+     * pages are excluded.
+     *
+     * @param numItems The maximum number of sites to return.
+     * @param frecencyThreshold The minimum frecency for a site to be included.
+     */
+    fun getTopFrecentSiteInfos(numItems: Long, frecencyThreshold: Long = 1): List<TopFrecentSiteInfo>
+
+    /**
+     * Returns the size of a page's favicon closest to [preferredSize]: the
+     * smallest size that's at least as big, or the biggest size if they're
+     * all smaller. Pages without an icon of their own use their site's
+     * `/favicon.ico`, if it's known.
+     *
+     * @param url The URL of the page.
+     * @param preferredSize The preferred width of the icon, in pixels.
+     * @return The icon, or null if the page doesn't have one.
+     */
+    fun getFaviconForPage(url: String, preferredSize: Int): Favicon?
+
+    /**
+     * Search all of history for pages whose title or URL contains every word in
+     * [query]. Each matching page is returned once, with its most recent visit
+     * in the date range.
+     *
+     * @param query The words to search for. If empty, every page visited in the
+     * date range is returned.
+     * @param start The (inclusive) start time to search.
+     * @param end The (inclusive) end time to search.
+     * @param excludeTypes List of visit types to exclude.
+     * @param order Whether to return the best matches or most recent visits first.
+     * @param offset The offset where the page of results begins.
+     * @param count The number of results to return.
+     */
+    fun searchHistory(
+        query: String,
+        start: Long = 0,
+        end: Long = Long.MAX_VALUE,
+        excludeTypes: List<VisitType> = listOf(),
+        order: HistorySearchOrder = HistorySearchOrder.RELEVANCE,
+        offset: Long = 0,
+        count: Long = 100
+    ): List<VisitInfo>
+
     /**
      * Get the number of history visits.
      *
@@ -725,6 +1267,28 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      */
     fun noteObservation(data: VisitObservation)
 
+    /**
+     * Records the description and preview image for a page, typically taken
+     * from its metadata. These are returned with the page's visits and
+     * autocomplete results. Does nothing if the page has never been visited.
+     *
+     * @param url The URL of the page.
+     * @param description The page description, or null to clear it.
+     * @param previewImageUrl The URL of the preview image, or null to clear it.
+     */
+    fun notePageMetadata(url: String, description: String?, previewImageUrl: String?)
+
+    /**
+     * Stores one size of a page's favicon. Call this once for each size of
+     * the icon. Setting an icon with a different URL replaces the page's old
+     * icon. Icons are removed along with the history they belong to.
+     *
+     * @param url The URL of the page, which must be in history or bookmarked.
+     * @param favicon The icon.
+     * @throws UrlParseFailed If `url` is not a valid URL.
+     */
+    fun setFaviconForPage(url: String, favicon: Favicon)
+
     /**
      * Deletes all history visits, without recording tombstones.
      *
@@ -740,6 +1304,8 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      * - `VACUUM`ing.
      * - Requesting that the indices in our tables be optimized.
      * - Expiring irrelevant history visits.
+     * - Decaying frecencies, at most once a day, by
+     *   [FrecencySettings.decayRate].
      * - Periodic repair or deletion of corrupted records.
      * - etc.
      *
@@ -749,6 +1315,27 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      */
     fun runMaintenance()
 
+    /**
+     * Recalculate the frecencies of every page marked as stale, for example,
+     * after calling [PlacesManager.setFrecencySettings]. This works in
+     * chunks, so if it's interrupted, the pages it already rescored stay
+     * rescored, and calling it again picks up where it left off.
+     *
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun recalculateStaleFrecencies()
+
+    /**
+     * Recalculate the frecency of every page, whether or not it's marked as
+     * stale. Like [recalculateStaleFrecencies], this can be interrupted, and
+     * calling [recalculateStaleFrecencies] afterward finishes the job.
+     *
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun recalculateAllFrecencies()
+
     /**
      * Aggressively prune history visits. These deletions are not intended
      * to be synced, however due to the way history sync works, this can
@@ -813,6 +1400,18 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      */
     fun deleteVisitsBetween(startTime: Long, endTime: Long)
 
+    /**
+     * Deletes all history for a site ("Forget about this site").
+     *
+     * Every visit to a page on [host], on any port, is deleted, as are the
+     * pages themselves unless they are bookmarked. These deletions are synced.
+     *
+     * @param host The host to delete history for, such as `example.com`.
+     * @param includeSubdomains Whether to also delete history for subdomains,
+     * such as `www.example.com`.
+     */
+    fun deleteVisitsForHost(host: String, includeSubdomains: Boolean)
+
     /**
      * Delete the single visit that occurred at the provided timestamp.
      *
@@ -834,6 +1433,28 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      * @param url The chosen URL string
      */
     fun acceptResult(searchString: String, url: String)
+
+    /**
+     * Removes the autocomplete matches recorded by [acceptResult], so that
+     * they no longer boost those pages in autocomplete results.
+     *
+     * @param url Only remove matches for this page, if not null.
+     * @param input Only remove matches for this query string, if not null.
+     * `url` and `input` can't both be null; use [removeAllAdaptiveEntries]
+     * to remove all recorded matches.
+     * @return The number of matches removed.
+     *
+     * @throws UrlParseFailed If `url` is not a valid URL.
+     * @throws PlacesException If both `url` and `input` are null.
+     */
+    fun removeAdaptiveEntries(url: String? = null, input: String? = null): Long
+
+    /**
+     * Removes all the autocomplete matches recorded by [acceptResult].
+     *
+     * @return The number of matches removed.
+     */
+    fun removeAllAdaptiveEntries(): Long
 }
 
 class InterruptHandle internal constructor(raw: RawPlacesInterruptHandle) : AutoCloseable {
@@ -885,6 +1506,16 @@ enum class VisitType(val type: Int) {
     RELOAD(9)
 }
 
+/**
+ * How results from [ReadableHistoryConnection.searchHistory] are sorted.
+ */
+enum class HistorySearchOrder(val value: Int) {
+    /** Best matches first. */
+    RELEVANCE(1),
+    /** Most recently visited first. */
+    DATE(2)
+}
+
 private val intToVisitType: Map<Int, VisitType> = VisitType.values().associateBy(VisitType::type)
 
 /**
@@ -929,13 +1560,60 @@ fun stringOrNull(jsonObject: JSONObject, key: String): String? {
     }
 }
 
+/**
+ * What an import did: how many rows it looked at, how many it brought in,
+ * why it skipped the rest, and how long each phase took. For history, a
+ * row is a visit, and for bookmarks, it's a bookmark, folder or separator.
+ */
+data class ImportReport(
+    val rowsSeen: Int,
+    val rowsImported: Int,
+    /** Rows skipped because their URL was missing, invalid, or too long. */
+    val skippedInvalidUrl: Int,
+    /** Rows skipped because their parent was missing or invalid. */
+    val skippedBadParent: Int,
+    /** Rows of a type we don't import, like livemarks or queries. */
+    val skippedUnsupported: Int,
+    /** Rows with a missing or nonsensical timestamp, imported with the current time instead. */
+    val invalidTimestamps: Int,
+    /** How long each phase took, in milliseconds, in the order they ran. */
+    val phaseDurations: Map<String, Long>
+) {
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.ImportReport): ImportReport {
+            return ImportReport(
+                rowsSeen = msg.rowsSeen,
+                rowsImported = msg.rowsImported,
+                skippedInvalidUrl = msg.skippedInvalidUrl,
+                skippedBadParent = msg.skippedBadParent,
+                skippedUnsupported = msg.skippedUnsupported,
+                invalidTimestamps = msg.invalidTimestamps,
+                phaseDurations = msg.phasesList.associate { it.name to it.durationMs }
+            )
+        }
+    }
+}
+
+/**
+ * The result of `PlacesManager.importBookmarksFromFennec`.
+ */
+data class FennecBookmarksImport(
+    /** Fennec's pinned websites, which aren't imported as bookmarks. */
+    val pinned: List<BookmarkItem>,
+    val report: ImportReport
+)
+
 data class SearchResult(
     val searchString: String,
     val url: String,
     val title: String,
     val frecency: Long,
-    val iconUrl: String? = null
-    // Skipping `reasons` for now...
+    val iconUrl: String? = null,
+    val description: String? = null,
+    val previewImageUrl: String? = null,
+    /** Whether the page is open in a tab, so the user can switch to it. */
+    val isOpenTab: Boolean = false
+    // Skipping the other `reasons` for now...
 ) {
     companion object {
         fun fromJSON(jsonObject: JSONObject): SearchResult {
@@ -944,10 +1622,18 @@ data class SearchResult(
                 url = jsonObject.getString("url"),
                 title = jsonObject.getString("title"),
                 frecency = jsonObject.getLong("frecency"),
-                iconUrl = stringOrNull(jsonObject, "icon_url")
+                iconUrl = stringOrNull(jsonObject, "icon_url"),
+                description = stringOrNull(jsonObject, "description"),
+                previewImageUrl = stringOrNull(jsonObject, "preview_image_url"),
+                isOpenTab = hasReason(jsonObject, "OpenTab")
             )
         }
 
+        private fun hasReason(jsonObject: JSONObject, reason: String): Boolean {
+            val reasons = jsonObject.optJSONArray("reasons") ?: return false
+            return (0 until reasons.length()).any { reasons.opt(it) == reason }
+        }
+
         fun fromJSONArray(jsonArrayText: String): List<SearchResult> {
             val result: MutableList<SearchResult> = mutableListOf()
             val array = JSONArray(jsonArrayText)
@@ -987,7 +1673,18 @@ data class VisitInfo(
      * Whether the page is hidden because it redirected to another page, or was
      * visited in a frame.
      */
-    val isHidden: Boolean
+    val isHidden: Boolean,
+
+    /**
+     * The description of the page, if one was recorded with `notePageMetadata`.
+     */
+    val description: String? = null,
+
+    /**
+     * The URL of the page's preview image, if one was recorded with
+     * `notePageMetadata`.
+     */
+    val previewImageUrl: String? = null
 ) {
     companion object {
         internal fun fromMessage(msg: MsgTypes.HistoryVisitInfos): List<VisitInfo> {
@@ -996,8 +1693,205 @@ data class VisitInfo(
                     title = it.title,
                     visitTime = it.timestamp,
                     visitType = intToVisitType[it.visitType]!!,
-                    isHidden = it.isHidden)
+                    isHidden = it.isHidden,
+                    description = if (it.hasDescription()) it.description else null,
+                    previewImageUrl = if (it.hasPreviewImageUrl()) it.previewImageUrl else null)
             }
         }
     }
 }
+
+/**
+ * One size of a favicon. Returned by `getFaviconForPage`.
+ */
+class Favicon(
+    /**
+     * The URL of the icon.
+     */
+    val url: String,
+
+    /**
+     * The width of the icon, in pixels. Icons are assumed to be square.
+     */
+    val width: Int,
+
+    /**
+     * The MIME type of the icon, like `image/png`.
+     */
+    val mimeType: String,
+
+    /**
+     * The icon's image data.
+     */
+    val data: ByteArray
+) {
+    internal fun toMessage(): MsgTypes.FaviconInfo {
+        return MsgTypes.FaviconInfo.newBuilder()
+                .setUrl(url)
+                .setWidth(width)
+                .setMimeType(mimeType)
+                .setData(ByteString.copyFrom(data))
+                .build()
+    }
+
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.FaviconInfo): Favicon {
+            return Favicon(url = msg.url,
+                width = msg.width,
+                mimeType = msg.mimeType,
+                data = msg.data.toByteArray())
+        }
+    }
+}
+
+/**
+ * Information about a top frecent site. Returned by `PlacesAPI.getTopFrecentSiteInfos`.
+ */
+data class TopFrecentSiteInfo(
+    /**
+     * The URL of the site's most frecent page.
+     */
+    val url: String,
+
+    /**
+     * The title of that page, if known.
+     */
+    val title: String?,
+
+    /**
+     * The frecency of the page.
+     */
+    val frecency: Long,
+
+    /**
+     * The number of times the page has been visited, on this and other devices.
+     */
+    val visitCount: Long,
+
+    /**
+     * The time the page was last visited in integer milliseconds since the unix epoch.
+     */
+    val lastVisitTime: Long
+) {
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.TopFrecentSiteInfos): List<TopFrecentSiteInfo> {
+            return msg.infosList.map {
+                TopFrecentSiteInfo(url = it.url,
+                    title = if (it.hasTitle()) it.title else null,
+                    frecency = it.frecency,
+                    visitCount = it.visitCount,
+                    lastVisitTime = it.lastVisitDate)
+            }
+        }
+    }
+}
+
+/**
+ * A change to history or bookmarks, as returned by [PlacesEventQueue.drain].
+ * Timestamps are in milliseconds.
+ */
+sealed class PlacesEvent {
+    data class PageVisited(
+        val url: String,
+        val visitDate: Long,
+        val visitType: VisitType,
+        val isRemote: Boolean
+    ) : PlacesEvent()
+    data class TitleChanged(val url: String, val title: String) : PlacesEvent()
+    /** If [url] is null, all visits between [start] and [end] were removed. */
+    data class VisitsRemoved(val url: String?, val start: Long, val end: Long) : PlacesEvent()
+    data class HostVisitsRemoved(val host: String, val includeSubdomains: Boolean) : PlacesEvent()
+    data class PageRemoved(val guid: String) : PlacesEvent()
+    object HistoryCleared : PlacesEvent()
+    /** Old history was expired, so any history being shown should be refreshed. */
+    object HistoryExpired : PlacesEvent()
+    data class BookmarkInserted(
+        val guid: String,
+        val parentGuid: String,
+        val position: Int,
+        val url: String?
+    ) : PlacesEvent()
+    data class BookmarkChanged(val guid: String) : PlacesEvent()
+    data class BookmarkMoved(
+        val guid: String,
+        val oldParentGuid: String,
+        val oldPosition: Int,
+        val newParentGuid: String,
+        val newPosition: Int
+    ) : PlacesEvent()
+    data class BookmarkRemoved(
+        val guid: String,
+        val parentGuid: String,
+        val position: Int,
+        val url: String?
+    ) : PlacesEvent()
+    object BookmarksCleared : PlacesEvent()
+    /** The bookmark tree was repaired, so any bookmarks being shown should be refreshed. */
+    object BookmarksRepaired : PlacesEvent()
+    /** Anything in [engine] may have changed. */
+    data class SyncFinished(val engine: String, val succeeded: Boolean) : PlacesEvent()
+    /** The queue overflowed, so everything should be refreshed. */
+    data class EventsDropped(val count: Int) : PlacesEvent()
+
+    companion object {
+        @Suppress("ComplexMethod")
+        fun fromJSON(o: JSONObject): PlacesEvent? {
+            return when (o.getString("type")) {
+                "page_visited" -> PageVisited(
+                    url = o.getString("url"),
+                    visitDate = o.getLong("visit_date"),
+                    visitType = intToVisitType[o.getInt("visit_type")]!!,
+                    isRemote = o.getBoolean("is_remote")
+                )
+                "title_changed" -> TitleChanged(o.getString("url"), o.getString("title"))
+                "visits_removed" -> VisitsRemoved(
+                    url = stringOrNull(o, "url"),
+                    start = o.getLong("start"),
+                    end = o.getLong("end")
+                )
+                "host_visits_removed" -> HostVisitsRemoved(
+                    host = o.getString("host"),
+                    includeSubdomains = o.getBoolean("include_subdomains")
+                )
+                "page_removed" -> PageRemoved(o.getString("guid"))
+                "history_cleared" -> HistoryCleared
+                "history_expired" -> HistoryExpired
+                "bookmark_inserted" -> BookmarkInserted(
+                    guid = o.getString("guid"),
+                    parentGuid = o.getString("parent_guid"),
+                    position = o.getInt("index"),
+                    url = stringOrNull(o, "url")
+                )
+                "bookmark_changed" -> BookmarkChanged(o.getString("guid"))
+                "bookmark_moved" -> BookmarkMoved(
+                    guid = o.getString("guid"),
+                    oldParentGuid = o.getString("old_parent_guid"),
+                    oldPosition = o.getInt("old_index"),
+                    newParentGuid = o.getString("new_parent_guid"),
+                    newPosition = o.getInt("new_index")
+                )
+                "bookmark_removed" -> BookmarkRemoved(
+                    guid = o.getString("guid"),
+                    parentGuid = o.getString("parent_guid"),
+                    position = o.getInt("index"),
+                    url = stringOrNull(o, "url")
+                )
+                "bookmarks_cleared" -> BookmarksCleared
+                "bookmarks_repaired" -> BookmarksRepaired
+                "sync_finished" -> SyncFinished(o.getString("engine"), o.getBoolean("succeeded"))
+                "events_dropped" -> EventsDropped(o.getInt("count"))
+                // Ignore events added by a newer version of the library.
+                else -> null
+            }
+        }
+
+        fun fromJSONArray(jsonArrayText: String): List<PlacesEvent> {
+            val result: MutableList<PlacesEvent> = mutableListOf()
+            val array = JSONArray(jsonArrayText)
+            for (index in 0 until array.length()) {
+                fromJSON(array.getJSONObject(index))?.let { result.add(it) }
+            }
+            return result
+        }
+    }
+}
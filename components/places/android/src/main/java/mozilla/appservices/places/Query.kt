@@ -0,0 +1,70 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+package mozilla.appservices.places
+
+/**
+ * A page matched by a `place:` query. For bookmark queries, there's one of
+ * these for each bookmark; otherwise, there's one for each URL.
+ */
+data class PlaceQueryPage(
+    val url: String,
+    val title: String?,
+    /** The GUID of the bookmark, for bookmark queries. */
+    val bookmarkGuid: String?,
+    val visitCount: Long,
+    /** When the page was last visited, in milliseconds since the epoch. */
+    val lastVisitDate: Long?,
+    /** When the page was bookmarked, in milliseconds since the epoch. */
+    val dateAdded: Long?,
+    /** When the bookmark was last changed, in milliseconds since the epoch. */
+    val lastModified: Long?,
+    val frecency: Long
+) {
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.QueryPage): PlaceQueryPage {
+            return PlaceQueryPage(
+                url = msg.url,
+                title = if (msg.hasTitle()) msg.title else null,
+                bookmarkGuid = if (msg.hasBookmarkGuid()) msg.bookmarkGuid else null,
+                visitCount = msg.visitCount,
+                lastVisitDate = if (msg.hasLastVisitDate()) msg.lastVisitDate else null,
+                dateAdded = if (msg.hasDateAdded()) msg.dateAdded else null,
+                lastModified = if (msg.hasLastModified()) msg.lastModified else null,
+                frecency = msg.frecency
+            )
+        }
+    }
+}
+
+/**
+ * A tag returned by a `place:` query with `type=6`.
+ */
+data class PlaceQueryTag(
+    val tag: String,
+    /** When the tag was last used, in milliseconds since the epoch. */
+    val lastModified: Long
+)
+
+/**
+ * The results of a `place:` query, which are either pages or tags,
+ * depending on the query's `type`.
+ */
+sealed class PlaceQueryResults {
+    data class Pages(val pages: List<PlaceQueryPage>) : PlaceQueryResults()
+    data class Tags(val tags: List<PlaceQueryTag>) : PlaceQueryResults()
+
+    companion object {
+        // Matches `QueryResultType::Tags` in query.rs.
+        private const val RESULT_TYPE_TAGS = 6
+
+        internal fun fromMessage(msg: MsgTypes.QueryResults): PlaceQueryResults {
+            return if (msg.resultType == RESULT_TYPE_TAGS) {
+                Tags(msg.tagsList.map { PlaceQueryTag(tag = it.tag, lastModified = it.lastModified) })
+            } else {
+                Pages(msg.pagesList.map { PlaceQueryPage.fromMessage(it) })
+            }
+        }
+    }
+}
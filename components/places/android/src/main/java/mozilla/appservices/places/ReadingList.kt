@@ -0,0 +1,133 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+package mozilla.appservices.places
+
+/**
+ * An interface defining the set of read-only reading list operations.
+ */
+interface ReadableReadingListConnection : InterruptibleConnection {
+    /**
+     * Returns every item in the reading list, most recently added first.
+     *
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun getReadingList(): List<ReadingListItem>
+
+    /**
+     * Returns the reading list item with the given GUID, or null if there
+     * isn't one.
+     *
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun getReadingListItem(guid: String): ReadingListItem?
+
+    /**
+     * Returns the reading list item for a page, or null if the page isn't in
+     * the reading list.
+     *
+     * @throws UrlParseFailed If `url` is not a valid URL.
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun getReadingListItemForUrl(url: String): ReadingListItem?
+}
+
+/**
+ * An interface defining the set of reading list operations which require
+ * write access.
+ */
+interface WritableReadingListConnection : ReadableReadingListConnection {
+    /**
+     * Adds a page to the reading list. Saving a page which is already in
+     * the list marks it as unread again, and updates its title and excerpt,
+     * if given.
+     *
+     * @param url The URL of the page.
+     * @param title The title of the page, if known.
+     * @param excerpt A short extract of the page, to show in the list.
+     * @return The item for the page.
+     *
+     * @throws UrlParseFailed If `url` is not a valid URL.
+     * @throws UrlTooLong If `url` exceeds the maximum length.
+     */
+    fun addToReadingList(url: String, title: String? = null, excerpt: String? = null): ReadingListItem
+
+    /**
+     * Updates a reading list item, for example, to mark it as read.
+     *
+     * @param guid The GUID of the item.
+     * @param info The changes to make. Fields which are null aren't changed.
+     *
+     * @throws UnknownBookmarkItem If `guid` does not refer to a reading list item.
+     */
+    fun updateReadingListItem(guid: String, info: ReadingListItemUpdateInfo)
+
+    /**
+     * Removes an item from the reading list.
+     *
+     * @param guid The GUID of the item.
+     * @return Whether or not the item existed.
+     */
+    fun deleteReadingListItem(guid: String): Boolean
+}
+
+/**
+ * A page in the reading list.
+ */
+data class ReadingListItem(
+    val guid: String,
+    val url: String,
+    val title: String?,
+    /** A short extract of the page, to show in the list. */
+    val excerpt: String?,
+    /** When the page was added, in milliseconds since the epoch. */
+    val dateAdded: Long,
+    /** When the item was last changed, in milliseconds since the epoch. */
+    val lastModified: Long,
+    val unread: Boolean,
+    val favorite: Boolean
+) {
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.ReadingListItem): ReadingListItem {
+            return ReadingListItem(
+                guid = msg.guid,
+                url = msg.url,
+                title = if (msg.hasTitle()) msg.title else null,
+                excerpt = if (msg.hasExcerpt()) msg.excerpt else null,
+                dateAdded = msg.dateAdded,
+                lastModified = msg.lastModified,
+                unread = msg.unread,
+                favorite = msg.favorite
+            )
+        }
+
+        internal fun fromCollectionMessage(msg: MsgTypes.ReadingListItems): List<ReadingListItem> {
+            return msg.itemsList.map { fromMessage(it) }
+        }
+    }
+}
+
+/**
+ * Changes to make to a reading list item in
+ * [WritableReadingListConnection.updateReadingListItem]. Fields which are
+ * null are left as they are.
+ */
+data class ReadingListItemUpdateInfo(
+    val title: String? = null,
+    val excerpt: String? = null,
+    val unread: Boolean? = null,
+    val favorite: Boolean? = null
+) {
+    internal fun toProtobuf(): MsgTypes.ReadingListItemUpdate {
+        val builder = MsgTypes.ReadingListItemUpdate.newBuilder()
+        this.title?.let { builder.setTitle(it) }
+        this.excerpt?.let { builder.setExcerpt(it) }
+        this.unread?.let { builder.setUnread(it) }
+        this.favorite?.let { builder.setFavorite(it) }
+        return builder.build()
+    }
+}
@@ -7,17 +7,14 @@
 
 use cli_support::fxa_creds::{get_cli_fxa, get_default_fxa_config};
 use places::bookmark_sync::store::BookmarksStore;
+use places::export::{export_bookmarks, BookmarksBackupFormat};
 use places::history_sync::store::HistoryStore;
 use places::storage::bookmarks::{
-    fetch_tree, insert_tree, BookmarkNode, BookmarkRootGuid, BookmarkTreeNode, FetchDepth,
-    FolderNode, SeparatorNode,
+    fetch_tree, insert_tree, BookmarkRootGuid, BookmarkTreeNode, FetchDepth,
 };
-use places::types::{BookmarkType, Timestamp};
 use places::{ConnectionType, PlacesApi, PlacesDb};
-use sync_guid::Guid as SyncGuid;
 
 use failure::Fail;
-use serde_derive::*;
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
 use structopt::StructOpt;
@@ -25,7 +22,6 @@ use sync15::{
     sync_multiple, MemoryCachedState, SetupStorageClient, Store, StoreSyncAssociation,
     Sync15StorageClient,
 };
-use url::Url;
 
 type Result<T> = std::result::Result<T, failure::Error>;
 
@@ -35,59 +31,7 @@ fn init_logging() {
     env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", spec));
 }
 
-// A struct in the format of desktop with a union of all fields.
-#[derive(Debug, Default, Deserialize)]
-#[serde(default, rename_all = "camelCase")]
-struct DesktopItem {
-    type_code: u8,
-    guid: Option<SyncGuid>,
-    date_added: Option<u64>,
-    last_modified: Option<u64>,
-    title: Option<String>,
-    #[serde(with = "url_serde")]
-    uri: Option<Url>,
-    children: Vec<DesktopItem>,
-}
-
-fn convert_node(dm: DesktopItem) -> Option<BookmarkTreeNode> {
-    let bookmark_type = BookmarkType::from_u8_with_valid_url(dm.type_code, || dm.uri.is_some());
-
-    Some(match bookmark_type {
-        BookmarkType::Bookmark => {
-            let url = match dm.uri {
-                Some(uri) => uri,
-                None => {
-                    log::warn!("ignoring bookmark node without url: {:?}", dm);
-                    return None;
-                }
-            };
-            BookmarkNode {
-                guid: dm.guid,
-                date_added: dm.date_added.map(|v| Timestamp(v / 1000)),
-                last_modified: dm.last_modified.map(|v| Timestamp(v / 1000)),
-                title: dm.title,
-                url,
-            }
-            .into()
-        }
-        BookmarkType::Separator => SeparatorNode {
-            guid: dm.guid,
-            date_added: dm.date_added.map(|v| Timestamp(v / 1000)),
-            last_modified: dm.last_modified.map(|v| Timestamp(v / 1000)),
-        }
-        .into(),
-        BookmarkType::Folder => FolderNode {
-            guid: dm.guid,
-            date_added: dm.date_added.map(|v| Timestamp(v / 1000)),
-            last_modified: dm.last_modified.map(|v| Timestamp(v / 1000)),
-            title: dm.title,
-            children: dm.children.into_iter().filter_map(convert_node).collect(),
-        }
-        .into(),
-    })
-}
-
-fn do_import(db: &PlacesDb, root: BookmarkTreeNode) -> Result<()> {
+fn do_native_import(db: &PlacesDb, root: BookmarkTreeNode) -> Result<()> {
     // We need to import each of the sub-trees individually.
     // Later we will want to get smarter around guids - currently we will
     // fail to do this twice due to guid dupes - but that's OK for now.
@@ -122,30 +66,6 @@ fn do_import(db: &PlacesDb, root: BookmarkTreeNode) -> Result<()> {
     Ok(())
 }
 
-fn run_desktop_import(db: &PlacesDb, filename: String) -> Result<()> {
-    println!("import from {}", filename);
-
-    let file = File::open(filename)?;
-    let reader = BufReader::new(file);
-    let m: DesktopItem = serde_json::from_reader(reader)?;
-    // convert mapping into our tree.
-    let root = match convert_node(m) {
-        Some(node) => node,
-        None => {
-            println!("Failed to read a tree from this file");
-            return Ok(());
-        }
-    };
-    do_import(db, root)
-}
-
-fn run_ios_import(api: &PlacesApi, filename: String) -> Result<()> {
-    println!("ios import from {}", filename);
-    places::import::import_ios_bookmarks(api, filename)?;
-    println!("Import finished!");
-    Ok(())
-}
-
 fn run_native_import(db: &PlacesDb, filename: String) -> Result<()> {
     println!("import from {}", filename);
 
@@ -153,7 +73,7 @@ fn run_native_import(db: &PlacesDb, filename: String) -> Result<()> {
     let reader = BufReader::new(file);
 
     let root: BookmarkTreeNode = serde_json::from_reader(reader)?;
-    do_import(db, root)
+    do_native_import(db, root)
 }
 
 fn run_native_export(db: &PlacesDb, filename: String) -> Result<()> {
@@ -167,6 +87,40 @@ fn run_native_export(db: &PlacesDb, filename: String) -> Result<()> {
     Ok(())
 }
 
+fn run_import(db: &PlacesDb, filename: String) -> Result<()> {
+    println!("import from {}", filename);
+    places::import::import_bookmarks_backup(db, filename)?;
+    println!("Import finished!");
+    Ok(())
+}
+
+fn run_ios_import(api: &PlacesApi, filename: String) -> Result<()> {
+    println!("ios import from {}", filename);
+    let report = places::import::import_ios_bookmarks(api, filename)?;
+    println!("Import finished! {:#?}", report);
+    Ok(())
+}
+
+fn run_export(db: &PlacesDb, filename: String) -> Result<()> {
+    println!("export to {}", filename);
+    let format = BookmarksBackupFormat::from_path(&filename).unwrap_or_else(|| {
+        println!("Unknown extension - exporting as JSON");
+        BookmarksBackupFormat::Json
+    });
+    export_bookmarks(db, filename, format)?;
+    Ok(())
+}
+
+fn run_check_bookmarks(db: &PlacesDb) -> Result<()> {
+    let report = places::storage::bookmarks::check_and_repair(db)?;
+    if report.is_empty() {
+        println!("No problems found!");
+    } else {
+        println!("Repaired the bookmark tree: {:#?}", report);
+    }
+    Ok(())
+}
+
 fn sync(
     api: &PlacesApi,
     mut engine_names: Vec<String>,
@@ -328,19 +282,30 @@ enum Command {
     },
 
     #[structopt(name = "export-bookmarks")]
-    /// Exports bookmarks (but not in a way Desktop can import it!)
+    /// Exports bookmarks as a `bookmarks.html` file, or a desktop JSON
+    /// backup, depending on the file's extension (.html, .json or .jsonlz4)
     ExportBookmarks {
         #[structopt(name = "output-file", long, short = "o")]
-        /// The name of the output file where the json will be written.
+        /// The name of the file to write.
         output_file: String,
+
+        #[structopt(name = "native", long)]
+        /// Write our own JSON tree format instead, which desktop can't import,
+        /// but `import-bookmarks --native` can.
+        native: bool,
     },
 
-    #[structopt(name = "import-bookmarks")]
-    /// Import bookmarks from a 'native' export (ie, as exported by this utility)
+    #[structopt(name = "import-bookmarks", alias = "import-desktop-bookmarks")]
+    /// Import bookmarks from a `bookmarks.html` file, or a desktop JSON
+    /// backup (.json or .jsonlz4)
     ImportBookmarks {
         #[structopt(name = "input-file", long, short = "i")]
         /// The name of the file to read.
         input_file: String,
+
+        #[structopt(name = "native", long)]
+        /// Read a 'native' export (ie, as written by `export-bookmarks --native`)
+        native: bool,
     },
 
     #[structopt(name = "import-ios-bookmarks")]
@@ -351,13 +316,10 @@ enum Command {
         input_file: String,
     },
 
-    #[structopt(name = "import-desktop-bookmarks")]
-    /// Import bookmarks from JSON file exported by desktop Firefox
-    ImportDesktopBookmarks {
-        #[structopt(name = "input-file", long, short = "i")]
-        /// Imports bookmarks from a desktop export
-        input_file: String,
-    },
+    #[structopt(name = "check-bookmarks")]
+    /// Check the bookmark tree for problems, like orphans and bad positions,
+    /// and fix any that are found
+    CheckBookmarks,
 }
 
 fn main() -> Result<()> {
@@ -378,9 +340,24 @@ fn main() -> Result<()> {
             wipe,
             reset,
         } => sync(&api, engines, credential_file, wipe_all, wipe, reset),
-        Command::ExportBookmarks { output_file } => run_native_export(&db, output_file),
-        Command::ImportBookmarks { input_file } => run_native_import(&db, input_file),
+        Command::ExportBookmarks {
+            output_file,
+            native,
+        } => {
+            if native {
+                run_native_export(&db, output_file)
+            } else {
+                run_export(&db, output_file)
+            }
+        }
+        Command::ImportBookmarks { input_file, native } => {
+            if native {
+                run_native_import(&db, input_file)
+            } else {
+                run_import(&db, input_file)
+            }
+        }
         Command::ImportIosBookmarks { input_file } => run_ios_import(&api, input_file),
-        Command::ImportDesktopBookmarks { input_file } => run_desktop_import(&db, input_file),
+        Command::CheckBookmarks => run_check_bookmarks(&db),
     }
 }
@@ -13,7 +13,9 @@ use ffi_support::{
     define_string_destructor, ByteBuffer, ConcurrentHandleMap, ExternError, FfiStr,
 };
 use places::error::*;
-use places::msg_types::BookmarkNodeList;
+use places::export::BookmarksBackupFormat;
+use places::msg_types::{BookmarkNodeList, ReadingListItems};
+use places::observer::{EventQueue, ObserverId};
 use places::storage::bookmarks;
 use places::types::VisitTransitionSet;
 use places::{storage, ConnectionType, PlacesApi, PlacesDb};
@@ -32,6 +34,22 @@ fn parse_url(url: &str) -> places::Result<url::Url> {
 lazy_static::lazy_static! {
     pub static ref APIS: ConcurrentHandleMap<Arc<PlacesApi>> = ConcurrentHandleMap::new();
     static ref CONNECTIONS: ConcurrentHandleMap<PlacesDb> = ConcurrentHandleMap::new();
+    static ref EVENT_QUEUES: ConcurrentHandleMap<EventQueueRegistration> =
+        ConcurrentHandleMap::new();
+}
+
+/// An `EventQueue` registered with a `PlacesApi`, which unregisters itself
+/// when its handle is destroyed.
+pub struct EventQueueRegistration {
+    api: Arc<PlacesApi>,
+    id: ObserverId,
+    queue: Arc<EventQueue>,
+}
+
+impl Drop for EventQueueRegistration {
+    fn drop(&mut self) {
+        self.api.unregister_observer(self.id);
+    }
 }
 
 /// Instantiate a places API. Returned api must be freed with
@@ -81,11 +99,10 @@ pub extern "C" fn places_bookmarks_import_from_ios(
     api_handle: u64,
     db_path: FfiStr<'_>,
     error: &mut ExternError,
-) {
+) -> ByteBuffer {
     log::debug!("places_bookmarks_import_from_ios");
     APIS.call_with_result(error, api_handle, |api| -> places::Result<_> {
-        places::import::import_ios_bookmarks(api, db_path.as_str())?;
-        Ok(())
+        places::import::import_ios_bookmarks(api, db_path.as_str())
     })
 }
 
@@ -97,9 +114,7 @@ pub extern "C" fn places_bookmarks_import_from_fennec(
 ) -> ByteBuffer {
     log::debug!("places_bookmarks_import_from_fennec");
     APIS.call_with_result(error, api_handle, |api| -> places::Result<_> {
-        Ok(BookmarkNodeList::from(
-            places::import::import_fennec_bookmarks(api, db_path.as_str())?,
-        ))
+        places::import::import_fennec_bookmarks(api, db_path.as_str())
     })
 }
 
@@ -108,11 +123,34 @@ pub extern "C" fn places_history_import_from_fennec(
     api_handle: u64,
     db_path: FfiStr<'_>,
     error: &mut ExternError,
-) {
+) -> ByteBuffer {
     log::debug!("places_history_import_from_fennec");
     APIS.call_with_result(error, api_handle, |api| -> places::Result<_> {
-        places::import::import_fennec_history(api, db_path.as_str())?;
-        Ok(())
+        places::import::import_fennec_history(api, db_path.as_str())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_history_import_from_chromium(
+    api_handle: u64,
+    db_path: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_history_import_from_chromium");
+    APIS.call_with_result(error, api_handle, |api| -> places::Result<_> {
+        places::import::import_chromium_history(api, db_path.as_str())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_bookmarks_import_from_chromium(
+    api_handle: u64,
+    path: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_bookmarks_import_from_chromium");
+    APIS.call_with_result(error, api_handle, |api| -> places::Result<_> {
+        places::import::import_chromium_bookmarks(api, path.as_str())
     })
 }
 
@@ -147,6 +185,73 @@ pub extern "C" fn places_api_reset_bookmarks(api_handle: u64, error: &mut Extern
     })
 }
 
+#[no_mangle]
+pub extern "C" fn places_api_reset_reading_list(api_handle: u64, error: &mut ExternError) {
+    log::debug!("places_api_reset_reading_list");
+    APIS.call_with_result(error, api_handle, |api| -> places::Result<_> {
+        api.reset_reading_list()?;
+        Ok(())
+    })
+}
+
+/// Note that a page was opened in a tab, so that autocomplete searches on any
+/// connection can offer to switch to it.
+#[no_mangle]
+pub extern "C" fn places_api_register_open_tab(
+    api_handle: u64,
+    url: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_api_register_open_tab");
+    APIS.call_with_result(error, api_handle, |api| -> places::Result<_> {
+        api.register_open_tab(&parse_url(url.as_str())?);
+        Ok(())
+    })
+}
+
+/// Note that a tab showing a page was closed. Returns 1 if the page was open.
+#[no_mangle]
+pub extern "C" fn places_api_unregister_open_tab(
+    api_handle: u64,
+    url: FfiStr<'_>,
+    error: &mut ExternError,
+) -> u8 {
+    log::debug!("places_api_unregister_open_tab");
+    APIS.call_with_result(error, api_handle, |api| -> places::Result<_> {
+        Ok(api.unregister_open_tab(&parse_url(url.as_str())?))
+    })
+}
+
+/// Get the settings used to calculate frecencies, as a `FrecencySettings`
+/// protobuf message.
+#[no_mangle]
+pub extern "C" fn places_api_get_frecency_settings(
+    api_handle: u64,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_api_get_frecency_settings");
+    APIS.call_with_output(error, api_handle, |api| api.frecency_settings())
+}
+
+/// Change the settings used to calculate frecencies. This marks every page's
+/// frecency as stale; use `places_recalculate_stale_frecencies` to
+/// recalculate them.
+#[no_mangle]
+pub unsafe extern "C" fn places_api_set_frecency_settings(
+    api_handle: u64,
+    data: *const u8,
+    len: i32,
+    error: &mut ExternError,
+) {
+    log::debug!("places_api_set_frecency_settings");
+    use places::msg_types::FrecencySettings;
+    APIS.call_with_result(error, api_handle, |api| -> places::Result<_> {
+        let buffer = get_buffer(data, len);
+        let settings: FrecencySettings = prost::Message::decode(buffer)?;
+        api.set_frecency_settings(places::frecency::FrecencySettings::from_message(settings))
+    })
+}
+
 /// Get the interrupt handle for a connection. Must be destroyed with
 /// `places_interrupt_handle_destroy`.
 #[no_mangle]
@@ -178,8 +283,76 @@ pub extern "C" fn places_note_observation(
     })
 }
 
+/// Set the description and preview image URL for a page. Either may be null,
+/// which clears the stored value.
+#[no_mangle]
+pub extern "C" fn places_note_page_metadata(
+    handle: u64,
+    url: FfiStr<'_>,
+    description: FfiStr<'_>,
+    preview_image_url: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_note_page_metadata");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        let preview_image_url = match preview_image_url.as_opt_str() {
+            Some(href) => Some(parse_url(href)?),
+            None => None,
+        };
+        storage::history::note_page_metadata(
+            conn,
+            &url,
+            description.as_opt_str(),
+            preview_image_url.as_ref(),
+        )
+    })
+}
+
+/// Set one size of the favicon for a page. `data` is a `FaviconInfo` protobuf.
+#[no_mangle]
+pub unsafe extern "C" fn places_set_favicon_for_page(
+    handle: u64,
+    page_url: FfiStr<'_>,
+    data: *const u8,
+    len: i32,
+    error: &mut ExternError,
+) {
+    log::debug!("places_set_favicon_for_page");
+    use places::msg_types::FaviconInfo;
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(page_url.as_str())?;
+        let buffer = get_buffer(data, len);
+        let icon: FaviconInfo = prost::Message::decode(buffer)?;
+        storage::favicons::set_favicon_for_page(
+            conn,
+            &url,
+            &storage::favicons::Favicon::from_message(icon)?,
+        )
+    })
+}
+
+/// Get the size of a page's favicon closest to `preferred_size`, as a
+/// `FaviconInfo` protobuf, or an empty buffer if the page has no icon.
+#[no_mangle]
+pub extern "C" fn places_get_favicon_for_page(
+    handle: u64,
+    page_url: FfiStr<'_>,
+    preferred_size: i32,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_get_favicon_for_page");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(page_url.as_str())?;
+        storage::favicons::get_favicon_for_page(conn, &url, preferred_size.max(0) as u32)
+    })
+}
+
 /// Execute a query, returning a `Vec<SearchResult>` as a JSON string. Returned string must be freed
 /// using `places_destroy_string`. Returns null and logs on errors (for now).
+///
+/// The query can be interrupted with `places_interrupt`, using the
+/// connection's interrupt handle.
 #[no_mangle]
 pub extern "C" fn places_query_autocomplete(
     handle: u64,
@@ -212,6 +385,20 @@ pub extern "C" fn places_match_url(
     CONNECTIONS.call_with_result(error, handle, |conn| match_url(conn, search.as_str()))
 }
 
+/// Runs a `place:` query, returning a `QueryResults`.
+#[no_mangle]
+pub extern "C" fn places_run_query(
+    handle: u64,
+    query: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_run_query");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let query = places::query::PlaceQuery::from_url(&parse_url(query.as_str())?)?;
+        places::query::run_query(conn, &query)
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn places_get_visited(
     handle: u64,
@@ -303,6 +490,20 @@ pub extern "C" fn places_delete_visits_between(
     })
 }
 
+#[no_mangle]
+pub extern "C" fn places_delete_visits_for_host(
+    handle: u64,
+    host: FfiStr<'_>,
+    include_subdomains: u8,
+    error: &mut ExternError,
+) {
+    log::debug!("places_delete_visits_for_host");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        storage::history::delete_visits_for_host(conn, host.as_str(), include_subdomains != 0)?;
+        Ok(())
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn places_delete_visit(
     handle: u64,
@@ -345,6 +546,28 @@ pub extern "C" fn places_run_maintenance(handle: u64, error: &mut ExternError) {
     CONNECTIONS.call_with_result(error, handle, |conn| storage::run_maintenance(conn))
 }
 
+/// Recalculate the frecencies of every page marked as stale, in chunks. If
+/// this is interrupted, the pages it already rescored stay rescored, and
+/// calling it again picks up where it left off.
+#[no_mangle]
+pub extern "C" fn places_recalculate_stale_frecencies(handle: u64, error: &mut ExternError) {
+    log::debug!("places_recalculate_stale_frecencies");
+    CONNECTIONS.call_with_result(error, handle, |conn| {
+        storage::history::update_stale_frecencies(conn, &conn.begin_interrupt_scope())
+    })
+}
+
+/// Recalculate the frecency of every page, stale or not. Like
+/// `places_recalculate_stale_frecencies`, this can be interrupted, and
+/// calling that afterward picks up where it left off.
+#[no_mangle]
+pub extern "C" fn places_recalculate_all_frecencies(handle: u64, error: &mut ExternError) {
+    log::debug!("places_recalculate_all_frecencies");
+    CONNECTIONS.call_with_result(error, handle, |conn| {
+        storage::history::recalculate_all_frecencies(conn, &conn.begin_interrupt_scope())
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn places_prune_destructively(handle: u64, error: &mut ExternError) {
     log::debug!("places_prune_destructively");
@@ -421,6 +644,54 @@ pub extern "C" fn places_get_visit_page(
     })
 }
 
+#[no_mangle]
+pub extern "C" fn places_get_top_frecent_site_infos(
+    handle: u64,
+    num_items: i64,
+    frecency_threshold: i64,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_get_top_frecent_site_infos");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        storage::history::get_top_frecent_sites(conn, num_items, frecency_threshold)
+    })
+}
+
+/// Search all of history. `order` is a `HistorySearchOrder`: 1 sorts by
+/// relevance, and 2 by date.
+#[no_mangle]
+pub extern "C" fn places_search_history(
+    handle: u64,
+    query: FfiStr<'_>,
+    start: i64,
+    end: i64,
+    exclude_types: i32,
+    order: i32,
+    offset: i64,
+    count: i64,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_search_history");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        storage::history::search_history(
+            conn,
+            &storage::history::HistorySearchParams {
+                query: query.into_string(),
+                start: places::Timestamp(start.max(0) as u64),
+                end: places::Timestamp(end.max(0) as u64),
+                // Note: it's a bug in our FFI android (or swift, eventually) code
+                // if either of these expects fire.
+                exclude_types: VisitTransitionSet::from_u16(exclude_types as u16)
+                    .expect("Bug: Invalid VisitTransitionSet"),
+                order: storage::history::HistorySearchOrder::from_primitive(order as u8)
+                    .expect("Bug: Invalid HistorySearchOrder"),
+                offset,
+                count,
+            },
+        )
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn places_accept_result(
     handle: u64,
@@ -442,6 +713,38 @@ pub extern "C" fn places_accept_result(
     })
 }
 
+/// Remove adaptive history entries for `url`, or `input`, or both. Either
+/// may be null, but not both; use `places_remove_all_adaptive_entries` to
+/// remove everything. Returns the number of entries removed.
+#[no_mangle]
+pub extern "C" fn places_remove_adaptive_entries(
+    handle: u64,
+    url: FfiStr<'_>,
+    input: FfiStr<'_>,
+    error: &mut ExternError,
+) -> i64 {
+    log::debug!("places_remove_adaptive_entries");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = match url.as_opt_str() {
+            Some(href) => Some(parse_url(href)?),
+            None => None,
+        };
+        let removed = matcher::remove_adaptive_entries(conn, url.as_ref(), input.as_opt_str())?;
+        Ok(removed as i64)
+    })
+}
+
+/// Remove all adaptive history entries. Returns the number of entries
+/// removed.
+#[no_mangle]
+pub extern "C" fn places_remove_all_adaptive_entries(handle: u64, error: &mut ExternError) -> i64 {
+    log::debug!("places_remove_all_adaptive_entries");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let removed = matcher::remove_all_adaptive_entries(conn)?;
+        Ok(removed as i64)
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn sync15_history_sync(
     handle: u64,
@@ -488,6 +791,125 @@ pub extern "C" fn sync15_bookmarks_sync(
     })
 }
 
+#[no_mangle]
+pub extern "C" fn sync15_reading_list_sync(
+    handle: u64,
+    key_id: FfiStr<'_>,
+    access_token: FfiStr<'_>,
+    sync_key: FfiStr<'_>,
+    tokenserver_url: FfiStr<'_>,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("sync15_reading_list_sync");
+    APIS.call_with_result(error, handle, |api| -> places::Result<_> {
+        let ping = api.sync_reading_list(
+            &sync15::Sync15StorageClientInit {
+                key_id: key_id.into_string(),
+                access_token: access_token.into_string(),
+                tokenserver_url: parse_url(tokenserver_url.as_str())?,
+            },
+            &sync15::KeyBundle::from_ksync_base64(sync_key.as_str())?,
+        )?;
+        Ok(ping)
+    })
+}
+
+/// Add a page to the reading list, returning the item as a `ReadingListItem`
+/// protobuf. `title` and `excerpt` may be null.
+#[no_mangle]
+pub extern "C" fn reading_list_add(
+    handle: u64,
+    url: FfiStr<'_>,
+    title: FfiStr<'_>,
+    excerpt: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("reading_list_add");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        storage::reading_list::add_reading_list_item(
+            conn,
+            &url,
+            title.as_opt_str(),
+            excerpt.as_opt_str(),
+        )
+    })
+}
+
+/// Get the whole reading list, as a `ReadingListItems` protobuf.
+#[no_mangle]
+pub extern "C" fn reading_list_get_all(handle: u64, error: &mut ExternError) -> ByteBuffer {
+    log::debug!("reading_list_get_all");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        Ok(ReadingListItems::from(
+            storage::reading_list::get_reading_list(conn)?,
+        ))
+    })
+}
+
+/// Get a reading list item by GUID, as a `ReadingListItem` protobuf, or an
+/// empty buffer if there's no such item.
+#[no_mangle]
+pub extern "C" fn reading_list_get_by_guid(
+    handle: u64,
+    guid: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("reading_list_get_by_guid");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        storage::reading_list::get_reading_list_item(conn, &SyncGuid::from(guid.as_str()))
+    })
+}
+
+/// Get the reading list item for a page, as a `ReadingListItem` protobuf, or
+/// an empty buffer if the page isn't in the reading list.
+#[no_mangle]
+pub extern "C" fn reading_list_get_by_url(
+    handle: u64,
+    url: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("reading_list_get_by_url");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        storage::reading_list::get_reading_list_item_for_url(conn, &url)
+    })
+}
+
+/// Update a reading list item. `data` is a `ReadingListItemUpdate` protobuf.
+#[no_mangle]
+pub unsafe extern "C" fn reading_list_update(
+    handle: u64,
+    guid: FfiStr<'_>,
+    data: *const u8,
+    len: i32,
+    error: &mut ExternError,
+) {
+    log::debug!("reading_list_update");
+    use places::msg_types::ReadingListItemUpdate;
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let buffer = get_buffer(data, len);
+        let update: ReadingListItemUpdate = prost::Message::decode(buffer)?;
+        storage::reading_list::update_reading_list_item(
+            conn,
+            &SyncGuid::from(guid.as_str()),
+            &storage::reading_list::ReadingListItemUpdate::from_message(update),
+        )
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn reading_list_delete(
+    handle: u64,
+    guid: FfiStr<'_>,
+    error: &mut ExternError,
+) -> u8 {
+    log::debug!("reading_list_delete");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        storage::reading_list::delete_reading_list_item(conn, &SyncGuid::from(guid.as_str()))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn bookmarks_get_tree(
     handle: u64,
@@ -510,6 +932,24 @@ pub extern "C" fn bookmarks_delete_everything(handle: u64, error: &mut ExternErr
     })
 }
 
+#[no_mangle]
+pub extern "C" fn bookmarks_export(handle: u64, path: FfiStr<'_>, error: &mut ExternError) {
+    log::debug!("bookmarks_export");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let path = path.as_str();
+        let format = BookmarksBackupFormat::from_path(path).unwrap_or(BookmarksBackupFormat::Json);
+        places::export::export_bookmarks(conn, path, format)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn bookmarks_import_backup(handle: u64, path: FfiStr<'_>, error: &mut ExternError) {
+    log::debug!("bookmarks_import_backup");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        places::import::import_bookmarks_backup(conn, path.as_str())
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn bookmarks_get_by_guid(
     handle: u64,
@@ -584,6 +1024,76 @@ pub extern "C" fn bookmarks_delete(handle: u64, id: FfiStr<'_>, error: &mut Exte
     })
 }
 
+#[no_mangle]
+pub extern "C" fn bookmarks_undo(handle: u64, error: &mut ExternError) -> u8 {
+    log::debug!("bookmarks_undo");
+    CONNECTIONS.call_with_result(error, handle, |conn| bookmarks::undo(conn))
+}
+
+#[no_mangle]
+pub extern "C" fn bookmarks_redo(handle: u64, error: &mut ExternError) -> u8 {
+    log::debug!("bookmarks_redo");
+    CONNECTIONS.call_with_result(error, handle, |conn| bookmarks::redo(conn))
+}
+
+unsafe fn get_guids(guids: *const *const c_char, guids_len: i32) -> Vec<SyncGuid> {
+    assert!(
+        guids_len >= 0,
+        "Negative array length provided for guids {}",
+        guids_len
+    );
+    if guids_len == 0 {
+        return Vec::new();
+    }
+    assert!(!guids.is_null(), "Unexpected null guids pointer");
+    std::slice::from_raw_parts(guids, guids_len as usize)
+        .iter()
+        .map(|&p| SyncGuid::from(FfiStr::from_raw(p).as_str()))
+        .collect()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn bookmarks_reorder_children(
+    handle: u64,
+    parent_guid: FfiStr<'_>,
+    child_guids: *const *const c_char,
+    child_guids_len: i32,
+    error: &mut ExternError,
+) {
+    log::debug!("bookmarks_reorder_children");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let child_guids = get_guids(child_guids, child_guids_len);
+        bookmarks::reorder_children(conn, &SyncGuid::from(parent_guid.as_str()), &child_guids)
+    })
+}
+
+/// Moves items into a folder. A negative `position` appends them.
+#[no_mangle]
+pub unsafe extern "C" fn bookmarks_move_items(
+    handle: u64,
+    guids: *const *const c_char,
+    guids_len: i32,
+    new_parent_guid: FfiStr<'_>,
+    position: i32,
+    error: &mut ExternError,
+) {
+    log::debug!("bookmarks_move_items");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let guids = get_guids(guids, guids_len);
+        let position = if position < 0 {
+            bookmarks::BookmarkPosition::Append
+        } else {
+            bookmarks::BookmarkPosition::Specific(position as u32)
+        };
+        bookmarks::move_items(
+            conn,
+            &guids,
+            &SyncGuid::from(new_parent_guid.as_str()),
+            position,
+        )
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn bookmarks_get_all_with_url(
     handle: u64,
@@ -633,6 +1143,69 @@ pub extern "C" fn bookmarks_search(
     })
 }
 
+/// List a page of a folder's children. `order` is a `FolderChildrenOrder`.
+#[no_mangle]
+pub extern "C" fn bookmarks_list_folder_children(
+    handle: u64,
+    guid: FfiStr<'_>,
+    order: i32,
+    offset: i32,
+    limit: i32,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("bookmarks_list_folder_children");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        Ok(BookmarkNodeList::from(
+            bookmarks::public_node::list_folder_children(
+                conn,
+                &SyncGuid::from(guid.as_str()),
+                // Note: it's a bug in our FFI android (or swift, eventually)
+                // code if this expect fires.
+                bookmarks::public_node::FolderChildrenOrder::from_primitive(order as u8)
+                    .expect("Bug: Invalid FolderChildrenOrder"),
+                offset.max(0) as u32,
+                limit.max(0) as u32,
+            )?,
+        ))
+    })
+}
+
+/// Returns the token for the most recent bookmark change.
+#[no_mangle]
+pub extern "C" fn bookmarks_get_change_token(handle: u64, error: &mut ExternError) -> i64 {
+    log::debug!("bookmarks_get_change_token");
+    CONNECTIONS.call_with_result(error, handle, |conn| {
+        bookmarks::get_bookmark_change_token(conn)
+    })
+}
+
+/// Returns the bookmarks changed since `token`, as a `BookmarkChanges`.
+#[no_mangle]
+pub extern "C" fn bookmarks_get_changes_since(
+    handle: u64,
+    token: i64,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("bookmarks_get_changes_since");
+    CONNECTIONS.call_with_result(error, handle, |conn| {
+        bookmarks::get_bookmark_changes_since(conn, token)
+    })
+}
+
+/// Runs the `place:` query for a bookmark, like a smart folder synced from
+/// desktop, returning a `QueryResults`.
+#[no_mangle]
+pub extern "C" fn bookmarks_run_query(
+    handle: u64,
+    guid: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("bookmarks_run_query");
+    CONNECTIONS.call_with_result(error, handle, |conn| {
+        places::query::run_query_for_bookmark(conn, &SyncGuid::from(guid.as_str()))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn bookmarks_get_recent(
     handle: u64,
@@ -647,9 +1220,37 @@ pub extern "C" fn bookmarks_get_recent(
     })
 }
 
+/// Start queueing change events for this places API. The returned handle
+/// must be polled with `places_event_queue_drain`, and freed with
+/// `places_event_queue_destroy`.
+#[no_mangle]
+pub extern "C" fn places_api_register_event_queue(api_handle: u64, error: &mut ExternError) -> u64 {
+    log::debug!("places_api_register_event_queue");
+    APIS.call_with_result(error, api_handle, |api| -> places::Result<_> {
+        let queue = Arc::new(EventQueue::default());
+        let id = api.register_observer(queue.clone());
+        Ok(EVENT_QUEUES.insert(EventQueueRegistration {
+            api: api.clone(),
+            id,
+            queue,
+        }))
+    })
+}
+
+/// Returns the events queued since the last call, as a JSON array of
+/// `PlacesEvent`s. Returned string must be freed using `places_destroy_string`.
+#[no_mangle]
+pub extern "C" fn places_event_queue_drain(handle: u64, error: &mut ExternError) -> *mut c_char {
+    log::debug!("places_event_queue_drain");
+    EVENT_QUEUES.call_with_result(error, handle, |registration| -> places::Result<_> {
+        Ok(serde_json::to_string(&registration.queue.drain())?)
+    })
+}
+
 define_string_destructor!(places_destroy_string);
 define_bytebuffer_destructor!(places_destroy_bytebuffer);
 define_handle_map_deleter!(APIS, places_api_destroy);
 
 define_handle_map_deleter!(CONNECTIONS, places_connection_destroy);
+define_handle_map_deleter!(EVENT_QUEUES, places_event_queue_destroy);
 define_box_destructor!(SqlInterruptHandle, places_interrupt_handle_destroy);
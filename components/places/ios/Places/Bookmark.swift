@@ -36,6 +36,24 @@ public enum BookmarkNodeType: Int32 {
     // are not supported
 }
 
+/**
+ * How `listFolderChildren` sorts a folder's children.
+ */
+public enum FolderChildrenOrder: Int32 {
+    // Note: these values need to match the Rust FolderChildrenOrder
+    // enum in public_node.rs
+    /// The order the children are stored in.
+    case position = 1
+    /// By title, A to Z, ignoring case. Untitled items come last.
+    case title = 2
+    /// Most recently added first.
+    case dateAdded = 3
+    /// Most recently modified first.
+    case lastModified = 4
+    /// Bookmarks for the most recently visited pages first.
+    case lastVisited = 5
+}
+
 /**
  * A base class containing the set of fields common to all nodes
  * in the bookmark tree.
@@ -124,7 +142,8 @@ public class BookmarkSeparator: BookmarkNode {
  * A bookmark tree node that actually represents a bookmark.
  *
  * It's type is always `BookmarkNodeType.bookmark`,  and in addition to the
- * fields provided by `BookmarkNode`, it has a `title` and a `url`.
+ * fields provided by `BookmarkNode`, it has a `title`, a `url`, and possibly
+ * a `keyword` and `tags`.
  */
 public class BookmarkItem: BookmarkNode {
     /**
@@ -140,15 +159,30 @@ public class BookmarkItem: BookmarkNode {
      */
     public let title: String
 
+    /**
+     * The keyword for this bookmark, if it has one.
+     */
+    public let keyword: String?
+
+    /**
+     * The tags for this bookmark's URL. These are shared by all bookmarks
+     * with the same URL.
+     */
+    public let tags: [String]
+
     public init(guid: String,
                 dateAdded: Int64,
                 lastModified: Int64,
                 parentGUID: String?,
                 position: UInt32,
                 url: String,
-                title: String) {
+                title: String,
+                keyword: String? = nil,
+                tags: [String] = []) {
         self.url = url
         self.title = title
+        self.keyword = keyword
+        self.tags = tags
         super.init(
             type: .bookmark,
             guid: guid,
@@ -235,7 +269,9 @@ internal func unpackProtobuf(msg: MsgTypes_BookmarkNode) -> BookmarkNode {
             parentGUID: parentGUID,
             position: position,
             url: msg.url,
-            title: title
+            title: title,
+            keyword: msg.hasKeyword ? msg.keyword : nil,
+            tags: msg.tags
         )
     case .separator:
         return BookmarkSeparator(
@@ -278,7 +314,9 @@ internal func unpackProtobufItemList(msg: MsgTypes_BookmarkNodeList) -> [Bookmar
             parentGUID: node.parentGuid,
             position: node.position,
             url: node.url,
-            title: node.hasTitle ? node.title : ""
+            title: node.hasTitle ? node.title : "",
+            keyword: node.hasKeyword ? node.keyword : nil,
+            tags: node.tags
         )
     }
 }
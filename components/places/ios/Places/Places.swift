@@ -93,6 +93,9 @@ public class PlacesAPI {
      *
      * This should be performed before any writes to the database.
      *
+     * - Returns: A report of how many bookmarks were imported, how many were
+     *            skipped and why, and how long each phase took.
+     *
      * Throws:
      *     - `PlacesError.databaseInterrupted`: If a call is made to `interrupt()` on this
      *                                          object from another thread.
@@ -109,11 +112,15 @@ public class PlacesAPI {
      *     - `PlacesError.panic`: If the rust code panics while completing this
      *                            operation. (If this occurs, please let us know).
      */
-    open func migrateBookmarksFromBrowserDb(path: String) throws {
-        try queue.sync {
-            try PlacesError.unwrap { error in
+    @discardableResult
+    open func migrateBookmarksFromBrowserDb(path: String) throws -> ImportReport {
+        return try queue.sync {
+            let buffer = try PlacesError.unwrap { error in
                 places_bookmarks_import_from_ios(handle, path, error)
             }
+            defer { places_destroy_bytebuffer(buffer) }
+            let msg = try MsgTypes_ImportReport(serializedData: Data(placesRustBuffer: buffer))
+            return ImportReport(msg: msg)
         }
     }
 
@@ -487,6 +494,203 @@ public class PlacesReadConnection {
         }
     }
 
+    /**
+     * Returns a page of a folder's children, without their own children.
+     * Folders in the list have their `childGUIDs` filled in, but not their
+     * `children`.
+     *
+     * - Parameter folderGUID: The GUID of the folder.
+     * - Parameter order: How to sort the children. Ties are broken by position.
+     * - Parameter offset: How many children to skip.
+     * - Parameter limit: The maximum number of children to return.
+     * - Returns: The requested page of children.
+     * - Throws:
+     *     - `PlacesError.noSuchItem`: If `folderGUID` does not refer to a
+     *                                 known bookmark.
+     *     - `PlacesError.invalidParent`: If `folderGUID` does not refer to a
+     *                                    folder.
+     *     - `PlacesError.databaseInterrupted`: If a call is made to
+     *                                          `interrupt()` on this object
+     *                                          from another thread.
+     *     - `PlacesError.connUseAfterAPIClosed`: If the PlacesAPI that returned
+     *                                            this connection object has
+     *                                            been closed. This indicates
+     *                                            API misuse.
+     *     - `PlacesError.databaseBusy`: If this query times out with a
+     *       SQLITE_BUSY error.
+     *     - `PlacesError.unexpected`: When an error that has not specifically
+     *                                 been exposed to Swift is encountered (for
+     *                                 example IO errors from the database code,
+     *                                 etc).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us
+     *                            know).
+     */
+    open func listFolderChildren(folderGUID: String,
+                                 order: FolderChildrenOrder = .position,
+                                 offset: UInt = 0,
+                                 limit: UInt) throws -> [BookmarkNode] {
+        return try queue.sync {
+            try self.checkApi()
+            let buffer = try PlacesError.unwrap { error in
+                bookmarks_list_folder_children(self.handle, folderGUID, order.rawValue,
+                                               Int32(offset), Int32(limit), error)
+            }
+            defer { places_destroy_bytebuffer(buffer) }
+            let msg = try MsgTypes_BookmarkNodeList(serializedData: Data(placesRustBuffer: buffer))
+            return msg.nodes.map { unpackProtobuf(msg: $0) }
+        }
+    }
+
+    /**
+     * Returns a token for the most recent bookmark change. Fetch it along
+     * with the tree, then pass it to `getBookmarkChanges(since:)` to find
+     * out what changed since, locally or by syncing.
+     *
+     * - Throws:
+     *     - `PlacesError.databaseInterrupted`: If a call is made to
+     *                                          `interrupt()` on this object
+     *                                          from another thread.
+     *     - `PlacesError.connUseAfterAPIClosed`: If the PlacesAPI that returned
+     *                                            this connection object has
+     *                                            been closed. This indicates
+     *                                            API misuse.
+     *     - `PlacesError.databaseBusy`: If this query times out with a
+     *       SQLITE_BUSY error.
+     *     - `PlacesError.unexpected`: When an error that has not specifically
+     *                                 been exposed to Swift is encountered (for
+     *                                 example IO errors from the database code,
+     *                                 etc).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us
+     *                            know).
+     */
+    open func getBookmarkChangeToken() throws -> Int64 {
+        return try queue.sync {
+            try self.checkApi()
+            return try PlacesError.unwrap { error in
+                bookmarks_get_change_token(self.handle, error)
+            }
+        }
+    }
+
+    /**
+     * Returns the bookmarks inserted, updated, moved to another folder, or
+     * deleted after `token`, and a new token to pass next time. Changes to
+     * tags aren't included. Deletions are forgotten after a while, so check
+     * `BookmarkChanges.tokenExpired` for tokens that are too old.
+     *
+     * - Parameter token: A token from `getBookmarkChangeToken`, or from an
+     *                    earlier call to this method.
+     * - Returns: The changed bookmarks.
+     * - Throws:
+     *     - `PlacesError.databaseInterrupted`: If a call is made to
+     *                                          `interrupt()` on this object
+     *                                          from another thread.
+     *     - `PlacesError.connUseAfterAPIClosed`: If the PlacesAPI that returned
+     *                                            this connection object has
+     *                                            been closed. This indicates
+     *                                            API misuse.
+     *     - `PlacesError.databaseBusy`: If this query times out with a
+     *       SQLITE_BUSY error.
+     *     - `PlacesError.unexpected`: When an error that has not specifically
+     *                                 been exposed to Swift is encountered (for
+     *                                 example IO errors from the database code,
+     *                                 etc).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us
+     *                            know).
+     */
+    open func getBookmarkChanges(since token: Int64) throws -> BookmarkChanges {
+        return try queue.sync {
+            try self.checkApi()
+            let buffer = try PlacesError.unwrap { error in
+                bookmarks_get_changes_since(self.handle, token, error)
+            }
+            defer { places_destroy_bytebuffer(buffer) }
+            let msg = try MsgTypes_BookmarkChanges(serializedData: Data(placesRustBuffer: buffer))
+            return BookmarkChanges(msg: msg)
+        }
+    }
+
+    /**
+     * Runs one of desktop's `place:` queries, like the ones behind its
+     * "Most Visited" and "Recent Tags" smart folders. Only some of its
+     * parameters are supported; see `places::query` for the list.
+     *
+     * - Parameter query: A `place:` URL.
+     * - Returns: The pages or tags the query matched.
+     * - Throws:
+     *     - `PlacesError.urlParseError`: If `query` isn't a valid URL.
+     *     - `PlacesError.unexpected`: If the query isn't a valid `place:`
+     *                                 query, or uses an unsupported
+     *                                 parameter value, or for other errors
+     *                                 which have not specifically been
+     *                                 exposed to Swift.
+     *     - `PlacesError.databaseInterrupted`: If a call is made to
+     *                                          `interrupt()` on this object
+     *                                          from another thread.
+     *     - `PlacesError.connUseAfterAPIClosed`: If the PlacesAPI that returned
+     *                                            this connection object has
+     *                                            been closed. This indicates
+     *                                            API misuse.
+     *     - `PlacesError.databaseBusy`: If this query times out with a
+     *       SQLITE_BUSY error.
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us
+     *                            know).
+     */
+    open func runPlaceQuery(query: String) throws -> PlaceQueryResults {
+        return try queue.sync {
+            try self.checkApi()
+            let buffer = try PlacesError.unwrap { error in
+                places_run_query(self.handle, query, error)
+            }
+            defer { places_destroy_bytebuffer(buffer) }
+            let msg = try MsgTypes_QueryResults(serializedData: Data(placesRustBuffer: buffer))
+            return PlaceQueryResults(msg: msg)
+        }
+    }
+
+    /**
+     * Runs the `place:` query for a bookmark, like a smart folder synced
+     * from desktop.
+     *
+     * - Parameter guid: The GUID of a bookmark with a `place:` URL.
+     * - Returns: The pages or tags the query matched.
+     * - Throws:
+     *     - `PlacesError.noSuchItem`: If `guid` does not refer to a known
+     *                                 bookmark.
+     *     - `PlacesError.unexpected`: If the query isn't a valid `place:`
+     *                                 query, or uses an unsupported
+     *                                 parameter value, or for other errors
+     *                                 which have not specifically been
+     *                                 exposed to Swift.
+     *     - `PlacesError.databaseInterrupted`: If a call is made to
+     *                                          `interrupt()` on this object
+     *                                          from another thread.
+     *     - `PlacesError.connUseAfterAPIClosed`: If the PlacesAPI that returned
+     *                                            this connection object has
+     *                                            been closed. This indicates
+     *                                            API misuse.
+     *     - `PlacesError.databaseBusy`: If this query times out with a
+     *       SQLITE_BUSY error.
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us
+     *                            know).
+     */
+    open func runBookmarkQuery(guid: String) throws -> PlaceQueryResults {
+        return try queue.sync {
+            try self.checkApi()
+            let buffer = try PlacesError.unwrap { error in
+                bookmarks_run_query(self.handle, guid, error)
+            }
+            defer { places_destroy_bytebuffer(buffer) }
+            let msg = try MsgTypes_QueryResults(serializedData: Data(placesRustBuffer: buffer))
+            return PlaceQueryResults(msg: msg)
+        }
+    }
+
     /**
      * Attempt to interrupt a long-running operation which may be
      * happening concurrently. If the operation is interrupted,
@@ -570,6 +774,52 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 
+    /**
+     * Undo the most recent bookmark change made through this connection
+     * (an insert, update or delete). Deleted items are restored with their
+     * original GUIDs, positions and creation dates.
+     *
+     * - Returns: Whether or not there was a change to undo.
+     *
+     * - Throws:
+     *     - `PlacesError.connUseAfterAPIClosed`: if the PlacesAPI that returned this connection
+     *                                            object has been closed. This indicates API
+     *                                            misuse.
+     *     - `PlacesError.unexpected`: When an error that has not specifically been exposed
+     *                                 to Swift is encountered (for example, if the items
+     *                                 the change touched have since been modified).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us know).
+     */
+    @discardableResult
+    open func undo() throws -> Bool {
+        return try queue.sync {
+            try self.checkApi()
+            let resByte = try PlacesError.unwrap { error in
+                bookmarks_undo(self.handle, error)
+            }
+            return resByte != 0
+        }
+    }
+
+    /**
+     * Make the most recently undone bookmark change again.
+     *
+     * - Returns: Whether or not there was a change to redo.
+     *
+     * - Throws: The same errors as `undo()`.
+     */
+    @discardableResult
+    open func redo() throws -> Bool {
+        return try queue.sync {
+            try self.checkApi()
+            let resByte = try PlacesError.unwrap { error in
+                bookmarks_redo(self.handle, error)
+            }
+            return resByte != 0
+        }
+    }
+
     /**
      * Create a bookmark folder, returning its guid.
      *
@@ -655,6 +905,12 @@ public class PlacesWriteConnection: PlacesReadConnection {
      *
      * - Parameter title: The title of the new bookmark, if any.
      *
+     * - Parameter keyword: The keyword for the new bookmark, if any. If
+     *                      another bookmark already has this keyword, it's
+     *                      removed from that bookmark.
+     *
+     * - Parameter tags: Tags to add to `url`. Any existing tags are kept.
+     *
      * - Returns: The GUID of the newly inserted bookmark item.
      *
      * - Throws:
@@ -678,7 +934,9 @@ public class PlacesWriteConnection: PlacesReadConnection {
     open func createBookmark(parentGUID: String,
                              url: String,
                              title: String?,
-                             position: UInt32? = nil) throws -> String {
+                             position: UInt32? = nil,
+                             keyword: String? = nil,
+                             tags: [String] = []) throws -> String {
         return try queue.sync {
             try self.checkApi()
             var msg = insertionMsg(type: .bookmark, parentGUID: parentGUID, position: position)
@@ -686,6 +944,10 @@ public class PlacesWriteConnection: PlacesReadConnection {
             if let t = title {
                 msg.title = t
             }
+            if let k = keyword {
+                msg.keyword = k
+            }
+            msg.tags = tags
             return try doInsert(msg: msg)
         }
     }
@@ -710,6 +972,14 @@ public class PlacesWriteConnection: PlacesReadConnection {
      *     - url: If the record is a `BookmarkNodeType.bookmark` node, and its `url`
      *            should be changed, then the new value for the url.
      *
+     *     - keyword: If the record is a `BookmarkNodeType.bookmark` node, and its
+     *                keyword should be changed, then the new keyword. An empty
+     *                string removes the keyword.
+     *
+     *     - tags: If the record is a `BookmarkNodeType.bookmark` node, and the tags
+     *             for its URL should be changed, then the new tags, which replace
+     *             the existing ones.
+     *
      * - Note: The `parentGUID` and `position` parameters interact with eachother
      *   as follows:
      *
@@ -745,7 +1015,9 @@ public class PlacesWriteConnection: PlacesReadConnection {
                                  parentGUID: String? = nil,
                                  position: UInt32? = nil,
                                  title: String? = nil,
-                                 url: String? = nil) throws {
+                                 url: String? = nil,
+                                 keyword: String? = nil,
+                                 tags: [String]? = nil) throws {
         try queue.sync {
             try self.checkApi()
             var msg = MsgTypes_BookmarkNode()
@@ -762,6 +1034,13 @@ public class PlacesWriteConnection: PlacesReadConnection {
             if let u = url {
                 msg.url = u
             }
+            if let k = keyword {
+                msg.keyword = k
+            }
+            if let t = tags {
+                msg.tags = t
+                msg.haveTags = true
+            }
             let data = try! msg.serializedData()
             let size = Int32(data.count)
             try data.withUnsafeBytes { (bytes: UnsafePointer<UInt8>) in
@@ -772,6 +1051,93 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 
+    /**
+     * Reorder the children of a folder in one go.
+     *
+     * The children listed in `childGUIDs` are placed first, in the order given,
+     * followed by any children which weren't listed, in their current order.
+     * GUIDs which aren't children of the folder are ignored.
+     *
+     * - Parameter parentGUID: The GUID of the folder to reorder.
+     * - Parameter childGUIDs: The GUIDs of the folder's children, in their new order.
+     *
+     * - Throws:
+     *     - `PlacesError.cannotUpdateRoot`: If `parentGUID` is `BookmarkRoots.RootGUID`.
+     *     - `PlacesError.noSuchItem`: If `parentGUID` does not refer to a known bookmark.
+     *     - `PlacesError.invalidParent`: If `parentGUID` refers to a bookmark which is
+     *                                    not a folder.
+     *     - `PlacesError.connUseAfterAPIClosed`: if the PlacesAPI that returned this connection
+     *                                            object has been closed. This indicates API
+     *                                            misuse.
+     *     - `PlacesError.unexpected`: When an error that has not specifically been exposed
+     *                                 to Swift is encountered (for example IO errors from
+     *                                 the database code, etc).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us know).
+     */
+    open func reorderChildren(parentGUID: String, childGUIDs: [String]) throws {
+        try queue.sync {
+            try self.checkApi()
+            try withCStrings(childGUIDs) { guids, count in
+                try PlacesError.unwrap { error in
+                    bookmarks_reorder_children(self.handle, parentGUID, guids, count, error)
+                }
+            }
+        }
+    }
+
+    /**
+     * Move several items into a folder in one go, keeping them together in the
+     * order given. The items may come from different folders.
+     *
+     * - Parameter guids: The GUIDs of the items to move.
+     * - Parameter parentGUID: The GUID of the folder to move them into.
+     * - Parameter position: The index of the child the items should be placed before,
+     *                       counting the folder's current children. If not provided,
+     *                       the items are appended.
+     *
+     * - Throws:
+     *     - `PlacesError.cannotUpdateRoot`: If any of `guids` is a member of
+     *                                       `BookmarkRoots.All`, or `parentGUID` is
+     *                                       `BookmarkRoots.RootGUID`.
+     *     - `PlacesError.noSuchItem`: If `parentGUID` or any of `guids` do not refer
+     *                                 to known bookmarks.
+     *     - `PlacesError.invalidParent`: If `parentGUID` refers to a bookmark which is
+     *                                    not a folder, or is one of the items being
+     *                                    moved, or inside one of them.
+     *     - `PlacesError.connUseAfterAPIClosed`: if the PlacesAPI that returned this connection
+     *                                            object has been closed. This indicates API
+     *                                            misuse.
+     *     - `PlacesError.unexpected`: When an error that has not specifically been exposed
+     *                                 to Swift is encountered (for example IO errors from
+     *                                 the database code, etc).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us know).
+     */
+    open func moveItems(guids: [String], parentGUID: String, position: UInt32? = nil) throws {
+        try queue.sync {
+            try self.checkApi()
+            let pos = position.map { Int32($0) } ?? -1
+            try withCStrings(guids) { guids, count in
+                try PlacesError.unwrap { error in
+                    bookmarks_move_items(self.handle, guids, count, parentGUID, pos, error)
+                }
+            }
+        }
+    }
+
+    // Helper for passing a list of strings to Rust as an array of C strings.
+    private func withCStrings<T>(
+        _ strings: [String],
+        _ body: (UnsafePointer<UnsafePointer<CChar>>?, Int32) throws -> T
+    ) rethrows -> T {
+        let cStrings = strings.map { UnsafePointer(strdup($0)!) }
+        defer { cStrings.forEach { free(UnsafeMutablePointer(mutating: $0)) } }
+        return try cStrings.withUnsafeBufferPointer { buffer in
+            try body(buffer.baseAddress, Int32(buffer.count))
+        }
+    }
+
     // Helper for the various creation functions.
     // Note: Caller synchronizes
     private func doInsert(msg: MsgTypes_BookmarkNode) throws -> String {
@@ -800,6 +1166,118 @@ public class PlacesWriteConnection: PlacesReadConnection {
     }
 }
 
+/**
+ * What an import did: how many rows it looked at, how many it brought in,
+ * why it skipped the rest, and how long each phase took.
+ */
+public struct ImportReport {
+    public let rowsSeen: UInt32
+    public let rowsImported: UInt32
+    /// Rows skipped because their URL was missing, invalid, or too long.
+    public let skippedInvalidUrl: UInt32
+    /// Rows skipped because their parent was missing or invalid.
+    public let skippedBadParent: UInt32
+    /// Rows of a type we don't import, like livemarks or queries.
+    public let skippedUnsupported: UInt32
+    /// Rows with a missing or nonsensical timestamp. These are imported
+    /// with the current time instead.
+    public let invalidTimestamps: UInt32
+    /// The name and duration, in milliseconds, of each phase, in order.
+    public let phases: [(name: String, durationMs: Int64)]
+
+    internal init(msg: MsgTypes_ImportReport) {
+        rowsSeen = msg.rowsSeen
+        rowsImported = msg.rowsImported
+        skippedInvalidUrl = msg.skippedInvalidURL
+        skippedBadParent = msg.skippedBadParent
+        skippedUnsupported = msg.skippedUnsupported
+        invalidTimestamps = msg.invalidTimestamps
+        phases = msg.phases.map { (name: $0.name, durationMs: $0.durationMs) }
+    }
+}
+
+/**
+ * The GUIDs of bookmarks changed since a token, returned by
+ * `getBookmarkChanges(since:)`.
+ *
+ * Items inserted since the token are only in `inserted`, and items deleted
+ * since are only in `deleted`. An item can be both `updated` and `moved`.
+ */
+public struct BookmarkChanges {
+    /// The token to pass next time.
+    public let token: Int64
+    public let inserted: [String]
+    /// Items whose title, URL, or keyword changed, and folders whose children
+    /// were reordered.
+    public let updated: [String]
+    /// Items moved to another folder.
+    public let moved: [String]
+    public let deleted: [String]
+    /// If true, the token was so old that some deletions since have been
+    /// forgotten, and the whole tree should be reloaded.
+    public let tokenExpired: Bool
+
+    internal init(msg: MsgTypes_BookmarkChanges) {
+        token = msg.token
+        inserted = msg.inserted
+        updated = msg.updated
+        moved = msg.moved
+        deleted = msg.deleted
+        tokenExpired = msg.tokenExpired
+    }
+}
+
+/**
+ * A page matched by a `place:` query. For bookmark queries, there's one of
+ * these for each bookmark; otherwise, there's one for each URL.
+ */
+public struct PlaceQueryPage {
+    public let url: String
+    public let title: String?
+    /// The GUID of the bookmark, for bookmark queries.
+    public let bookmarkGUID: String?
+    public let visitCount: Int64
+    /// When the page was last visited, in milliseconds since the epoch.
+    public let lastVisitDate: Int64?
+    /// When the page was bookmarked, in milliseconds since the epoch.
+    public let dateAdded: Int64?
+    /// When the bookmark was last changed, in milliseconds since the epoch.
+    public let lastModified: Int64?
+    public let frecency: Int64
+
+    internal init(msg: MsgTypes_QueryPage) {
+        url = msg.url
+        title = msg.hasTitle ? msg.title : nil
+        bookmarkGUID = msg.hasBookmarkGuid ? msg.bookmarkGuid : nil
+        visitCount = msg.visitCount
+        lastVisitDate = msg.hasLastVisitDate ? msg.lastVisitDate : nil
+        dateAdded = msg.hasDateAdded ? msg.dateAdded : nil
+        lastModified = msg.hasLastModified ? msg.lastModified : nil
+        frecency = msg.frecency
+    }
+}
+
+/**
+ * The results of a `place:` query, which are either pages or tags,
+ * depending on the query's `type`. Tags come with when they were last used,
+ * in milliseconds since the epoch.
+ */
+public enum PlaceQueryResults {
+    case pages([PlaceQueryPage])
+    case tags([(tag: String, lastModified: Int64)])
+
+    // Matches `QueryResultType::Tags` in query.rs.
+    private static let resultTypeTags: Int32 = 6
+
+    internal init(msg: MsgTypes_QueryResults) {
+        if msg.resultType == PlaceQueryResults.resultTypeTags {
+            self = .tags(msg.tags.map { (tag: $0.tag, lastModified: $0.lastModified) })
+        } else {
+            self = .pages(msg.pages.map { PlaceQueryPage(msg: $0) })
+        }
+    }
+}
+
 // Wrapper around rust interrupt handle.
 private class InterruptHandle {
     let ptr: OpaquePointer
@@ -69,9 +69,9 @@ char *_Nullable places_match_url(PlacesConnectionHandle handle,
                                  const char *_Nonnull search,
                                  PlacesRustError *_Nonnull out_err);
 
-void places_bookmarks_import_from_ios(PlacesAPIHandle handle,
-                                      const char *_Nonnull db_path,
-                                      PlacesRustError *_Nonnull out_err);
+PlacesRustBuffer places_bookmarks_import_from_ios(PlacesAPIHandle handle,
+                                                  const char *_Nonnull db_path,
+                                                  PlacesRustError *_Nonnull out_err);
 
 // XXX we should move this to protobufs rather than port it to swift.
 // char *_Nullable places_get_visited(PlacesConnectionHandle handle,
@@ -170,6 +170,28 @@ PlacesRustBuffer bookmarks_get_recent(PlacesConnectionHandle handle,
                                       int32_t limit,
                                       PlacesRustError *_Nonnull out_err);
 
+PlacesRustBuffer bookmarks_list_folder_children(PlacesConnectionHandle handle,
+                                                char const *_Nonnull guid,
+                                                int32_t order,
+                                                int32_t offset,
+                                                int32_t limit,
+                                                PlacesRustError *_Nonnull out_err);
+
+int64_t bookmarks_get_change_token(PlacesConnectionHandle handle,
+                                   PlacesRustError *_Nonnull out_err);
+
+PlacesRustBuffer bookmarks_get_changes_since(PlacesConnectionHandle handle,
+                                             int64_t token,
+                                             PlacesRustError *_Nonnull out_err);
+
+PlacesRustBuffer places_run_query(PlacesConnectionHandle handle,
+                                  const char *_Nonnull query,
+                                  PlacesRustError *_Nonnull out_err);
+
+PlacesRustBuffer bookmarks_run_query(PlacesConnectionHandle handle,
+                                     const char *_Nonnull guid,
+                                     PlacesRustError *_Nonnull out_err);
+
 PlacesRustBuffer bookmarks_get_tree(PlacesConnectionHandle handle,
                                     char const *_Nullable root_guid,
                                     PlacesRustError *_Nonnull out_err);
@@ -188,6 +210,26 @@ uint8_t bookmarks_delete(PlacesConnectionHandle handle,
                          char const *_Nonnull guid_to_delete,
                          PlacesRustError *_Nonnull out_err);
 
+uint8_t bookmarks_undo(PlacesConnectionHandle handle,
+                       PlacesRustError *_Nonnull out_err);
+
+uint8_t bookmarks_redo(PlacesConnectionHandle handle,
+                       PlacesRustError *_Nonnull out_err);
+
+void bookmarks_reorder_children(PlacesConnectionHandle handle,
+                                char const *_Nonnull parent_guid,
+                                char const *_Nonnull const *_Nullable child_guids,
+                                int32_t child_guids_len,
+                                PlacesRustError *_Nonnull out_err);
+
+// A negative position appends the items.
+void bookmarks_move_items(PlacesConnectionHandle handle,
+                          char const *_Nonnull const *_Nullable guids,
+                          int32_t guids_len,
+                          char const *_Nonnull new_parent_guid,
+                          int32_t position,
+                          PlacesRustError *_Nonnull out_err);
+
 // MARK: memory/lifecycle management
 
 void places_api_return_write_conn(PlacesAPIHandle api,
@@ -43,6 +43,16 @@ CREATE INDEX IF NOT EXISTS lastvisitdateremoteindex ON moz_places(last_visit_dat
 CREATE UNIQUE INDEX IF NOT EXISTS guid_uniqueindex ON moz_places(guid);
 CREATE INDEX IF NOT EXISTS originidindex ON moz_places(origin_id);
 
+-- A full-text index over page titles and URLs, used for searching history.
+-- This is an external content table, so it doesn't store another copy of the
+-- text; the `moz_places_*_trigger_fts` triggers keep it up to date.
+CREATE VIRTUAL TABLE IF NOT EXISTS moz_places_fts USING fts5(
+    title,
+    url,
+    content = 'moz_places',
+    content_rowid = 'id'
+);
+
 
 CREATE TABLE IF NOT EXISTS moz_places_tombstones (
     guid TEXT PRIMARY KEY
@@ -110,6 +120,10 @@ CREATE TABLE IF NOT EXISTS moz_bookmarks (
     syncStatus INTEGER NOT NULL DEFAULT 0,
     syncChangeCounter INTEGER NOT NULL DEFAULT 1,
 
+    -- Only set for bookmarks. Like on Sync (and unlike desktop, which keeps
+    -- a keyword per URL) this is per bookmark.
+    keyword TEXT,
+
     -- bookmarks must have a fk to a URL, other types must not.
     CHECK((type == 1 AND fk IS NOT NULL) OR (type > 1 AND fk IS NULL))
     -- only the root is allowed to have a non-null parent
@@ -131,7 +145,8 @@ CREATE TABLE IF NOT EXISTS moz_bookmarks_deleted (
     dateRemoved INTEGER NOT NULL
 ) WITHOUT ROWID;
 
--- Note: desktop has/had a 'keywords' table, but we intentionally do not.
+-- Note: desktop has/had a 'keywords' table, but we intentionally do not -
+-- keywords live in moz_bookmarks.keyword.
 
 
 CREATE TABLE IF NOT EXISTS moz_origins (
@@ -167,6 +182,80 @@ CREATE TABLE IF NOT EXISTS moz_tags_relation(
     PRIMARY KEY(tag_id, place_id)
 ) WITHOUT ROWID;
 
+-- Favicons. Each row is one size of an icon, so an icon URL can have several
+-- rows. Icons are mapped to the pages that use them and, for icons at the root
+-- of a site (like `/favicon.ico`), to the site's origin, which is used for
+-- pages without an icon of their own. When pages or origins are removed, their
+-- mappings are too, and icons which are no longer mapped to anything are
+-- removed as part of expiring history.
+CREATE TABLE IF NOT EXISTS moz_icons(
+    id INTEGER PRIMARY KEY,
+    icon_url TEXT NOT NULL,
+    icon_url_hash INTEGER NOT NULL,
+    width INTEGER NOT NULL,
+    mime_type TEXT NOT NULL,
+    data BLOB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS iconurlhashindex ON moz_icons(icon_url_hash);
+
+CREATE TABLE IF NOT EXISTS moz_icons_to_pages(
+    page_id INTEGER NOT NULL REFERENCES moz_places(id) ON DELETE CASCADE,
+    icon_id INTEGER NOT NULL REFERENCES moz_icons(id) ON DELETE CASCADE,
+    PRIMARY KEY(page_id, icon_id)
+) WITHOUT ROWID;
+
+CREATE TABLE IF NOT EXISTS moz_icons_to_origins(
+    origin_id INTEGER NOT NULL REFERENCES moz_origins(id) ON DELETE CASCADE,
+    icon_id INTEGER NOT NULL REFERENCES moz_icons(id) ON DELETE CASCADE,
+    PRIMARY KEY(origin_id, icon_id)
+) WITHOUT ROWID;
+
+-- The most recent changes to each local bookmark, so that consumers can ask
+-- what changed since they last looked. Each change, whether it's a local edit
+-- or applied by Sync, gets a token one higher than the last; the
+-- `moz_bookmarks_changes_*` triggers keep this up to date. We don't use
+-- `moz_bookmarks_deleted` for this, because it only has tombstones for synced
+-- items, and only until they're uploaded.
+CREATE TABLE IF NOT EXISTS moz_bookmarks_changes(
+    guid TEXT PRIMARY KEY,
+    insertedToken INTEGER,
+    updatedToken INTEGER,
+    movedToken INTEGER,
+    deletedToken INTEGER,
+    -- The most recent of the above.
+    changeToken INTEGER NOT NULL
+) WITHOUT ROWID;
+
+CREATE INDEX IF NOT EXISTS bookmarkschangetokenindex ON moz_bookmarks_changes(changeToken);
+
+-- Pages the user saved to read later. These are kept apart from history and
+-- bookmarks, and synced in their own collection, but use the same
+-- `syncStatus` and `syncChangeCounter` conventions as `moz_bookmarks`.
+CREATE TABLE IF NOT EXISTS moz_reading_list(
+    id INTEGER PRIMARY KEY,
+    guid TEXT NOT NULL UNIQUE,
+    url TEXT NOT NULL UNIQUE,
+    title TEXT,
+    excerpt TEXT,
+    dateAdded INTEGER NOT NULL DEFAULT 0,
+    lastModified INTEGER NOT NULL DEFAULT 0,
+    unread INTEGER NOT NULL DEFAULT 1,
+    favorite INTEGER NOT NULL DEFAULT 0,
+
+    syncStatus INTEGER NOT NULL DEFAULT 0,
+    syncChangeCounter INTEGER NOT NULL DEFAULT 1
+);
+
+CREATE INDEX IF NOT EXISTS readinglistdateaddedindex ON moz_reading_list(dateAdded);
+
+-- Tombstones for removed reading list items which were synced, until they're
+-- uploaded.
+CREATE TABLE IF NOT EXISTS moz_reading_list_deleted(
+    guid TEXT PRIMARY KEY,
+    dateRemoved INTEGER NOT NULL
+) WITHOUT ROWID;
+
 -- This table holds synced items, including tombstones. It's unused if Sync
 -- isn't configured. At the end of a sync, this table's contents should match
 -- both what's on the server, and the local tree in `moz_bookmarks`.
@@ -28,6 +28,32 @@ BEGIN
     DELETE FROM moz_places_tombstones WHERE guid = NEW.guid;
 END;
 
+-- Triggers which keep the full-text index in sync with moz_places. Since the
+-- index uses moz_places as its content table, removing an entry requires
+-- passing the old values to the special 'delete' command.
+CREATE TEMP TRIGGER moz_places_afterinsert_trigger_fts
+AFTER INSERT ON moz_places FOR EACH ROW
+BEGIN
+    INSERT INTO moz_places_fts(rowid, title, url)
+    VALUES(NEW.id, NEW.title, NEW.url);
+END;
+
+CREATE TEMP TRIGGER moz_places_afterdelete_trigger_fts
+AFTER DELETE ON moz_places FOR EACH ROW
+BEGIN
+    INSERT INTO moz_places_fts(moz_places_fts, rowid, title, url)
+    VALUES('delete', OLD.id, OLD.title, OLD.url);
+END;
+
+CREATE TEMP TRIGGER moz_places_afterupdate_trigger_fts
+AFTER UPDATE OF title, url ON moz_places FOR EACH ROW
+BEGIN
+    INSERT INTO moz_places_fts(moz_places_fts, rowid, title, url)
+    VALUES('delete', OLD.id, OLD.title, OLD.url);
+    INSERT INTO moz_places_fts(rowid, title, url)
+    VALUES(NEW.id, NEW.title, NEW.url);
+END;
+
 -- Triggers which update visit_count and last_visit_date based on historyvisits
 -- table changes.
 -- NOTE: the values "0, 4, 7, 8, 9" below are EXCLUDED_VISIT_TYPES, stolen
@@ -46,10 +72,16 @@ BEGIN
 END;
 
 -- NOTE: the values "0, 4, 7, 8, 9" below are EXCLUDED_VISIT_TYPES, stolen
--- from desktop.
+-- from desktop. Visits which came from the deleted visit are unlinked from
+-- it, so that their `from_visit` doesn't point at a missing visit, or at a
+-- new visit which reuses its id.
 CREATE TEMP TRIGGER moz_historyvisits_afterdelete_trigger
 AFTER DELETE ON moz_historyvisits FOR EACH ROW
 BEGIN
+    UPDATE moz_historyvisits SET
+        from_visit = NULL
+    WHERE from_visit = OLD.id;
+
     UPDATE moz_places SET
         visit_count_local = visit_count_local - (OLD.visit_type NOT IN (0, 4, 7, 8, 9) AND OLD.is_local),
         visit_count_remote = visit_count_remote - (OLD.visit_type NOT IN (0, 4, 7, 8, 9) AND NOT(OLD.is_local)),
@@ -93,6 +125,56 @@ BEGIN
         WHERE OLD.fk <> NEW.fk AND id = OLD.fk;
 END;
 
+-- These triggers record changes to bookmarks in `moz_bookmarks_changes`.
+-- Only moves to another folder count as moves; otherwise, inserting or
+-- removing an item would count as moving all its later siblings. Instead,
+-- changing the positions of a folder's children counts as updating the folder.
+-- Changing a GUID, which Sync does when deduping, counts as deleting the item
+-- with the old GUID, and inserting one with the new GUID.
+CREATE TEMP TRIGGER moz_bookmarks_changes_afterinsert_trigger
+AFTER INSERT ON moz_bookmarks FOR EACH ROW
+BEGIN
+    {note_bookmark_inserted};
+END;
+
+CREATE TEMP TRIGGER moz_bookmarks_changes_afterdelete_trigger
+AFTER DELETE ON moz_bookmarks FOR EACH ROW
+BEGIN
+    {note_bookmark_deleted};
+END;
+
+CREATE TEMP TRIGGER moz_bookmarks_changes_afterupdate_trigger
+AFTER UPDATE OF title, fk, keyword ON moz_bookmarks FOR EACH ROW
+WHEN OLD.title IS NOT NEW.title OR
+     OLD.fk IS NOT NEW.fk OR
+     OLD.keyword IS NOT NEW.keyword
+BEGIN
+    {note_bookmark_updated};
+END;
+
+CREATE TEMP TRIGGER moz_bookmarks_changes_aftermove_trigger
+AFTER UPDATE OF parent ON moz_bookmarks FOR EACH ROW
+WHEN OLD.parent IS NOT NEW.parent
+BEGIN
+    {note_bookmark_moved};
+END;
+
+CREATE TEMP TRIGGER moz_bookmarks_changes_afterreorder_trigger
+AFTER UPDATE OF position ON moz_bookmarks FOR EACH ROW
+WHEN OLD.parent IS NEW.parent AND
+     OLD.position <> NEW.position
+BEGIN
+    {note_parent_updated};
+END;
+
+CREATE TEMP TRIGGER moz_bookmarks_changes_afterguidchange_trigger
+AFTER UPDATE OF guid ON moz_bookmarks FOR EACH ROW
+WHEN OLD.guid <> NEW.guid
+BEGIN
+    {note_bookmark_deleted};
+    {note_bookmark_inserted};
+END;
+
 -- The next several triggers are a workaround for the lack of FOR EACH STATEMENT
 -- in Sqlite, (see bug 871908).
 --
@@ -200,10 +282,12 @@ BEGIN
     {increase_frecency_stats};
 END;
 
--- Note: desktop places also has a notion of "frecency decay", and it only runs this
--- `WHEN NOT is_frecency_decaying()`.
+-- Like desktop places, we don't run this while decaying frecencies. Decay
+-- updates every page, and then decays origins and recalculates the origin
+-- frecency stats itself, which is much faster than doing it for each page.
 CREATE TEMP TRIGGER moz_places_afterupdate_frecency_trigger
 AFTER UPDATE OF frecency ON moz_places FOR EACH ROW
+WHEN NOT is_frecency_decaying()
 BEGIN
     INSERT INTO moz_updateoriginsupdate_temp (prefix, host, frecency_delta)
     VALUES (
@@ -113,3 +113,12 @@ CREATE TEMP TABLE tagsToUpload(
     tag TEXT,
     PRIMARY KEY(id, tag)
 ) WITHOUT ROWID;
+
+-- Stores reading list items and tombstones staged for upload, with their
+-- change counters at the time, so that changes made during the sync are
+-- uploaded next time.
+CREATE TEMP TABLE readingListToUpload(
+    guid TEXT PRIMARY KEY,
+    syncChangeCounter INTEGER NOT NULL,
+    isDeleted BOOLEAN NOT NULL DEFAULT 0
+) WITHOUT ROWID;
@@ -128,17 +128,18 @@ BEGIN
     -- -1 as the position. We'll update these later, when we fire the
     -- `updateLocalStructure` trigger.
     INSERT INTO moz_bookmarks(id, guid, parent, position, type, fk, title,
-                              dateAdded, lastModified, syncStatus,
+                              keyword, dateAdded, lastModified, syncStatus,
                               syncChangeCounter)
     VALUES(OLD.localId, OLD.mergedGuid,
            (SELECT id FROM moz_bookmarks WHERE guid = "root________"), -1,
            OLD.newType, OLD.newPlaceId,
-           OLD.newTitle, OLD.newDateAdded,
+           OLD.newTitle, OLD.newKeyword, OLD.newDateAdded,
            OLD.mergedAt,
            2, -- SyncStatus::Normal
            OLD.shouldUpload)
     ON CONFLICT(id) DO UPDATE SET
         title = excluded.title,
+        keyword = excluded.keyword,
         dateAdded = excluded.dateAdded,
         lastModified = excluded.lastModified,
         fk = excluded.fk;
@@ -2,17 +2,22 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use super::providers::{AutocompleteProvider, KeywordProvider, OpenTabsProvider};
 use crate::db::PlacesDb;
-use crate::error::Result;
+use crate::error::{ErrorKind, Result};
 pub use crate::match_impl::{MatchBehavior, SearchBehavior};
 use rusqlite::{types::ToSql, Row};
 use serde_derive::*;
-use sql_support::{maybe_log_plan, ConnExt};
+use sql_support::{maybe_log_plan, ConnExt, SqlInterruptScope};
+use std::collections::HashMap;
 use url::Url;
 
 // A helper to log, cache and execute a query, returning a vector of flattened rows.
+// If the search is interrupted while the query runs, this returns an
+// `InterruptedError` instead of the rows it found so far.
 fn query_flat_rows_and_then_named<T, F>(
     conn: &PlacesDb,
+    scope: &SqlInterruptScope,
     sql: &str,
     params: &[(&str, &dyn ToSql)],
     mapper: F,
@@ -23,17 +28,21 @@ where
     maybe_log_plan(conn, sql, params);
     let mut stmt = conn.prepare_maybe_cached(sql, true)?;
     let iter = stmt.query_and_then_named(params, mapper)?;
-    Ok(iter
-        .inspect(|r| {
-            if let Err(ref e) = *r {
+    let mut results = Vec::new();
+    for result in iter {
+        scope.err_if_interrupted()?;
+        match result {
+            Ok(row) => results.push(row),
+            Err(e) => {
                 log::warn!("Failed to perform a search: {}", e);
                 if cfg!(debug_assertions) {
                     panic!("Failed to perform a search: {}", e);
                 }
             }
-        })
-        .flatten()
-        .collect::<Vec<_>>())
+        }
+    }
+    scope.err_if_interrupted()?;
+    Ok(results)
 }
 
 #[derive(Debug, Clone)]
@@ -42,54 +51,174 @@ pub struct SearchParams {
     pub limit: u32,
 }
 
+/// Restriction tokens, which limit a search to certain kinds of results.
+/// These are the defaults for Desktop's `browser.urlbar.restrict.*` prefs.
+const RESTRICTION_TOKENS: &[(&str, SearchBehavior)] = &[
+    ("^", SearchBehavior::HISTORY),
+    ("*", SearchBehavior::BOOKMARK),
+    ("+", SearchBehavior::TAG),
+    ("%", SearchBehavior::OPENPAGE),
+];
+
+/// A search string, split into words, with any restriction tokens removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchTokens<'query> {
+    /// The words to match. A result must match every word.
+    pub words: Vec<&'query str>,
+    /// The kinds of results the restriction tokens asked for, or an empty set
+    /// if the search string didn't have any.
+    pub restrict: SearchBehavior,
+}
+
+impl<'query> SearchTokens<'query> {
+    /// Splits a search string on whitespace, and pulls out the restriction
+    /// tokens. Like Desktop, restriction tokens only count if they're words on
+    /// their own, so "c++" searches for "c++", but "c +" searches for tagged
+    /// pages matching "c".
+    pub fn new(search_string: &'query str) -> Self {
+        let mut words = Vec::new();
+        let mut restrict = SearchBehavior::empty();
+        for word in search_string.split_whitespace() {
+            match RESTRICTION_TOKENS.iter().find(|(token, _)| *token == word) {
+                Some((_, behavior)) => restrict |= *behavior,
+                None => words.push(word),
+            }
+        }
+        SearchTokens { words, restrict }
+    }
+
+    /// Returns the words to match, joined with single spaces. This is what we
+    /// pass to `AUTOCOMPLETE_MATCH`, which splits it again and requires each
+    /// word to match.
+    pub fn query(&self) -> String {
+        self.words.join(" ")
+    }
+
+    /// Returns the search behavior for the matchers. Without restriction
+    /// tokens, this is the default behavior; with them, results must match all
+    /// the restrictions.
+    pub fn search_behavior(&self) -> SearchBehavior {
+        if self.restrict.is_empty() {
+            SearchBehavior::default()
+        } else {
+            self.restrict | SearchBehavior::RESTRICT
+        }
+    }
+}
+
 /// Synchronously queries all providers for autocomplete matches, then filters
-/// the matches. This isn't cancelable yet; once a search is started, it can't
-/// be interrupted, even if the user moves on (see
-/// https://github.com/mozilla/application-services/issues/265).
+/// the matches. The search string is split into words, and each result must
+/// match all of them; restriction tokens like `*` limit the search to certain
+/// kinds of results (see `SearchTokens`).
+///
+/// A search can be interrupted from another thread, using the connection's
+/// interrupt handle, if the user moves on before it's done. In that case, this
+/// returns an `InterruptedError`.
 ///
 /// A provider can be anything that returns URL suggestions: Places history
-/// and bookmarks, synced tabs, search engine suggestions, and search keywords.
+/// and bookmarks, open and synced tabs, search engine suggestions, and search
+/// keywords. Besides the built-in ones, hosts can register their own with
+/// `PlacesApi::register_autocomplete_provider`. Matches for the same URL from
+/// different providers are merged, then ranked together.
 pub fn search_frecent(conn: &PlacesDb, params: SearchParams) -> Result<Vec<SearchResult>> {
-    // TODO: Tokenize the query.
+    let tokens = SearchTokens::new(&params.search_string);
+    let query = tokens.query();
+    let search_behavior = tokens.search_behavior();
 
     // Try to find the first heuristic result. Desktop tries extensions,
     // search engine aliases, origins, URLs, search engine domains, and
     // preloaded sites, before trying to fall back to fixing up the URL,
     // and a search if all else fails. We only try origins and URLs for
-    // heuristic matches, since that's all we support.
-
-    let mut matches = match_with_limit(
-        conn,
-        &[
-            // Try to match on the origin, or the full URL.
-            &OriginOrUrl::new(&params.search_string),
-            // query adaptive matches and suggestions, matching Anywhere.
-            &Adaptive::with_behavior(
-                &params.search_string,
-                MatchBehavior::Anywhere,
-                SearchBehavior::default(),
-            ),
-            &Suggestions::with_behavior(
-                &params.search_string,
-                MatchBehavior::Anywhere,
-                SearchBehavior::default(),
-            ),
-        ],
-        params.limit,
-    )?;
-
-    matches.sort_unstable_by(|a, b| a.url.cmp(&b.url));
-    matches.dedup_by(|a, b| a.url == b.url);
+    // heuristic matches, since that's all we support, and only if the user
+    // typed a single word without any restrictions.
+    let origin_or_url = OriginOrUrl::new(&query);
+    let adaptive = Adaptive::with_behavior(&query, MatchBehavior::Anywhere, search_behavior);
+    let suggestions = Suggestions::with_behavior(&query, MatchBehavior::Anywhere, search_behavior);
+    let keywords = Provider::new(&KeywordProvider, &tokens);
+    let open_tabs = Provider::new(&OpenTabsProvider, &tokens);
+    let registered = conn.providers.registered();
+    let registered = registered
+        .iter()
+        .map(|provider| Provider::new(provider.as_ref(), &tokens))
+        .collect::<Vec<_>>();
+
+    let mut matchers: Vec<&dyn Matcher> = Vec::with_capacity(5 + registered.len());
+    matchers.push(&keywords);
+    if tokens.words.len() == 1 && tokens.restrict.is_empty() {
+        // Try to match on the origin, or the full URL.
+        matchers.push(&origin_or_url);
+    }
+    matchers.push(&open_tabs);
+    // query adaptive matches and suggestions, matching Anywhere.
+    matchers.push(&adaptive);
+    matchers.push(&suggestions);
+    // Providers registered by the host go last, so they only choose titles
+    // for URLs that the built-in ones didn't match.
+    matchers.extend(registered.iter().map(|m| m as &dyn Matcher));
+
+    let matches = match_with_limit(conn, &matchers, params.limit)?;
+    let mut matches = merge_matches(matches);
+    matches.truncate(params.limit as usize);
 
     Ok(matches)
 }
 
+/// Merges matches for the same URL from different providers, then ranks them.
+/// Keywords come first, then heuristic origin and URL matches, then
+/// everything else by frecency.
+fn merge_matches(matches: Vec<SearchResult>) -> Vec<SearchResult> {
+    let mut merged: Vec<SearchResult> = Vec::with_capacity(matches.len());
+    let mut indices: HashMap<String, usize> = HashMap::with_capacity(matches.len());
+    for m in matches {
+        match indices.get(m.url.as_str()) {
+            Some(&index) => {
+                // The first provider to match a URL chooses its title, since
+                // providers run in order of precedence.
+                let existing = &mut merged[index];
+                existing.frecency = existing.frecency.max(m.frecency);
+                existing.description = existing.description.take().or(m.description);
+                existing.preview_image_url =
+                    existing.preview_image_url.take().or(m.preview_image_url);
+                existing.icon_url = existing.icon_url.take().or(m.icon_url);
+                for reason in m.reasons {
+                    if !existing.reasons.contains(&reason) {
+                        existing.reasons.push(reason);
+                    }
+                }
+            }
+            None => {
+                indices.insert(m.url.as_str().to_owned(), merged.len());
+                merged.push(m);
+            }
+        }
+    }
+    let rank = |m: &SearchResult| {
+        if m.reasons.contains(&MatchReason::Keyword) {
+            0
+        } else if m
+            .reasons
+            .iter()
+            .any(|r| *r == MatchReason::Origin || *r == MatchReason::Url)
+        {
+            1
+        } else {
+            2
+        }
+    };
+    merged.sort_by(|a, b| {
+        rank(a)
+            .cmp(&rank(b))
+            .then_with(|| b.frecency.cmp(&a.frecency))
+            .then_with(|| a.url.cmp(&b.url))
+    });
+    merged
+}
+
 pub fn match_url(conn: &PlacesDb, query: impl AsRef<str>) -> Result<Option<String>> {
     let scope = conn.begin_interrupt_scope();
     let matcher = OriginOrUrl::new(query.as_ref());
     // Note: The matcher ignores the limit argument (it's a trait method)
-    let results = matcher.search(conn, 1)?;
-    scope.err_if_interrupted()?;
+    let results = matcher.search(conn, &scope, 1)?;
     // Doing it like this lets us move the result, avoiding a copy (which almost
     // certainly doesn't matter but whatever)
     if let Some(res) = results.into_iter().next() {
@@ -99,22 +228,20 @@ pub fn match_url(conn: &PlacesDb, query: impl AsRef<str>) -> Result<Option<Strin
     }
 }
 
+/// Asks each matcher for up to `max_results` matches. A later matcher can
+/// have better matches than an earlier one, so we don't stop once we have
+/// enough; the caller ranks all the matches, then truncates them.
 fn match_with_limit(
     conn: &PlacesDb,
     matchers: &[&dyn Matcher],
     max_results: u32,
 ) -> Result<(Vec<SearchResult>)> {
     let mut results = Vec::new();
-    let mut rem_results = max_results;
     let scope = conn.begin_interrupt_scope();
     for m in matchers {
-        if rem_results == 0 {
-            break;
-        }
         scope.err_if_interrupted()?;
-        let matches = m.search(conn, rem_results)?;
+        let matches = m.search(conn, &scope, max_results)?;
         results.extend(matches);
-        rem_results = rem_results.saturating_sub(results.len() as u32);
     }
     Ok(results)
 }
@@ -138,6 +265,40 @@ pub fn accept_result(conn: &PlacesDb, search_string: &str, url: &Url) -> Result<
     Ok(())
 }
 
+/// Removes adaptive history entries recorded by `accept_result`, so that
+/// they no longer boost autocomplete matches. If `url` is given, only
+/// entries for that page are removed; if `input` is given, only entries for
+/// that query string are removed. At least one of them must be given; use
+/// `remove_all_adaptive_entries` to remove everything. Returns the number of
+/// entries removed.
+pub fn remove_adaptive_entries(
+    conn: &PlacesDb,
+    url: Option<&Url>,
+    input: Option<&str>,
+) -> Result<usize> {
+    if url.is_none() && input.is_none() {
+        return Err(ErrorKind::NoAdaptiveEntriesFilter.into());
+    }
+    let removed = conn.execute_named_cached(
+        "DELETE FROM moz_inputhistory
+         WHERE (:page_url IS NULL OR place_id = (
+                  SELECT id FROM moz_places
+                  WHERE url_hash = hash(:page_url) AND url = :page_url))
+           AND (:input_text IS NULL OR input = :input_text)",
+        &[
+            (":page_url", &url.map(Url::as_str)),
+            (":input_text", &input),
+        ],
+    )?;
+    Ok(removed)
+}
+
+/// Removes all adaptive history recorded by `accept_result`. Returns the
+/// number of entries removed.
+pub fn remove_all_adaptive_entries(conn: &PlacesDb) -> Result<usize> {
+    Ok(conn.execute_named_cached("DELETE FROM moz_inputhistory", &[])?)
+}
+
 pub fn split_after_prefix(href: &str) -> (&str, &str) {
     match memchr::memchr(b':', href.as_bytes()) {
         None => ("", href),
@@ -181,6 +342,8 @@ pub enum MatchReason {
     Url,
     PreviousUse,
     Bookmark,
+    /// The page is open in a tab, so the user can switch to it.
+    OpenTab,
     // Hrm... This will probably make this all serialize weird...
     Tags(String),
 }
@@ -207,6 +370,16 @@ pub struct SearchResult {
     /// A frecency score for this match.
     pub frecency: i64,
 
+    /// The page description, if one was noted with `note_page_metadata`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// The page's preview image URL, if one was noted with
+    /// `note_page_metadata`.
+    #[serde(with = "url_serde")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview_image_url: Option<Url>,
+
     /// A list of reasons why this matched.
     pub reasons: Vec<MatchReason>,
 }
@@ -235,6 +408,7 @@ impl SearchResult {
             reasons.push(MatchReason::Bookmark);
         }
         let url = Url::parse(&url)?;
+        let (description, preview_image_url) = metadata_from_row(row)?;
 
         Ok(Self {
             search_string,
@@ -242,15 +416,20 @@ impl SearchResult {
             title,
             icon_url: None,
             frecency,
+            description,
+            preview_image_url,
             reasons,
         })
     }
 
+    /// Suggestions come from history as well as bookmarks, so only
+    /// bookmarked pages have `MatchReason::Bookmark`.
     pub fn from_suggestion_row(row: &rusqlite::Row<'_>) -> Result<Self> {
-        let mut reasons = vec![MatchReason::Bookmark];
+        let mut reasons = Vec::new();
 
         let search_string = row.get::<_, String>("searchString")?;
         let url = row.get::<_, String>("url")?;
+        let bookmarked = row.get::<_, bool>("bookmarked")?;
 
         let history_title = row.get::<_, Option<String>>("title")?;
         let bookmark_title = row.get::<_, Option<String>>("btitle")?;
@@ -260,9 +439,13 @@ impl SearchResult {
         if let Some(tags) = tags {
             reasons.push(MatchReason::Tags(tags));
         }
+        if bookmarked {
+            reasons.push(MatchReason::Bookmark);
+        }
         let url = Url::parse(&url)?;
 
         let frecency = row.get::<_, i64>("frecency")?;
+        let (description, preview_image_url) = metadata_from_row(row)?;
 
         Ok(Self {
             search_string,
@@ -270,6 +453,8 @@ impl SearchResult {
             title,
             icon_url: None,
             frecency,
+            description,
+            preview_image_url,
             reasons,
         })
     }
@@ -288,6 +473,8 @@ impl SearchResult {
             title: display_url,
             icon_url: None,
             frecency,
+            description: None,
+            preview_image_url: None,
             reasons: vec![MatchReason::Origin],
         })
     }
@@ -329,13 +516,55 @@ impl SearchResult {
             title: display_url,
             icon_url: None,
             frecency,
+            description: None,
+            preview_image_url: None,
             reasons,
         })
     }
 }
 
+/// Reads the page description and preview image URL for an adaptive or
+/// suggestion row. Origin and URL matches don't refer to a single page, so
+/// don't have these.
+fn metadata_from_row(row: &rusqlite::Row<'_>) -> Result<(Option<String>, Option<Url>)> {
+    let description = row.get::<_, Option<String>>("description")?;
+    let preview_image_url = match row.get::<_, Option<String>>("preview_image_url")? {
+        Some(href) => Some(Url::parse(&href)?),
+        None => None,
+    };
+    Ok((description, preview_image_url))
+}
+
+/// Adapts an `AutocompleteProvider` to a `Matcher` for one search.
+struct Provider<'a> {
+    provider: &'a dyn AutocompleteProvider,
+    tokens: &'a SearchTokens<'a>,
+}
+
+impl<'a> Provider<'a> {
+    fn new(provider: &'a dyn AutocompleteProvider, tokens: &'a SearchTokens<'a>) -> Self {
+        Provider { provider, tokens }
+    }
+}
+
+impl<'a> Matcher for Provider<'a> {
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        scope: &SqlInterruptScope,
+        max_results: u32,
+    ) -> Result<Vec<SearchResult>> {
+        self.provider.search(conn, self.tokens, scope, max_results)
+    }
+}
+
 trait Matcher {
-    fn search(&self, conn: &PlacesDb, max_results: u32) -> Result<Vec<SearchResult>>;
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        scope: &SqlInterruptScope,
+        max_results: u32,
+    ) -> Result<Vec<SearchResult>>;
 }
 
 struct OriginOrUrl<'query> {
@@ -409,10 +638,16 @@ const ORIGIN_SQL: &str = "
 ";
 
 impl<'query> Matcher for OriginOrUrl<'query> {
-    fn search(&self, conn: &PlacesDb, _: u32) -> Result<Vec<SearchResult>> {
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        scope: &SqlInterruptScope,
+        _: u32,
+    ) -> Result<Vec<SearchResult>> {
         Ok(if looks_like_origin(self.query) {
             query_flat_rows_and_then_named(
                 conn,
+                scope,
                 ORIGIN_SQL,
                 &[
                     (":prefix", &rusqlite::types::Null),
@@ -436,6 +671,7 @@ impl<'query> Matcher for OriginOrUrl<'query> {
             };
             query_flat_rows_and_then_named(
                 conn,
+                scope,
                 URL_SQL,
                 &[
                     (":searchString", &self.query),
@@ -472,9 +708,15 @@ impl<'query> Adaptive<'query> {
 }
 
 impl<'query> Matcher for Adaptive<'query> {
-    fn search(&self, conn: &PlacesDb, max_results: u32) -> Result<Vec<SearchResult>> {
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        scope: &SqlInterruptScope,
+        max_results: u32,
+    ) -> Result<Vec<SearchResult>> {
         Ok(query_flat_rows_and_then_named(
             conn,
+            scope,
             "
             SELECT h.url as url,
                    h.title as title,
@@ -485,12 +727,17 @@ impl<'query> Matcher for Adaptive<'query> {
                           title NOT NULL
                     ORDER BY lastModified DESC
                     LIMIT 1) AS btitle,
-                   NULL AS tags,
+                   (SELECT GROUP_CONCAT(t.tag, ',')
+                    FROM moz_tags t
+                    JOIN moz_tags_relation r ON r.tag_id = t.id
+                    WHERE r.place_id = h.id) AS tags,
                    h.visit_count_local + h.visit_count_remote AS visit_count,
                    h.typed as typed,
                    h.id as id,
                    NULL AS open_count,
                    h.frecency as frecency,
+                   h.description as description,
+                   h.preview_image_url as preview_image_url,
                    :searchString AS searchString
             FROM (
               SELECT ROUND(MAX(use_count) * (1 + (input = :searchString)), 1) AS rank,
@@ -538,9 +785,15 @@ impl<'query> Suggestions<'query> {
 }
 
 impl<'query> Matcher for Suggestions<'query> {
-    fn search(&self, conn: &PlacesDb, max_results: u32) -> Result<Vec<SearchResult>> {
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        scope: &SqlInterruptScope,
+        max_results: u32,
+    ) -> Result<Vec<SearchResult>> {
         Ok(query_flat_rows_and_then_named(
             conn,
+            scope,
             "
             SELECT h.url, h.title,
                    EXISTS(SELECT 1 FROM moz_bookmarks
@@ -550,11 +803,16 @@ impl<'query> Matcher for Suggestions<'query> {
                           title NOT NULL
                     ORDER BY lastModified DESC
                     LIMIT 1) AS btitle,
-                   NULL AS tags,
+                   (SELECT GROUP_CONCAT(t.tag, ',')
+                    FROM moz_tags t
+                    JOIN moz_tags_relation r ON r.tag_id = t.id
+                    WHERE r.place_id = h.id) AS tags,
                    h.visit_count_local + h.visit_count_remote AS visit_count,
                    h.typed as typed,
                    h.id as id,
-                   NULL AS open_count, h.frecency, :searchString AS searchString
+                   NULL AS open_count, h.frecency,
+                   h.description, h.preview_image_url,
+                   :searchString AS searchString
             FROM moz_places h
             WHERE h.frecency > 0
               AND AUTOCOMPLETE_MATCH(:searchString, h.url,
@@ -580,9 +838,11 @@ impl<'query> Matcher for Suggestions<'query> {
 mod tests {
     use super::*;
     use crate::api::places_api::test::new_mem_connection;
+    use crate::api::providers::AutocompleteProvider;
     use crate::observation::VisitObservation;
-    use crate::storage::history::apply_observation;
+    use crate::storage::history::{apply_observation, note_page_metadata};
     use crate::types::{Timestamp, VisitTransition};
+    use sql_support::SqlInterruptHandle;
 
     #[test]
     fn split() {
@@ -690,6 +950,9 @@ mod tests {
             },
         )
         .expect("Should search until reaching limit");
+        let origin_frecency = conn
+            .query_one::<i64>("SELECT frecency FROM moz_origins WHERE host = 'example.com'")
+            .expect("Should have an origin");
         assert_eq!(
             with_limit,
             vec![SearchResult {
@@ -697,11 +960,91 @@ mod tests {
                 url: Url::parse("http://example.com/").unwrap(),
                 title: "example.com/".into(),
                 icon_url: None,
-                frecency: -1,
+                frecency: origin_frecency,
+                description: None,
+                preview_image_url: None,
                 reasons: vec![MatchReason::Origin],
             }]
         );
     }
+    #[test]
+    fn search_suggestion_reasons() -> Result<()> {
+        use crate::storage::bookmarks::{
+            insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+        };
+
+        let conn = new_mem_connection();
+        let visited = Url::parse("http://example.com/visited-fox")?;
+        let bookmarked = Url::parse("http://example.com/bookmarked-fox")?;
+        for url in &[&visited, &bookmarked] {
+            let visit = VisitObservation::new((*url).clone())
+                .with_title("A quick brown fox".to_string())
+                .with_visit_type(VisitTransition::Link)
+                .with_at(Timestamp::now());
+            apply_observation(&conn, visit)?;
+        }
+        insert_bookmark(
+            &conn,
+            &InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: bookmarked.clone(),
+                title: None,
+                keyword: None,
+                tags: vec![],
+            }
+            .into(),
+        )?;
+
+        let results = search_frecent(
+            &conn,
+            SearchParams {
+                search_string: "brown fox".into(),
+                limit: 10,
+            },
+        )?;
+        let reasons = |url: &Url| {
+            results
+                .iter()
+                .find(|result| result.url == *url)
+                .map(|result| result.reasons.clone())
+        };
+        assert_eq!(reasons(&visited), Some(vec![]));
+        assert_eq!(reasons(&bookmarked), Some(vec![MatchReason::Bookmark]));
+        Ok(())
+    }
+
+    #[test]
+    fn search_metadata() {
+        let conn = new_mem_connection();
+
+        let url = Url::parse("http://example.com/123").unwrap();
+        let image_url = Url::parse("http://example.com/123.png").unwrap();
+        let visit = VisitObservation::new(url.clone())
+            .with_title("Example page 123".to_string())
+            .with_visit_type(VisitTransition::Typed)
+            .with_at(Timestamp::now());
+        apply_observation(&conn, visit).expect("Should apply visit");
+        note_page_metadata(&conn, &url, Some("An example page"), Some(&image_url))
+            .expect("Should note metadata");
+
+        let results = search_frecent(
+            &conn,
+            SearchParams {
+                search_string: "page 123".into(),
+                limit: 10,
+            },
+        )
+        .expect("Should search by title");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].url, url);
+        assert_eq!(results[0].description, Some("An example page".to_string()));
+        assert_eq!(results[0].preview_image_url, Some(image_url));
+    }
+
     #[test]
     fn search_unicode() {
         let conn = new_mem_connection();
@@ -762,6 +1105,357 @@ mod tests {
         )
         .unwrap();
     }
+    #[test]
+    fn tokenize() {
+        let tokens = SearchTokens::new("  mozilla   firefox ");
+        assert_eq!(tokens.words, vec!["mozilla", "firefox"]);
+        assert_eq!(tokens.query(), "mozilla firefox");
+        assert_eq!(tokens.search_behavior(), SearchBehavior::default());
+
+        let tokens = SearchTokens::new("* c++ ^ *");
+        assert_eq!(tokens.words, vec!["c++"]);
+        assert_eq!(
+            tokens.search_behavior(),
+            SearchBehavior::BOOKMARK | SearchBehavior::HISTORY | SearchBehavior::RESTRICT
+        );
+
+        let tokens = SearchTokens::new("+");
+        assert!(tokens.words.is_empty());
+        assert_eq!(
+            tokens.search_behavior(),
+            SearchBehavior::TAG | SearchBehavior::RESTRICT
+        );
+    }
+
+    #[test]
+    fn search_tokens() {
+        use crate::storage::{bookmarks::BookmarkRootGuid, tags::tag_url};
+        use crate::tests::insert_json_tree;
+        use serde_json::json;
+
+        let conn = new_mem_connection();
+        let pages = [
+            ("https://www.mozilla.org/firefox/", "Mozilla Firefox"),
+            ("https://www.thunderbird.net/", "Mozilla Thunderbird"),
+            ("https://example.com/fox", "A quick brown fox"),
+        ];
+        for (href, title) in &pages {
+            let visit = VisitObservation::new(Url::parse(href).unwrap())
+                .with_title(title.to_string())
+                .with_visit_type(VisitTransition::Link)
+                .with_at(Timestamp::now());
+            apply_observation(&conn, visit).expect("Should apply visit");
+        }
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [{ "url": "https://www.mozilla.org/firefox/" }]
+            }),
+        );
+        tag_url(
+            &conn,
+            &Url::parse("https://example.com/fox").unwrap(),
+            "animals",
+        )
+        .expect("Should tag page");
+
+        let search = |search_string: &str| -> Vec<String> {
+            search_frecent(
+                &conn,
+                SearchParams {
+                    search_string: search_string.into(),
+                    limit: 10,
+                },
+            )
+            .expect("Should search")
+            .into_iter()
+            .map(|result| result.url.into_string())
+            .collect()
+        };
+
+        // Every word must match, in any order.
+        assert_eq!(
+            search("fire mozilla"),
+            vec!["https://www.mozilla.org/firefox/"]
+        );
+        // Single words without restrictions can also match origins.
+        assert_eq!(
+            search("mozilla"),
+            vec![
+                "https://www.mozilla.org/",
+                "https://www.mozilla.org/firefox/",
+                "https://www.thunderbird.net/",
+            ]
+        );
+        assert!(search("mozilla fox quick").is_empty());
+
+        // Restriction tokens limit the search to bookmarks, history, or tags.
+        assert_eq!(
+            search("mozilla *"),
+            vec!["https://www.mozilla.org/firefox/"]
+        );
+        assert_eq!(search("* ^"), vec!["https://www.mozilla.org/firefox/"]);
+        assert_eq!(search("fox +"), vec!["https://example.com/fox"]);
+        assert_eq!(search("+ animals"), vec!["https://example.com/fox"]);
+        assert_eq!(search("^ fox").len(), 2);
+        // Nothing is open yet.
+        assert!(search("% fox").is_empty());
+    }
+
+    struct RemoteTabs {
+        frecency: i64,
+    }
+
+    impl AutocompleteProvider for RemoteTabs {
+        fn search(
+            &self,
+            _: &PlacesDb,
+            tokens: &SearchTokens<'_>,
+            _: &SqlInterruptScope,
+            _: u32,
+        ) -> Result<Vec<SearchResult>> {
+            Ok(vec![SearchResult {
+                search_string: tokens.query(),
+                url: Url::parse("https://example.com/remote").unwrap(),
+                title: "A remote tab".into(),
+                icon_url: None,
+                frecency: self.frecency,
+                description: None,
+                preview_image_url: None,
+                reasons: vec![],
+            }])
+        }
+    }
+
+    #[test]
+    fn search_providers() -> Result<()> {
+        use crate::api::places_api::{test::new_mem_api, ConnectionType};
+        use crate::storage::bookmarks::{
+            insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+        };
+        use std::sync::Arc;
+
+        let api = new_mem_api();
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        let fox = Url::parse("https://example.com/fox")?;
+        let visit = VisitObservation::new(fox.clone())
+            .with_title("A quick brown fox".to_string())
+            .with_visit_type(VisitTransition::Link)
+            .with_at(Timestamp::now());
+        apply_observation(&conn, visit)?;
+        insert_bookmark(
+            &conn,
+            &InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("https://example.com/search?q=%s")?,
+                title: Some("Search".into()),
+                keyword: Some("s".into()),
+                tags: vec![],
+            }
+            .into(),
+        )?;
+        let search = |search_string: &str| {
+            search_frecent(
+                &conn,
+                SearchParams {
+                    search_string: search_string.into(),
+                    limit: 10,
+                },
+            )
+        };
+
+        // Keywords come first, with the rest of the words substituted into
+        // the URL.
+        let results = search("s quick fox")?;
+        assert_eq!(
+            results[0].url.as_str(),
+            "https://example.com/search?q=quick+fox"
+        );
+        assert_eq!(results[0].reasons, vec![MatchReason::Keyword]);
+
+        // Open pages are merged with history matches for the same URL.
+        api.register_open_tab(&fox);
+        api.register_open_tab(&Url::parse("https://example.com/fox-not-in-history")?);
+        let results = search("fox")?;
+        assert_eq!(results[0].url, fox);
+        assert_eq!(results[0].title, "A quick brown fox");
+        assert_eq!(results[0].reasons, vec![MatchReason::OpenTab]);
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            search("% brown")?
+                .into_iter()
+                .map(|result| result.url)
+                .collect::<Vec<_>>(),
+            vec![fox.clone()]
+        );
+        // If more open pages match than we asked for, the most frecent ones
+        // are returned.
+        for i in 0..5 {
+            api.register_open_tab(&Url::parse(&format!("https://example.com/fox-{}", i))?);
+        }
+        let results = search_frecent(
+            &conn,
+            SearchParams {
+                search_string: "% fox".into(),
+                limit: 1,
+            },
+        )?;
+        assert_eq!(
+            results
+                .into_iter()
+                .map(|result| result.url)
+                .collect::<Vec<_>>(),
+            vec![fox.clone()]
+        );
+        for i in 0..5 {
+            api.unregister_open_tab(&Url::parse(&format!("https://example.com/fox-{}", i))?);
+        }
+        assert!(api.unregister_open_tab(&fox));
+        assert!(search("% brown")?.is_empty());
+
+        // Registered providers are ranked along with the built-in ones, and
+        // can be unregistered.
+        let id = api.register_autocomplete_provider(Arc::new(RemoteTabs { frecency: 0 }));
+        let results = search("fox")?;
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results.last().map(|result| result.title.as_str()),
+            Some("A remote tab")
+        );
+        assert!(api.unregister_autocomplete_provider(id));
+        assert_eq!(search("fox")?.len(), 2);
+
+        // Registered providers are asked for matches even if the built-in
+        // ones already found enough, and can outrank them.
+        let id = api.register_autocomplete_provider(Arc::new(RemoteTabs { frecency: i64::MAX }));
+        let results = search_frecent(
+            &conn,
+            SearchParams {
+                search_string: "fox".into(),
+                limit: 1,
+            },
+        )?;
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| result.title.as_str())
+                .collect::<Vec<_>>(),
+            vec!["A remote tab"]
+        );
+        assert!(api.unregister_autocomplete_provider(id));
+        Ok(())
+    }
+
+    struct Interrupting<'query> {
+        handle: SqlInterruptHandle,
+        query: &'query str,
+    }
+
+    impl<'query> Matcher for Interrupting<'query> {
+        fn search(
+            &self,
+            conn: &PlacesDb,
+            scope: &SqlInterruptScope,
+            max_results: u32,
+        ) -> Result<Vec<SearchResult>> {
+            self.handle.interrupt();
+            Suggestions::with_behavior(
+                self.query,
+                MatchBehavior::Anywhere,
+                SearchBehavior::default(),
+            )
+            .search(conn, scope, max_results)
+        }
+    }
+
+    #[test]
+    fn search_interrupted() {
+        use crate::error::ErrorKind;
+
+        let conn = new_mem_connection();
+        let url = Url::parse("http://example.com/123").unwrap();
+        let visit = VisitObservation::new(url)
+            .with_title("Example page 123".to_string())
+            .with_visit_type(VisitTransition::Typed)
+            .with_at(Timestamp::now());
+        apply_observation(&conn, visit).expect("Should apply visit");
+
+        let matcher = Interrupting {
+            handle: conn.new_interrupt_handle(),
+            query: "example",
+        };
+        let err = match_with_limit(&conn, &[&matcher], 10).expect_err("Should interrupt search");
+        match err.kind() {
+            ErrorKind::InterruptedError(_) => {}
+            kind => panic!("Expected interrupted error; got {}", kind),
+        }
+
+        // Interrupting a search doesn't affect the next one.
+        let results = search_frecent(
+            &conn,
+            SearchParams {
+                search_string: "example".into(),
+                limit: 10,
+            },
+        )
+        .expect("Should search after interrupting");
+        assert!(!results.is_empty());
+    }
+
+    #[test]
+    fn remove_adaptive() -> Result<()> {
+        let conn = new_mem_connection();
+        let a = Url::parse("http://example.com/a")?;
+        let b = Url::parse("http://example.com/b")?;
+        for url in &[&a, &b] {
+            apply_observation(
+                &conn,
+                VisitObservation::new((*url).clone())
+                    .with_visit_type(VisitTransition::Link)
+                    .with_at(Timestamp::now()),
+            )?;
+        }
+        let accept_all = || -> Result<()> {
+            accept_result(&conn, "ex", &a)?;
+            accept_result(&conn, "ex", &b)?;
+            accept_result(&conn, "exa", &a)?;
+            Ok(())
+        };
+        let count =
+            || -> Result<i64> { Ok(conn.query_one("SELECT COUNT(*) FROM moz_inputhistory")?) };
+        accept_all()?;
+        assert_eq!(count()?, 3);
+
+        assert_eq!(remove_adaptive_entries(&conn, None, Some("ex"))?, 2);
+        assert_eq!(remove_adaptive_entries(&conn, None, Some("ex"))?, 0);
+        assert_eq!(count()?, 1);
+
+        accept_all()?;
+        assert_eq!(remove_adaptive_entries(&conn, Some(&a), None)?, 2);
+        assert_eq!(count()?, 1);
+
+        accept_all()?;
+        assert_eq!(remove_adaptive_entries(&conn, Some(&b), Some("exa"))?, 0);
+        assert_eq!(remove_adaptive_entries(&conn, Some(&a), Some("exa"))?, 1);
+        assert_eq!(
+            remove_adaptive_entries(&conn, Some(&Url::parse("http://example.com/c")?), None)?,
+            0
+        );
+        assert_eq!(count()?, 2);
+
+        // Removing everything needs its own call.
+        assert!(remove_adaptive_entries(&conn, None, None).is_err());
+        assert_eq!(count()?, 2);
+        assert_eq!(remove_all_adaptive_entries(&conn)?, 2);
+        assert_eq!(count()?, 0);
+        Ok(())
+    }
+
     // This panics in tests but not for "real" consumers. In an effort to ensure
     // we are panicing where we think we are, note the 'expected' string.
     // (Not really clear this test offers much value, but seems worth having...)
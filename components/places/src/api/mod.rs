@@ -5,6 +5,7 @@
 pub mod history;
 pub mod matcher;
 pub mod places_api;
+pub mod providers;
 use crate::db::PlacesDb;
 use crate::error::Result;
 use crate::observation::VisitObservation;
@@ -2,10 +2,15 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::api::providers::{AutocompleteProvider, ProviderId, Providers};
 use crate::bookmark_sync::store::BookmarksStore;
 use crate::db::db::PlacesDb;
 use crate::error::*;
+use crate::frecency::{FrecencySettings, FRECENCY_SETTINGS_META_KEY};
 use crate::history_sync::store::HistoryStore;
+use crate::observer::{ObserverId, Observers, PlacesEvent, PlacesObserver};
+use crate::reading_list_sync::store::ReadingListStore;
+use crate::storage::bookmarks::UndoLog;
 use crate::storage::{self, delete_meta, get_meta, put_meta};
 use crate::util::normalize_path;
 use lazy_static::lazy_static;
@@ -18,9 +23,10 @@ use std::mem;
 use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicBool, AtomicUsize, Ordering},
-    Arc, Mutex, Weak,
+    Arc, Mutex, RwLock, Weak,
 };
 use sync15::{sync_multiple, telemetry, MemoryCachedState, SyncResult};
+use url::Url;
 
 // Not clear if this should be here, but this is the "global sync state"
 // which is persisted to disk and reused for all engines.
@@ -83,6 +89,10 @@ pub struct PlacesApi {
     coop_tx_lock: Arc<Mutex<()>>,
     sync_conn_active: AtomicBool,
     id: usize,
+    observers: Arc<Observers>,
+    providers: Arc<Providers>,
+    frecency_settings: Arc<RwLock<FrecencySettings>>,
+    undo_log: Arc<Mutex<UndoLog>>,
 }
 impl PlacesApi {
     /// Create a new, or fetch an already open, PlacesApi backed by a file on disk.
@@ -110,13 +120,22 @@ impl PlacesApi {
                 // We always create a new read-write connection for an initial open so
                 // we can create the schema and/or do version upgrades.
                 let coop_tx_lock = Arc::new(Mutex::new(()));
+                let observers = Arc::new(Observers::default());
+                let providers = Arc::new(Providers::default());
+                let undo_log = Arc::new(Mutex::new(UndoLog::default()));
                 match PlacesDb::open(
                     &db_name,
                     ConnectionType::ReadWrite,
                     id,
                     coop_tx_lock.clone(),
                 ) {
-                    Ok(connection) => {
+                    Ok(mut connection) => {
+                        connection.observers = observers.clone();
+                        connection.providers = providers.clone();
+                        let frecency_settings =
+                            Arc::new(RwLock::new(load_frecency_settings(&connection)?));
+                        connection.frecency_settings = frecency_settings.clone();
+                        connection.undo_log = undo_log.clone();
                         let new = PlacesApi {
                             db_name: db_name.clone(),
                             write_connection: Mutex::new(Some(connection)),
@@ -124,6 +143,10 @@ impl PlacesApi {
                             sync_conn_active: AtomicBool::new(false),
                             id,
                             coop_tx_lock,
+                            observers,
+                            providers,
+                            frecency_settings,
+                            undo_log,
                         };
                         let arc = Arc::new(new);
                         target.insert(db_name, Arc::downgrade(&arc));
@@ -155,12 +178,17 @@ impl PlacesApi {
         match conn_type {
             ConnectionType::ReadOnly => {
                 // make a new one - we can have as many of these as we want.
-                PlacesDb::open(
+                let mut db = PlacesDb::open(
                     self.db_name.clone(),
                     ConnectionType::ReadOnly,
                     self.id,
                     self.coop_tx_lock.clone(),
-                )
+                )?;
+                db.observers = self.observers.clone();
+                db.providers = self.providers.clone();
+                db.frecency_settings = self.frecency_settings.clone();
+                db.undo_log = self.undo_log.clone();
+                Ok(db)
             }
             ConnectionType::ReadWrite => {
                 // We only allow one of these.
@@ -183,12 +211,16 @@ impl PlacesApi {
         if prev_value {
             Err(ErrorKind::ConnectionAlreadyOpen.into())
         } else {
-            let db = PlacesDb::open(
+            let mut db = PlacesDb::open(
                 self.db_name.clone(),
                 ConnectionType::Sync,
                 self.id,
                 self.coop_tx_lock.clone(),
             )?;
+            db.observers = self.observers.clone();
+            db.providers = self.providers.clone();
+            db.frecency_settings = self.frecency_settings.clone();
+            db.undo_log = self.undo_log.clone();
             Ok(SyncConn {
                 db,
                 flag: &self.sync_conn_active,
@@ -211,6 +243,42 @@ impl PlacesApi {
         Ok(())
     }
 
+    /// Register an observer to be told about changes made by any connection
+    /// opened by this API. See the `observer` module for details.
+    pub fn register_observer(&self, observer: Arc<dyn PlacesObserver>) -> ObserverId {
+        self.observers.register(observer)
+    }
+
+    /// Returns true if the observer was registered.
+    pub fn unregister_observer(&self, id: ObserverId) -> bool {
+        self.observers.unregister(id)
+    }
+
+    /// Register a provider to be asked for autocomplete matches, after the
+    /// built-in ones, by searches on any connection opened by this API.
+    pub fn register_autocomplete_provider(
+        &self,
+        provider: Arc<dyn AutocompleteProvider>,
+    ) -> ProviderId {
+        self.providers.register(provider)
+    }
+
+    /// Returns true if the provider was registered.
+    pub fn unregister_autocomplete_provider(&self, id: ProviderId) -> bool {
+        self.providers.unregister(id)
+    }
+
+    /// Notes that a page was opened in a tab, so that autocomplete can offer
+    /// to switch to it. See `Providers::register_open_tab`.
+    pub fn register_open_tab(&self, url: &Url) {
+        self.providers.register_open_tab(url)
+    }
+
+    /// Returns true if the page was open.
+    pub fn unregister_open_tab(&self, url: &Url) -> bool {
+        self.providers.unregister_open_tab(url)
+    }
+
     fn get_disk_persisted_state(&self, conn: &PlacesDb) -> Result<Option<String>> {
         Ok(get_meta::<String>(&conn, GLOBAL_STATE_META_KEY)?)
     }
@@ -271,6 +339,29 @@ impl PlacesApi {
         )
     }
 
+    pub fn sync_reading_list(
+        &self,
+        client_init: &sync15::Sync15StorageClientInit,
+        key_bundle: &sync15::KeyBundle,
+    ) -> Result<telemetry::SyncTelemetryPing> {
+        self.do_sync_one(
+            crate::reading_list_sync::COLLECTION_NAME,
+            move |conn, mem_cached_state, disk_cached_state| {
+                let interruptee = conn.begin_interrupt_scope();
+                let store = ReadingListStore::new(&conn, &interruptee);
+                sync_multiple(
+                    &[&store],
+                    disk_cached_state,
+                    mem_cached_state,
+                    client_init,
+                    key_bundle,
+                    &interruptee,
+                    None,
+                )
+            },
+        )
+    }
+
     pub fn do_sync_one<F>(
         &self,
         name: &'static str,
@@ -302,6 +393,17 @@ impl PlacesApi {
         sync_state.mem_cached_state.replace(mem_cached_state);
         sync_state.disk_cached_state.replace(disk_cached_state);
 
+        let succeeded = result.result.is_ok()
+            && result
+                .engine_results
+                .get(name)
+                .and_then(|engine_result| engine_result.as_ref().err())
+                .is_none();
+        self.observers.notify(&PlacesEvent::SyncFinished {
+            engine: name.to_string(),
+            succeeded,
+        });
+
         // for b/w compat reasons, we do some dances with the result.
         if let Err(e) = result.result {
             return Err(e.into());
@@ -363,6 +465,13 @@ impl PlacesApi {
         sync_state.mem_cached_state.replace(mem_cached_state);
         sync_state.disk_cached_state.replace(disk_cached_state);
 
+        for (engine, engine_result) in &result.engine_results {
+            self.observers.notify(&PlacesEvent::SyncFinished {
+                engine: engine.clone(),
+                succeeded: result.result.is_ok() && engine_result.is_ok(),
+            });
+        }
+
         Ok(result)
     }
 
@@ -430,6 +539,43 @@ impl PlacesApi {
         store.do_reset(&sync15::StoreSyncAssociation::Disconnected)
     }
 
+    pub fn reset_reading_list(&self) -> Result<()> {
+        // Take the lock to prevent syncing while we're doing this.
+        let _guard = self.sync_state.lock().unwrap();
+        let conn = self.open_sync_connection()?;
+
+        // We'd rather you didn't interrupt this, but it's a required arg for
+        // ReadingListStore.
+        let scope = conn.begin_interrupt_scope();
+        let store = ReadingListStore::new(&conn, &scope);
+        store.reset(&sync15::StoreSyncAssociation::Disconnected)
+    }
+
+    /// Returns the settings used to calculate frecencies.
+    pub fn frecency_settings(&self) -> FrecencySettings {
+        self.frecency_settings.read().unwrap().clone()
+    }
+
+    /// Changes the settings used to calculate frecencies, and saves them for
+    /// the next time the database is opened. This marks every page's frecency
+    /// as stale, but doesn't recalculate them; call
+    /// `storage::history::update_stale_frecencies` to do that. Returns an
+    /// error, and keeps the current settings, if the new ones are invalid.
+    pub fn set_frecency_settings(&self, settings: FrecencySettings) -> Result<()> {
+        settings.validate()?;
+        let conn = self.open_sync_connection()?;
+        let tx = conn.begin_transaction()?;
+        put_meta(
+            &conn,
+            FRECENCY_SETTINGS_META_KEY,
+            &serde_json::to_string(&settings)?,
+        )?;
+        storage::history::mark_all_frecencies_stale(&conn)?;
+        tx.commit()?;
+        *self.frecency_settings.write().unwrap() = settings;
+        Ok(())
+    }
+
     /// Get a new interrupt handle for the sync connection.
     pub fn new_sync_conn_interrupt_handle(&self) -> Result<SqlInterruptHandle> {
         // Probably not necessary to lock here, since this should only get
@@ -440,6 +586,20 @@ impl PlacesApi {
     }
 }
 
+/// Loads the frecency settings saved by `PlacesApi::set_frecency_settings`,
+/// or the defaults if there aren't any.
+fn load_frecency_settings(conn: &PlacesDb) -> Result<FrecencySettings> {
+    Ok(
+        match get_meta::<String>(conn, FRECENCY_SETTINGS_META_KEY)? {
+            Some(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                log::warn!("Ignoring invalid frecency settings: {}", e);
+                FrecencySettings::default()
+            }),
+            None => FrecencySettings::default(),
+        },
+    )
+}
+
 /// Wrapper around PlacesDb that automatically sets a flag (`sync_conn_active`)
 /// to false when finished
 pub struct SyncConn<'api> {
@@ -611,4 +771,215 @@ mod tests {
         assert_ne!(1, conn.db.query_one::<i64>("PRAGMA user_version")?);
         Ok(())
     }
+
+    #[test]
+    fn test_observers() -> Result<()> {
+        use crate::observation::VisitObservation;
+        use crate::observer::EventQueue;
+        use crate::storage::bookmarks::{
+            self, BookmarkPosition, BookmarkRootGuid, InsertableBookmark, UpdatableBookmark,
+            UpdateTreeLocation,
+        };
+        use crate::types::{Timestamp, VisitTransition};
+        use url::Url;
+
+        let _ = env_logger::try_init();
+        let api = new_mem_api();
+        let queue = Arc::new(EventQueue::default());
+        let id = api.register_observer(queue.clone());
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+
+        let url = Url::parse("https://example.com/").unwrap();
+        let visit_date = Timestamp(1_000_000);
+        storage::history::apply_observation(
+            &conn,
+            VisitObservation::new(url.clone())
+                .with_title("Example".to_string())
+                .with_visit_type(VisitTransition::Link)
+                .with_at(visit_date),
+        )?;
+        // Observing the same title again isn't a change.
+        storage::history::apply_observation(
+            &conn,
+            VisitObservation::new(url.clone()).with_title("Example".to_string()),
+        )?;
+        storage::history::delete_visits_between(&conn, Timestamp(0), visit_date)?;
+        // Removing pages which don't exist, or expiring when there's nothing
+        // to expire, shouldn't notify.
+        storage::history::delete_place_by_guid(&conn, &"nonexistent0".into())?;
+        storage::history::prune_destructively(&conn)?;
+        assert_eq!(
+            queue.drain(),
+            vec![
+                PlacesEvent::PageVisited {
+                    url: url.to_string(),
+                    visit_date,
+                    visit_type: VisitTransition::Link,
+                    is_remote: false,
+                },
+                PlacesEvent::TitleChanged {
+                    url: url.to_string(),
+                    title: "Example".into(),
+                },
+                PlacesEvent::VisitsRemoved {
+                    url: None,
+                    start: Timestamp(0),
+                    end: visit_date,
+                },
+            ]
+        );
+
+        let guid = bookmarks::insert_bookmark(
+            &conn,
+            &InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: url.clone(),
+                title: None,
+                keyword: None,
+                tags: Vec::new(),
+            }
+            .into(),
+        )?;
+        bookmarks::update_bookmark(
+            &conn,
+            &guid,
+            &UpdatableBookmark {
+                location: UpdateTreeLocation::Parent(
+                    BookmarkRootGuid::Mobile.into(),
+                    BookmarkPosition::Append,
+                ),
+                title: Some("Example".into()),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        // Updating without changing anything shouldn't notify.
+        bookmarks::update_bookmark(&conn, &guid, &UpdatableBookmark::default().into())?;
+        assert!(bookmarks::delete_bookmark(&conn, &guid)?);
+        assert_eq!(
+            queue.drain(),
+            vec![
+                PlacesEvent::BookmarkInserted {
+                    guid: guid.clone(),
+                    parent_guid: BookmarkRootGuid::Unfiled.into(),
+                    index: 0,
+                    url: Some(url.to_string()),
+                },
+                PlacesEvent::BookmarkChanged { guid: guid.clone() },
+                PlacesEvent::BookmarkMoved {
+                    guid: guid.clone(),
+                    old_parent_guid: BookmarkRootGuid::Unfiled.into(),
+                    old_index: 0,
+                    new_parent_guid: BookmarkRootGuid::Mobile.into(),
+                    new_index: 0,
+                },
+                PlacesEvent::BookmarkRemoved {
+                    guid,
+                    parent_guid: BookmarkRootGuid::Mobile.into(),
+                    index: 0,
+                    url: Some(url.to_string()),
+                },
+            ]
+        );
+
+        // Connections opened later see the observers too.
+        let reader = api.open_connection(ConnectionType::ReadOnly)?;
+        reader.observers.notify(&PlacesEvent::HistoryExpired);
+        assert_eq!(queue.drain(), vec![PlacesEvent::HistoryExpired]);
+
+        assert!(api.unregister_observer(id));
+        storage::history::delete_everything(&conn)?;
+        assert_eq!(queue.drain(), vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_frecency_settings() -> Result<()> {
+        use crate::observation::VisitObservation;
+        use crate::types::{Timestamp, VisitTransition};
+        use url::Url;
+
+        let _ = env_logger::try_init();
+        let api = new_mem_api();
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        assert_eq!(api.frecency_settings(), FrecencySettings::default());
+
+        let url = Url::parse("https://example.com/")?;
+        storage::history::apply_observation(
+            &conn,
+            VisitObservation::new(url.clone())
+                .with_visit_type(VisitTransition::Typed)
+                .with_at(Timestamp::now()),
+        )?;
+        let frecency = |conn: &PlacesDb| -> Result<(i64, i64)> {
+            Ok((
+                conn.query_one("SELECT frecency FROM moz_places")?,
+                conn.query_one("SELECT frecency FROM moz_origins")?,
+            ))
+        };
+        let (old_frecency, old_origin_frecency) = frecency(&conn)?;
+        assert!(old_frecency > 0);
+
+        let settings = FrecencySettings {
+            typed_visit_bonus: 4000,
+            ..FrecencySettings::default()
+        };
+        api.set_frecency_settings(settings.clone())?;
+        assert_eq!(api.frecency_settings(), settings);
+        assert_eq!(conn.frecency_settings(), settings);
+        assert_eq!(load_frecency_settings(&conn)?, settings);
+        assert!(storage::history::frecency_stale_at(&conn, &url)?.is_some());
+        assert_eq!(frecency(&conn)?.0, old_frecency);
+
+        storage::history::update_stale_frecencies(&conn, &conn.begin_interrupt_scope())?;
+        assert!(storage::history::frecency_stale_at(&conn, &url)?.is_none());
+        let (new_frecency, origin_frecency) = frecency(&conn)?;
+        assert!(new_frecency > old_frecency);
+        assert_eq!(
+            origin_frecency - old_origin_frecency,
+            new_frecency - old_frecency
+        );
+
+        // Recalculating all frecencies doesn't need them to be marked first.
+        conn.execute_batch("UPDATE moz_places SET frecency = 1")?;
+        storage::history::recalculate_all_frecencies(&conn, &conn.begin_interrupt_scope())?;
+        assert_eq!(frecency(&conn)?.0, new_frecency);
+
+        // Invalid settings are rejected, and the old ones are kept.
+        for invalid in &[
+            FrecencySettings {
+                num_visits: 0,
+                ..settings.clone()
+            },
+            FrecencySettings {
+                first_bucket_cutoff_days: -1,
+                ..settings.clone()
+            },
+            FrecencySettings {
+                second_bucket_cutoff_days: 100,
+                ..settings.clone()
+            },
+            FrecencySettings {
+                default_bucket_weight: -10,
+                ..settings.clone()
+            },
+            FrecencySettings {
+                decay_rate: 0.0,
+                ..settings.clone()
+            },
+            FrecencySettings {
+                decay_rate: f64::NAN,
+                ..settings.clone()
+            },
+        ] {
+            assert!(api.set_frecency_settings(invalid.clone()).is_err());
+        }
+        assert_eq!(api.frecency_settings(), settings);
+        assert_eq!(load_frecency_settings(&conn)?, settings);
+        Ok(())
+    }
 }
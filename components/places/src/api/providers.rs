@@ -0,0 +1,309 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Autocomplete providers, which `search_frecent` asks for matches along with
+//! its own history and bookmark matchers.
+//!
+//! Like observers, providers are registered on the `PlacesApi`, and every
+//! connection opened by that API shares them. The built-in providers match
+//! bookmark keywords, and pages which are open in a tab, which the host
+//! registers with `PlacesApi::register_open_tab`.
+
+use super::matcher::{MatchReason, SearchResult, SearchTokens};
+use crate::db::PlacesDb;
+use crate::error::Result;
+use crate::match_impl::{AutocompleteMatch, MatchBehavior, SearchBehavior};
+use crate::storage::bookmarks::bookmarks_get_url_for_keyword;
+use sql_support::{ConnExt, SqlInterruptScope};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use url::{form_urlencoded, Url};
+
+pub trait AutocompleteProvider: Send + Sync {
+    /// Returns up to `max_results` matches for a search. Providers which take
+    /// a while should check `scope` as they go, and stop if the search was
+    /// interrupted.
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        tokens: &SearchTokens<'_>,
+        scope: &SqlInterruptScope,
+        max_results: u32,
+    ) -> Result<Vec<SearchResult>>;
+}
+
+/// Identifies a registered provider, so it can be unregistered later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProviderId(pub usize);
+
+/// The providers registered with a `PlacesApi`, and the pages open in tabs.
+#[derive(Default)]
+pub struct Providers {
+    next_id: AtomicUsize,
+    providers: Mutex<Vec<(ProviderId, Arc<dyn AutocompleteProvider>)>>,
+    // The number of tabs each URL is open in.
+    open_tabs: Mutex<HashMap<String, u32>>,
+}
+
+impl Providers {
+    pub fn register(&self, provider: Arc<dyn AutocompleteProvider>) -> ProviderId {
+        let id = ProviderId(self.next_id.fetch_add(1, Ordering::SeqCst));
+        self.providers.lock().unwrap().push((id, provider));
+        id
+    }
+
+    /// Returns true if the provider was registered.
+    pub fn unregister(&self, id: ProviderId) -> bool {
+        let mut providers = self.providers.lock().unwrap();
+        let len = providers.len();
+        providers.retain(|(existing, _)| *existing != id);
+        providers.len() != len
+    }
+
+    /// Returns the registered providers, in the order they were registered.
+    /// This is a copy, so a provider can unregister itself while searching.
+    pub fn registered(&self) -> Vec<Arc<dyn AutocompleteProvider>> {
+        self.providers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, p)| p.clone())
+            .collect()
+    }
+
+    /// Notes that a page was opened in a tab. A page can be open in more than
+    /// one tab, so each call should be matched by a call to
+    /// `unregister_open_tab` when the tab is closed or navigates away.
+    pub fn register_open_tab(&self, url: &Url) {
+        *self
+            .open_tabs
+            .lock()
+            .unwrap()
+            .entry(url.as_str().to_owned())
+            .or_insert(0) += 1;
+    }
+
+    /// Notes that a tab showing a page was closed. Returns true if the page
+    /// was open.
+    pub fn unregister_open_tab(&self, url: &Url) -> bool {
+        let mut open_tabs = self.open_tabs.lock().unwrap();
+        match open_tabs.get_mut(url.as_str()) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                open_tabs.remove(url.as_str());
+            }
+            None => return false,
+        }
+        true
+    }
+
+    /// Returns the URLs of the open pages, and how many tabs each is open in.
+    pub fn open_tabs(&self) -> Vec<(String, u32)> {
+        self.open_tabs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(url, count)| (url.clone(), *count))
+            .collect()
+    }
+}
+
+impl fmt::Debug for Providers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Providers")
+            .field("count", &self.providers.lock().unwrap().len())
+            .field("open_tabs", &self.open_tabs.lock().unwrap().len())
+            .finish()
+    }
+}
+
+/// Matches bookmark keywords. If the first word of the search is a keyword,
+/// this returns the bookmark's URL, with any `%s` replaced by the rest of the
+/// words, so "w places" can search Wikipedia for "places".
+#[derive(Debug, Default)]
+pub struct KeywordProvider;
+
+impl AutocompleteProvider for KeywordProvider {
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        tokens: &SearchTokens<'_>,
+        _: &SqlInterruptScope,
+        _: u32,
+    ) -> Result<Vec<SearchResult>> {
+        if !tokens.restrict.is_empty() {
+            return Ok(vec![]);
+        }
+        let (keyword, params) = match tokens.words.split_first() {
+            Some((keyword, params)) => (keyword, params.join(" ")),
+            None => return Ok(vec![]),
+        };
+        let bookmark_url = match bookmarks_get_url_for_keyword(conn, keyword)? {
+            Some(url) => url,
+            None => return Ok(vec![]),
+        };
+        let frecency = conn
+            .try_query_one(
+                "SELECT frecency FROM moz_places
+                 WHERE url_hash = hash(:url) AND url = :url",
+                &[(":url", &bookmark_url.as_str())],
+                true,
+            )?
+            .unwrap_or_default();
+        let url = if bookmark_url.as_str().contains("%s") {
+            let encoded = form_urlencoded::byte_serialize(params.as_bytes()).collect::<String>();
+            Url::parse(&bookmark_url.as_str().replace("%s", &encoded))?
+        } else {
+            bookmark_url
+        };
+        Ok(vec![SearchResult {
+            search_string: tokens.query(),
+            title: url.as_str().to_owned(),
+            url,
+            icon_url: None,
+            frecency,
+            description: None,
+            preview_image_url: None,
+            reasons: vec![MatchReason::Keyword],
+        }])
+    }
+}
+
+/// Matches pages which are open in a tab, so the user can switch to the tab
+/// instead of opening the page again. This is the only provider which knows
+/// about open pages, so it's also the only one which returns results for the
+/// `%` restriction token.
+#[derive(Debug, Default)]
+pub struct OpenTabsProvider;
+
+impl AutocompleteProvider for OpenTabsProvider {
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        tokens: &SearchTokens<'_>,
+        scope: &SqlInterruptScope,
+        max_results: u32,
+    ) -> Result<Vec<SearchResult>> {
+        let search_behavior = tokens.search_behavior();
+        if !search_behavior.contains(SearchBehavior::OPENPAGE) {
+            return Ok(vec![]);
+        }
+        let query = tokens.query();
+        let mut results = Vec::new();
+        for (href, open_count) in conn.providers.open_tabs() {
+            scope.err_if_interrupted()?;
+            let page = conn.try_query_row(
+                "SELECT h.title, h.frecency, h.typed,
+                        h.visit_count_local + h.visit_count_remote AS visit_count,
+                        h.foreign_count > 0 AS bookmarked,
+                        (SELECT GROUP_CONCAT(t.tag, ',')
+                         FROM moz_tags t
+                         JOIN moz_tags_relation r ON r.tag_id = t.id
+                         WHERE r.place_id = h.id) AS tags
+                 FROM moz_places h
+                 WHERE h.url_hash = hash(:url) AND h.url = :url",
+                &[(":url", &href)],
+                |row| -> Result<_> {
+                    Ok((
+                        row.get::<_, Option<String>>("title")?,
+                        row.get::<_, i64>("frecency")?,
+                        row.get::<_, bool>("typed")?,
+                        row.get::<_, u32>("visit_count")?,
+                        row.get::<_, bool>("bookmarked")?,
+                        row.get::<_, Option<String>>("tags")?,
+                    ))
+                },
+                true,
+            )?;
+            // Pages which aren't in history can still be open.
+            let (title, frecency, typed, visit_count, bookmarked, tags) =
+                page.unwrap_or((None, 0, false, 0, false, None));
+            let matches = AutocompleteMatch {
+                search_str: &query,
+                url_str: &href,
+                title_str: title.as_ref().map_or("", String::as_str),
+                tags: tags.as_ref().map_or("", String::as_str),
+                visit_count,
+                typed,
+                bookmarked,
+                open_page_count: open_count,
+                match_behavior: MatchBehavior::Anywhere,
+                search_behavior,
+            }
+            .invoke();
+            if !matches {
+                continue;
+            }
+            let url = Url::parse(&href)?;
+            results.push(SearchResult {
+                search_string: query.clone(),
+                title: title.unwrap_or_else(|| href.clone()),
+                url,
+                icon_url: None,
+                frecency,
+                description: None,
+                preview_image_url: None,
+                reasons: vec![MatchReason::OpenTab],
+            });
+        }
+        // Open tabs aren't kept in any order, so we need to look at all of
+        // them before we can pick the most frecent.
+        results.sort_by(|a, b| b.frecency.cmp(&a.frecency).then_with(|| a.url.cmp(&b.url)));
+        results.truncate(max_results as usize);
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Nothing;
+
+    impl AutocompleteProvider for Nothing {
+        fn search(
+            &self,
+            _: &PlacesDb,
+            _: &SearchTokens<'_>,
+            _: &SqlInterruptScope,
+            _: u32,
+        ) -> Result<Vec<SearchResult>> {
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn test_providers() {
+        let providers = Providers::default();
+        let first = providers.register(Arc::new(Nothing));
+        let second = providers.register(Arc::new(Nothing));
+        assert_ne!(first, second);
+        assert_eq!(providers.registered().len(), 2);
+        assert!(providers.unregister(first));
+        assert!(!providers.unregister(first));
+        assert_eq!(providers.registered().len(), 1);
+    }
+
+    #[test]
+    fn test_open_tabs() {
+        let providers = Providers::default();
+        let url = Url::parse("https://example.com/").unwrap();
+        providers.register_open_tab(&url);
+        providers.register_open_tab(&url);
+        assert_eq!(
+            providers.open_tabs(),
+            vec![("https://example.com/".to_string(), 2)]
+        );
+        assert!(providers.unregister_open_tab(&url));
+        assert_eq!(
+            providers.open_tabs(),
+            vec![("https://example.com/".to_string(), 1)]
+        );
+        assert!(providers.unregister_open_tab(&url));
+        assert!(providers.open_tabs().is_empty());
+        assert!(!providers.unregister_open_tab(&url));
+    }
+}
@@ -12,8 +12,10 @@ use super::{SyncedBookmarkKind, SyncedBookmarkValidity};
 use crate::api::places_api::ConnectionType;
 use crate::db::PlacesDb;
 use crate::error::*;
-use crate::frecency::{calculate_frecency, DEFAULT_FRECENCY_SETTINGS};
-use crate::storage::{bookmarks::BookmarkRootGuid, delete_meta, get_meta, put_meta};
+use crate::storage::{
+    bookmarks::{clear_undo_log, BookmarkRootGuid},
+    delete_meta, get_meta, history, put_meta,
+};
 use crate::types::{BookmarkType, SyncStatus, Timestamp};
 use dogear::{
     self, AbortSignal, Content, Deletion, Item, MergedDescendant, MergedRoot, TelemetryEvent, Tree,
@@ -37,12 +39,6 @@ pub const LAST_SYNC_META_KEY: &str = "bookmarks_last_sync_time";
 pub const GLOBAL_SYNCID_META_KEY: &str = "bookmarks_global_sync_id";
 pub const COLLECTION_SYNCID_META_KEY: &str = "bookmarks_sync_id";
 
-/// The maximum number of URLs for which to recalculate frecencies at once.
-/// This is a trade-off between write efficiency and transaction time: higher
-/// maximums mean fewer write statements, but longer transactions, possibly
-/// blocking writes from other connections.
-const MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK: usize = 400;
-
 /// Adapts an interruptee to a Dogear abort signal.
 struct MergeInterruptee<'a, I>(&'a I);
 
@@ -245,10 +241,9 @@ impl<'a> BookmarksStore<'a> {
                                        kind, url, keyword, position)
              SELECT s.id, s.guid, s.syncChangeCounter, s.parentGuid,
                     s.parentTitle, s.dateAdded, s.title, s.placeId,
-                    {kind}, h.url, v.keyword, s.position
+                    {kind}, h.url, s.keyword, s.position
              FROM localItems s
              JOIN mergedTree r ON r.mergedGuid = s.guid
-             LEFT JOIN moz_bookmarks_synced v ON v.guid = r.remoteGuid
              LEFT JOIN moz_places h ON h.id = s.placeId
              LEFT JOIN idsToWeaklyUpload w ON w.id = s.id
              WHERE s.guid <> '{root_guid}' AND
@@ -455,76 +450,7 @@ impl<'a> BookmarksStore<'a> {
     }
 
     pub(crate) fn update_frecencies(&self) -> Result<()> {
-        let mut tx = self.db.begin_transaction()?;
-
-        let mut frecencies = Vec::with_capacity(MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK);
-        loop {
-            let sql = format!(
-                "SELECT place_id FROM moz_places_stale_frecencies
-                 ORDER BY stale_at DESC
-                 LIMIT {}",
-                MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK
-            );
-            let mut stmt = self.db.prepare_maybe_cached(&sql, true)?;
-            let mut results = stmt.query(NO_PARAMS)?;
-            while let Some(row) = results.next()? {
-                let place_id = row.get("place_id")?;
-                // Frecency recalculation runs several statements, so check to
-                // make sure we aren't interrupted before each calculation.
-                self.interruptee.err_if_interrupted()?;
-                let frecency = calculate_frecency(
-                    &self.db,
-                    &DEFAULT_FRECENCY_SETTINGS,
-                    place_id,
-                    Some(false),
-                )?;
-                frecencies.push((place_id, frecency));
-            }
-            if frecencies.is_empty() {
-                break;
-            }
-
-            // Update all frecencies in one fell swoop...
-            self.db.execute_batch(&format!(
-                "WITH frecencies(id, frecency) AS (
-                   VALUES {}
-                 )
-                 UPDATE moz_places SET
-                   frecency = (SELECT frecency FROM frecencies f
-                               WHERE f.id = id)
-                 WHERE id IN (SELECT f.id FROM frecencies f)",
-                sql_support::repeat_display(frecencies.len(), ",", |index, f| {
-                    let (id, frecency) = frecencies[index];
-                    write!(f, "({}, {})", id, frecency)
-                })
-            ))?;
-            tx.maybe_commit()?;
-            self.interruptee.err_if_interrupted()?;
-
-            // ...And remove them from the stale table.
-            self.db.execute_batch(&format!(
-                "DELETE FROM moz_places_stale_frecencies
-                 WHERE place_id IN ({})",
-                sql_support::repeat_display(frecencies.len(), ",", |index, f| {
-                    let (id, _) = frecencies[index];
-                    write!(f, "{}", id)
-                })
-            ))?;
-            tx.maybe_commit()?;
-            self.interruptee.err_if_interrupted()?;
-
-            // If the query returned fewer URLs than the maximum, we're done.
-            // Otherwise, we might have more, so clear the ones we just
-            // recalculated and fetch the next chunk.
-            if frecencies.len() < MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK {
-                break;
-            }
-            frecencies.clear();
-        }
-
-        tx.commit()?;
-
-        Ok(())
+        history::update_stale_frecencies(self.db, self.interruptee)
     }
 
     /// Removes all sync metadata, such that the next sync is treated as a
@@ -572,6 +498,7 @@ impl<'a> Store for BookmarksStore<'a> {
         telem: &mut telemetry::Engine,
     ) -> result::Result<OutgoingChangeset, failure::Error> {
         // Stage all incoming items.
+        let has_incoming = !inbound.changes.is_empty();
         let mut incoming_telemetry = telemetry::EngineIncoming::new();
         let timestamp = self.stage_incoming(inbound, &mut incoming_telemetry)?;
         telem.incoming(incoming_telemetry);
@@ -584,6 +511,10 @@ impl<'a> Store for BookmarksStore<'a> {
         // Merge.
         let mut merger = Merger::with_telemetry(&self, timestamp, telem);
         merger.merge()?;
+        if has_incoming {
+            // Undoing a local change now could overwrite the incoming ones.
+            clear_undo_log(self.db);
+        }
 
         // Finally, stage outgoing items.
         let outgoing = self.fetch_outgoing_records(timestamp)?;
@@ -1054,14 +985,16 @@ impl<'a> fmt::Display for LocalItemsFragment<'a> {
         write!(
             f,
             "{name}(id, guid, parentId, parentGuid, position, type, title, parentTitle,
-                    placeId, dateAdded, lastModified, syncChangeCounter, level) AS (
+                    placeId, keyword, dateAdded, lastModified, syncChangeCounter,
+                    level) AS (
              SELECT b.id, b.guid, 0, NULL, b.position, b.type, b.title, NULL,
-                    b.fk, b.dateAdded, b.lastModified, b.syncChangeCounter, 0
+                    b.fk, b.keyword, b.dateAdded, b.lastModified, b.syncChangeCounter, 0
              FROM moz_bookmarks b
              WHERE b.guid = '{root_guid}'
              UNION ALL
              SELECT b.id, b.guid, s.id, s.guid, b.position, b.type, b.title, s.title,
-                    b.fk, b.dateAdded, b.lastModified, b.syncChangeCounter, s.level + 1
+                    b.fk, b.keyword, b.dateAdded, b.lastModified, b.syncChangeCounter,
+                    s.level + 1
              FROM moz_bookmarks b
              JOIN {name} s ON s.id = b.parent)",
             name = self.0,
@@ -1180,7 +1113,10 @@ mod tests {
     use crate::bookmark_sync::store::BookmarksStore;
     use crate::db::PlacesDb;
     use crate::storage::{
-        bookmarks::{get_raw_bookmark, update_bookmark, UpdatableBookmark, USER_CONTENT_ROOTS},
+        bookmarks::{
+            bookmarks_get_url_for_keyword, get_raw_bookmark, update_bookmark, UpdatableBookmark,
+            USER_CONTENT_ROOTS,
+        },
         history::frecency_stale_at,
         tags,
     };
@@ -1820,6 +1756,34 @@ mod tests {
         assert_eq!(outgoing.changes[0].id, "bookmarkAAAA");
         assert_eq!(outgoing.changes[0].data["keyword"], "a");
 
+        // The incoming keyword should have been applied locally, and a local
+        // change should be uploaded.
+        assert_eq!(
+            bookmarks_get_url_for_keyword(&writer, "a")?,
+            Some(Url::parse("http://example.com/a")?)
+        );
+        store
+            .sync_finished(ServerTimestamp(1000), vec!["bookmarkAAAA".into()])
+            .expect("Should push synced changes back to the store");
+        update_bookmark(
+            &writer,
+            &"bookmarkAAAA".into(),
+            &UpdatableBookmark {
+                keyword: Some("b".into()),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        let outgoing = store
+            .apply_incoming(
+                IncomingChangeset::new(store.collection_name().to_string(), ServerTimestamp(2000)),
+                &mut telemetry::Engine::new("bookmarks"),
+            )
+            .expect("Should fetch outgoing records after changing the keyword");
+        assert_eq!(outgoing.changes.len(), 1);
+        assert_eq!(outgoing.changes[0].id, "bookmarkAAAA");
+        assert_eq!(outgoing.changes[0].data["keyword"], "b");
+
         Ok(())
     }
 
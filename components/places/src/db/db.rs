@@ -4,13 +4,20 @@
 
 use super::schema;
 use crate::api::places_api::ConnectionType;
+use crate::api::providers::Providers;
 use crate::error::*;
+use crate::frecency::FrecencySettings;
+use crate::observer::Observers;
+use crate::storage::bookmarks::UndoLog;
 use rusqlite::Connection;
 use sql_support::{ConnExt, SqlInterruptHandle, SqlInterruptScope};
 use std::ops::Deref;
 use std::path::Path;
 
-use std::sync::{atomic::AtomicUsize, Arc, Mutex};
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex, RwLock,
+};
 
 pub const MAX_VARIABLE_NUMBER: usize = 999;
 
@@ -22,6 +29,18 @@ pub struct PlacesDb {
     api_id: usize,
     in_memory: bool,
     pub(super) coop_tx_lock: Arc<Mutex<()>>,
+    // Shared with the `PlacesApi` which opened this connection, if any.
+    pub(crate) observers: Arc<Observers>,
+    // Autocomplete providers and open tabs, also shared with the API.
+    pub(crate) providers: Arc<Providers>,
+    // The frecency settings, also shared with the API, which persists them.
+    pub(crate) frecency_settings: Arc<RwLock<FrecencySettings>>,
+    // Bookmark changes, for undo and redo. Also shared with the API, so that
+    // syncing on another connection can clear it.
+    pub(crate) undo_log: Arc<Mutex<UndoLog>>,
+    // Set while decaying frecencies, so that the frecency triggers don't
+    // update origins for every page. See `is_frecency_decaying()`.
+    pub(crate) frecency_decaying: Arc<AtomicBool>,
 }
 
 impl PlacesDb {
@@ -69,7 +88,8 @@ impl PlacesDb {
         ";
 
         db.execute_batch(initial_pragmas)?;
-        define_functions(&db)?;
+        let frecency_decaying = Arc::new(AtomicBool::new(false));
+        define_functions(&db, frecency_decaying.clone())?;
         db.set_prepared_statement_cache_capacity(128);
         let res = Self {
             db,
@@ -79,6 +99,12 @@ impl PlacesDb {
             interrupt_counter: Arc::new(AtomicUsize::new(0)),
             coop_tx_lock,
             in_memory,
+            // The API sets this explicitly too.
+            observers: Arc::default(),
+            providers: Arc::default(),
+            frecency_settings: Arc::default(),
+            undo_log: Arc::default(),
+            frecency_decaying,
         };
         match res.conn_type() {
             // For read-only connections, we can avoid opening a transaction,
@@ -131,6 +157,11 @@ impl PlacesDb {
         )
     }
 
+    /// Returns the settings to use when calculating frecencies.
+    pub fn frecency_settings(&self) -> FrecencySettings {
+        self.frecency_settings.read().unwrap().clone()
+    }
+
     #[inline]
     pub fn begin_interrupt_scope(&self) -> SqlInterruptScope {
         SqlInterruptScope::new(self.interrupt_counter.clone())
@@ -178,7 +209,7 @@ impl Deref for PlacesDb {
     }
 }
 
-fn define_functions(c: &Connection) -> Result<()> {
+fn define_functions(c: &Connection, frecency_decaying: Arc<AtomicBool>) -> Result<()> {
     c.create_scalar_function("get_prefix", 1, true, sql_fns::get_prefix)?;
     c.create_scalar_function("get_host_and_port", 1, true, sql_fns::get_host_and_port)?;
     c.create_scalar_function(
@@ -192,6 +223,9 @@ fn define_functions(c: &Connection) -> Result<()> {
     c.create_scalar_function("hash", -1, true, sql_fns::hash)?;
     c.create_scalar_function("now", 0, false, sql_fns::now)?;
     c.create_scalar_function("generate_guid", 0, false, sql_fns::generate_guid)?;
+    c.create_scalar_function("is_frecency_decaying", 0, false, move |_| {
+        Ok(frecency_decaying.load(Ordering::SeqCst))
+    })?;
     Ok(())
 }
 
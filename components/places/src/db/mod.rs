@@ -7,6 +7,7 @@
 pub mod db;
 mod schema;
 mod tx;
+pub(crate) use self::schema::recalculate_origin_frecency_stats;
 pub use self::tx::PlacesTransaction;
 
 pub use crate::db::db::PlacesDb;
@@ -16,7 +16,7 @@ use crate::types::SyncStatus;
 use rusqlite::NO_PARAMS;
 use sql_support::ConnExt;
 
-const VERSION: i64 = 9;
+const VERSION: i64 = 14;
 
 // Shared schema and temp tables for the read-write and Sync connections.
 const CREATE_SHARED_SCHEMA_SQL: &str = include_str!("../../sql/create_shared_schema.sql");
@@ -36,6 +36,14 @@ lazy_static::lazy_static! {
             include_str!("../../sql/create_shared_triggers.sql"),
             increase_frecency_stats = update_origin_frecency_stats("+"),
             decrease_frecency_stats = update_origin_frecency_stats("-"),
+            note_bookmark_inserted = note_bookmark_change("NEW.guid", "insertedToken"),
+            note_bookmark_updated = note_bookmark_change("NEW.guid", "updatedToken"),
+            note_bookmark_moved = note_bookmark_change("NEW.guid", "movedToken"),
+            note_bookmark_deleted = note_bookmark_change("OLD.guid", "deletedToken"),
+            note_parent_updated = note_bookmark_change(
+                "(SELECT guid FROM moz_bookmarks WHERE id = NEW.parent)",
+                "updatedToken",
+            ),
         )
     };
 }
@@ -74,6 +82,45 @@ fn update_origin_frecency_stats(op: &str) -> String {
     )
 }
 
+/// Recalculates the origin frecency stats from scratch. The triggers keep
+/// these up to date as origins change, but decaying frecencies changes every
+/// origin at once, without running them.
+pub(crate) fn recalculate_origin_frecency_stats(db: &PlacesDb) -> Result<()> {
+    db.execute_batch(&format!(
+        "INSERT OR REPLACE INTO moz_meta(key, value)
+         SELECT '{frecency_count}', COUNT(*)
+         FROM moz_origins WHERE frecency > 0
+         UNION
+         SELECT '{frecency_sum}', IFNULL(SUM(frecency), 0)
+         FROM moz_origins WHERE frecency > 0
+         UNION
+         SELECT '{frecency_sum_of_squares}', IFNULL(SUM(frecency * frecency), 0)
+         FROM moz_origins WHERE frecency > 0",
+        frecency_count = MOZ_META_KEY_ORIGIN_FRECENCY_COUNT,
+        frecency_sum = MOZ_META_KEY_ORIGIN_FRECENCY_SUM,
+        frecency_sum_of_squares = MOZ_META_KEY_ORIGIN_FRECENCY_SUM_OF_SQUARES,
+    ))?;
+    Ok(())
+}
+
+/// Records a change to the bookmark with `guid`, an SQL expression like
+/// `NEW.guid`, in `moz_bookmarks_changes`, with the next change token.
+fn note_bookmark_change(guid: &str, token_column: &str) -> String {
+    format!(
+        "
+        INSERT INTO moz_bookmarks_changes(guid, {token_column}, changeToken)
+        SELECT {guid}, token, token
+        FROM (SELECT IFNULL(MAX(changeToken), 0) + 1 AS token
+              FROM moz_bookmarks_changes)
+        WHERE {guid} IS NOT NULL
+        ON CONFLICT(guid) DO UPDATE SET
+            {token_column} = excluded.changeToken,
+            changeToken = excluded.changeToken",
+        guid = guid,
+        token_column = token_column,
+    )
+}
+
 fn get_current_schema_version(db: &PlacesDb) -> Result<i64> {
     Ok(db.query_one::<i64>("PRAGMA user_version")?)
 }
@@ -219,6 +266,36 @@ fn upgrade(db: &PlacesDb, from: i64) -> Result<()> {
         ],
         || Ok(()),
     )?;
+    migration(
+        db,
+        9,
+        10,
+        &[
+            // Add the full-text index, and populate it from moz_places.
+            CREATE_SHARED_SCHEMA_SQL,
+            "INSERT INTO moz_places_fts(moz_places_fts) VALUES('rebuild')",
+        ],
+        || Ok(()),
+    )?;
+    migration(db, 10, 11, &[], || {
+        // Keywords used to only be stored in the mirror. Databases older
+        // than v4 already have the column, because moz_bookmarks was
+        // recreated when they were upgraded to v4.
+        let has_keyword = db.query_one::<bool>(
+            "SELECT EXISTS(SELECT 1 FROM pragma_table_info('moz_bookmarks')
+                               WHERE name = 'keyword')",
+        )?;
+        if !has_keyword {
+            db.execute_batch("ALTER TABLE moz_bookmarks ADD COLUMN keyword TEXT")?;
+        }
+        Ok(())
+    })?;
+    // Add the favicon tables.
+    migration(db, 11, 12, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?;
+    // Add the bookmark change tracking table.
+    migration(db, 12, 13, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?;
+    // Add the reading list tables.
+    migration(db, 13, 14, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?;
     // Add more migrations here...
 
     if get_current_schema_version(db)? == VERSION {
@@ -626,7 +703,7 @@ mod tests {
             ),
             NO_PARAMS,
         )
-        .expect("should insert regular bookmark folder");;
+        .expect("should insert regular bookmark folder");
         conn.execute(
             "DELETE FROM moz_bookmarks WHERE guid = 'bookmarkguid'",
             NO_PARAMS,
@@ -659,7 +736,7 @@ mod tests {
                         (3, 1, 0, 1, 1, 'bookmarkguid')",
             NO_PARAMS,
         )
-        .expect("should insert regular bookmark folder");;
+        .expect("should insert regular bookmark folder");
         // tombstone should have vanished.
         assert_eq!(
             select_simple_int(&conn, "SELECT COUNT(*) from moz_bookmarks_deleted"),
@@ -686,7 +763,7 @@ mod tests {
                         (3, 1, 0, 1, 1, 'fake_guid___')",
             NO_PARAMS,
         )
-        .expect("should insert regular bookmark folder");;
+        .expect("should insert regular bookmark folder");
         // tombstone should remain.
         assert_eq!(
             select_simple_int(&conn, "SELECT COUNT(*) from moz_bookmarks_deleted"),
@@ -77,6 +77,24 @@ pub enum ErrorKind {
 
     #[fail(display = "Database version {} is not supported", _0)]
     UnsupportedDatabaseVersion(i64),
+
+    #[fail(display = "Invalid bookmarks backup: {}", _0)]
+    InvalidBookmarksBackup(&'static str),
+
+    // Like URLs, `place:` queries can include private info, like tags, so
+    // only the name of the problem parameter is included.
+    #[fail(display = "Invalid place: query: {}", _0)]
+    InvalidPlaceQuery(String),
+
+    #[fail(display = "Invalid frecency settings: {}", _0)]
+    InvalidFrecencySettings(&'static str),
+
+    #[fail(display = "Removing adaptive history entries needs a URL or query string")]
+    NoAdaptiveEntriesFilter,
+
+    // Like `NoSuchGuid`, this includes the item's GUID, which isn't private.
+    #[fail(display = "Can't undo or redo, since {} has changed", _0)]
+    UndoConflict(String),
 }
 
 error_support::define_error! {
@@ -138,6 +156,10 @@ pub enum InvalidPlaceInfo {
 
     #[fail(display = "Cannot update the bookmark root {:?}", _0)]
     CannotUpdateRoot(BookmarkRootGuid),
+
+    // Returned when merging bookmarks which aren't all for the same URL.
+    #[fail(display = "Bookmark {} isn't a duplicate", _0)]
+    NotADuplicate(String),
 }
 
 // Error types used when we can't continue due to corruption.
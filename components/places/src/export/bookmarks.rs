@@ -0,0 +1,474 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Exports bookmarks in the formats desktop Firefox uses: the Netscape
+//! `bookmarks.html` format, which most browsers can import, and desktop's
+//! JSON backups, which may be compressed with mozlz4. Both include tags,
+//! keywords and dates. `crate::import::bookmarks_backup` reads them back.
+
+use super::mozlz4;
+use crate::db::PlacesDb;
+use crate::error::{Corruption, Result};
+use crate::storage::bookmarks::{
+    fetch_tree, BookmarkRootGuid, BookmarkTreeNode, FetchDepth, FolderNode,
+};
+use crate::types::{BookmarkType, Timestamp};
+use serde_derive::*;
+use sql_support::ConnExt;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use sync_guid::Guid as SyncGuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookmarksBackupFormat {
+    /// The Netscape bookmarks file format, as written by desktop's "Export
+    /// Bookmarks to HTML".
+    Html,
+    /// Desktop's JSON backup format.
+    Json,
+    /// Desktop's JSON backup format, compressed with mozlz4. This is what
+    /// desktop writes for its automatic backups.
+    JsonLz4,
+}
+
+impl BookmarksBackupFormat {
+    /// Picks the format based on a file's extension: `.html` or `.htm`,
+    /// `.json`, or `.jsonlz4`.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "html" | "htm" => Some(BookmarksBackupFormat::Html),
+            "json" => Some(BookmarksBackupFormat::Json),
+            "jsonlz4" => Some(BookmarksBackupFormat::JsonLz4),
+            _ => None,
+        }
+    }
+
+    /// Works out the format of a backup from its contents.
+    pub(crate) fn sniff(data: &[u8]) -> Self {
+        if mozlz4::is_mozlz4(data) {
+            BookmarksBackupFormat::JsonLz4
+        } else if data
+            .iter()
+            .find(|b| !b.is_ascii_whitespace())
+            .map_or(false, |b| *b == b'{')
+        {
+            BookmarksBackupFormat::Json
+        } else {
+            BookmarksBackupFormat::Html
+        }
+    }
+}
+
+/// An item in desktop's JSON backup format. Desktop writes (and we ignore)
+/// a few more fields, like `id`, `iconuri` and `charset`. Dates are in
+/// microseconds.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub(crate) struct DesktopItem {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guid: Option<SyncGuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub index: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_added: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<u64>,
+    pub type_code: u8,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_name: Option<String>,
+    /// Set for the roots, to one of the names in `DESKTOP_ROOT_NAMES`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    /// A comma-separated list.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyword: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<DesktopItem>,
+}
+
+impl DesktopItem {
+    pub(crate) fn bookmark_type(&self) -> BookmarkType {
+        // Very old backups only have the `type` string.
+        let type_code = match self.type_name.as_ref().map(String::as_str) {
+            _ if self.type_code != 0 => self.type_code,
+            Some(PLACE_CONTAINER_TYPE) => BookmarkType::Folder as u8,
+            Some(PLACE_SEPARATOR_TYPE) => BookmarkType::Separator as u8,
+            _ => BookmarkType::Bookmark as u8,
+        };
+        BookmarkType::from_u8_with_valid_url(type_code, || self.uri.is_some())
+    }
+}
+
+const PLACE_TYPE: &str = "text/x-moz-place";
+const PLACE_CONTAINER_TYPE: &str = "text/x-moz-place-container";
+const PLACE_SEPARATOR_TYPE: &str = "text/x-moz-place-separator";
+
+/// The names desktop uses for its roots in JSON backups.
+pub(crate) const DESKTOP_ROOT_NAMES: &[(BookmarkRootGuid, &str)] = &[
+    (BookmarkRootGuid::Root, "placesRoot"),
+    (BookmarkRootGuid::Menu, "bookmarksMenuFolder"),
+    (BookmarkRootGuid::Toolbar, "toolbarFolder"),
+    (BookmarkRootGuid::Unfiled, "unfiledBookmarksFolder"),
+    (BookmarkRootGuid::Mobile, "mobileFolder"),
+];
+
+/// The attributes which mark a `bookmarks.html` folder as one of the roots,
+/// and the titles desktop gives those folders. The menu isn't here because
+/// its children are written at the top level.
+pub(crate) const HTML_ROOT_FOLDERS: &[(BookmarkRootGuid, &str, &str)] = &[
+    (
+        BookmarkRootGuid::Toolbar,
+        "PERSONAL_TOOLBAR_FOLDER",
+        "Bookmarks Toolbar",
+    ),
+    (
+        BookmarkRootGuid::Unfiled,
+        "UNFILED_BOOKMARKS_FOLDER",
+        "Other Bookmarks",
+    ),
+    (
+        BookmarkRootGuid::Mobile,
+        "MOBILE_BOOKMARKS_FOLDER",
+        "Mobile Bookmarks",
+    ),
+];
+
+/// Exports all bookmarks to `path`, in the given format.
+pub fn export_bookmarks(
+    db: &PlacesDb,
+    path: impl AsRef<Path>,
+    format: BookmarksBackupFormat,
+) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_bookmarks(db, &mut writer, format)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Like `export_bookmarks`, but writes to `writer` instead of a file.
+pub fn write_bookmarks(
+    db: &PlacesDb,
+    writer: &mut impl Write,
+    format: BookmarksBackupFormat,
+) -> Result<()> {
+    let snapshot = BookmarksSnapshot::fetch(db)?;
+    match format {
+        BookmarksBackupFormat::Html => snapshot.write_html(writer)?,
+        BookmarksBackupFormat::Json => {
+            serde_json::to_writer(writer, &snapshot.to_desktop_item(&snapshot.root, 0))?
+        }
+        BookmarksBackupFormat::JsonLz4 => {
+            let json = serde_json::to_vec(&snapshot.to_desktop_item(&snapshot.root, 0))?;
+            writer.write_all(&mozlz4::compress(&json))?;
+        }
+    }
+    Ok(())
+}
+
+const HTML_HEADER: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file.
+     It will be read and overwritten.
+     DO NOT EDIT! -->
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<meta http-equiv="Content-Security-Policy"
+      content="default-src 'self'; script-src 'none'; img-src data: *; object-src 'none'"></meta>
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks Menu</H1>
+
+<DL><p>
+"#;
+
+/// Escapes text for use in either an attribute value or an element.
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// `bookmarks.html` dates are in seconds.
+fn write_html_dates(
+    writer: &mut impl Write,
+    date_added: Option<Timestamp>,
+    last_modified: Option<Timestamp>,
+) -> Result<()> {
+    if let Some(date_added) = date_added {
+        write!(writer, " ADD_DATE=\"{}\"", date_added.as_millis() / 1000)?;
+    }
+    if let Some(last_modified) = last_modified {
+        write!(
+            writer,
+            " LAST_MODIFIED=\"{}\"",
+            last_modified.as_millis() / 1000
+        )?;
+    }
+    Ok(())
+}
+
+/// Everything we export - the tree, plus the keywords and tags which
+/// `fetch_tree` doesn't return.
+struct BookmarksSnapshot {
+    root: BookmarkTreeNode,
+    keywords: HashMap<SyncGuid, String>,
+    tags: HashMap<String, Vec<String>>,
+}
+
+impl BookmarksSnapshot {
+    fn fetch(db: &PlacesDb) -> Result<Self> {
+        let root = match fetch_tree(db, BookmarkRootGuid::Root.guid(), &FetchDepth::Deepest)? {
+            Some((root, _, _)) => root,
+            None => return Err(Corruption::InvalidLocalRoots.into()),
+        };
+        let keywords = db
+            .query_rows_and_then_named(
+                "SELECT guid, keyword FROM moz_bookmarks WHERE keyword NOT NULL",
+                &[],
+                |row| -> Result<_> { Ok((row.get("guid")?, row.get("keyword")?)) },
+            )?
+            .into_iter()
+            .collect();
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        let tagged = db.query_rows_and_then_named(
+            "SELECT h.url, t.tag
+             FROM moz_tags_relation r
+             JOIN moz_tags t ON t.id = r.tag_id
+             JOIN moz_places h ON h.id = r.place_id
+             ORDER BY t.tag",
+            &[],
+            |row| -> Result<(String, String)> { Ok((row.get("url")?, row.get("tag")?)) },
+        )?;
+        for (url, tag) in tagged {
+            tags.entry(url).or_default().push(tag);
+        }
+        Ok(Self {
+            root,
+            keywords,
+            tags,
+        })
+    }
+
+    fn root_folder(&self, root: BookmarkRootGuid) -> Option<&FolderNode> {
+        let children = match &self.root {
+            BookmarkTreeNode::Folder(f) => &f.children,
+            _ => return None,
+        };
+        children.iter().find_map(|child| match child {
+            BookmarkTreeNode::Folder(f) if root == child.guid() => Some(f),
+            _ => None,
+        })
+    }
+
+    fn tags_for(&self, url: &str) -> Option<String> {
+        self.tags.get(url).map(|tags| tags.join(","))
+    }
+
+    fn to_desktop_item(&self, node: &BookmarkTreeNode, index: u32) -> DesktopItem {
+        let (date_added, last_modified) = node.created_modified();
+        let mut item = DesktopItem {
+            guid: Some(node.guid().clone()),
+            index,
+            date_added: Some(date_added.as_millis() * 1000),
+            last_modified: Some(last_modified.as_millis() * 1000),
+            type_code: node.node_type() as u8,
+            ..DesktopItem::default()
+        };
+        match node {
+            BookmarkTreeNode::Bookmark(b) => {
+                item.type_name = Some(PLACE_TYPE.into());
+                item.title = b.title.clone();
+                item.uri = Some(b.url.as_str().into());
+                item.tags = self.tags_for(b.url.as_str());
+                item.keyword = self.keywords.get(node.guid()).cloned();
+            }
+            BookmarkTreeNode::Separator(_) => {
+                item.type_name = Some(PLACE_SEPARATOR_TYPE.into());
+            }
+            BookmarkTreeNode::Folder(f) => {
+                item.type_name = Some(PLACE_CONTAINER_TYPE.into());
+                item.title = Some(f.title.clone().unwrap_or_default());
+                item.root = DESKTOP_ROOT_NAMES
+                    .iter()
+                    .find(|(root, _)| root == node.guid())
+                    .map(|(_, name)| (*name).to_string());
+                item.children = f
+                    .children
+                    .iter()
+                    .enumerate()
+                    .map(|(i, child)| self.to_desktop_item(child, i as u32))
+                    .collect();
+            }
+        }
+        item
+    }
+
+    fn write_html(&self, writer: &mut impl Write) -> Result<()> {
+        writer.write_all(HTML_HEADER.as_bytes())?;
+        // Like desktop, the menu's children are at the top level, followed
+        // by folders for the other roots, if they aren't empty.
+        if let Some(menu) = self.root_folder(BookmarkRootGuid::Menu) {
+            for child in &menu.children {
+                self.write_html_node(writer, child, 1)?;
+            }
+        }
+        for (root, attribute, title) in HTML_ROOT_FOLDERS {
+            if let Some(folder) = self.root_folder(*root) {
+                if !folder.children.is_empty() {
+                    self.write_html_folder(writer, folder, 1, Some((attribute, title)))?;
+                }
+            }
+        }
+        writer.write_all(b"</DL>\n")?;
+        Ok(())
+    }
+
+    fn write_html_node(
+        &self,
+        writer: &mut impl Write,
+        node: &BookmarkTreeNode,
+        depth: usize,
+    ) -> Result<()> {
+        let indent = "    ".repeat(depth);
+        match node {
+            BookmarkTreeNode::Bookmark(b) => {
+                write!(
+                    writer,
+                    "{}<DT><A HREF=\"{}\"",
+                    indent,
+                    escape_html(b.url.as_str())
+                )?;
+                write_html_dates(writer, b.date_added, b.last_modified)?;
+                if let Some(keyword) = self.keywords.get(node.guid()) {
+                    write!(writer, " SHORTCUTURL=\"{}\"", escape_html(keyword))?;
+                }
+                if let Some(tags) = self.tags_for(b.url.as_str()) {
+                    write!(writer, " TAGS=\"{}\"", escape_html(&tags))?;
+                }
+                let title = b.title.as_ref().map_or("", String::as_str);
+                writeln!(writer, ">{}</A>", escape_html(title))?;
+            }
+            BookmarkTreeNode::Separator(_) => writeln!(writer, "{}<HR>", indent)?,
+            BookmarkTreeNode::Folder(f) => self.write_html_folder(writer, f, depth, None)?,
+        }
+        Ok(())
+    }
+
+    /// Writes a folder. `root` is the attribute and title to use if the
+    /// folder is one of the roots.
+    fn write_html_folder(
+        &self,
+        writer: &mut impl Write,
+        folder: &FolderNode,
+        depth: usize,
+        root: Option<(&str, &str)>,
+    ) -> Result<()> {
+        let indent = "    ".repeat(depth);
+        write!(writer, "{}<DT><H3", indent)?;
+        write_html_dates(writer, folder.date_added, folder.last_modified)?;
+        let title = match root {
+            Some((attribute, title)) => {
+                write!(writer, " {}=\"true\"", attribute)?;
+                title
+            }
+            None => folder.title.as_ref().map_or("", String::as_str),
+        };
+        writeln!(writer, ">{}</H3>", escape_html(title))?;
+        writeln!(writer, "{}<DL><p>", indent)?;
+        for child in &folder.children {
+            self.write_html_node(writer, child, depth + 1)?;
+        }
+        writeln!(writer, "{}</DL><p>", indent)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::{
+        insert_bookmark, BookmarkPosition, InsertableBookmark, InsertableItem,
+    };
+    use url::Url;
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(
+            BookmarksBackupFormat::from_path("/sdcard/bookmarks.HTML"),
+            Some(BookmarksBackupFormat::Html)
+        );
+        assert_eq!(
+            BookmarksBackupFormat::from_path("bookmarks-2019-12-01.json"),
+            Some(BookmarksBackupFormat::Json)
+        );
+        assert_eq!(
+            BookmarksBackupFormat::from_path("bookmarks-2019-12-01_5_abc.jsonlz4"),
+            Some(BookmarksBackupFormat::JsonLz4)
+        );
+        assert_eq!(BookmarksBackupFormat::from_path("places.sqlite"), None);
+        assert_eq!(BookmarksBackupFormat::from_path("bookmarks"), None);
+    }
+
+    #[test]
+    fn test_write_html() -> Result<()> {
+        let db = new_mem_connection();
+        insert_bookmark(
+            &db,
+            &InsertableItem::Bookmark(InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Toolbar.into(),
+                position: BookmarkPosition::Append,
+                date_added: Some(Timestamp(1_500_000_000_999)),
+                last_modified: Some(Timestamp(1_500_000_001_000)),
+                guid: None,
+                url: Url::parse("https://example.com/?a=1&b=2")?,
+                title: Some("<Example>".into()),
+                keyword: None,
+                tags: Vec::new(),
+            }),
+        )?;
+        let mut html = Vec::new();
+        write_bookmarks(&db, &mut html, BookmarksBackupFormat::Html)?;
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.starts_with(HTML_HEADER));
+        let body = html[HTML_HEADER.len()..]
+            .lines()
+            .map(|line| {
+                // The dates of the roots change.
+                match line.find(" ADD_DATE") {
+                    Some(i) if line.contains("<H3") => {
+                        format!("{}{}", &line[..i], &line[line.find(" PERSONAL").unwrap()..])
+                    }
+                    _ => line.to_string(),
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            body,
+            vec![
+                "    <DT><H3 PERSONAL_TOOLBAR_FOLDER=\"true\">Bookmarks Toolbar</H3>",
+                "    <DL><p>",
+                "        <DT><A HREF=\"https://example.com/?a=1&amp;b=2\" ADD_DATE=\"1500000000\" \
+                 LAST_MODIFIED=\"1500000001\">&lt;Example&gt;</A>",
+                "    </DL><p>",
+                "</DL>",
+            ]
+        );
+        Ok(())
+    }
+}
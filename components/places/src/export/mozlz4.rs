@@ -0,0 +1,253 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Desktop's "mozlz4" format, used for `.jsonlz4` bookmark backups: an 8 byte
+//! magic number, the decompressed size as a little-endian u32, and then a
+//! single LZ4 block.
+//!
+//! There's no LZ4 crate we can use, but the block format is simple enough
+//! that we implement it here. The compressor is a basic greedy one - backups
+//! are small, so we don't need anything smarter.
+
+use crate::error::{ErrorKind, Result};
+use std::convert::TryInto;
+
+const MAGIC: &[u8] = b"mozLz40\0";
+const HEADER_LEN: usize = 12;
+
+// From the LZ4 block format description.
+const MIN_MATCH: usize = 4;
+// The last match must start at least this many bytes before the end...
+const MF_LIMIT: usize = 12;
+// ...and the last 5 bytes are always literals.
+const LAST_LITERALS: usize = 5;
+const MAX_OFFSET: usize = 0xFFFF;
+// Each input byte decompresses to at most about 255 output bytes, so a header
+// claiming more than this is bogus, and we shouldn't allocate for it.
+const MAX_RATIO: usize = 255;
+
+const HASH_LOG: u32 = 14;
+
+pub(crate) fn is_mozlz4(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < HEADER_LEN || !is_mozlz4(data) {
+        return Err(ErrorKind::InvalidBookmarksBackup("missing mozlz4 header").into());
+    }
+    let size = u32::from_le_bytes(data[8..HEADER_LEN].try_into().unwrap()) as usize;
+    if size > (data.len() - HEADER_LEN).saturating_mul(MAX_RATIO) {
+        return Err(ErrorKind::InvalidBookmarksBackup("wrong mozlz4 size").into());
+    }
+    let output = decompress_block(&data[HEADER_LEN..], size)?;
+    if output.len() != size {
+        return Err(ErrorKind::InvalidBookmarksBackup("wrong mozlz4 size").into());
+    }
+    Ok(output)
+}
+
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(HEADER_LEN + data.len() / 2);
+    output.extend_from_slice(MAGIC);
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    compress_block(data, &mut output);
+    output
+}
+
+fn truncated() -> crate::Error {
+    ErrorKind::InvalidBookmarksBackup("truncated lz4 block").into()
+}
+
+fn too_long() -> crate::Error {
+    ErrorKind::InvalidBookmarksBackup("wrong mozlz4 size").into()
+}
+
+fn read_length(input: &[u8], pos: &mut usize, initial: usize) -> Result<usize> {
+    let mut len = initial;
+    if initial == 15 {
+        loop {
+            let b = *input.get(*pos).ok_or_else(truncated)?;
+            *pos += 1;
+            len += b as usize;
+            if b != 255 {
+                break;
+            }
+        }
+    }
+    Ok(len)
+}
+
+fn decompress_block(input: &[u8], size: usize) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(size);
+    let mut pos = 0;
+    while pos < input.len() {
+        let token = input[pos];
+        pos += 1;
+
+        let literals = read_length(input, &mut pos, (token >> 4) as usize)?;
+        let literals_end = pos.checked_add(literals).ok_or_else(truncated)?;
+        if output.len() + literals > size {
+            return Err(too_long());
+        }
+        output.extend_from_slice(input.get(pos..literals_end).ok_or_else(truncated)?);
+        pos = literals_end;
+        if pos == input.len() {
+            // The last sequence only has literals.
+            break;
+        }
+
+        let offset = input
+            .get(pos..pos + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(truncated)?;
+        pos += 2;
+        if offset == 0 || offset > output.len() {
+            return Err(ErrorKind::InvalidBookmarksBackup("bad lz4 match offset").into());
+        }
+        let match_len = read_length(input, &mut pos, (token & 0xF) as usize)? + MIN_MATCH;
+        if output.len() + match_len > size {
+            return Err(too_long());
+        }
+        // Matches can overlap the bytes they produce, so copy one at a time.
+        let start = output.len() - offset;
+        for i in 0..match_len {
+            let b = output[start + i];
+            output.push(b);
+        }
+    }
+    Ok(output)
+}
+
+fn write_length(output: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        output.push(255);
+        len -= 255;
+    }
+    output.push(len as u8);
+}
+
+fn write_sequence(output: &mut Vec<u8>, literals: &[u8], m: Option<(usize, usize)>) {
+    let match_len = m.map_or(0, |(_, len)| len - MIN_MATCH);
+    let token = ((literals.len().min(15) as u8) << 4) | match_len.min(15) as u8;
+    output.push(token);
+    if literals.len() >= 15 {
+        write_length(output, literals.len() - 15);
+    }
+    output.extend_from_slice(literals);
+    if let Some((offset, _)) = m {
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= 15 {
+            write_length(output, match_len - 15);
+        }
+    }
+}
+
+fn hash(seq: u32) -> usize {
+    (seq.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn compress_block(input: &[u8], output: &mut Vec<u8>) {
+    // Positions are stored off by one, so that 0 means "empty".
+    let mut table = vec![0usize; 1 << HASH_LOG];
+    let mut anchor = 0;
+    let mut pos = 0;
+    let match_limit = input.len().saturating_sub(MF_LIMIT);
+    let match_end_limit = input.len().saturating_sub(LAST_LITERALS);
+    while pos < match_limit {
+        let seq = read_u32(input, pos);
+        let slot = hash(seq);
+        let candidate = table[slot];
+        table[slot] = pos + 1;
+        if candidate > 0 {
+            let candidate = candidate - 1;
+            if pos - candidate <= MAX_OFFSET && read_u32(input, candidate) == seq {
+                let mut len = MIN_MATCH;
+                while pos + len < match_end_limit && input[candidate + len] == input[pos + len] {
+                    len += 1;
+                }
+                write_sequence(output, &input[anchor..pos], Some((pos - candidate, len)));
+                pos += len;
+                anchor = pos;
+                continue;
+            }
+        }
+        pos += 1;
+    }
+    write_sequence(output, &input[anchor..], None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let long = "{\"title\":\"Bookmarks\",\"children\":[]}".repeat(500);
+        let mut noisy = Vec::new();
+        let mut x = 1u32;
+        for _ in 0..5000 {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            noisy.push((x >> 16) as u8 % 4);
+        }
+        let inputs: Vec<&[u8]> = vec![
+            b"",
+            b"a",
+            b"abcdefghijklmnop",
+            &[b'x'; 1000],
+            long.as_bytes(),
+            &noisy,
+        ];
+        for input in inputs {
+            let compressed = compress(input);
+            assert!(is_mozlz4(&compressed));
+            assert_eq!(decompress(&compressed).expect("should decompress"), input);
+        }
+        assert!(compress(long.as_bytes()).len() < long.len() / 10);
+    }
+
+    #[test]
+    fn test_decompress_known_block() {
+        // A hand-assembled block for "hello hello hello hello!": 6 literals
+        // and a 13 byte match at offset 6, then 5 final literals.
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&24u32.to_le_bytes());
+        data.extend_from_slice(b"\x69\x68\x65\x6c\x6c\x6f\x20\x06\x00");
+        data.push(0x50);
+        data.extend_from_slice(b"ello!");
+        assert_eq!(
+            decompress(&data).expect("should decompress"),
+            b"hello hello hello hello!".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        assert!(decompress(b"").is_err());
+        assert!(decompress(b"{\"guid\": \"root________\"}").is_err());
+        // An offset pointing before the start of the output.
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&8u32.to_le_bytes());
+        data.extend_from_slice(b"\x10a\x05\x00");
+        assert!(decompress(&data).is_err());
+        // A length which doesn't match the header.
+        let mut data = compress(b"some bookmarks");
+        data[8] = 100;
+        assert!(decompress(&data).is_err());
+        // A header which is cut off.
+        assert!(decompress(&compress(b"some bookmarks")[..10]).is_err());
+        // A header claiming far more output than the block could produce.
+        let mut data = compress(b"some bookmarks");
+        data[8..HEADER_LEN].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decompress(&data).is_err());
+        // A header claiming less output than the block produces.
+        let mut data = compress(&[b'x'; 1000]);
+        data[8..HEADER_LEN].copy_from_slice(&10u32.to_le_bytes());
+        assert!(decompress(&data).is_err());
+    }
+}
@@ -141,9 +141,37 @@ impl From<Error> for ExternError {
 
 implement_into_ffi_by_json!(SearchResult);
 implement_into_ffi_by_protobuf!(msg_types::HistoryVisitInfos);
+implement_into_ffi_by_protobuf!(msg_types::TopFrecentSiteInfos);
 implement_into_ffi_by_protobuf!(msg_types::BookmarkNode);
 implement_into_ffi_by_protobuf!(msg_types::BookmarkNodeList);
+implement_into_ffi_by_protobuf!(msg_types::ImportReport);
+implement_into_ffi_by_protobuf!(msg_types::FennecBookmarksImport);
+implement_into_ffi_by_protobuf!(msg_types::FaviconInfo);
+implement_into_ffi_by_protobuf!(msg_types::BookmarkChanges);
+implement_into_ffi_by_protobuf!(msg_types::ReadingListItem);
+implement_into_ffi_by_protobuf!(msg_types::ReadingListItems);
+implement_into_ffi_by_protobuf!(msg_types::FrecencySettings);
+implement_into_ffi_by_protobuf!(msg_types::QueryResults);
 implement_into_ffi_by_delegation!(
     crate::storage::bookmarks::PublicNode,
     msg_types::BookmarkNode
 );
+implement_into_ffi_by_delegation!(crate::import::ImportReport, msg_types::ImportReport);
+implement_into_ffi_by_delegation!(crate::storage::favicons::Favicon, msg_types::FaviconInfo);
+implement_into_ffi_by_delegation!(
+    crate::storage::bookmarks::BookmarkChanges,
+    msg_types::BookmarkChanges
+);
+implement_into_ffi_by_delegation!(
+    crate::storage::reading_list::ReadingListItem,
+    msg_types::ReadingListItem
+);
+implement_into_ffi_by_delegation!(
+    crate::frecency::FrecencySettings,
+    msg_types::FrecencySettings
+);
+implement_into_ffi_by_delegation!(crate::query::QueryResults, msg_types::QueryResults);
+implement_into_ffi_by_delegation!(
+    crate::import::FennecBookmarksImport,
+    msg_types::FennecBookmarksImport
+);
@@ -3,8 +3,14 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::error::*;
+use crate::msg_types;
 use crate::types::VisitTransition;
 use rusqlite::Connection;
+use serde_derive::*;
+
+/// The `moz_meta` key for custom frecency settings. If this isn't set, we use
+/// `DEFAULT_FRECENCY_SETTINGS`.
+pub(crate) const FRECENCY_SETTINGS_META_KEY: &str = "frecency_settings";
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum RedirectBonus {
@@ -13,7 +19,8 @@ enum RedirectBonus {
     Normal,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
 pub struct FrecencySettings {
     // TODO: These probably should not all be i32s...
     pub num_visits: i32,                     // from "places.frecency.numVisits"
@@ -39,6 +46,10 @@ pub struct FrecencySettings {
     pub unvisited_bookmark_bonus: i32,       // from "places.frecency.unvisitedBookmarkBonus"
     pub unvisited_typed_bonus: i32,          // from "places.frecency.unvisitedTypedBonus"
     pub reload_visit_bonus: i32,             // from "places.frecency.reloadVisitBonus"
+    /// How much to multiply frecencies by each day, so that pages which
+    /// haven't been visited in a while rank lower. Based on
+    /// "places.frecency.decayRate"; 1.0 turns decay off.
+    pub decay_rate: f64,
 }
 
 pub const DEFAULT_FRECENCY_SETTINGS: FrecencySettings = FrecencySettings {
@@ -66,6 +77,7 @@ pub const DEFAULT_FRECENCY_SETTINGS: FrecencySettings = FrecencySettings {
     unvisited_bookmark_bonus: 140,
     unvisited_typed_bonus: 200,
     reload_visit_bonus: 0,
+    decay_rate: 0.975,
 };
 
 impl Default for FrecencySettings {
@@ -75,7 +87,142 @@ impl Default for FrecencySettings {
     }
 }
 
+impl From<FrecencySettings> for msg_types::FrecencySettings {
+    fn from(settings: FrecencySettings) -> Self {
+        msg_types::FrecencySettings {
+            num_visits: Some(settings.num_visits),
+            first_bucket_cutoff_days: Some(settings.first_bucket_cutoff_days),
+            second_bucket_cutoff_days: Some(settings.second_bucket_cutoff_days),
+            third_bucket_cutoff_days: Some(settings.third_bucket_cutoff_days),
+            fourth_bucket_cutoff_days: Some(settings.fourth_bucket_cutoff_days),
+            first_bucket_weight: Some(settings.first_bucket_weight),
+            second_bucket_weight: Some(settings.second_bucket_weight),
+            third_bucket_weight: Some(settings.third_bucket_weight),
+            fourth_bucket_weight: Some(settings.fourth_bucket_weight),
+            default_bucket_weight: Some(settings.default_bucket_weight),
+            embed_visit_bonus: Some(settings.embed_visit_bonus),
+            framed_link_visit_bonus: Some(settings.framed_link_visit_bonus),
+            link_visit_bonus: Some(settings.link_visit_bonus),
+            typed_visit_bonus: Some(settings.typed_visit_bonus),
+            bookmark_visit_bonus: Some(settings.bookmark_visit_bonus),
+            download_visit_bonus: Some(settings.download_visit_bonus),
+            permanent_redirect_visit_bonus: Some(settings.permanent_redirect_visit_bonus),
+            temporary_redirect_visit_bonus: Some(settings.temporary_redirect_visit_bonus),
+            redirect_source_visit_bonus: Some(settings.redirect_source_visit_bonus),
+            default_visit_bonus: Some(settings.default_visit_bonus),
+            unvisited_bookmark_bonus: Some(settings.unvisited_bookmark_bonus),
+            unvisited_typed_bonus: Some(settings.unvisited_typed_bonus),
+            reload_visit_bonus: Some(settings.reload_visit_bonus),
+            decay_rate: Some(settings.decay_rate),
+        }
+    }
+}
+
 impl FrecencySettings {
+    /// Settings which aren't in the message keep their default values.
+    pub fn from_message(msg: msg_types::FrecencySettings) -> Self {
+        let defaults = DEFAULT_FRECENCY_SETTINGS;
+        Self {
+            num_visits: msg.num_visits.unwrap_or(defaults.num_visits),
+            first_bucket_cutoff_days: msg
+                .first_bucket_cutoff_days
+                .unwrap_or(defaults.first_bucket_cutoff_days),
+            second_bucket_cutoff_days: msg
+                .second_bucket_cutoff_days
+                .unwrap_or(defaults.second_bucket_cutoff_days),
+            third_bucket_cutoff_days: msg
+                .third_bucket_cutoff_days
+                .unwrap_or(defaults.third_bucket_cutoff_days),
+            fourth_bucket_cutoff_days: msg
+                .fourth_bucket_cutoff_days
+                .unwrap_or(defaults.fourth_bucket_cutoff_days),
+            first_bucket_weight: msg
+                .first_bucket_weight
+                .unwrap_or(defaults.first_bucket_weight),
+            second_bucket_weight: msg
+                .second_bucket_weight
+                .unwrap_or(defaults.second_bucket_weight),
+            third_bucket_weight: msg
+                .third_bucket_weight
+                .unwrap_or(defaults.third_bucket_weight),
+            fourth_bucket_weight: msg
+                .fourth_bucket_weight
+                .unwrap_or(defaults.fourth_bucket_weight),
+            default_bucket_weight: msg
+                .default_bucket_weight
+                .unwrap_or(defaults.default_bucket_weight),
+            embed_visit_bonus: msg.embed_visit_bonus.unwrap_or(defaults.embed_visit_bonus),
+            framed_link_visit_bonus: msg
+                .framed_link_visit_bonus
+                .unwrap_or(defaults.framed_link_visit_bonus),
+            link_visit_bonus: msg.link_visit_bonus.unwrap_or(defaults.link_visit_bonus),
+            typed_visit_bonus: msg.typed_visit_bonus.unwrap_or(defaults.typed_visit_bonus),
+            bookmark_visit_bonus: msg
+                .bookmark_visit_bonus
+                .unwrap_or(defaults.bookmark_visit_bonus),
+            download_visit_bonus: msg
+                .download_visit_bonus
+                .unwrap_or(defaults.download_visit_bonus),
+            permanent_redirect_visit_bonus: msg
+                .permanent_redirect_visit_bonus
+                .unwrap_or(defaults.permanent_redirect_visit_bonus),
+            temporary_redirect_visit_bonus: msg
+                .temporary_redirect_visit_bonus
+                .unwrap_or(defaults.temporary_redirect_visit_bonus),
+            redirect_source_visit_bonus: msg
+                .redirect_source_visit_bonus
+                .unwrap_or(defaults.redirect_source_visit_bonus),
+            default_visit_bonus: msg
+                .default_visit_bonus
+                .unwrap_or(defaults.default_visit_bonus),
+            unvisited_bookmark_bonus: msg
+                .unvisited_bookmark_bonus
+                .unwrap_or(defaults.unvisited_bookmark_bonus),
+            unvisited_typed_bonus: msg
+                .unvisited_typed_bonus
+                .unwrap_or(defaults.unvisited_typed_bonus),
+            reload_visit_bonus: msg
+                .reload_visit_bonus
+                .unwrap_or(defaults.reload_visit_bonus),
+            decay_rate: msg.decay_rate.unwrap_or(defaults.decay_rate),
+        }
+    }
+
+    /// Returns an error if these settings would make frecencies meaningless:
+    /// if they don't sample any visits, if the buckets are negative or out of
+    /// order, or if they'd decay frecencies to nothing, or grow them forever.
+    pub fn validate(&self) -> Result<()> {
+        if self.num_visits <= 0 {
+            return Err(ErrorKind::InvalidFrecencySettings("num_visits must be positive").into());
+        }
+        if self.first_bucket_cutoff_days < 0 {
+            return Err(ErrorKind::InvalidFrecencySettings("Cutoffs can't be negative").into());
+        }
+        if self.first_bucket_cutoff_days > self.second_bucket_cutoff_days
+            || self.second_bucket_cutoff_days > self.third_bucket_cutoff_days
+            || self.third_bucket_cutoff_days > self.fourth_bucket_cutoff_days
+        {
+            return Err(ErrorKind::InvalidFrecencySettings("Cutoffs must be in order").into());
+        }
+        if [
+            self.first_bucket_weight,
+            self.second_bucket_weight,
+            self.third_bucket_weight,
+            self.fourth_bucket_weight,
+            self.default_bucket_weight,
+        ]
+        .iter()
+        .any(|&weight| weight < 0)
+        {
+            return Err(ErrorKind::InvalidFrecencySettings("Weights can't be negative").into());
+        }
+        // This also rejects NaN.
+        if !(self.decay_rate > 0.0 && self.decay_rate <= 1.0) {
+            return Err(ErrorKind::InvalidFrecencySettings("decay_rate must be in (0, 1]").into());
+        }
+        Ok(())
+    }
+
     // Note: in Places, `redirect` defaults to false.
     pub fn get_transition_bonus(
         &self,
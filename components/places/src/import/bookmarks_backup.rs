@@ -0,0 +1,979 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Imports bookmarks from the formats written by `crate::export::bookmarks`,
+//! which are also the formats desktop Firefox uses for its backups. Most
+//! other browsers can export `bookmarks.html` too.
+//!
+//! Items are added to the end of the matching local roots, and nothing is
+//! removed, so this is a merge rather than a restore. The GUIDs in JSON
+//! backups are kept unless they're invalid or already used - which means
+//! importing the same backup twice gives you two copies, with different
+//! GUIDs.
+
+use crate::db::PlacesDb;
+use crate::error::Result;
+use crate::export::bookmarks::{
+    BookmarksBackupFormat, DesktopItem, DESKTOP_ROOT_NAMES, HTML_ROOT_FOLDERS,
+};
+use crate::export::mozlz4;
+use crate::storage::bookmarks::{
+    get_raw_bookmark, insert_bookmark_in_tx, notify_inserted, BookmarkPosition, BookmarkRootGuid,
+    InsertableBookmark, InsertableFolder, InsertableItem, InsertableSeparator,
+};
+use crate::storage::tags::{tag_url_in_tx, validate_tag};
+use crate::storage::{delete_pending_temp_tables, URL_LENGTH_MAX};
+use crate::types::{BookmarkType, Timestamp};
+use sql_support::ConnExt;
+use std::path::Path;
+use sync_guid::Guid as SyncGuid;
+use url::Url;
+
+/// Imports the bookmarks from a `bookmarks.html`, `.json` or `.jsonlz4`
+/// file. The format is worked out from the file's contents.
+pub fn import_bookmarks_backup(db: &PlacesDb, path: impl AsRef<Path>) -> Result<()> {
+    let data = std::fs::read(path)?;
+    import_bookmarks_backup_data(db, &data)
+}
+
+/// Like `import_bookmarks_backup`, but for a backup which has already been
+/// read into memory.
+pub fn import_bookmarks_backup_data(db: &PlacesDb, data: &[u8]) -> Result<()> {
+    let root = match BookmarksBackupFormat::sniff(data) {
+        BookmarksBackupFormat::Html => html::parse(&String::from_utf8_lossy(data)),
+        BookmarksBackupFormat::Json => serde_json::from_slice(data)?,
+        BookmarksBackupFormat::JsonLz4 => serde_json::from_slice(&mozlz4::decompress(data)?)?,
+    };
+    let tx = db.begin_transaction()?;
+    let mut importer = Importer {
+        db,
+        inserted: Vec::new(),
+        tagged: Vec::new(),
+    };
+    let result = importer.import(root);
+    delete_pending_temp_tables(db)?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result?;
+    log::info!(
+        "Imported {} bookmarks, folders and separators from a backup",
+        importer.inserted.len()
+    );
+    notify_inserted(db, &importer.inserted);
+    Ok(())
+}
+
+struct Importer<'a> {
+    db: &'a PlacesDb,
+    inserted: Vec<SyncGuid>,
+    // Tags from the separate "tags" root in old JSON backups are added once
+    // all the bookmarks are in.
+    tagged: Vec<(Url, String)>,
+}
+
+impl<'a> Importer<'a> {
+    fn import(&mut self, root: DesktopItem) -> Result<()> {
+        if root.root.as_ref().map(String::as_str) != Some("placesRoot") {
+            // Not a full backup, so import the whole thing into "Other
+            // Bookmarks".
+            self.insert_item(BookmarkRootGuid::Unfiled.guid(), root)?;
+        } else {
+            for child in root.children {
+                let name = child.root.as_ref().map(String::as_str);
+                if name == Some("tagsFolder") {
+                    self.collect_tags_root(child);
+                    continue;
+                }
+                let local_root = DESKTOP_ROOT_NAMES
+                    .iter()
+                    .find(|(_, n)| Some(*n) == name)
+                    .map(|(guid, _)| *guid)
+                    .or_else(|| child.guid.as_ref().and_then(BookmarkRootGuid::from_guid));
+                match local_root {
+                    Some(BookmarkRootGuid::Root) | None => {
+                        self.insert_item(BookmarkRootGuid::Unfiled.guid(), child)?
+                    }
+                    Some(local_root) => self.insert_children(local_root.guid(), child.children)?,
+                }
+            }
+        }
+        for (url, tag) in std::mem::replace(&mut self.tagged, Vec::new()) {
+            // Old backups can have tags for URLs which aren't bookmarked,
+            // which we don't support.
+            let place_exists = self
+                .db
+                .try_query_row(
+                    "SELECT 1 FROM moz_places WHERE url_hash = hash(:url) AND url = :url",
+                    &[(":url", &url.as_str())],
+                    |_| -> Result<_> { Ok(()) },
+                    true,
+                )?
+                .is_some();
+            if place_exists {
+                tag_url_in_tx(self.db, &url, &tag)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_children(
+        &mut self,
+        parent_guid: &SyncGuid,
+        children: Vec<DesktopItem>,
+    ) -> Result<()> {
+        for child in children {
+            self.insert_item(parent_guid, child)?;
+        }
+        Ok(())
+    }
+
+    /// A keyword can only point at one bookmark, and inserting a bookmark
+    /// with a keyword takes it away from any other bookmark which has it, so
+    /// we skip keywords which are already in use, rather than let an import
+    /// change the user's existing bookmarks.
+    fn keyword_in_use(&self, keyword: &str) -> Result<bool> {
+        Ok(self
+            .db
+            .try_query_row(
+                "SELECT 1 FROM moz_bookmarks WHERE keyword = :keyword",
+                &[(":keyword", &keyword)],
+                |_| -> Result<_> { Ok(()) },
+                true,
+            )?
+            .is_some())
+    }
+
+    fn insert_item(&mut self, parent_guid: &SyncGuid, item: DesktopItem) -> Result<()> {
+        let guid = match item.guid {
+            Some(ref guid)
+                if guid.is_valid_for_places()
+                    && BookmarkRootGuid::from_guid(guid).is_none()
+                    && get_raw_bookmark(self.db, guid)?.is_none() =>
+            {
+                Some(guid.clone())
+            }
+            _ => None,
+        };
+        let date_added = item.date_added.map(|d| Timestamp(d / 1000));
+        let last_modified = item.last_modified.map(|d| Timestamp(d / 1000));
+        let bookmark_type = item.bookmark_type();
+        let title = item.title.filter(|t| !t.is_empty());
+        let insertable: InsertableItem = match bookmark_type {
+            BookmarkType::Bookmark => {
+                let url = match item.uri.as_ref().map(|u| Url::parse(u)) {
+                    Some(Ok(url)) if url.as_str().len() <= URL_LENGTH_MAX => url,
+                    _ => {
+                        log::warn!("Skipping a bookmark with an invalid URL");
+                        return Ok(());
+                    }
+                };
+                // Invalid keywords and tags are dropped, rather than failing
+                // the whole import.
+                let keyword = match item
+                    .keyword
+                    .as_ref()
+                    .and_then(|k| validate_tag(k).ensure_valid().ok())
+                {
+                    Some(keyword) if self.keyword_in_use(keyword)? => {
+                        log::warn!("Skipping a keyword which another bookmark already has");
+                        None
+                    }
+                    keyword => keyword.map(str::to_string),
+                };
+                let mut tags = Vec::new();
+                for tag in item.tags.iter().flat_map(|tags| tags.split(',')) {
+                    if let Ok(tag) = validate_tag(tag).ensure_valid() {
+                        if !tags.iter().any(|t| t == tag) {
+                            tags.push(tag.to_string());
+                        }
+                    }
+                }
+                InsertableBookmark {
+                    parent_guid: parent_guid.clone(),
+                    position: BookmarkPosition::Append,
+                    date_added,
+                    last_modified,
+                    guid,
+                    url,
+                    title,
+                    keyword,
+                    tags,
+                }
+                .into()
+            }
+            BookmarkType::Separator => InsertableSeparator {
+                parent_guid: parent_guid.clone(),
+                position: BookmarkPosition::Append,
+                date_added,
+                last_modified,
+                guid,
+            }
+            .into(),
+            BookmarkType::Folder => InsertableFolder {
+                parent_guid: parent_guid.clone(),
+                position: BookmarkPosition::Append,
+                date_added,
+                last_modified,
+                guid,
+                title,
+            }
+            .into(),
+        };
+        let guid = insert_bookmark_in_tx(self.db, &insertable)?;
+        self.inserted.push(guid.clone());
+        if let InsertableItem::Folder(_) = insertable {
+            self.insert_children(&guid, item.children)?;
+        }
+        Ok(())
+    }
+
+    /// Old JSON backups have a root with a folder for each tag, containing
+    /// the tagged URLs.
+    fn collect_tags_root(&mut self, tags_root: DesktopItem) {
+        for tag_folder in tags_root.children {
+            let tag = match tag_folder
+                .title
+                .as_ref()
+                .and_then(|t| validate_tag(t).ensure_valid().ok())
+            {
+                Some(tag) => tag.to_string(),
+                None => continue,
+            };
+            for item in tag_folder.children {
+                if let Some(Ok(url)) = item.uri.as_ref().map(|u| Url::parse(u)) {
+                    self.tagged.push((url, tag.clone()));
+                }
+            }
+        }
+    }
+}
+
+/// A forgiving parser for `bookmarks.html` files. They're only loosely HTML,
+/// with tags that are rarely closed, and some browsers write very odd files.
+/// So we don't try to build a DOM, and just look at the tags we care about.
+mod html {
+    use super::*;
+
+    enum Token<'a> {
+        Start(String, Vec<(String, String)>),
+        End(String),
+        Text(&'a str),
+    }
+
+    struct Tokenizer<'a> {
+        input: &'a str,
+        pos: usize,
+    }
+
+    impl<'a> Tokenizer<'a> {
+        fn rest(&self) -> &'a str {
+            &self.input[self.pos..]
+        }
+
+        fn skip_past(&mut self, needle: &str) {
+            self.pos = match self.rest().find(needle) {
+                Some(i) => self.pos + i + needle.len(),
+                None => self.input.len(),
+            };
+        }
+
+        fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+            let rest = self.rest();
+            let len = rest.find(|c| !f(c)).unwrap_or(rest.len());
+            self.pos += len;
+            &rest[..len]
+        }
+
+        fn parse_tag(&mut self) -> Option<Token<'a>> {
+            // We're just past the '<'.
+            let is_end = self.rest().starts_with('/');
+            if is_end {
+                self.pos += 1;
+            }
+            let name = self
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .to_ascii_uppercase();
+            let mut attributes = Vec::new();
+            loop {
+                self.take_while(|c| c.is_whitespace() || c == '/');
+                if self.rest().is_empty() {
+                    break;
+                }
+                if self.rest().starts_with('>') {
+                    self.pos += 1;
+                    break;
+                }
+                let attr_name = self
+                    .take_while(|c| !c.is_whitespace() && c != '=' && c != '>' && c != '/')
+                    .to_ascii_uppercase();
+                self.take_while(char::is_whitespace);
+                let mut value = "";
+                if self.rest().starts_with('=') {
+                    self.pos += 1;
+                    self.take_while(char::is_whitespace);
+                    value = match self.rest().chars().next() {
+                        Some(quote) if quote == '"' || quote == '\'' => {
+                            self.pos += 1;
+                            let value = self.take_while(|c| c != quote);
+                            self.pos = (self.pos + 1).min(self.input.len());
+                            value
+                        }
+                        _ => self.take_while(|c| !c.is_whitespace() && c != '>'),
+                    };
+                }
+                if !attr_name.is_empty() {
+                    attributes.push((attr_name, decode_entities(value)));
+                }
+            }
+            if name.is_empty() {
+                None
+            } else if is_end {
+                Some(Token::End(name))
+            } else {
+                Some(Token::Start(name, attributes))
+            }
+        }
+    }
+
+    impl<'a> Iterator for Tokenizer<'a> {
+        type Item = Token<'a>;
+
+        fn next(&mut self) -> Option<Token<'a>> {
+            while self.pos < self.input.len() {
+                let rest = self.rest();
+                if !rest.starts_with('<') {
+                    let len = rest.find('<').unwrap_or(rest.len());
+                    self.pos += len;
+                    return Some(Token::Text(&rest[..len]));
+                }
+                if rest.starts_with("<!--") {
+                    self.skip_past("-->");
+                } else if rest.starts_with("<!") || rest.starts_with("<?") {
+                    self.skip_past(">");
+                } else {
+                    self.pos += 1;
+                    if let Some(token) = self.parse_tag() {
+                        return Some(token);
+                    }
+                }
+            }
+            None
+        }
+    }
+
+    fn decode_entities(s: &str) -> String {
+        let mut decoded = String::with_capacity(s.len());
+        let mut rest = s;
+        while let Some(amp) = rest.find('&') {
+            decoded.push_str(&rest[..amp]);
+            rest = &rest[amp..];
+            let entity = rest[1..].find(';').map(|end| &rest[1..=end]);
+            let c = entity.and_then(|entity| match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                    u32::from_str_radix(&entity[2..], 16)
+                        .ok()
+                        .and_then(std::char::from_u32)
+                }
+                _ if entity.starts_with('#') => {
+                    entity[1..].parse().ok().and_then(std::char::from_u32)
+                }
+                _ => None,
+            });
+            match (c, entity) {
+                (Some(c), Some(entity)) => {
+                    decoded.push(c);
+                    rest = &rest[entity.len() + 2..];
+                }
+                _ => {
+                    decoded.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        decoded.push_str(rest);
+        decoded
+    }
+
+    fn attribute<'b>(attributes: &'b [(String, String)], name: &str) -> Option<&'b str> {
+        attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // `bookmarks.html` dates are in seconds, `DesktopItem`s are in
+    // microseconds.
+    fn date_attribute(attributes: &[(String, String)], name: &str) -> Option<u64> {
+        attribute(attributes, name)
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(|secs| secs.saturating_mul(1_000_000))
+    }
+
+    fn new_item(bookmark_type: BookmarkType, attributes: &[(String, String)]) -> DesktopItem {
+        DesktopItem {
+            type_code: bookmark_type as u8,
+            date_added: date_attribute(attributes, "ADD_DATE"),
+            last_modified: date_attribute(attributes, "LAST_MODIFIED"),
+            ..DesktopItem::default()
+        }
+    }
+
+    fn folder(root: Option<BookmarkRootGuid>) -> DesktopItem {
+        DesktopItem {
+            type_code: BookmarkType::Folder as u8,
+            root: root.and_then(|root| {
+                DESKTOP_ROOT_NAMES
+                    .iter()
+                    .find(|(guid, _)| *guid == root)
+                    .map(|(_, name)| (*name).to_string())
+            }),
+            ..DesktopItem::default()
+        }
+    }
+
+    struct Frame {
+        folder: DesktopItem,
+        root: Option<BookmarkRootGuid>,
+    }
+
+    #[derive(Default)]
+    struct Parser {
+        // The folders we're in. The first is the menu.
+        stack: Vec<Frame>,
+        // The `<A>` or `<H3>` we're reading the title of.
+        current: Option<(DesktopItem, Option<BookmarkRootGuid>)>,
+        // A folder whose `<H3>` we've seen, but not its `<DL>` yet.
+        pending_folder: Option<(DesktopItem, Option<BookmarkRootGuid>)>,
+        roots: Vec<(BookmarkRootGuid, Vec<DesktopItem>)>,
+    }
+
+    impl Parser {
+        fn push_item(&mut self, item: DesktopItem) {
+            if let Some(frame) = self.stack.last_mut() {
+                frame.folder.children.push(item);
+            }
+        }
+
+        // A folder without a list of children is just an empty folder.
+        fn flush_pending_folder(&mut self) {
+            if let Some((folder, root)) = self.pending_folder.take() {
+                match root {
+                    Some(root) => self.roots.push((root, Vec::new())),
+                    None => self.push_item(folder),
+                }
+            }
+        }
+
+        /// Finishes the `<A>` or `<H3>` we're reading, if any. Their end tags
+        /// are often missing, so this is also called for any tag which
+        /// can't be part of a title.
+        fn finish_current(&mut self) {
+            if let Some((mut item, root)) = self.current.take() {
+                item.title = item.title.map(|t| t.trim().to_string());
+                if item.type_code == BookmarkType::Folder as u8 {
+                    self.pending_folder = Some((item, root));
+                } else {
+                    self.push_item(item);
+                }
+            }
+        }
+
+        fn start(&mut self, name: &str, attributes: &[(String, String)]) {
+            if let "DT" | "DD" | "DL" | "A" | "H3" | "HR" = name {
+                self.finish_current();
+            }
+            match name {
+                "DL" => {
+                    let (folder, root) = match self.pending_folder.take() {
+                        Some(pending) => pending,
+                        None if self.stack.is_empty() => {
+                            (folder(None), Some(BookmarkRootGuid::Menu))
+                        }
+                        None => (folder(None), None),
+                    };
+                    self.stack.push(Frame { folder, root });
+                }
+                "A" => {
+                    self.flush_pending_folder();
+                    let mut item = new_item(BookmarkType::Bookmark, attributes);
+                    item.uri = attribute(attributes, "HREF").map(str::to_string);
+                    item.keyword = attribute(attributes, "SHORTCUTURL").map(str::to_string);
+                    item.tags = attribute(attributes, "TAGS").map(str::to_string);
+                    item.title = Some(String::new());
+                    self.current = Some((item, None));
+                }
+                "H3" => {
+                    self.flush_pending_folder();
+                    let mut item = new_item(BookmarkType::Folder, attributes);
+                    item.title = Some(String::new());
+                    let root = HTML_ROOT_FOLDERS
+                        .iter()
+                        .find(|(_, attr, _)| attribute(attributes, attr).is_some())
+                        .map(|(root, _, _)| *root);
+                    self.current = Some((item, root));
+                }
+                "HR" => {
+                    self.flush_pending_folder();
+                    self.push_item(new_item(BookmarkType::Separator, attributes));
+                }
+                _ => {}
+            }
+        }
+
+        fn end(&mut self, name: &str) {
+            match name {
+                "A" | "H3" => self.finish_current(),
+                "DL" => {
+                    self.finish_current();
+                    self.flush_pending_folder();
+                    if let Some(frame) = self.stack.pop() {
+                        match frame.root {
+                            Some(root) => self.roots.push((root, frame.folder.children)),
+                            None => self.push_item(frame.folder),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        fn text(&mut self, text: &str) {
+            if let Some((item, _)) = self.current.as_mut() {
+                if let Some(title) = item.title.as_mut() {
+                    title.push_str(&decode_entities(text));
+                }
+            }
+        }
+
+        fn finish(mut self) -> DesktopItem {
+            self.finish_current();
+            self.flush_pending_folder();
+            while !self.stack.is_empty() {
+                self.end("DL");
+            }
+            let mut root = folder(Some(BookmarkRootGuid::Root));
+            for (guid, _) in DESKTOP_ROOT_NAMES {
+                let mut children = Vec::new();
+                for (root, items) in &mut self.roots {
+                    if *root == *guid {
+                        children.append(items);
+                    }
+                }
+                if *guid != BookmarkRootGuid::Root && !children.is_empty() {
+                    let mut folder = folder(Some(*guid));
+                    folder.children = children;
+                    root.children.push(folder);
+                }
+            }
+            root
+        }
+    }
+
+    /// Parses a `bookmarks.html` file into a tree in desktop's JSON format.
+    pub(super) fn parse(input: &str) -> DesktopItem {
+        let mut parser = Parser::default();
+        for token in (Tokenizer { input, pos: 0 }) {
+            match token {
+                Token::Start(name, attributes) => parser.start(&name, &attributes),
+                Token::End(name) => parser.end(&name),
+                Token::Text(text) => parser.text(text),
+            }
+        }
+        parser.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::export::bookmarks::write_bookmarks;
+    use crate::storage::bookmarks::{
+        bookmarks_get_url_for_keyword, insert_tree, BookmarkNode, FolderNode, SeparatorNode,
+    };
+    use crate::storage::tags::{get_tags_for_url, tag_url};
+    use crate::tests::assert_json_tree;
+    use serde_json::{json, Value};
+
+    fn sorted_tags(db: &PlacesDb, url: &str) -> Result<Vec<String>> {
+        let mut tags = get_tags_for_url(db, &Url::parse(url)?)?;
+        tags.sort();
+        Ok(tags)
+    }
+
+    fn insert_test_bookmarks(db: &PlacesDb) -> Result<()> {
+        let when = |ms| Some(Timestamp(ms));
+        insert_tree(
+            db,
+            &FolderNode {
+                guid: Some(BookmarkRootGuid::Menu.into()),
+                children: vec![
+                    BookmarkNode {
+                        guid: Some("bookmarkAAAA".into()),
+                        date_added: when(1_500_000_000_123),
+                        last_modified: when(1_500_000_100_456),
+                        title: Some("A & <b>\"quoted\"</b>".into()),
+                        url: Url::parse("https://example.com/a?x=1&y=2")?,
+                    }
+                    .into(),
+                    FolderNode {
+                        guid: Some("folderBBBBBB".into()),
+                        date_added: when(1_400_000_000_000),
+                        last_modified: when(1_400_000_000_000),
+                        title: Some("Folder".into()),
+                        children: vec![
+                            BookmarkNode {
+                                guid: Some("bookmarkCCCC".into()),
+                                date_added: when(1_450_000_000_000),
+                                last_modified: when(1_450_000_000_000),
+                                title: None,
+                                url: Url::parse("https://example.com/c")?,
+                            }
+                            .into(),
+                            SeparatorNode {
+                                guid: Some("separatorDDD".into()),
+                                date_added: when(1_450_000_000_000),
+                                last_modified: when(1_450_000_000_000),
+                            }
+                            .into(),
+                        ],
+                    }
+                    .into(),
+                ],
+                ..Default::default()
+            },
+        )?;
+        for (root, guid, url) in &[
+            (
+                BookmarkRootGuid::Toolbar,
+                "bookmarkEEEE",
+                "https://example.org/e",
+            ),
+            (
+                BookmarkRootGuid::Mobile,
+                "bookmarkFFFF",
+                "https://example.net/f",
+            ),
+        ] {
+            insert_tree(
+                db,
+                &FolderNode {
+                    guid: Some(root.as_guid()),
+                    children: vec![BookmarkNode {
+                        guid: Some((*guid).into()),
+                        date_added: when(1_300_000_000_000),
+                        last_modified: when(1_300_000_000_000),
+                        title: Some((*guid).into()),
+                        url: Url::parse(url)?,
+                    }
+                    .into()],
+                    ..Default::default()
+                },
+            )?;
+        }
+        db.execute_named_cached(
+            "UPDATE moz_bookmarks SET keyword = 'ex' WHERE guid = 'bookmarkAAAA'",
+            &[],
+        )?;
+        tag_url(db, &Url::parse("https://example.com/a?x=1&y=2")?, "foo")?;
+        tag_url(db, &Url::parse("https://example.com/a?x=1&y=2")?, "bar")?;
+        tag_url(db, &Url::parse("https://example.com/c")?, "foo")?;
+        Ok(())
+    }
+
+    // Exports `db` as JSON, without the fields for the roots, which are
+    // never imported.
+    fn exported_roots(db: &PlacesDb) -> Result<Vec<Value>> {
+        let mut json = Vec::new();
+        write_bookmarks(db, &mut json, BookmarksBackupFormat::Json)?;
+        let mut root: Value = serde_json::from_slice(&json)?;
+        Ok(root["children"]
+            .as_array_mut()
+            .unwrap()
+            .drain(..)
+            .map(|local_root| json!([local_root["root"], local_root["children"]]))
+            .collect())
+    }
+
+    // `bookmarks.html` doesn't have GUIDs, and its dates are in seconds.
+    fn strip_guids_and_millis(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                map.remove("guid");
+                if map["typeCode"] == json!(3) {
+                    map.remove("dateAdded");
+                    map.remove("lastModified");
+                }
+                for key in &["dateAdded", "lastModified"] {
+                    if let Some(date) = map.get_mut(*key) {
+                        *date = json!(date.as_u64().unwrap() / 1_000_000);
+                    }
+                }
+                map.values_mut().for_each(strip_guids_and_millis);
+            }
+            Value::Array(values) => values.iter_mut().for_each(strip_guids_and_millis),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let _ = env_logger::try_init();
+        let source = new_mem_connection();
+        insert_test_bookmarks(&source)?;
+        let expected = exported_roots(&source)?;
+
+        for format in &[
+            BookmarksBackupFormat::Html,
+            BookmarksBackupFormat::Json,
+            BookmarksBackupFormat::JsonLz4,
+        ] {
+            let mut backup = Vec::new();
+            write_bookmarks(&source, &mut backup, *format)?;
+            assert_eq!(BookmarksBackupFormat::sniff(&backup), *format);
+
+            let db = new_mem_connection();
+            import_bookmarks_backup_data(&db, &backup)?;
+            let mut expected = expected.clone();
+            let mut imported = exported_roots(&db)?;
+            if *format == BookmarksBackupFormat::Html {
+                expected.iter_mut().for_each(strip_guids_and_millis);
+                imported.iter_mut().for_each(strip_guids_and_millis);
+            }
+            assert_eq!(imported, expected, "{:?}", format);
+
+            assert_eq!(
+                bookmarks_get_url_for_keyword(&db, "ex")?,
+                Some(Url::parse("https://example.com/a?x=1&y=2")?)
+            );
+            assert_eq!(
+                sorted_tags(&db, "https://example.com/a?x=1&y=2")?,
+                vec!["bar", "foo"]
+            );
+            assert_eq!(sorted_tags(&db, "https://example.com/c")?, vec!["foo"]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_twice() -> Result<()> {
+        let source = new_mem_connection();
+        insert_test_bookmarks(&source)?;
+        let mut backup = Vec::new();
+        write_bookmarks(&source, &mut backup, BookmarksBackupFormat::Json)?;
+
+        let db = new_mem_connection();
+        import_bookmarks_backup_data(&db, &backup)?;
+        import_bookmarks_backup_data(&db, &backup)?;
+        let guids = db.query_rows_and_then_named(
+            "SELECT guid FROM moz_bookmarks WHERE parent = (
+               SELECT id FROM moz_bookmarks WHERE guid = 'toolbar_____')
+             ORDER BY position",
+            &[],
+            |row| row.get::<_, SyncGuid>(0),
+        )?;
+        assert_eq!(guids.len(), 2);
+        assert_eq!(guids[0], "bookmarkEEEE");
+        assert_ne!(guids[1], "bookmarkEEEE");
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_html() -> Result<()> {
+        let _ = env_logger::try_init();
+        let db = new_mem_connection();
+        // Roughly what desktop writes, with some of the oddities found in
+        // files written by other browsers.
+        let html = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+            <!-- This is an automatically generated file. -->
+            <META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+            <TITLE>Bookmarks</TITLE>
+            <H1>Bookmarks Menu</H1>
+            <DL><p>
+                <DT><a href='https://example.com/' add_date="1500000000" SHORTCUTURL=ex>Example &amp; co &#8212; &#x263A;</a>
+                <DD>A description, which we ignore.
+                <DT><H3 ADD_DATE="1400000000" LAST_MODIFIED="1400000001">Empty</H3>
+                <DT><H3>Folder</H3>
+                <DL><p>
+                    <DT><A HREF="https://example.com/1" TAGS="one, two,,">One</A>
+                    <HR>
+                    <DT><A HREF="not a url">Invalid</A>
+                    <DT><H3>Subfolder</H3>
+                    <DL><p>
+                        <DT><A HREF="https://example.com/2">Two
+                </DL><p>
+                <DT><H3 PERSONAL_TOOLBAR_FOLDER="true">Bookmarks Toolbar</H3>
+                <DL><p>
+                    <DT><A HREF="https://example.org/">Toolbar</A>
+                </DL><p>
+            </DL><p>"#;
+        import_bookmarks_backup_data(&db, html.as_bytes())?;
+
+        assert_json_tree(
+            &db,
+            BookmarkRootGuid::Menu.guid(),
+            json!({
+                "guid": BookmarkRootGuid::Menu.as_guid(),
+                "children": [
+                    {
+                        "title": "Example & co \u{2014} \u{263a}",
+                        "url": "https://example.com/",
+                    },
+                    {"title": "Empty", "children": []},
+                    {
+                        "title": "Folder",
+                        "children": [
+                            {"title": "One", "url": "https://example.com/1"},
+                            {"type": 3},
+                            {
+                                "title": "Subfolder",
+                                "children": [
+                                    {"title": "Two", "url": "https://example.com/2"},
+                                ],
+                            },
+                        ],
+                    },
+                ],
+            }),
+        );
+        assert_json_tree(
+            &db,
+            BookmarkRootGuid::Toolbar.guid(),
+            json!({
+                "guid": BookmarkRootGuid::Toolbar.as_guid(),
+                "children": [{"title": "Toolbar", "url": "https://example.org/"}],
+            }),
+        );
+        assert_eq!(
+            bookmarks_get_url_for_keyword(&db, "ex")?,
+            Some(Url::parse("https://example.com/")?)
+        );
+        assert_eq!(
+            sorted_tags(&db, "https://example.com/1")?,
+            vec!["one", "two"]
+        );
+        let date_added = db.query_row_and_then_named(
+            "SELECT dateAdded FROM moz_bookmarks WHERE title = 'Empty'",
+            &[],
+            |row| row.get::<_, Timestamp>(0),
+            false,
+        )?;
+        assert_eq!(date_added, Timestamp(1_400_000_000_000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_legacy_json() -> Result<()> {
+        let db = new_mem_connection();
+        let existing = crate::storage::bookmarks::insert_bookmark(
+            &db,
+            &InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Mobile.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("https://example.com/existing")?,
+                title: None,
+                keyword: Some("a".into()),
+                tags: Vec::new(),
+            }
+            .into(),
+        )?;
+        // Old desktop backups put tags in their own root, and some items
+        // only have a `type`.
+        let backup = json!({
+            "guid": "root________",
+            "title": "",
+            "root": "placesRoot",
+            "typeCode": 2,
+            "children": [
+                {
+                    "guid": "unfiled_____",
+                    "root": "unfiledBookmarksFolder",
+                    "type": "text/x-moz-place-container",
+                    "children": [
+                        {
+                            "guid": "bookmarkAAAA",
+                            "title": "A",
+                            "type": "text/x-moz-place",
+                            "uri": "https://example.com/a",
+                            "keyword": "a",
+                        },
+                        {
+                            "title": "B",
+                            "type": "text/x-moz-place",
+                            "uri": "https://example.com/b",
+                            "keyword": "   ",
+                            "tags": "b, ,b",
+                        },
+                        {"type": "text/x-moz-place-separator"},
+                        {
+                            "guid": "root________",
+                            "title": "Not really a root",
+                            "type": "text/x-moz-place-container",
+                        },
+                    ],
+                },
+                {
+                    "guid": "tags________",
+                    "root": "tagsFolder",
+                    "typeCode": 2,
+                    "children": [
+                        {
+                            "title": "tagged",
+                            "typeCode": 2,
+                            "children": [
+                                {"typeCode": 1, "uri": "https://example.com/a"},
+                                {"typeCode": 1, "uri": "https://example.com/unbookmarked"},
+                            ],
+                        },
+                    ],
+                },
+            ],
+        });
+        import_bookmarks_backup_data(&db, backup.to_string().as_bytes())?;
+        assert_json_tree(
+            &db,
+            BookmarkRootGuid::Unfiled.guid(),
+            json!({
+                "guid": BookmarkRootGuid::Unfiled.as_guid(),
+                "children": [
+                    {"guid": "bookmarkAAAA", "title": "A", "url": "https://example.com/a"},
+                    {"title": "B", "url": "https://example.com/b"},
+                    {"type": 3},
+                    {"title": "Not really a root", "children": []},
+                ],
+            }),
+        );
+        assert_eq!(sorted_tags(&db, "https://example.com/a")?, vec!["tagged"]);
+        assert_eq!(sorted_tags(&db, "https://example.com/b")?, vec!["b"]);
+        // Imported keywords which existing bookmarks already have are
+        // skipped, and invalid ones are ignored.
+        assert_eq!(
+            bookmarks_get_url_for_keyword(&db, "a")?,
+            Some(Url::parse("https://example.com/existing")?)
+        );
+        let existing = get_raw_bookmark(&db, &existing)?.expect("should exist");
+        assert_eq!(existing.keyword, Some("a".into()));
+        let imported = get_raw_bookmark(&db, &"bookmarkAAAA".into())?.expect("should exist");
+        assert_eq!(imported.keyword, None);
+        let keywords =
+            db.query_one::<i64>("SELECT COUNT(*) FROM moz_bookmarks WHERE keyword IS NOT NULL")?;
+        assert_eq!(keywords, 1);
+        assert_eq!(
+            sorted_tags(&db, "https://example.com/unbookmarked")?,
+            Vec::<String>::new()
+        );
+        Ok(())
+    }
+}
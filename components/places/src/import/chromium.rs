@@ -0,0 +1,16 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Importers for Chrome and Chromium profiles, for people switching to us
+//! from those browsers. History lives in the profile's `History` SQLite
+//! database, which we attach, and bookmarks in its `Bookmarks` JSON file.
+
+mod bookmarks;
+mod history;
+pub use bookmarks::import as import_bookmarks;
+pub use history::import as import_history;
+
+// Chromium stores times as microseconds since 1601-01-01 (the Windows epoch).
+// This is the number of milliseconds between that and the Unix epoch.
+const WINDOWS_EPOCH_OFFSET_MS: i64 = 11_644_473_600_000;
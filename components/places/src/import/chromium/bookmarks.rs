@@ -0,0 +1,205 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::WINDOWS_EPOCH_OFFSET_MS;
+use crate::api::places_api::PlacesApi;
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::import::common::{ImportPhase, ImportReport};
+use crate::storage::bookmarks::{
+    insert_bookmark_in_tx, notify_inserted, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+    InsertableFolder, InsertableItem,
+};
+use crate::storage::{delete_pending_temp_tables, URL_LENGTH_MAX};
+use crate::types::Timestamp;
+use serde_derive::*;
+use std::convert::TryFrom;
+use std::path::Path;
+use std::time::Instant;
+use sync_guid::Guid as SyncGuid;
+use url::Url;
+
+/// The parts of Chromium's `Bookmarks` file we care about. The `checksum`
+/// and the ids and GUIDs of the nodes are ignored - the GUIDs aren't valid
+/// for us anyway, so everything gets a new one.
+#[derive(Debug, Deserialize)]
+struct ChromiumBookmarks {
+    roots: ChromiumRoots,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChromiumRoots {
+    bookmark_bar: Option<ChromiumNode>,
+    other: Option<ChromiumNode>,
+    synced: Option<ChromiumNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChromiumNode {
+    #[serde(rename = "type")]
+    node_type: String,
+    #[serde(default)]
+    name: String,
+    url: Option<String>,
+    // Times are strings holding microseconds since 1601-01-01.
+    date_added: Option<String>,
+    date_modified: Option<String>,
+    #[serde(default)]
+    children: Vec<ChromiumNode>,
+}
+
+pub fn import(places_api: &PlacesApi, path: impl AsRef<Path>) -> Result<ImportReport> {
+    let mut report = ImportReport::default();
+    let bookmarks = report.time_phase("parse", || -> Result<ChromiumBookmarks> {
+        let data = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&data)?)
+    })?;
+
+    let conn = places_api.open_sync_connection()?;
+    let scope = conn.begin_interrupt_scope();
+
+    let tx = conn.begin_transaction()?;
+    let mut importer = Importer {
+        db: &conn,
+        inserted: Vec::new(),
+        report,
+    };
+    // The bookmarks bar is the toolbar, "Other bookmarks" is our unfiled
+    // root, and "Mobile bookmarks" is our mobile root. We don't create the
+    // roots themselves, just append their children to ours.
+    let roots = bookmarks.roots;
+    let start = Instant::now();
+    let result = [
+        (roots.bookmark_bar, BookmarkRootGuid::Toolbar),
+        (roots.other, BookmarkRootGuid::Unfiled),
+        (roots.synced, BookmarkRootGuid::Mobile),
+    ]
+    .iter()
+    .filter_map(|(root, guid)| root.as_ref().map(|root| (root, guid)))
+    .try_for_each(|(root, guid)| {
+        scope.err_if_interrupted()?;
+        importer.insert_children(guid.guid(), &root.children)
+    });
+    importer.report.phases.push(ImportPhase {
+        name: "insert",
+        duration: start.elapsed(),
+    });
+    delete_pending_temp_tables(&conn)?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result?;
+
+    notify_inserted(&conn, &importer.inserted);
+
+    let mut report = importer.report;
+    report.rows_imported = importer.inserted.len() as u32;
+    log::info!(
+        "Successfully imported {} of {} bookmarks!",
+        report.rows_imported,
+        report.rows_seen
+    );
+    Ok(report)
+}
+
+struct Importer<'a> {
+    db: &'a PlacesDb,
+    inserted: Vec<SyncGuid>,
+    report: ImportReport,
+}
+
+impl<'a> Importer<'a> {
+    fn insert_children(&mut self, parent_guid: &SyncGuid, children: &[ChromiumNode]) -> Result<()> {
+        for child in children {
+            self.insert_node(parent_guid, child)?;
+        }
+        Ok(())
+    }
+
+    fn insert_node(&mut self, parent_guid: &SyncGuid, node: &ChromiumNode) -> Result<()> {
+        self.report.rows_seen += 1;
+        let date_added = node.date_added.as_ref().and_then(|t| to_timestamp(t));
+        if date_added.is_none() {
+            self.report.invalid_timestamps += 1;
+        }
+        let last_modified = node.date_modified.as_ref().and_then(|t| to_timestamp(t));
+        let title = if node.name.is_empty() {
+            None
+        } else {
+            Some(node.name.clone())
+        };
+        let insertable: InsertableItem = match node.node_type.as_str() {
+            "url" => {
+                let url = match node.url.as_ref().map(|u| Url::parse(u)) {
+                    Some(Ok(url)) if url.as_str().len() <= URL_LENGTH_MAX => url,
+                    _ => {
+                        log::warn!("Skipping a Chromium bookmark with an invalid URL");
+                        self.report.skipped_invalid_url += 1;
+                        return Ok(());
+                    }
+                };
+                InsertableBookmark {
+                    parent_guid: parent_guid.clone(),
+                    position: BookmarkPosition::Append,
+                    date_added,
+                    last_modified,
+                    guid: None,
+                    url,
+                    title,
+                    keyword: None,
+                    tags: Vec::new(),
+                }
+                .into()
+            }
+            "folder" => InsertableFolder {
+                parent_guid: parent_guid.clone(),
+                position: BookmarkPosition::Append,
+                date_added,
+                last_modified,
+                guid: None,
+                title,
+            }
+            .into(),
+            other => {
+                log::warn!("Skipping a Chromium bookmark node of type {:?}", other);
+                self.report.skipped_unsupported += 1;
+                return Ok(());
+            }
+        };
+        let guid = insert_bookmark_in_tx(self.db, &insertable)?;
+        self.inserted.push(guid.clone());
+        self.insert_children(&guid, &node.children)
+    }
+}
+
+/// Converts a Chromium time to a timestamp, returning `None` for times which
+/// are missing (Chromium uses "0") or don't make sense, so that we use the
+/// current time instead.
+fn to_timestamp(time: &str) -> Option<Timestamp> {
+    let ms = time.parse::<i64>().ok()? / 1000 - WINDOWS_EPOCH_OFFSET_MS;
+    let ts = Timestamp(u64::try_from(ms).ok()?);
+    if Timestamp::EARLIEST < ts && ts < Timestamp::now() {
+        Some(ts)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_timestamp() {
+        // 2019-09-05T12:00:00Z.
+        assert_eq!(
+            to_timestamp("13212158400000000"),
+            Some(Timestamp(1_567_684_800_000))
+        );
+        assert_eq!(to_timestamp("0"), None);
+        assert_eq!(to_timestamp(""), None);
+        assert_eq!(to_timestamp("99999999999999999"), None);
+    }
+}
@@ -0,0 +1,218 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::WINDOWS_EPOCH_OFFSET_MS;
+use crate::api::places_api::PlacesApi;
+use crate::bookmark_sync::store::BookmarksStore;
+use crate::error::*;
+use crate::import::common::{attached_database, ImportReport};
+use crate::types::Timestamp;
+use rusqlite::{Connection, NO_PARAMS};
+use sql_support::ConnExt;
+use url::Url;
+
+// The columns of `urls` and `visits` we read have been around since well
+// before this version, but anything older isn't worth supporting.
+const MIN_CHROMIUM_DB_VERSION: i64 = 20;
+
+pub fn import(places_api: &PlacesApi, path: impl AsRef<std::path::Path>) -> Result<ImportReport> {
+    let url = crate::util::ensure_url_path(path)?;
+    do_import(places_api, url)
+}
+
+fn do_import(places_api: &PlacesApi, chromium_db_file_url: Url) -> Result<ImportReport> {
+    let conn = places_api.open_sync_connection()?;
+
+    let scope = conn.begin_interrupt_scope();
+
+    define_sql_functions(&conn)?;
+
+    let mut report = ImportReport::default();
+
+    log::trace!("Attaching database {}", chromium_db_file_url);
+    let auto_detach = report.time_phase("attach", || {
+        attached_database(&conn, &chromium_db_file_url, "chromium")
+    })?;
+
+    let db_version = conn
+        .try_query_row(
+            "SELECT CAST(value AS INTEGER) FROM chromium.meta WHERE key = 'version'",
+            &[],
+            |row| row.get::<_, i64>(0),
+            false,
+        )?
+        .unwrap_or(0);
+    if db_version < MIN_CHROMIUM_DB_VERSION {
+        return Err(ErrorKind::UnsupportedDatabaseVersion(db_version).into());
+    }
+
+    let tx = conn.begin_transaction()?;
+
+    log::debug!("Counting history visits");
+    let (seen, invalid_url, unsupported, invalid_timestamps) =
+        report.time_phase("count", || {
+            conn.query_row_and_then_named(
+                &COUNT_VISITS,
+                &[],
+                |row| -> Result<_> { Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)) },
+                false,
+            )
+        })?;
+    report.rows_seen = seen;
+    report.skipped_invalid_url = invalid_url;
+    report.skipped_unsupported = unsupported;
+    report.invalid_timestamps = invalid_timestamps;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Populating missing entries in moz_places");
+    report.time_phase("fill_moz_places", || conn.execute_batch(&FILL_MOZ_PLACES))?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Inserting the history visits");
+    report.rows_imported = report.time_phase("insert_history_visits", || {
+        conn.execute(&INSERT_HISTORY_VISITS, NO_PARAMS)
+    })? as u32;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Marking frecencies as stale");
+    conn.execute_named(&MARK_STALE_FRECENCIES, &[(":now", &Timestamp::now())])?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Committing...");
+    tx.commit()?;
+
+    // Note: update_frecencies manages its own transaction, which is fine,
+    // since nothing that bad will happen if it is aborted.
+    log::debug!("Updating frecencies");
+    let store = BookmarksStore::new(&conn, &scope);
+    report.time_phase("update_frecencies", || store.update_frecencies())?;
+
+    log::info!(
+        "Successfully imported {} of {} history visits!",
+        report.rows_imported,
+        report.rows_seen
+    );
+
+    auto_detach.execute_now()?;
+
+    Ok(report)
+}
+
+// AUTO_SUBFRAME visits are for frames the user didn't navigate, so aren't
+// worth keeping. Neither are URLs which only have those.
+const IMPORTED_VISITS: &str = "v.transition & 0xFF <> 3";
+
+lazy_static::lazy_static! {
+    // Counts the visits we'll look at, and what's wrong with them.
+    static ref COUNT_VISITS: String = format!(
+        "SELECT
+            COUNT(*),
+            IFNULL(SUM(NOT is_valid_url(u.url)), 0),
+            IFNULL(SUM(is_valid_url(u.url) AND NOT ({imported_visits})), 0),
+            IFNULL(SUM(is_valid_url(u.url) AND {imported_visits}
+                       AND NOT is_valid_timestamp(v.visit_time / 1000 - {offset})), 0)
+         FROM chromium.visits v
+         LEFT JOIN chromium.urls u ON u.id = v.url",
+        imported_visits = IMPORTED_VISITS,
+        offset = WINDOWS_EPOCH_OFFSET_MS,
+    );
+
+    // Insert any missing entries into moz_places that we'll need for this.
+    static ref FILL_MOZ_PLACES: String = format!(
+        "INSERT OR IGNORE INTO main.moz_places(guid, url, url_hash, title, hidden, typed, frecency, sync_change_counter)
+            SELECT
+                IFNULL(
+                    (SELECT p.guid FROM main.moz_places p WHERE p.url_hash = hash(u.url) AND p.url = u.url),
+                    generate_guid()
+                ),
+                u.url,
+                hash(u.url),
+                u.title,
+                u.hidden,
+                u.typed_count,
+                -1,
+                1
+            FROM chromium.urls u
+            WHERE is_valid_url(u.url)
+              AND EXISTS(SELECT 1 FROM chromium.visits v WHERE v.url = u.id AND {})",
+        IMPORTED_VISITS
+    );
+
+    // Insert history visits, skipping ones we already have so that importing
+    // the same profile twice doesn't double them up.
+    static ref INSERT_HISTORY_VISITS: String = format!(
+        "INSERT INTO main.moz_historyvisits(from_visit, place_id, visit_date, visit_type, is_local)
+            SELECT NULL, n.place_id, n.visit_date, n.visit_type, 1
+            FROM (
+                SELECT
+                    (SELECT p.id FROM main.moz_places p WHERE p.url_hash = hash(u.url) AND p.url = u.url) AS place_id,
+                    sanitize_timestamp(v.visit_time / 1000 - {offset}) AS visit_date,
+                    -- See `ui::PageTransition` in Chromium. The low byte is
+                    -- the core type, and the rest are qualifiers.
+                    CASE
+                        -- SERVER_REDIRECT. We can't tell permanent from
+                        -- temporary. Chromium stores transitions as signed
+                        -- 32 bit ints, but the sign extension keeps this bit.
+                        WHEN v.transition & 0x80000000 THEN 6
+                        ELSE CASE v.transition & 0xFF
+                            WHEN 1 THEN 2  -- TYPED
+                            WHEN 2 THEN 3  -- AUTO_BOOKMARK
+                            WHEN 4 THEN 8  -- MANUAL_SUBFRAME
+                            WHEN 5 THEN 2  -- GENERATED, picked from URL bar suggestions.
+                            WHEN 8 THEN 9  -- RELOAD
+                            WHEN 9 THEN 2  -- KEYWORD
+                            WHEN 10 THEN 2 -- KEYWORD_GENERATED
+                            ELSE 1         -- LINK, FORM_SUBMIT and AUTO_TOPLEVEL.
+                        END
+                    END AS visit_type
+                FROM chromium.visits v
+                JOIN chromium.urls u ON u.id = v.url
+                WHERE is_valid_url(u.url) AND {imported_visits}
+            ) n
+            WHERE NOT EXISTS(SELECT 1 FROM main.moz_historyvisits e
+                             WHERE e.place_id = n.place_id AND e.visit_date = n.visit_date)",
+        offset = WINDOWS_EPOCH_OFFSET_MS,
+        imported_visits = IMPORTED_VISITS
+    );
+
+    // So that `update_frecencies` picks up the places we added visits to.
+    static ref MARK_STALE_FRECENCIES: String = format!(
+        "INSERT OR REPLACE INTO main.moz_places_stale_frecencies(place_id, stale_at)
+            SELECT p.id, :now
+            FROM chromium.urls u
+            JOIN main.moz_places p ON p.url_hash = hash(u.url) AND p.url = u.url
+            WHERE is_valid_url(u.url)
+              AND EXISTS(SELECT 1 FROM chromium.visits v WHERE v.url = u.id AND {})",
+        IMPORTED_VISITS
+    );
+}
+
+fn define_sql_functions(c: &Connection) -> Result<()> {
+    c.create_scalar_function(
+        "is_valid_url",
+        1,
+        true,
+        crate::import::common::sql_fns::is_valid_url,
+    )?;
+    c.create_scalar_function(
+        "sanitize_timestamp",
+        1,
+        true,
+        crate::import::common::sql_fns::sanitize_timestamp,
+    )?;
+    c.create_scalar_function(
+        "is_valid_timestamp",
+        1,
+        false,
+        crate::import::common::sql_fns::is_valid_timestamp,
+    )?;
+    c.create_scalar_function("hash", -1, true, crate::db::db::sql_fns::hash)?;
+    c.create_scalar_function(
+        "generate_guid",
+        0,
+        false,
+        crate::db::db::sql_fns::generate_guid,
+    )?;
+    Ok(())
+}
@@ -4,7 +4,9 @@
 
 use crate::api::places_api::SyncConn;
 use crate::error::*;
+use crate::msg_types;
 use rusqlite::named_params;
+use std::time::{Duration, Instant};
 use url::Url;
 
 pub mod sql_fns {
@@ -14,22 +16,33 @@ pub mod sql_fns {
     use std::convert::TryFrom;
     use url::Url;
 
-    #[inline(never)]
-    pub fn sanitize_timestamp(ctx: &Context<'_>) -> Result<Timestamp> {
-        let now = Timestamp::now();
+    fn sane_timestamp(ctx: &Context<'_>, now: Timestamp) -> Option<Timestamp> {
         let is_sane = |ts: Timestamp| -> bool { Timestamp::EARLIEST < ts && ts < now };
         if let Ok(ts) = ctx.get::<i64>(0) {
             let ts = Timestamp(u64::try_from(ts).unwrap_or(0));
             if is_sane(ts) {
-                return Ok(ts);
+                return Some(ts);
             }
             // Maybe the timestamp was actually in μs?
             let ts = Timestamp(ts.as_millis() / 1000);
             if is_sane(ts) {
-                return Ok(ts);
+                return Some(ts);
             }
         }
-        Ok(now)
+        None
+    }
+
+    #[inline(never)]
+    pub fn sanitize_timestamp(ctx: &Context<'_>) -> Result<Timestamp> {
+        let now = Timestamp::now();
+        Ok(sane_timestamp(ctx, now).unwrap_or(now))
+    }
+
+    /// Returns false for timestamps which `sanitize_timestamp` would replace
+    /// with the current time.
+    #[inline(never)]
+    pub fn is_valid_timestamp(ctx: &Context<'_>) -> Result<bool> {
+        Ok(sane_timestamp(ctx, Timestamp::now()).is_some())
     }
 
     #[inline(never)]
@@ -65,6 +78,75 @@ pub mod sql_fns {
     }
 }
 
+/// What an import did: how many rows it looked at, how many it brought in,
+/// why it skipped the rest, and how long each phase took.
+///
+/// What a "row" is depends on the importer - for history, it's a visit, and
+/// for bookmarks, it's a bookmark, folder or separator. Roots aren't counted,
+/// and neither are rows we already had, which are neither imported nor
+/// skipped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportReport {
+    pub rows_seen: u32,
+    pub rows_imported: u32,
+    /// Rows skipped because their URL was missing, invalid, or too long.
+    pub skipped_invalid_url: u32,
+    /// Rows skipped because their parent was missing or invalid. For
+    /// bookmarks, this includes anything else dropped by the merge.
+    pub skipped_bad_parent: u32,
+    /// Rows of a type we don't import, like livemarks, queries, or visits
+    /// to embedded frames.
+    pub skipped_unsupported: u32,
+    /// Rows with a missing or nonsensical timestamp. These aren't skipped;
+    /// we use the current time instead.
+    pub invalid_timestamps: u32,
+    pub phases: Vec<ImportPhase>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportPhase {
+    pub name: &'static str,
+    pub duration: Duration,
+}
+
+impl ImportReport {
+    /// Runs one phase of an import, recording how long it took.
+    pub(crate) fn time_phase<T, E>(
+        &mut self,
+        name: &'static str,
+        f: impl FnOnce() -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E> {
+        let start = Instant::now();
+        let result = f();
+        self.phases.push(ImportPhase {
+            name,
+            duration: start.elapsed(),
+        });
+        result
+    }
+}
+
+impl From<ImportReport> for msg_types::ImportReport {
+    fn from(report: ImportReport) -> Self {
+        msg_types::ImportReport {
+            rows_seen: report.rows_seen,
+            rows_imported: report.rows_imported,
+            skipped_invalid_url: report.skipped_invalid_url,
+            skipped_bad_parent: report.skipped_bad_parent,
+            skipped_unsupported: report.skipped_unsupported,
+            invalid_timestamps: report.invalid_timestamps,
+            phases: report
+                .phases
+                .into_iter()
+                .map(|phase| msg_types::ImportPhase {
+                    name: phase.name.to_owned(),
+                    duration_ms: phase.duration.as_millis() as i64,
+                })
+                .collect(),
+        }
+    }
+}
+
 pub fn attached_database<'a>(
     conn: &'a SyncConn<'a>,
     path: &Url,
@@ -5,4 +5,5 @@
 mod bookmarks;
 mod history;
 pub use bookmarks::import as import_bookmarks;
+pub use bookmarks::FennecBookmarksImport;
 pub use history::import as import_history;
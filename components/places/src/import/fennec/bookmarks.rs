@@ -8,7 +8,8 @@ use crate::bookmark_sync::{
     SyncedBookmarkKind,
 };
 use crate::error::*;
-use crate::import::common::{attached_database, ExecuteOnDrop};
+use crate::import::common::{attached_database, ExecuteOnDrop, ImportReport};
+use crate::msg_types;
 use crate::storage::bookmarks::PublicNode;
 use crate::types::{BookmarkType, SyncStatus};
 use rusqlite::NO_PARAMS;
@@ -18,15 +19,33 @@ use url::Url;
 // From https://searchfox.org/mozilla-central/rev/597a69c70a5cce6f42f159eb54ad1ef6745f5432/mobile/android/base/java/org/mozilla/gecko/db/BrowserDatabaseHelper.java#73.
 const FENNEC_DB_VERSION: i64 = 39;
 
+/// The result of a Fennec bookmarks import.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FennecBookmarksImport {
+    /// Fennec's pinned sites, which are stored as bookmarks in a root we
+    /// don't import.
+    pub pinned: Vec<PublicNode>,
+    pub report: ImportReport,
+}
+
+impl From<FennecBookmarksImport> for msg_types::FennecBookmarksImport {
+    fn from(import: FennecBookmarksImport) -> Self {
+        msg_types::FennecBookmarksImport {
+            pinned: import.pinned.into_iter().map(Into::into).collect(),
+            report: import.report.into(),
+        }
+    }
+}
+
 pub fn import(
     places_api: &PlacesApi,
     path: impl AsRef<std::path::Path>,
-) -> Result<Vec<PublicNode>> {
+) -> Result<FennecBookmarksImport> {
     let url = crate::util::ensure_url_path(path)?;
     do_import(places_api, url)
 }
 
-fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<Vec<PublicNode>> {
+fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<FennecBookmarksImport> {
     let conn = places_api.open_sync_connection()?;
 
     let scope = conn.begin_interrupt_scope();
@@ -38,8 +57,12 @@ fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<Vec<Publ
     // unintentionally write to it anywhere...
     // fennec_db_file_url.query_pairs_mut().append_pair("mode", "ro");
 
+    let mut report = ImportReport::default();
+
     log::trace!("Attaching database {}", fennec_db_file_url);
-    let auto_detach = attached_database(&conn, &fennec_db_file_url, "fennec")?;
+    let auto_detach = report.time_phase("attach", || {
+        attached_database(&conn, &fennec_db_file_url, "fennec")
+    })?;
 
     let db_version = conn.db.query_one::<i64>("PRAGMA fennec.user_version")?;
     if db_version != FENNEC_DB_VERSION {
@@ -60,19 +83,38 @@ fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<Vec<Publ
     crate::bookmark_sync::create_synced_bookmark_roots(&conn)?;
     scope.err_if_interrupted()?;
 
+    log::debug!("Counting bookmarks");
+    let (seen, unsupported, invalid_url, invalid_timestamps) =
+        report.time_phase("count", || {
+            conn.query_row_and_then_named(
+                &COUNT_BOOKMARKS,
+                &[],
+                |row| -> Result<_> { Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)) },
+                false,
+            )
+        })?;
+    // Rows we already have are neither imported nor skipped, so they aren't
+    // counted at all.
+    let already_local: u32 = conn.query_one(&COUNT_LOCAL)?;
+    report.rows_seen = u32::saturating_sub(seen, already_local);
+    report.skipped_unsupported = unsupported;
+    report.skipped_invalid_url = invalid_url;
+    report.invalid_timestamps = invalid_timestamps;
+    scope.err_if_interrupted()?;
+
     log::debug!("Creating staging table");
     conn.execute_batch(&CREATE_STAGING_TABLE)?;
 
     log::debug!("Importing from Fennec to staging table");
-    conn.execute_batch(&POPULATE_STAGING)?;
+    report.time_phase("populate_staging", || conn.execute_batch(&POPULATE_STAGING))?;
     scope.err_if_interrupted()?;
 
     log::debug!("Populating missing entries in moz_places");
-    conn.execute_batch(&FILL_MOZ_PLACES)?;
+    report.time_phase("fill_moz_places", || conn.execute_batch(&FILL_MOZ_PLACES))?;
     scope.err_if_interrupted()?;
 
     log::debug!("Populating mirror");
-    conn.execute_batch(&POPULATE_MIRROR)?;
+    report.time_phase("populate_mirror", || conn.execute_batch(&POPULATE_MIRROR))?;
     scope.err_if_interrupted()?;
 
     // Ideally we could just do this right after `CREATE_AND_POPULATE_STAGING`,
@@ -81,7 +123,9 @@ fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<Vec<Publ
     // could turn use `PRAGMA defer_foreign_keys = true`, but since we commit
     // everything in one go, that seems harder to debug.
     log::debug!("Populating mirror structure");
-    conn.execute_batch(&POPULATE_MIRROR_STRUCTURE)?;
+    report.time_phase("populate_mirror_structure", || {
+        conn.execute_batch(&POPULATE_MIRROR_STRUCTURE)
+    })?;
     scope.err_if_interrupted()?;
 
     // Grab the pinned websites (they are stored as bookmarks).
@@ -102,13 +146,22 @@ fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<Vec<Publ
     // We're already in a transaction.
     merger.set_external_transaction(true);
     log::debug!("Merging with local records");
-    merger.merge()?;
+    report.time_phase("merge", || merger.merge())?;
     scope.err_if_interrupted()?;
 
     // Update last modification time, sync status, etc
     log::debug!("Fixing up bookmarks");
-    conn.execute_batch(&FIXUP_MOZ_BOOKMARKS)?;
+    report.time_phase("fixup", || conn.execute_batch(&FIXUP_MOZ_BOOKMARKS))?;
     scope.err_if_interrupted()?;
+
+    report.rows_imported = conn
+        .query_one::<u32>(&COUNT_LOCAL)?
+        .saturating_sub(already_local);
+    // Whatever's left was dropped when building the tree.
+    report.skipped_bad_parent = report.rows_seen.saturating_sub(
+        report.rows_imported + report.skipped_unsupported + report.skipped_invalid_url,
+    );
+
     log::debug!("Cleaning up mirror...");
     clear_mirror_on_drop.execute_now()?;
     log::debug!("Committing...");
@@ -117,13 +170,17 @@ fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<Vec<Publ
     // Note: update_frecencies manages its own transaction, which is fine,
     // since nothing that bad will happen if it is aborted.
     log::debug!("Updating frecencies");
-    store.update_frecencies()?;
+    report.time_phase("update_frecencies", || store.update_frecencies())?;
 
-    log::info!("Successfully imported bookmarks!");
+    log::info!(
+        "Successfully imported {} of {} bookmarks!",
+        report.rows_imported,
+        report.rows_seen
+    );
 
     auto_detach.execute_now()?;
 
-    Ok(pinned)
+    Ok(FennecBookmarksImport { pinned, report })
 }
 
 #[derive(Clone, Copy, PartialEq, PartialOrd, Hash, Debug, Eq, Ord)]
@@ -138,7 +195,45 @@ pub enum FennecBookmarkType {
     // Query = 4,
 }
 
+// Fennec's roots and special folders, which we don't count as rows. Pinned
+// sites are returned separately, so we don't count those either.
+const FENNEC_SPECIAL_GUIDS: &str =
+    "('places', 'menu', 'toolbar', 'unfiled', 'mobile', 'tags', 'pinned')";
+const FENNEC_PINNED_PARENT_ID: i64 = -3;
+
 lazy_static::lazy_static! {
+    // Counts the bookmarks we'll look at, and what's wrong with them.
+    static ref COUNT_BOOKMARKS: String = format!(
+        "SELECT
+            COUNT(*),
+            IFNULL(SUM(b.type NOT IN ({fennec_bookmark_type}, {fennec_folder_type}, {fennec_separator_type})), 0),
+            IFNULL(SUM(b.type = {fennec_bookmark_type} AND NOT is_valid_url(validate_url(b.url))), 0),
+            IFNULL(SUM(NOT is_valid_timestamp(b.created)), 0)
+         FROM fennec.bookmarks b
+         WHERE NOT b.deleted
+           AND b.guid NOT IN {special_guids}
+           AND b.parent <> {pinned_parent}",
+        fennec_bookmark_type = FennecBookmarkType::Bookmark as u8,
+        fennec_folder_type = FennecBookmarkType::Folder as u8,
+        fennec_separator_type = FennecBookmarkType::Separator as u8,
+        special_guids = FENNEC_SPECIAL_GUIDS,
+        pinned_parent = FENNEC_PINNED_PARENT_ID,
+    );
+
+    // Counts the rows we're importing which are in the local tree. The
+    // difference between this before and after the merge is how many were
+    // imported.
+    static ref COUNT_LOCAL: String = format!(
+        "SELECT COUNT(*)
+         FROM fennec.bookmarks b
+         JOIN main.moz_bookmarks l ON l.guid = b.guid
+         WHERE NOT b.deleted
+           AND b.guid NOT IN {special_guids}
+           AND b.parent <> {pinned_parent}",
+        special_guids = FENNEC_SPECIAL_GUIDS,
+        pinned_parent = FENNEC_PINNED_PARENT_ID,
+    );
+
     // Insert any missing entries into moz_places that we'll need for this.
     static ref FILL_MOZ_PLACES: String = format!(
         "INSERT OR IGNORE INTO main.moz_places(guid, url, url_hash, frecency)
@@ -262,7 +357,7 @@ lazy_static::lazy_static! {
         ;
     ",
         fennec_bookmark_type = FennecBookmarkType::Bookmark as u8,
-        fennec_pinned_parent_id = -3,
+        fennec_pinned_parent_id = FENNEC_PINNED_PARENT_ID,
     );
 
     static ref CREATE_STAGING_TABLE: String = format!("
@@ -319,7 +414,9 @@ fn public_node_from_fennec_pinned(
 }
 
 mod sql_fns {
-    use crate::import::common::sql_fns::{is_valid_url, sanitize_timestamp, validate_url};
+    use crate::import::common::sql_fns::{
+        is_valid_timestamp, is_valid_url, sanitize_timestamp, validate_url,
+    };
     use rusqlite::{functions::Context, Connection, Result};
 
     pub(super) fn define_functions(c: &Connection) -> Result<()> {
@@ -327,6 +424,7 @@ mod sql_fns {
         c.create_scalar_function("validate_url", 1, true, validate_url)?;
         c.create_scalar_function("is_valid_url", 1, true, is_valid_url)?;
         c.create_scalar_function("sanitize_timestamp", 1, true, sanitize_timestamp)?;
+        c.create_scalar_function("is_valid_timestamp", 1, false, is_valid_timestamp)?;
         Ok(())
     }
 
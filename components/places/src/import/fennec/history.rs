@@ -5,20 +5,20 @@
 use crate::api::places_api::PlacesApi;
 use crate::bookmark_sync::store::BookmarksStore;
 use crate::error::*;
-use crate::import::common::attached_database;
-use rusqlite::Connection;
+use crate::import::common::{attached_database, ImportReport};
+use rusqlite::{Connection, NO_PARAMS};
 use sql_support::ConnExt;
 use url::Url;
 
 // From https://searchfox.org/mozilla-central/rev/597a69c70a5cce6f42f159eb54ad1ef6745f5432/mobile/android/base/java/org/mozilla/gecko/db/BrowserDatabaseHelper.java#73.
 const FENNEC_DB_VERSION: i64 = 39;
 
-pub fn import(places_api: &PlacesApi, path: impl AsRef<std::path::Path>) -> Result<()> {
+pub fn import(places_api: &PlacesApi, path: impl AsRef<std::path::Path>) -> Result<ImportReport> {
     let url = crate::util::ensure_url_path(path)?;
     do_import(places_api, url)
 }
 
-fn do_import(places_api: &PlacesApi, android_db_file_url: Url) -> Result<()> {
+fn do_import(places_api: &PlacesApi, android_db_file_url: Url) -> Result<ImportReport> {
     let conn = places_api.open_sync_connection()?;
 
     let scope = conn.begin_interrupt_scope();
@@ -30,8 +30,12 @@ fn do_import(places_api: &PlacesApi, android_db_file_url: Url) -> Result<()> {
     // unintentionally write to it anywhere...
     // android_db_file_url.query_pairs_mut().append_pair("mode", "ro");
 
+    let mut report = ImportReport::default();
+
     log::trace!("Attaching database {}", android_db_file_url);
-    let auto_detach = attached_database(&conn, &android_db_file_url, "fennec")?;
+    let auto_detach = report.time_phase("attach", || {
+        attached_database(&conn, &android_db_file_url, "fennec")
+    })?;
 
     let db_version = conn.db.query_one::<i64>("PRAGMA fennec.user_version")?;
     if db_version != FENNEC_DB_VERSION {
@@ -40,12 +44,28 @@ fn do_import(places_api: &PlacesApi, android_db_file_url: Url) -> Result<()> {
 
     let tx = conn.begin_transaction()?;
 
+    log::debug!("Counting history visits");
+    let (seen, invalid_url, invalid_timestamps) = report.time_phase("count", || {
+        conn.query_row_and_then_named(
+            &COUNT_VISITS,
+            &[],
+            |row| -> Result<_> { Ok((row.get(0)?, row.get(1)?, row.get(2)?)) },
+            false,
+        )
+    })?;
+    report.rows_seen = seen;
+    report.skipped_invalid_url = invalid_url;
+    report.invalid_timestamps = invalid_timestamps;
+    scope.err_if_interrupted()?;
+
     log::debug!("Populating missing entries in moz_places");
-    conn.execute_batch(&FILL_MOZ_PLACES)?;
+    report.time_phase("fill_moz_places", || conn.execute_batch(&FILL_MOZ_PLACES))?;
     scope.err_if_interrupted()?;
 
     log::debug!("Inserting the history visits");
-    conn.execute_batch(&INSERT_HISTORY_VISITS)?;
+    report.rows_imported = report.time_phase("insert_history_visits", || {
+        conn.execute(&INSERT_HISTORY_VISITS, NO_PARAMS)
+    })? as u32;
     scope.err_if_interrupted()?;
 
     log::debug!("Committing...");
@@ -55,16 +75,30 @@ fn do_import(places_api: &PlacesApi, android_db_file_url: Url) -> Result<()> {
     // since nothing that bad will happen if it is aborted.
     log::debug!("Updating frecencies");
     let store = BookmarksStore::new(&conn, &scope);
-    store.update_frecencies()?;
+    report.time_phase("update_frecencies", || store.update_frecencies())?;
 
-    log::info!("Successfully imported history visits!");
+    log::info!(
+        "Successfully imported {} of {} history visits!",
+        report.rows_imported,
+        report.rows_seen
+    );
 
     auto_detach.execute_now()?;
 
-    Ok(())
+    Ok(report)
 }
 
 lazy_static::lazy_static! {
+    // Counts the visits we'll look at, and what's wrong with them.
+    static ref COUNT_VISITS: &'static str =
+        "SELECT
+            COUNT(*),
+            IFNULL(SUM(NOT is_valid_url(h.url)), 0),
+            IFNULL(SUM(is_valid_url(h.url) AND NOT is_valid_timestamp(v.date)), 0)
+         FROM fennec.visits v
+         LEFT JOIN fennec.history h on v.history_guid = h.guid"
+    ;
+
     // Insert any missing entries into moz_places that we'll need for this.
     static ref FILL_MOZ_PLACES: &'static str =
         "INSERT OR IGNORE INTO main.moz_places(guid, url, url_hash, title, frecency, sync_change_counter)
@@ -110,6 +144,12 @@ pub(super) fn define_sql_functions(c: &Connection) -> Result<()> {
         true,
         crate::import::common::sql_fns::sanitize_timestamp,
     )?;
+    c.create_scalar_function(
+        "is_valid_timestamp",
+        1,
+        false,
+        crate::import::common::sql_fns::is_valid_timestamp,
+    )?;
     c.create_scalar_function("hash", -1, true, crate::db::db::sql_fns::hash)?;
     c.create_scalar_function(
         "generate_guid",
@@ -8,7 +8,7 @@ use crate::bookmark_sync::{
     SyncedBookmarkKind,
 };
 use crate::error::*;
-use crate::import::common::{attached_database, ExecuteOnDrop};
+use crate::import::common::{attached_database, ExecuteOnDrop, ImportReport};
 use crate::types::SyncStatus;
 use rusqlite::{named_params, NO_PARAMS};
 use sql_support::ConnExt;
@@ -73,17 +73,18 @@ use url::Url;
 /// - Fill mirror structure using both iOS database and iosBookmarksStaging.
 /// - Run dogear merge
 /// - Use iosBookmarksStaging to fixup the data that was actually inserted.
+/// - Count what was imported and skipped, for the `ImportReport`.
 /// - Update frecency for new items.
 /// - Cleanup (Delete mirror and mirror structure, detach iOS database, etc).
 pub fn import_ios_bookmarks(
     places_api: &PlacesApi,
     path: impl AsRef<std::path::Path>,
-) -> Result<()> {
+) -> Result<ImportReport> {
     let url = crate::util::ensure_url_path(path)?;
     do_import_ios_bookmarks(places_api, url)
 }
 
-fn do_import_ios_bookmarks(places_api: &PlacesApi, ios_db_file_url: Url) -> Result<()> {
+fn do_import_ios_bookmarks(places_api: &PlacesApi, ios_db_file_url: Url) -> Result<ImportReport> {
     let conn = places_api.open_sync_connection()?;
 
     let scope = conn.begin_interrupt_scope();
@@ -95,8 +96,12 @@ fn do_import_ios_bookmarks(places_api: &PlacesApi, ios_db_file_url: Url) -> Resu
     // unintentionally write to it anywhere...
     // ios_db_file_url.query_pairs_mut().append_pair("mode", "ro");
 
+    let mut report = ImportReport::default();
+
     log::trace!("Attaching database {}", ios_db_file_url);
-    let auto_detach = attached_database(&conn, &ios_db_file_url, "ios")?;
+    let auto_detach = report.time_phase("attach", || {
+        attached_database(&conn, &ios_db_file_url, "ios")
+    })?;
 
     let tx = conn.begin_transaction()?;
 
@@ -108,23 +113,42 @@ fn do_import_ios_bookmarks(places_api: &PlacesApi, ios_db_file_url: Url) -> Resu
     conn.execute_batch(&WIPE_MIRROR)?;
     scope.err_if_interrupted()?;
 
+    log::debug!("Counting bookmarks");
+    let (seen, unsupported, invalid_url, invalid_timestamps) =
+        report.time_phase("count", || {
+            conn.query_row_and_then_named(
+                &COUNT_BOOKMARKS,
+                &[],
+                |row| -> Result<_> { Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)) },
+                false,
+            )
+        })?;
+    // Rows we already have are neither imported nor skipped, so they aren't
+    // counted at all.
+    let already_local: u32 = conn.query_one(&COUNT_LOCAL)?;
+    report.rows_seen = u32::saturating_sub(seen, already_local);
+    report.skipped_unsupported = unsupported;
+    report.skipped_invalid_url = invalid_url;
+    report.invalid_timestamps = invalid_timestamps;
+    scope.err_if_interrupted()?;
+
     log::debug!("Creating staging table");
     conn.execute_batch(&CREATE_STAGING_TABLE)?;
 
     log::debug!("Importing from iOS to staging table");
-    conn.execute_batch(&POPULATE_STAGING)?;
+    report.time_phase("populate_staging", || conn.execute_batch(&POPULATE_STAGING))?;
     scope.err_if_interrupted()?;
 
     log::debug!("Populating missing entries in moz_places");
-    conn.execute_batch(&FILL_MOZ_PLACES)?;
+    report.time_phase("fill_moz_places", || conn.execute_batch(&FILL_MOZ_PLACES))?;
     scope.err_if_interrupted()?;
 
     log::debug!("Populating mirror");
-    conn.execute_batch(&POPULATE_MIRROR)?;
+    report.time_phase("populate_mirror", || conn.execute_batch(&POPULATE_MIRROR))?;
     scope.err_if_interrupted()?;
 
     log::debug!("Populating mirror tags");
-    populate_mirror_tags(&conn)?;
+    report.time_phase("populate_mirror_tags", || populate_mirror_tags(&conn))?;
     scope.err_if_interrupted()?;
 
     // Ideally we could just do this right after `CREATE_AND_POPULATE_STAGING`,
@@ -133,7 +157,9 @@ fn do_import_ios_bookmarks(places_api: &PlacesApi, ios_db_file_url: Url) -> Resu
     // could turn use `PRAGMA defer_foreign_keys = true`, but since we commit
     // everything in one go, that seems harder to debug.
     log::debug!("Populating mirror structure");
-    conn.execute_batch(&POPULATE_MIRROR_STRUCTURE)?;
+    report.time_phase("populate_mirror_structure", || {
+        conn.execute_batch(&POPULATE_MIRROR_STRUCTURE)
+    })?;
     scope.err_if_interrupted()?;
 
     // log::debug!("Detaching iOS database");
@@ -145,13 +171,22 @@ fn do_import_ios_bookmarks(places_api: &PlacesApi, ios_db_file_url: Url) -> Resu
     // We're already in a transaction.
     merger.set_external_transaction(true);
     log::debug!("Merging with local records");
-    merger.merge()?;
+    report.time_phase("merge", || merger.merge())?;
     scope.err_if_interrupted()?;
 
     // Update last modification time, sync status, etc
     log::debug!("Fixing up bookmarks");
-    conn.execute_batch(&FIXUP_MOZ_BOOKMARKS)?;
+    report.time_phase("fixup", || conn.execute_batch(&FIXUP_MOZ_BOOKMARKS))?;
     scope.err_if_interrupted()?;
+
+    report.rows_imported = conn
+        .query_one::<u32>(&COUNT_LOCAL)?
+        .saturating_sub(already_local);
+    // Whatever's left was dropped when building the tree.
+    report.skipped_bad_parent = report.rows_seen.saturating_sub(
+        report.rows_imported + report.skipped_unsupported + report.skipped_invalid_url,
+    );
+
     log::debug!("Cleaning up mirror...");
     clear_mirror_on_drop.execute_now()?;
     log::debug!("Committing...");
@@ -160,13 +195,17 @@ fn do_import_ios_bookmarks(places_api: &PlacesApi, ios_db_file_url: Url) -> Resu
     // Note: update_frecencies manages its own transaction, which is fine,
     // since nothing that bad will happen if it is aborted.
     log::debug!("Updating frecencies");
-    store.update_frecencies()?;
+    report.time_phase("update_frecencies", || store.update_frecencies())?;
 
-    log::info!("Successfully imported bookmarks!");
+    log::info!(
+        "Successfully imported {} of {} bookmarks!",
+        report.rows_imported,
+        report.rows_seen
+    );
 
     auto_detach.execute_now()?;
 
-    Ok(())
+    Ok(report)
 }
 
 // If we must.
@@ -256,6 +295,43 @@ const ROOTS: &str =
     "('root________', 'menu________', 'toolbar_____', 'unfiled_____', 'mobile______')";
 
 lazy_static::lazy_static! {
+    // Items in `bookmarksLocal` replace the ones in `bookmarksBuffer`, like
+    // in `POPULATE_STAGING`.
+    static ref IOS_ITEMS: String = format!(
+        "SELECT l.guid, l.type, l.bmkUri, l.date_added
+         FROM ios.bookmarksLocal l
+         WHERE NOT l.is_deleted AND l.guid NOT IN {roots}
+         UNION ALL
+         SELECT b.guid, b.type, b.bmkUri, b.date_added
+         FROM ios.bookmarksBuffer b
+         WHERE NOT b.is_deleted AND b.guid NOT IN {roots}
+           AND b.guid NOT IN (SELECT l.guid FROM ios.bookmarksLocal l)",
+        roots = ROOTS,
+    );
+
+    // Counts the bookmarks we'll look at, and what's wrong with them.
+    static ref COUNT_BOOKMARKS: String = format!(
+        "SELECT
+            COUNT(*),
+            IFNULL(SUM(i.type NOT IN {valid_types}), 0),
+            IFNULL(SUM(i.type = {bookmark_type} AND NOT is_valid_url(validate_url(i.bmkUri))), 0),
+            IFNULL(SUM(NOT is_valid_timestamp(i.date_added)), 0)
+         FROM ({items}) i",
+        valid_types = &*IOS_VALID_TYPES,
+        bookmark_type = IosBookmarkType::Bookmark as u8,
+        items = &*IOS_ITEMS,
+    );
+
+    // Counts the rows we're importing which are in the local tree. The
+    // difference between this before and after the merge is how many were
+    // imported.
+    static ref COUNT_LOCAL: String = format!(
+        "SELECT COUNT(*)
+         FROM ({items}) i
+         JOIN main.moz_bookmarks b ON b.guid = i.guid",
+        items = &*IOS_ITEMS,
+    );
+
     static ref WIPE_MIRROR: String = format!(
         // Is omitting the roots right?
         "DELETE FROM main.moz_bookmarks_synced
@@ -468,13 +544,16 @@ lazy_static::lazy_static! {
 }
 
 mod sql_fns {
-    use crate::import::common::sql_fns::{is_valid_url, sanitize_timestamp, validate_url};
+    use crate::import::common::sql_fns::{
+        is_valid_timestamp, is_valid_url, sanitize_timestamp, validate_url,
+    };
     use rusqlite::{Connection, Result};
 
     pub(super) fn define_functions(c: &Connection) -> Result<()> {
         c.create_scalar_function("validate_url", 1, true, validate_url)?;
         c.create_scalar_function("is_valid_url", 1, true, is_valid_url)?;
         c.create_scalar_function("sanitize_timestamp", 1, true, sanitize_timestamp)?;
+        c.create_scalar_function("is_valid_timestamp", 1, false, is_valid_timestamp)?;
         Ok(())
     }
 }
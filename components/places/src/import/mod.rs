@@ -2,9 +2,16 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+pub mod bookmarks_backup;
+pub use bookmarks_backup::import_bookmarks_backup;
+pub mod chromium;
+pub use chromium::import_bookmarks as import_chromium_bookmarks;
+pub use chromium::import_history as import_chromium_history;
 pub mod common;
+pub use common::ImportReport;
 pub mod fennec;
 pub use fennec::import_bookmarks as import_fennec_bookmarks;
 pub use fennec::import_history as import_fennec_history;
+pub use fennec::FennecBookmarksImport;
 pub mod ios_bookmarks;
 pub use ios_bookmarks::import_ios_bookmarks;
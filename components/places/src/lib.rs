@@ -7,6 +7,7 @@
 
 pub mod api;
 pub mod error;
+pub mod export;
 pub mod types;
 // Making these all pub for now while we flesh out the API.
 pub mod bookmark_sync;
@@ -19,6 +20,9 @@ pub mod history_sync;
 pub mod import;
 pub mod match_impl;
 pub mod observation;
+pub mod observer;
+pub mod query;
+pub mod reading_list_sync;
 pub mod storage;
 #[cfg(test)]
 mod tests;
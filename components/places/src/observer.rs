@@ -0,0 +1,299 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Change notifications for consumers which need to keep a view of places
+//! up to date, loosely modelled on desktop's `PlacesObservers`.
+//!
+//! Observers are registered on the `PlacesApi`, and every connection opened
+//! by that API shares them. Events are only sent once the transaction which
+//! made the change has been committed, and always from the thread which made
+//! the change - observers which need to do real work should hand the event
+//! off elsewhere, or use an `EventQueue` and poll it.
+
+use crate::types::{Timestamp, VisitTransition};
+use serde_derive::*;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use sync_guid::Guid as SyncGuid;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlacesEvent {
+    /// A visit to a page was recorded.
+    PageVisited {
+        url: String,
+        visit_date: Timestamp,
+        visit_type: VisitTransition,
+        is_remote: bool,
+    },
+    /// The title of a page was set.
+    TitleChanged { url: String, title: String },
+    /// Visits were removed. If `url` is set only visits to that page between
+    /// `start` and `end` (inclusive) were removed, otherwise all visits in
+    /// that range were.
+    VisitsRemoved {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        start: Timestamp,
+        end: Timestamp,
+    },
+    /// All visits to pages on `host` (and its subdomains, if
+    /// `include_subdomains` is set) were removed, along with the pages
+    /// themselves unless they are bookmarked.
+    HostVisitsRemoved {
+        host: String,
+        include_subdomains: bool,
+    },
+    /// A page, and all of its visits, was removed.
+    PageRemoved { guid: SyncGuid },
+    /// All history was removed.
+    HistoryCleared,
+    /// Old history was expired by `prune_destructively`. There's no record of
+    /// exactly what was removed, so observers should refresh any history they
+    /// are showing.
+    HistoryExpired,
+    BookmarkInserted {
+        guid: SyncGuid,
+        parent_guid: SyncGuid,
+        index: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    /// The title or URL of a bookmark or folder changed.
+    BookmarkChanged { guid: SyncGuid },
+    BookmarkMoved {
+        guid: SyncGuid,
+        old_parent_guid: SyncGuid,
+        old_index: u32,
+        new_parent_guid: SyncGuid,
+        new_index: u32,
+    },
+    /// A bookmark was removed. If it was a folder, its descendants were also
+    /// removed, but there are no separate events for them.
+    BookmarkRemoved {
+        guid: SyncGuid,
+        parent_guid: SyncGuid,
+        index: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    /// All bookmarks other than the roots were removed.
+    BookmarksCleared,
+    /// Problems in the bookmark tree were fixed by `check_and_repair`, which
+    /// may have moved or changed any number of items, so observers should
+    /// refresh any bookmarks they are showing.
+    BookmarksRepaired,
+    /// A sync of the named engine ("history" or "bookmarks") finished. Incoming
+    /// changes don't get their own events, so observers should treat this as
+    /// "anything may have changed".
+    SyncFinished { engine: String, succeeded: bool },
+    /// Only sent by an `EventQueue`, when it filled up before being drained
+    /// and had to drop the oldest `count` events.
+    EventsDropped { count: usize },
+}
+
+pub trait PlacesObserver: Send + Sync {
+    fn on_event(&self, event: &PlacesEvent);
+}
+
+impl<F> PlacesObserver for F
+where
+    F: Fn(&PlacesEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &PlacesEvent) {
+        self(event)
+    }
+}
+
+/// Identifies a registered observer, so it can be unregistered later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(pub usize);
+
+/// The set of observers registered with a `PlacesApi`.
+#[derive(Default)]
+pub struct Observers {
+    next_id: AtomicUsize,
+    observers: Mutex<Vec<(ObserverId, Arc<dyn PlacesObserver>)>>,
+}
+
+impl Observers {
+    pub fn register(&self, observer: Arc<dyn PlacesObserver>) -> ObserverId {
+        let id = ObserverId(self.next_id.fetch_add(1, Ordering::SeqCst));
+        self.observers.lock().unwrap().push((id, observer));
+        id
+    }
+
+    /// Returns true if the observer was registered.
+    pub fn unregister(&self, id: ObserverId) -> bool {
+        let mut observers = self.observers.lock().unwrap();
+        let len = observers.len();
+        observers.retain(|(existing, _)| *existing != id);
+        observers.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.lock().unwrap().is_empty()
+    }
+
+    pub fn notify(&self, event: &PlacesEvent) {
+        self.notify_all(std::slice::from_ref(event));
+    }
+
+    pub fn notify_all(&self, events: &[PlacesEvent]) {
+        if events.is_empty() {
+            return;
+        }
+        // Take a copy of the list so an observer can unregister itself (or
+        // register others) without deadlocking.
+        let observers = self
+            .observers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, o)| o.clone())
+            .collect::<Vec<_>>();
+        for observer in observers {
+            for event in events {
+                observer.on_event(event);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observers")
+            .field("count", &self.observers.lock().unwrap().len())
+            .finish()
+    }
+}
+
+/// The default number of events an `EventQueue` holds before it starts to
+/// drop the oldest ones.
+pub const DEFAULT_EVENT_QUEUE_CAPACITY: usize = 1000;
+
+/// An observer which buffers events until they are drained, for consumers
+/// (such as the FFI) which would rather poll than be called back.
+#[derive(Debug)]
+pub struct EventQueue {
+    capacity: usize,
+    state: Mutex<EventQueueState>,
+}
+
+#[derive(Debug, Default)]
+struct EventQueueState {
+    events: VecDeque<PlacesEvent>,
+    dropped: usize,
+}
+
+impl EventQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Removes and returns all the queued events, oldest first. If events
+    /// were dropped since the last drain, the first is `EventsDropped`.
+    pub fn drain(&self) -> Vec<PlacesEvent> {
+        let mut state = self.state.lock().unwrap();
+        let mut result = Vec::with_capacity(state.events.len() + 1);
+        if state.dropped > 0 {
+            result.push(PlacesEvent::EventsDropped {
+                count: state.dropped,
+            });
+            state.dropped = 0;
+        }
+        result.extend(state.events.drain(..));
+        result
+    }
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_QUEUE_CAPACITY)
+    }
+}
+
+impl PlacesObserver for EventQueue {
+    fn on_event(&self, event: &PlacesEvent) {
+        let mut state = self.state.lock().unwrap();
+        if state.events.len() >= self.capacity {
+            state.events.pop_front();
+            state.dropped += 1;
+        }
+        state.events.push_back(event.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observers() {
+        let observers = Observers::default();
+        assert!(observers.is_empty());
+        let queue = Arc::new(EventQueue::default());
+        let count = Arc::new(AtomicUsize::new(0));
+        let queue_id = observers.register(queue.clone());
+        let count_id = observers.register({
+            let count = count.clone();
+            Arc::new(move |_: &PlacesEvent| {
+                count.fetch_add(1, Ordering::SeqCst);
+            })
+        });
+        assert_ne!(queue_id, count_id);
+
+        observers.notify_all(&[PlacesEvent::HistoryCleared, PlacesEvent::BookmarksCleared]);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            queue.drain(),
+            vec![PlacesEvent::HistoryCleared, PlacesEvent::BookmarksCleared]
+        );
+        assert_eq!(queue.drain(), vec![]);
+
+        assert!(observers.unregister(count_id));
+        assert!(!observers.unregister(count_id));
+        observers.notify(&PlacesEvent::HistoryExpired);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        assert_eq!(queue.drain(), vec![PlacesEvent::HistoryExpired]);
+    }
+
+    #[test]
+    fn test_event_queue_overflow() {
+        let queue = EventQueue::new(2);
+        queue.on_event(&PlacesEvent::HistoryCleared);
+        queue.on_event(&PlacesEvent::HistoryExpired);
+        queue.on_event(&PlacesEvent::BookmarksCleared);
+        assert_eq!(
+            queue.drain(),
+            vec![
+                PlacesEvent::EventsDropped { count: 1 },
+                PlacesEvent::HistoryExpired,
+                PlacesEvent::BookmarksCleared,
+            ]
+        );
+        assert_eq!(queue.drain(), vec![]);
+    }
+
+    #[test]
+    fn test_event_json() {
+        let event = PlacesEvent::SyncFinished {
+            engine: "bookmarks".into(),
+            succeeded: true,
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "type": "sync_finished",
+                "engine": "bookmarks",
+                "succeeded": true,
+            })
+        );
+    }
+}
@@ -0,0 +1,739 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Parsing and running desktop's `place:` queries.
+//!
+//! Desktop's "smart" bookmarks, like "Most Visited" and "Recent Tags", are
+//! bookmarks with a `place:` URL whose parameters describe a query over
+//! history and bookmarks. We sync these as `query` records, and this module
+//! lets us run them locally. Only part of desktop's syntax is supported:
+//!
+//! * `sort`: one of desktop's `nsINavHistoryQueryOptions::SORT_BY_*` values,
+//!   except for sorting by keyword or tags.
+//! * `maxResults`: the maximum number of results, where `0` means no limit.
+//! * `tag`: only match pages with this tag. If repeated, pages must have all
+//!   of the tags.
+//! * `parent` and `folder`: only match bookmarks in this folder. `parent`
+//!   takes a GUID, and `folder` takes a root name like `TOOLBAR`. Numeric
+//!   `folder` IDs come from desktop's database, so they never match anything
+//!   here. Either can be repeated.
+//! * `type`: `0` for pages, or `6` for the list of tags.
+//! * `queryType`: `0` for history, or `1` for bookmarks.
+//! * `excludeQueries`: `1` to leave out other `place:` queries.
+//!
+//! Any other parameters are ignored.
+
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::msg_types;
+use crate::storage::bookmarks::BookmarkRootGuid;
+use crate::storage::tags::validate_tag;
+use crate::types::{BookmarkType, Timestamp};
+use rusqlite::types::ToSql;
+use rusqlite::Row;
+use sql_support::ConnExt;
+use sync_guid::Guid as SyncGuid;
+use url::Url;
+
+/// How to sort query results. The values match desktop's
+/// `nsINavHistoryQueryOptions::SORT_BY_*` constants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum QuerySort {
+    None = 0,
+    TitleAscending = 1,
+    TitleDescending = 2,
+    DateAscending = 3,
+    DateDescending = 4,
+    UriAscending = 5,
+    UriDescending = 6,
+    VisitCountAscending = 7,
+    VisitCountDescending = 8,
+    DateAddedAscending = 11,
+    DateAddedDescending = 12,
+    LastModifiedAscending = 13,
+    LastModifiedDescending = 14,
+    FrecencyAscending = 19,
+    FrecencyDescending = 20,
+}
+
+impl QuerySort {
+    #[inline]
+    pub fn from_primitive(p: u8) -> Option<Self> {
+        match p {
+            0 => Some(QuerySort::None),
+            1 => Some(QuerySort::TitleAscending),
+            2 => Some(QuerySort::TitleDescending),
+            3 => Some(QuerySort::DateAscending),
+            4 => Some(QuerySort::DateDescending),
+            5 => Some(QuerySort::UriAscending),
+            6 => Some(QuerySort::UriDescending),
+            7 => Some(QuerySort::VisitCountAscending),
+            8 => Some(QuerySort::VisitCountDescending),
+            11 => Some(QuerySort::DateAddedAscending),
+            12 => Some(QuerySort::DateAddedDescending),
+            13 => Some(QuerySort::LastModifiedAscending),
+            14 => Some(QuerySort::LastModifiedDescending),
+            19 => Some(QuerySort::FrecencyAscending),
+            20 => Some(QuerySort::FrecencyDescending),
+            _ => None,
+        }
+    }
+
+    /// The `ORDER BY` clause for pages, in terms of the columns selected in
+    /// `run_page_query`. Ties are broken by position, for bookmarks, then by
+    /// row ID, so the results are stable.
+    fn page_order_by(self) -> &'static str {
+        match self {
+            QuerySort::None => "position, id",
+            QuerySort::TitleAscending => "title COLLATE NOCASE ASC, id",
+            QuerySort::TitleDescending => "title COLLATE NOCASE DESC, id",
+            QuerySort::DateAscending => "last_visit_date ASC, id",
+            QuerySort::DateDescending => "last_visit_date DESC, id",
+            QuerySort::UriAscending => "url ASC, id",
+            QuerySort::UriDescending => "url DESC, id",
+            QuerySort::VisitCountAscending => "visit_count ASC, id",
+            QuerySort::VisitCountDescending => "visit_count DESC, id",
+            QuerySort::DateAddedAscending => "date_added ASC, id",
+            QuerySort::DateAddedDescending => "date_added DESC, id",
+            QuerySort::LastModifiedAscending => "last_modified ASC, id",
+            QuerySort::LastModifiedDescending => "last_modified DESC, id",
+            QuerySort::FrecencyAscending => "frecency ASC, id",
+            QuerySort::FrecencyDescending => "frecency DESC, id",
+        }
+    }
+
+    /// Tags only have a name and a last modified time, so other sorts fall
+    /// back to sorting by name.
+    fn tag_order_by(self) -> &'static str {
+        match self {
+            QuerySort::TitleDescending => "t.tag DESC",
+            QuerySort::LastModifiedAscending => "t.lastModified ASC, t.tag",
+            QuerySort::LastModifiedDescending => "t.lastModified DESC, t.tag",
+            _ => "t.tag ASC",
+        }
+    }
+}
+
+impl Default for QuerySort {
+    fn default() -> Self {
+        QuerySort::None
+    }
+}
+
+/// What a query returns. The values match desktop's
+/// `nsINavHistoryQueryOptions::RESULTS_AS_*` constants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum QueryResultType {
+    Pages = 0,
+    Tags = 6,
+}
+
+impl Default for QueryResultType {
+    fn default() -> Self {
+        QueryResultType::Pages
+    }
+}
+
+/// Whether a query matches visited pages or bookmarks. The values match
+/// desktop's `nsINavHistoryQueryOptions::QUERY_TYPE_*` constants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum QueryType {
+    History = 0,
+    Bookmarks = 1,
+}
+
+impl Default for QueryType {
+    fn default() -> Self {
+        QueryType::History
+    }
+}
+
+/// A parsed `place:` query.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlaceQuery {
+    pub query_type: QueryType,
+    pub result_type: QueryResultType,
+    pub sort: QuerySort,
+    pub max_results: Option<u32>,
+    /// Pages must have all of these tags.
+    pub tags: Vec<String>,
+    /// If set, only bookmarks in one of these folders match. This may be
+    /// empty if none of the query's folders exist here.
+    pub folders: Option<Vec<SyncGuid>>,
+    pub exclude_queries: bool,
+}
+
+impl PlaceQuery {
+    pub fn from_url(url: &Url) -> Result<Self> {
+        if url.scheme() != "place" {
+            return Err(ErrorKind::InvalidPlaceQuery("not a place: URL".into()).into());
+        }
+        let mut query = PlaceQuery::default();
+        // As in `bookmark_sync::incoming`, the parameters are the path of a
+        // `place:` URL, not its query.
+        for (key, value) in url::form_urlencoded::parse(url.path().as_bytes()) {
+            match key.as_ref() {
+                "sort" => {
+                    query.sort = QuerySort::from_primitive(parse_param(&key, &value)?)
+                        .ok_or_else(|| unsupported(&key))?;
+                }
+                "maxResults" => {
+                    query.max_results = match parse_param::<u32>(&key, &value)? {
+                        0 => None,
+                        n => Some(n),
+                    };
+                }
+                "tag" => {
+                    let tag = validate_tag(&value).ensure_valid()?;
+                    query.tags.push(tag.to_string());
+                }
+                "parent" => {
+                    let guid = SyncGuid::from(value.as_ref());
+                    if !guid.is_valid_for_places() {
+                        return Err(InvalidPlaceInfo::InvalidGuid.into());
+                    }
+                    query.folders.get_or_insert_with(Vec::new).push(guid);
+                }
+                "folder" => {
+                    let folders = query.folders.get_or_insert_with(Vec::new);
+                    if let Some(root) = root_for_folder_name(&value) {
+                        folders.push(root.as_guid());
+                    } else if value.parse::<i64>().is_err() {
+                        return Err(unsupported(&key));
+                    }
+                }
+                "type" => {
+                    query.result_type = match parse_param::<u8>(&key, &value)? {
+                        0 => QueryResultType::Pages,
+                        6 => QueryResultType::Tags,
+                        _ => return Err(unsupported(&key)),
+                    };
+                }
+                "queryType" => {
+                    query.query_type = match parse_param::<u8>(&key, &value)? {
+                        0 => QueryType::History,
+                        1 => QueryType::Bookmarks,
+                        _ => return Err(unsupported(&key)),
+                    };
+                }
+                "excludeQueries" => {
+                    query.exclude_queries = value == "1";
+                }
+                _ => {
+                    log::debug!("Ignoring unsupported place: query parameter {}", key);
+                }
+            }
+        }
+        Ok(query)
+    }
+
+    /// Folder queries match bookmarks even if they don't say so, as on
+    /// desktop.
+    fn matches_bookmarks(&self) -> bool {
+        self.query_type == QueryType::Bookmarks || self.folders.is_some()
+    }
+}
+
+fn parse_param<T: std::str::FromStr>(key: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| ErrorKind::InvalidPlaceQuery(format!("invalid {}", key)).into())
+}
+
+fn unsupported(key: &str) -> Error {
+    ErrorKind::InvalidPlaceQuery(format!("unsupported {}", key)).into()
+}
+
+/// Maps desktop's names for the roots, which are all `folder=` accepts now
+/// that folder IDs are gone, to our GUIDs.
+fn root_for_folder_name(name: &str) -> Option<BookmarkRootGuid> {
+    match name {
+        "PLACES_ROOT" => Some(BookmarkRootGuid::Root),
+        "BOOKMARKS_MENU" => Some(BookmarkRootGuid::Menu),
+        "TOOLBAR" => Some(BookmarkRootGuid::Toolbar),
+        "UNFILED_BOOKMARKS" => Some(BookmarkRootGuid::Unfiled),
+        "MOBILE_BOOKMARKS" => Some(BookmarkRootGuid::Mobile),
+        _ => None,
+    }
+}
+
+/// A page matched by a query. For bookmark queries, there's one of these for
+/// each bookmark, with the bookmark's GUID and title; otherwise, there's one
+/// for each URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryPage {
+    pub url: Url,
+    pub title: Option<String>,
+    pub bookmark_guid: Option<SyncGuid>,
+    pub visit_count: i64,
+    pub last_visit_date: Option<Timestamp>,
+    /// For page queries, the latest of the dates for the page's bookmarks.
+    pub date_added: Option<Timestamp>,
+    pub last_modified: Option<Timestamp>,
+    pub frecency: i64,
+}
+
+impl QueryPage {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        let url: String = row.get("url")?;
+        Ok(Self {
+            url: Url::parse(&url)?,
+            title: row.get("title")?,
+            bookmark_guid: row.get("bookmark_guid")?,
+            visit_count: row.get("visit_count")?,
+            last_visit_date: match row.get("last_visit_date")? {
+                Timestamp(0) => None,
+                date => Some(date),
+            },
+            date_added: row.get("date_added")?,
+            last_modified: row.get("last_modified")?,
+            frecency: row.get("frecency")?,
+        })
+    }
+}
+
+/// A tag returned by a `type=6` query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryTag {
+    pub tag: String,
+    pub last_modified: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryResults {
+    Pages(Vec<QueryPage>),
+    Tags(Vec<QueryTag>),
+}
+
+impl From<QueryPage> for msg_types::QueryPage {
+    fn from(page: QueryPage) -> Self {
+        msg_types::QueryPage {
+            url: page.url.into_string(),
+            title: page.title,
+            bookmark_guid: page.bookmark_guid.map(SyncGuid::into_string),
+            visit_count: page.visit_count,
+            last_visit_date: page.last_visit_date.map(|t| t.0 as i64),
+            date_added: page.date_added.map(|t| t.0 as i64),
+            last_modified: page.last_modified.map(|t| t.0 as i64),
+            frecency: page.frecency,
+        }
+    }
+}
+
+impl From<QueryTag> for msg_types::QueryTag {
+    fn from(tag: QueryTag) -> Self {
+        msg_types::QueryTag {
+            tag: tag.tag,
+            last_modified: tag.last_modified.0 as i64,
+        }
+    }
+}
+
+impl From<QueryResults> for msg_types::QueryResults {
+    fn from(results: QueryResults) -> Self {
+        match results {
+            QueryResults::Pages(pages) => msg_types::QueryResults {
+                result_type: QueryResultType::Pages as i32,
+                pages: pages.into_iter().map(Into::into).collect(),
+                tags: Vec::new(),
+            },
+            QueryResults::Tags(tags) => msg_types::QueryResults {
+                result_type: QueryResultType::Tags as i32,
+                pages: Vec::new(),
+                tags: tags.into_iter().map(Into::into).collect(),
+            },
+        }
+    }
+}
+
+/// Runs a query.
+pub fn run_query(db: &PlacesDb, query: &PlaceQuery) -> Result<QueryResults> {
+    Ok(match query.result_type {
+        QueryResultType::Pages => QueryResults::Pages(run_page_query(db, query)?),
+        QueryResultType::Tags => QueryResults::Tags(run_tag_query(db, query)?),
+    })
+}
+
+/// Runs the query for a bookmark with a `place:` URL, like a smart folder
+/// synced from desktop.
+pub fn run_query_for_bookmark(db: &PlacesDb, guid: &SyncGuid) -> Result<QueryResults> {
+    let (bookmark_type, url): (u8, Option<String>) = db
+        .try_query_row(
+            "SELECT b.type, h.url FROM moz_bookmarks b
+             LEFT JOIN moz_places h ON h.id = b.fk
+             WHERE b.guid = :guid",
+            &[(":guid", guid)],
+            |row| -> Result<_> { Ok((row.get(0)?, row.get(1)?)) },
+            true,
+        )?
+        .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
+    match url {
+        Some(url) => run_query(db, &PlaceQuery::from_url(&Url::parse(&url)?)?),
+        None => Err(InvalidPlaceInfo::MismatchedBookmarkType(
+            bookmark_type,
+            BookmarkType::Bookmark as u8,
+        )
+        .into()),
+    }
+}
+
+fn run_page_query(db: &PlacesDb, query: &PlaceQuery) -> Result<Vec<QueryPage>> {
+    let scope = db.begin_interrupt_scope();
+    let mut conditions = Vec::new();
+    let mut params: Vec<(String, &dyn ToSql)> = Vec::new();
+    for (i, tag) in query.tags.iter().enumerate() {
+        let name = format!(":tag{}", i);
+        conditions.push(format!(
+            "EXISTS(SELECT 1 FROM moz_tags_relation r
+                    JOIN moz_tags t ON t.id = r.tag_id
+                    WHERE r.place_id = h.id AND t.tag = {})",
+            name
+        ));
+        params.push((name, tag));
+    }
+    if let Some(folders) = &query.folders {
+        let mut names = Vec::with_capacity(folders.len());
+        for (i, guid) in folders.iter().enumerate() {
+            let name = format!(":folder{}", i);
+            names.push(name.clone());
+            params.push((name, guid));
+        }
+        conditions.push(if names.is_empty() {
+            "0".to_string()
+        } else {
+            format!(
+                "b.parent IN (SELECT id FROM moz_bookmarks WHERE guid IN ({}))",
+                names.join(", ")
+            )
+        });
+    }
+    if query.exclude_queries {
+        conditions.push("substr(h.url, 1, 6) <> 'place:'".into());
+    }
+
+    let sql = if query.matches_bookmarks() {
+        conditions.insert(0, format!("b.type = {}", BookmarkType::Bookmark as u8));
+        format!(
+            "SELECT b.id AS id, b.position AS position, b.guid AS bookmark_guid,
+                    h.url AS url, b.title AS title,
+                    h.visit_count_local + h.visit_count_remote AS visit_count,
+                    MAX(h.last_visit_date_local, h.last_visit_date_remote) AS last_visit_date,
+                    b.dateAdded AS date_added, b.lastModified AS last_modified,
+                    h.frecency AS frecency
+             FROM moz_bookmarks b
+             JOIN moz_places h ON h.id = b.fk
+             WHERE {}
+             ORDER BY {}
+             LIMIT :limit",
+            conditions.join(" AND "),
+            query.sort.page_order_by()
+        )
+    } else {
+        // Tag queries match pages whether or not they've been visited; other
+        // history queries only match visited pages.
+        if query.tags.is_empty() {
+            conditions.insert(
+                0,
+                "h.hidden = 0 AND h.visit_count_local + h.visit_count_remote > 0".into(),
+            );
+        }
+        format!(
+            "SELECT h.id AS id, NULL AS position, NULL AS bookmark_guid,
+                    h.url AS url, h.title AS title,
+                    h.visit_count_local + h.visit_count_remote AS visit_count,
+                    MAX(h.last_visit_date_local, h.last_visit_date_remote) AS last_visit_date,
+                    (SELECT MAX(dateAdded) FROM moz_bookmarks
+                     WHERE fk = h.id) AS date_added,
+                    (SELECT MAX(lastModified) FROM moz_bookmarks
+                     WHERE fk = h.id) AS last_modified,
+                    h.frecency AS frecency
+             FROM moz_places h
+             WHERE {}
+             ORDER BY {}
+             LIMIT :limit",
+            if conditions.is_empty() {
+                "1".to_string()
+            } else {
+                conditions.join(" AND ")
+            },
+            query.sort.page_order_by()
+        )
+    };
+    // A negative limit means no limit to SQLite.
+    let limit = query.max_results.map_or(-1, i64::from);
+    params.push((":limit".into(), &limit));
+    let params: Vec<(&str, &dyn ToSql)> = params
+        .iter()
+        .map(|(name, value)| (name.as_str(), *value))
+        .collect();
+    db.query_rows_into(&sql, &params, |row| -> Result<_> {
+        scope.err_if_interrupted()?;
+        QueryPage::from_row(row)
+    })
+}
+
+fn run_tag_query(db: &PlacesDb, query: &PlaceQuery) -> Result<Vec<QueryTag>> {
+    let scope = db.begin_interrupt_scope();
+    let sql = format!(
+        "SELECT t.tag, t.lastModified FROM moz_tags t
+         WHERE EXISTS(SELECT 1 FROM moz_tags_relation r
+                      WHERE r.tag_id = t.id)
+         ORDER BY {}
+         LIMIT :limit",
+        query.sort.tag_order_by()
+    );
+    let limit = query.max_results.map_or(-1, i64::from);
+    db.query_rows_into(&sql, &[(":limit", &limit)], |row| -> Result<_> {
+        scope.err_if_interrupted()?;
+        Ok(QueryTag {
+            tag: row.get(0)?,
+            last_modified: row.get(1)?,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::apply_observation;
+    use crate::storage::tags::tag_url;
+    use crate::tests::insert_json_tree;
+    use crate::types::VisitTransition;
+    use serde_json::json;
+
+    fn parse(url: &str) -> Result<PlaceQuery> {
+        PlaceQuery::from_url(&Url::parse(url).unwrap())
+    }
+
+    fn urls(results: QueryResults) -> Vec<String> {
+        match results {
+            QueryResults::Pages(pages) => pages.into_iter().map(|p| p.url.into_string()).collect(),
+            QueryResults::Tags(_) => panic!("Expected pages"),
+        }
+    }
+
+    fn run(conn: &PlacesDb, url: &str) -> Result<QueryResults> {
+        run_query(conn, &parse(url)?)
+    }
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        assert_eq!(
+            parse("place:sort=8&maxResults=10")?,
+            PlaceQuery {
+                sort: QuerySort::VisitCountDescending,
+                max_results: Some(10),
+                ..PlaceQuery::default()
+            }
+        );
+        assert_eq!(
+            parse("place:type=6&sort=14&maxResults=0")?,
+            PlaceQuery {
+                result_type: QueryResultType::Tags,
+                sort: QuerySort::LastModifiedDescending,
+                ..PlaceQuery::default()
+            }
+        );
+        assert_eq!(
+            parse("place:tag=foo&tag=b%20ar&parent=folderAAAAAA&folder=TOOLBAR&folder=123")?,
+            PlaceQuery {
+                tags: vec!["foo".into(), "b ar".into()],
+                folders: Some(vec!["folderAAAAAA".into(), "toolbar_____".into()]),
+                ..PlaceQuery::default()
+            }
+        );
+        assert_eq!(
+            parse("place:queryType=1&sort=12&excludeQueries=1&excludeItems=1")?,
+            PlaceQuery {
+                query_type: QueryType::Bookmarks,
+                sort: QuerySort::DateAddedDescending,
+                exclude_queries: true,
+                ..PlaceQuery::default()
+            }
+        );
+
+        assert!(parse("http://example.com/?sort=1").is_err());
+        assert!(parse("place:sort=9").is_err());
+        assert!(parse("place:sort=abc").is_err());
+        assert!(parse("place:type=3").is_err());
+        assert!(parse("place:queryType=2").is_err());
+        assert!(parse("place:folder=SOMEWHERE").is_err());
+        assert!(parse("place:parent=bad").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_queries() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+
+        for (url, visits) in &[
+            ("https://example.com/a", 1),
+            ("https://example.com/b", 3),
+            ("https://example.com/c", 2),
+        ] {
+            for i in 0..*visits {
+                apply_observation(
+                    &conn,
+                    VisitObservation::new(Url::parse(url)?)
+                        .with_visit_type(VisitTransition::Link)
+                        .with_at(Timestamp(10_000 + i * 1_000)),
+                )?;
+            }
+        }
+        // Bookmarked, but never visited.
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [{ "guid": "bookmarkDDDD", "url": "https://example.com/d" }],
+            }),
+        );
+        tag_url(&conn, &Url::parse("https://example.com/c")?, "foo")?;
+        tag_url(&conn, &Url::parse("https://example.com/d")?, "foo")?;
+        tag_url(&conn, &Url::parse("https://example.com/d")?, "bar")?;
+
+        // "Most Visited".
+        assert_eq!(
+            urls(run(&conn, "place:sort=8&maxResults=2")?),
+            vec!["https://example.com/b", "https://example.com/c"]
+        );
+        assert_eq!(
+            urls(run(&conn, "place:sort=5")?),
+            vec![
+                "https://example.com/a",
+                "https://example.com/b",
+                "https://example.com/c"
+            ]
+        );
+        assert_eq!(
+            urls(run(&conn, "place:sort=4&maxResults=1")?),
+            vec!["https://example.com/b"]
+        );
+
+        // Tag queries include unvisited pages.
+        assert_eq!(
+            urls(run(&conn, "place:tag=foo&sort=5")?),
+            vec!["https://example.com/c", "https://example.com/d"]
+        );
+        assert_eq!(
+            urls(run(&conn, "place:tag=foo&tag=bar")?),
+            vec!["https://example.com/d"]
+        );
+
+        match run(&conn, "place:type=6&sort=1")? {
+            QueryResults::Tags(tags) => assert_eq!(
+                tags.into_iter().map(|t| t.tag).collect::<Vec<_>>(),
+                vec!["bar", "foo"]
+            ),
+            QueryResults::Pages(_) => panic!("Expected tags"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_bookmark_queries() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Toolbar.as_str()),
+                "children": [
+                    {
+                        "guid": "bookmarkAAAA",
+                        "url": "https://example.com/a",
+                        "title": "A",
+                        "date_added": 1_000,
+                    },
+                    {
+                        "guid": "queryBBBBBBB",
+                        "url": "place:queryType=1&sort=12&excludeQueries=1",
+                        "title": "Recently Bookmarked",
+                        "date_added": 2_000,
+                    },
+                ],
+            }),
+        );
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    {
+                        "guid": "bookmarkCCCC",
+                        "url": "https://example.com/c",
+                        "title": "C",
+                        "date_added": 3_000,
+                    },
+                    {
+                        "guid": "bookmarkDDDD",
+                        "url": "https://example.com/a",
+                        "title": "Also A",
+                        "date_added": 4_000,
+                    },
+                ],
+            }),
+        );
+
+        let pages = match run_query_for_bookmark(&conn, &"queryBBBBBBB".into())? {
+            QueryResults::Pages(pages) => pages,
+            QueryResults::Tags(_) => panic!("Expected pages"),
+        };
+        assert_eq!(
+            pages
+                .iter()
+                .map(|p| (p.bookmark_guid.clone().unwrap(), p.title.clone().unwrap()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("bookmarkDDDD".into(), "Also A".into()),
+                ("bookmarkCCCC".into(), "C".into()),
+                ("bookmarkAAAA".into(), "A".into()),
+            ]
+        );
+        assert_eq!(pages[0].date_added, Some(Timestamp(4_000)));
+        assert_eq!(pages[0].last_visit_date, None);
+
+        // The FFI says which kind of results it's returning.
+        let msg = msg_types::QueryResults::from(QueryResults::Pages(pages));
+        assert_eq!(msg.result_type, QueryResultType::Pages as i32);
+        assert_eq!(msg.pages[0].bookmark_guid, Some("bookmarkDDDD".into()));
+        assert_eq!(msg.pages[0].date_added, Some(4_000));
+        assert_eq!(msg.pages[0].last_visit_date, None);
+        assert!(msg.tags.is_empty());
+
+        assert_eq!(
+            urls(run(&conn, "place:folder=TOOLBAR")?),
+            vec![
+                "https://example.com/a",
+                "place:queryType=1&sort=12&excludeQueries=1"
+            ]
+        );
+        assert_eq!(
+            urls(run(
+                &conn,
+                "place:parent=unfiled_____&parent=toolbar_____&sort=1"
+            )?),
+            vec![
+                "https://example.com/a",
+                "https://example.com/a",
+                "https://example.com/c",
+                "place:queryType=1&sort=12&excludeQueries=1"
+            ]
+        );
+        // Desktop's folder IDs don't mean anything here.
+        assert!(urls(run(&conn, "place:folder=2")?).is_empty());
+
+        assert!(run_query_for_bookmark(&conn, &"bookmarkXXXX".into()).is_err());
+        assert!(run_query_for_bookmark(&conn, &"bookmarkAAAA".into()).is_err());
+        assert!(run_query_for_bookmark(&conn, &BookmarkRootGuid::Toolbar.as_guid()).is_err());
+        Ok(())
+    }
+}
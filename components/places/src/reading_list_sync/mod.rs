@@ -0,0 +1,15 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Syncing the reading list, in its own collection.
+//!
+//! The reading list is small and flat, so unlike bookmarks, there's no
+//! mirror: incoming records are reconciled against local items directly.
+//! Items for the same URL are merged, and when an item changed on both
+//! sides, the most recent change wins.
+
+pub mod record;
+pub mod store;
+
+pub const COLLECTION_NAME: &str = "readinglist";
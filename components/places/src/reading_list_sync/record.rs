@@ -0,0 +1,52 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::storage::reading_list::ReadingListItem;
+use crate::types::Timestamp;
+use serde_derive::*;
+use sync_guid::Guid as SyncGuid;
+
+fn default_unread() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingListRecord {
+    pub id: SyncGuid,
+
+    pub url: String,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excerpt: Option<String>,
+
+    /// In milliseconds.
+    #[serde(default)]
+    pub date_added: Timestamp,
+
+    #[serde(default = "default_unread")]
+    pub unread: bool,
+
+    #[serde(default)]
+    pub favorite: bool,
+}
+
+impl From<ReadingListItem> for ReadingListRecord {
+    fn from(item: ReadingListItem) -> Self {
+        ReadingListRecord {
+            id: item.guid,
+            url: item.url.into_string(),
+            title: item.title,
+            excerpt: item.excerpt,
+            date_added: item.date_added,
+            unread: item.unread,
+            favorite: item.favorite,
+        }
+    }
+}
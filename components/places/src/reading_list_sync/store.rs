@@ -0,0 +1,618 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::record::ReadingListRecord;
+use super::COLLECTION_NAME;
+use crate::api::places_api::ConnectionType;
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::storage::reading_list::{self, ReadingListItem};
+use crate::storage::{delete_meta, get_meta, put_meta, URL_LENGTH_MAX};
+use crate::types::{SyncStatus, Timestamp};
+use rusqlite::Row;
+use sql_support::{self, ConnExt, SqlInterruptScope};
+use std::cmp::{max, min};
+use std::result;
+use sync15::{
+    telemetry, CollSyncIds, CollectionRequest, IncomingChangeset, OutgoingChangeset, Payload,
+    ServerTimestamp, Store, StoreSyncAssociation,
+};
+use sync_guid::Guid as SyncGuid;
+use url::Url;
+
+pub const LAST_SYNC_META_KEY: &str = "reading_list_last_sync_time";
+// Note that all engines in this crate should use a *different* meta key
+// for the global sync ID, because engines are reset individually.
+pub const GLOBAL_SYNCID_META_KEY: &str = "reading_list_global_sync_id";
+pub const COLLECTION_SYNCID_META_KEY: &str = "reading_list_sync_id";
+
+/// A local reading list item, with its sync metadata.
+struct LocalItem {
+    item: ReadingListItem,
+    sync_status: SyncStatus,
+    sync_change_counter: i64,
+}
+
+impl LocalItem {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            item: ReadingListItem::from_row(row)?,
+            sync_status: SyncStatus::from_u8(row.get("syncStatus")?),
+            sync_change_counter: row.get("syncChangeCounter")?,
+        })
+    }
+}
+
+// A ReadingListStore is short-lived and constructed each sync by something
+// which owns the connection and ClientInfo.
+pub struct ReadingListStore<'a> {
+    pub db: &'a PlacesDb,
+    interruptee: &'a SqlInterruptScope,
+}
+
+impl<'a> ReadingListStore<'a> {
+    pub fn new(db: &'a PlacesDb, interruptee: &'a SqlInterruptScope) -> Self {
+        assert_eq!(db.conn_type(), ConnectionType::Sync);
+        Self { db, interruptee }
+    }
+
+    fn apply_incoming_records(
+        &self,
+        inbound: IncomingChangeset,
+        incoming_telemetry: &mut telemetry::EngineIncoming,
+    ) -> Result<()> {
+        for (payload, server_modified) in inbound.changes {
+            self.interruptee.err_if_interrupted()?;
+            let guid = payload.id.clone();
+            if payload.is_tombstone() {
+                self.apply_incoming_tombstone(&guid)?;
+                incoming_telemetry.applied(1);
+                continue;
+            }
+            let record: ReadingListRecord = match payload.into_record() {
+                Ok(record) => record,
+                Err(e) => {
+                    log::warn!("Skipping invalid reading list record {}: {}", guid, e);
+                    incoming_telemetry.failed(1);
+                    continue;
+                }
+            };
+            match Url::parse(&record.url) {
+                Ok(ref url) if url.as_str().len() <= URL_LENGTH_MAX => {
+                    self.apply_incoming_record(&record, url, server_modified)?;
+                    incoming_telemetry.applied(1);
+                }
+                _ => {
+                    log::warn!("Skipping reading list record {} with a bad URL", guid);
+                    incoming_telemetry.failed(1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_incoming_tombstone(&self, guid: &SyncGuid) -> Result<()> {
+        self.db.execute_named_cached(
+            "DELETE FROM moz_reading_list WHERE guid = :guid",
+            &[(":guid", guid)],
+        )?;
+        self.db.execute_named_cached(
+            "DELETE FROM moz_reading_list_deleted WHERE guid = :guid",
+            &[(":guid", guid)],
+        )?;
+        Ok(())
+    }
+
+    fn apply_incoming_record(
+        &self,
+        record: &ReadingListRecord,
+        url: &Url,
+        server_modified: ServerTimestamp,
+    ) -> Result<()> {
+        // If we removed the item locally, keep it removed. We'll upload our
+        // tombstone, replacing the record.
+        let locally_deleted = self.db.try_query_one::<i64>(
+            "SELECT 1 FROM moz_reading_list_deleted WHERE guid = :guid",
+            &[(":guid", &record.id)],
+            true,
+        )?;
+        if locally_deleted.is_some() {
+            return Ok(());
+        }
+
+        let by_guid = self.fetch_local_item("guid = :value", &record.id)?;
+        let dupe = self
+            .fetch_local_item("url = :value", &url.as_str())?
+            .filter(|dupe| dupe.item.guid != record.id);
+        let local = match (by_guid, dupe) {
+            (None, Some(dupe)) => {
+                // We have the same page under a different GUID, so take the
+                // incoming GUID for it.
+                self.remove_local_item(&dupe, Some(&record.id))?;
+                Some(LocalItem {
+                    item: ReadingListItem {
+                        guid: record.id.clone(),
+                        ..dupe.item
+                    },
+                    ..dupe
+                })
+            }
+            (Some(local), Some(dupe)) => {
+                // The incoming record moved to a page we already have under
+                // another GUID. We only need one of them.
+                self.remove_local_item(&dupe, None)?;
+                Some(local)
+            }
+            (local, None) => local,
+        };
+
+        let remote_modified = Timestamp(max(server_modified.as_millis(), 0) as u64);
+        let remote_added = if record.date_added == Timestamp(0) {
+            remote_modified
+        } else {
+            record.date_added
+        };
+        match local {
+            None => {
+                self.db.execute_named_cached(
+                    "INSERT INTO moz_reading_list(guid, url, title, excerpt, dateAdded,
+                                                  lastModified, unread, favorite,
+                                                  syncStatus, syncChangeCounter)
+                     VALUES(:guid, :url, :title, :excerpt, :dateAdded,
+                            :lastModified, :unread, :favorite,
+                            :syncStatus, 0)",
+                    &[
+                        (":guid", &record.id),
+                        (":url", &url.as_str()),
+                        (":title", &record.title),
+                        (":excerpt", &record.excerpt),
+                        (":dateAdded", &remote_added),
+                        (":lastModified", &remote_modified),
+                        (":unread", &record.unread),
+                        (":favorite", &record.favorite),
+                        (":syncStatus", &SyncStatus::Normal),
+                    ],
+                )?;
+            }
+            Some(local) => {
+                // Keep the oldest date added on both sides, and upload it if
+                // the server doesn't have it.
+                let date_added = min(local.item.date_added, remote_added);
+                let remote_wins =
+                    local.sync_change_counter == 0 || remote_modified >= local.item.last_modified;
+                if remote_wins {
+                    self.db.execute_named_cached(
+                        "UPDATE moz_reading_list SET
+                             url = :url,
+                             title = :title,
+                             excerpt = :excerpt,
+                             dateAdded = :dateAdded,
+                             lastModified = :lastModified,
+                             unread = :unread,
+                             favorite = :favorite,
+                             syncStatus = :syncStatus,
+                             syncChangeCounter = :syncChangeCounter
+                         WHERE guid = :guid",
+                        &[
+                            (":guid", &record.id),
+                            (":url", &url.as_str()),
+                            (":title", &record.title),
+                            (":excerpt", &record.excerpt),
+                            (":dateAdded", &date_added),
+                            (
+                                ":lastModified",
+                                &max(local.item.last_modified, remote_modified),
+                            ),
+                            (":unread", &record.unread),
+                            (":favorite", &record.favorite),
+                            (":syncStatus", &SyncStatus::Normal),
+                            (
+                                ":syncChangeCounter",
+                                &if date_added == remote_added { 0 } else { 1 },
+                            ),
+                        ],
+                    )?;
+                } else {
+                    self.db.execute_named_cached(
+                        "UPDATE moz_reading_list SET
+                             dateAdded = :dateAdded,
+                             syncStatus = :syncStatus
+                         WHERE guid = :guid",
+                        &[
+                            (":guid", &record.id),
+                            (":dateAdded", &date_added),
+                            (":syncStatus", &SyncStatus::Normal),
+                        ],
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn fetch_local_item(
+        &self,
+        condition: &str,
+        value: &dyn rusqlite::ToSql,
+    ) -> Result<Option<LocalItem>> {
+        self.db.try_query_row(
+            &format!(
+                "SELECT guid, url, title, excerpt, dateAdded, lastModified, unread,
+                        favorite, syncStatus, syncChangeCounter
+                 FROM moz_reading_list
+                 WHERE {}",
+                condition
+            ),
+            &[(":value", value)],
+            LocalItem::from_row,
+            true,
+        )
+    }
+
+    /// Removes a duplicate local item, leaving a tombstone if it was synced.
+    /// If `new_guid` is given, the item is kept, but takes that GUID instead.
+    fn remove_local_item(&self, local: &LocalItem, new_guid: Option<&SyncGuid>) -> Result<()> {
+        if local.sync_status == SyncStatus::Normal {
+            self.db.execute_named_cached(
+                "INSERT OR IGNORE INTO moz_reading_list_deleted(guid, dateRemoved)
+                 VALUES(:guid, :now)",
+                &[(":guid", &local.item.guid), (":now", &Timestamp::now())],
+            )?;
+        }
+        match new_guid {
+            Some(new_guid) => self.db.execute_named_cached(
+                "UPDATE moz_reading_list SET guid = :newGuid WHERE guid = :guid",
+                &[(":guid", &local.item.guid), (":newGuid", new_guid)],
+            )?,
+            None => self.db.execute_named_cached(
+                "DELETE FROM moz_reading_list WHERE guid = :guid",
+                &[(":guid", &local.item.guid)],
+            )?,
+        };
+        Ok(())
+    }
+
+    /// Stages changed items and tombstones for upload, along with their
+    /// change counters, and returns their records.
+    fn fetch_outgoing_records(&self, timestamp: ServerTimestamp) -> Result<OutgoingChangeset> {
+        self.db.execute_batch(
+            "DELETE FROM readingListToUpload;
+
+             INSERT INTO readingListToUpload(guid, syncChangeCounter)
+             SELECT guid, syncChangeCounter FROM moz_reading_list
+             WHERE syncChangeCounter > 0;
+
+             INSERT OR IGNORE INTO readingListToUpload(guid, syncChangeCounter, isDeleted)
+             SELECT guid, 1, 1 FROM moz_reading_list_deleted;",
+        )?;
+
+        let mut outgoing = OutgoingChangeset::new(COLLECTION_NAME.into(), timestamp);
+        let items = self.db.query_rows_and_then_named(
+            "SELECT r.guid, r.url, r.title, r.excerpt, r.dateAdded, r.lastModified,
+                    r.unread, r.favorite
+             FROM readingListToUpload u
+             JOIN moz_reading_list r ON r.guid = u.guid
+             WHERE NOT u.isDeleted",
+            &[],
+            ReadingListItem::from_row,
+        )?;
+        for item in items {
+            self.interruptee.err_if_interrupted()?;
+            outgoing
+                .changes
+                .push(Payload::from_record(ReadingListRecord::from(item))?);
+        }
+        let tombstones: Vec<SyncGuid> = self.db.query_rows_into(
+            "SELECT guid FROM readingListToUpload WHERE isDeleted",
+            &[],
+            |row| row.get(0),
+        )?;
+        for guid in tombstones {
+            outgoing
+                .changes
+                .push(Payload::new_tombstone(guid.into_string()));
+        }
+        Ok(outgoing)
+    }
+
+    /// Decrements the change counter, updates the sync status, and cleans up
+    /// tombstones for successfully synced items.
+    fn push_synced_items(
+        &self,
+        uploaded_at: ServerTimestamp,
+        records_synced: Vec<SyncGuid>,
+    ) -> Result<()> {
+        let mut tx = self.db.begin_transaction()?;
+        sql_support::each_chunk(&records_synced, |chunk, _| -> Result<()> {
+            // If the item was changed during the sync, its change counter
+            // will still be > 0 for the next sync.
+            self.db.execute(
+                &format!(
+                    "UPDATE moz_reading_list SET
+                         syncChangeCounter = max(syncChangeCounter - (
+                             SELECT u.syncChangeCounter FROM readingListToUpload u
+                             WHERE u.guid = moz_reading_list.guid), 0),
+                         syncStatus = {sync_status}
+                     WHERE guid IN (SELECT guid FROM readingListToUpload
+                                    WHERE NOT isDeleted) AND
+                           guid IN ({values})",
+                    sync_status = SyncStatus::Normal as u8,
+                    values = sql_support::repeat_sql_values(chunk.len())
+                ),
+                chunk,
+            )?;
+            self.db.execute(
+                &format!(
+                    "DELETE FROM moz_reading_list_deleted
+                     WHERE guid IN (SELECT guid FROM readingListToUpload
+                                    WHERE isDeleted) AND
+                           guid IN ({values})",
+                    values = sql_support::repeat_sql_values(chunk.len())
+                ),
+                chunk,
+            )?;
+            tx.maybe_commit()?;
+            self.interruptee.err_if_interrupted()?;
+            Ok(())
+        })?;
+
+        // Fast-forward the last sync time, so that we don't download the
+        // records we just uploaded on the next sync.
+        put_meta(self.db, LAST_SYNC_META_KEY, &uploaded_at.as_millis())?;
+        self.db.execute_batch("DELETE FROM readingListToUpload")?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Removes all sync metadata, such that the next sync is treated as a
+    /// first sync. Unlike `wipe`, this keeps all local items, but clears
+    /// pending tombstones, and forgets the last sync time.
+    pub(crate) fn reset(&self, assoc: &StoreSyncAssociation) -> Result<()> {
+        let tx = self.db.begin_transaction()?;
+        self.db.execute_batch(&format!(
+            "DELETE FROM moz_reading_list_deleted;
+
+             UPDATE moz_reading_list
+             SET syncChangeCounter = 1,
+                 syncStatus = {}",
+            (SyncStatus::New as u8)
+        ))?;
+        put_meta(self.db, LAST_SYNC_META_KEY, &0)?;
+        match assoc {
+            StoreSyncAssociation::Disconnected => {
+                delete_meta(self.db, GLOBAL_SYNCID_META_KEY)?;
+                delete_meta(self.db, COLLECTION_SYNCID_META_KEY)?;
+            }
+            StoreSyncAssociation::Connected(ids) => {
+                put_meta(self.db, GLOBAL_SYNCID_META_KEY, &ids.global)?;
+                put_meta(self.db, COLLECTION_SYNCID_META_KEY, &ids.coll)?;
+            }
+        };
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl<'a> Store for ReadingListStore<'a> {
+    #[inline]
+    fn collection_name(&self) -> &'static str {
+        COLLECTION_NAME
+    }
+
+    fn apply_incoming(
+        &self,
+        inbound: IncomingChangeset,
+        telem: &mut telemetry::Engine,
+    ) -> result::Result<OutgoingChangeset, failure::Error> {
+        let timestamp = inbound.timestamp;
+        let tx = self.db.begin_transaction()?;
+        let mut incoming_telemetry = telemetry::EngineIncoming::new();
+        let result = self.apply_incoming_records(inbound, &mut incoming_telemetry);
+        telem.incoming(incoming_telemetry);
+        result?;
+
+        // write the timestamp now, so if we are interrupted before uploading
+        // we don't need to re-download the same records.
+        put_meta(self.db, LAST_SYNC_META_KEY, &timestamp.as_millis())?;
+
+        let outgoing = self.fetch_outgoing_records(timestamp)?;
+        tx.commit()?;
+        Ok(outgoing)
+    }
+
+    fn sync_finished(
+        &self,
+        new_timestamp: ServerTimestamp,
+        records_synced: Vec<SyncGuid>,
+    ) -> result::Result<(), failure::Error> {
+        self.push_synced_items(new_timestamp, records_synced)?;
+        Ok(())
+    }
+
+    fn get_collection_request(&self) -> result::Result<CollectionRequest, failure::Error> {
+        let since = get_meta::<i64>(self.db, LAST_SYNC_META_KEY)?.unwrap_or_default();
+        Ok(CollectionRequest::new(COLLECTION_NAME)
+            .full()
+            .newer_than(ServerTimestamp(since)))
+    }
+
+    fn get_sync_assoc(&self) -> result::Result<StoreSyncAssociation, failure::Error> {
+        let global = get_meta(self.db, GLOBAL_SYNCID_META_KEY)?;
+        let coll = get_meta(self.db, COLLECTION_SYNCID_META_KEY)?;
+        Ok(if let (Some(global), Some(coll)) = (global, coll) {
+            StoreSyncAssociation::Connected(CollSyncIds { global, coll })
+        } else {
+            StoreSyncAssociation::Disconnected
+        })
+    }
+
+    fn reset(&self, assoc: &StoreSyncAssociation) -> result::Result<(), failure::Error> {
+        ReadingListStore::reset(self, assoc)?;
+        Ok(())
+    }
+
+    /// Erases all local items and tombstones, but keeps the last sync time.
+    fn wipe(&self) -> result::Result<(), failure::Error> {
+        let tx = self.db.begin_transaction()?;
+        reading_list::delete_everything(self.db)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_api;
+    use crate::storage::reading_list::{add_reading_list_item, delete_reading_list_item};
+    use serde_json::json;
+
+    fn guids(outgoing: &OutgoingChangeset) -> Vec<(String, bool)> {
+        let mut guids: Vec<(String, bool)> = outgoing
+            .changes
+            .iter()
+            .map(|payload| (payload.id.to_string(), payload.is_tombstone()))
+            .collect();
+        guids.sort();
+        guids
+    }
+
+    #[test]
+    fn test_sync() -> Result<()> {
+        let _ = env_logger::try_init();
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+
+        let url = |path: &str| Url::parse(&format!("https://example.com/{}", path)).unwrap();
+        // A was never synced, and is also on the server with another GUID.
+        let a = add_reading_list_item(&writer, &url("a"), Some("A"), None)?;
+        // B, C, D, E and F were synced, and C and D were changed since. C
+        // changed before the server's copy, and D after.
+        let mut synced = Vec::new();
+        for path in &["b", "c", "d", "e", "f"] {
+            synced.push(add_reading_list_item(&writer, &url(path), None, None)?.guid);
+        }
+        writer.execute_batch(&format!(
+            "UPDATE moz_reading_list SET
+                 syncStatus = {normal},
+                 syncChangeCounter = 0
+             WHERE url <> 'https://example.com/a';
+             UPDATE moz_reading_list SET
+                 syncChangeCounter = 1,
+                 lastModified = 1
+             WHERE url = 'https://example.com/c';
+             UPDATE moz_reading_list SET
+                 syncChangeCounter = 1
+             WHERE url = 'https://example.com/d';",
+            normal = SyncStatus::Normal as u8,
+        ))?;
+        // F was removed locally.
+        delete_reading_list_item(&writer, &synced[4])?;
+
+        let syncer = api.open_sync_connection()?;
+        let interrupt_scope = syncer.begin_interrupt_scope();
+        let store = ReadingListStore::new(&syncer, &interrupt_scope);
+        let records = vec![
+            json!({
+                "id": "itemAAAAAAAA",
+                "url": "https://example.com/a",
+                "title": "Remote A",
+                "dateAdded": 1_000,
+            }),
+            json!({
+                "id": synced[0],
+                "url": "https://example.com/b",
+                "title": "Remote B",
+                "unread": false,
+            }),
+            json!({
+                "id": synced[1],
+                "url": "https://example.com/c",
+                "title": "Remote C",
+            }),
+            json!({
+                "id": synced[2],
+                "url": "https://example.com/d",
+                "title": "Remote D",
+            }),
+            json!({
+                "id": synced[3],
+                "deleted": true,
+            }),
+            json!({
+                "id": synced[4],
+                "url": "https://example.com/f",
+                "title": "Remote F",
+            }),
+            json!({
+                "id": "itemGGGGGGGG",
+                "url": "https://example.com/g",
+                "favorite": true,
+            }),
+        ];
+        let mut incoming = IncomingChangeset::new(COLLECTION_NAME.into(), ServerTimestamp(10_000));
+        for record in records {
+            let payload = Payload::from_json(record).unwrap();
+            incoming.changes.push((payload, ServerTimestamp(10_000)));
+        }
+        let outgoing = store
+            .apply_incoming(incoming, &mut telemetry::Engine::new("readinglist"))
+            .expect("Should apply incoming records");
+
+        // A takes the incoming GUID, and the older date added. D's local
+        // changes win, and F stays removed.
+        let mut expected = vec![
+            (synced[2].to_string(), false),
+            (synced[4].to_string(), true),
+            ("itemAAAAAAAA".to_string(), false),
+        ];
+        expected.sort();
+        assert_eq!(guids(&outgoing), expected);
+        assert!(reading_list::get_reading_list_item(&syncer, &a.guid)?.is_none());
+        let new_a = reading_list::get_reading_list_item(&syncer, &"itemAAAAAAAA".into())?.unwrap();
+        assert_eq!(new_a.title, Some("A".to_string()));
+        assert_eq!(new_a.date_added, Timestamp(1_000));
+        let b = reading_list::get_reading_list_item(&syncer, &synced[0])?.unwrap();
+        assert_eq!(b.title, Some("Remote B".to_string()));
+        assert!(!b.unread);
+        let c = reading_list::get_reading_list_item(&syncer, &synced[1])?.unwrap();
+        assert_eq!(c.title, Some("Remote C".to_string()));
+        let d = reading_list::get_reading_list_item(&syncer, &synced[2])?.unwrap();
+        assert_eq!(d.title, None);
+        assert!(reading_list::get_reading_list_item(&syncer, &synced[3])?.is_none());
+        assert!(reading_list::get_reading_list_item(&syncer, &synced[4])?.is_none());
+        let g = reading_list::get_reading_list_item(&syncer, &"itemGGGGGGGG".into())?.unwrap();
+        assert!(g.favorite);
+        assert!(g.unread);
+
+        // D changes again during the sync, so it should be uploaded next time.
+        writer.execute_batch(
+            "UPDATE moz_reading_list SET
+                 syncChangeCounter = syncChangeCounter + 1
+             WHERE url = 'https://example.com/d'",
+        )?;
+        let uploaded = outgoing
+            .changes
+            .iter()
+            .map(|payload| payload.id.clone())
+            .collect();
+        store
+            .sync_finished(ServerTimestamp(20_000), uploaded)
+            .expect("Should finish sync");
+
+        let changed: Vec<(SyncGuid, i64, u8)> = syncer.query_rows_and_then_named(
+            "SELECT guid, syncChangeCounter, syncStatus FROM moz_reading_list
+             WHERE syncChangeCounter > 0 OR syncStatus <> 2",
+            &[],
+            |row| -> rusqlite::Result<_> { Ok((row.get(0)?, row.get(1)?, row.get(2)?)) },
+        )?;
+        assert_eq!(changed, vec![(synced[2].clone(), 1, 2)]);
+        assert_eq!(
+            syncer.query_one::<i64>("SELECT COUNT(*) FROM moz_reading_list_deleted")?,
+            0
+        );
+        assert_eq!(get_meta::<i64>(&syncer, LAST_SYNC_META_KEY)?, Some(20_000));
+        Ok(())
+    }
+}
@@ -2,12 +2,14 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use super::tags::{get_tags_for_url, remove_all_tags_from_url, tag_url_in_tx, validate_tag};
 use super::RowId;
 use super::{delete_meta, put_meta};
 use super::{fetch_page_info, new_page_info};
 use crate::bookmark_sync;
 use crate::db::PlacesDb;
 use crate::error::*;
+use crate::observer::PlacesEvent;
 use crate::types::{BookmarkType, SyncStatus, Timestamp};
 use rusqlite::types::ToSql;
 use rusqlite::{Connection, Row};
@@ -20,16 +22,28 @@ use serde_derive::*;
 use serde_json::{self, json};
 use sql_support::{self, ConnExt};
 use std::cmp::{max, min};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use sync_guid::Guid as SyncGuid;
 use url::Url;
 
+pub(crate) use changes::prune_bookmark_changes;
+pub use changes::{get_bookmark_change_token, get_bookmark_changes_since, BookmarkChanges};
+pub use duplicates::{find_duplicate_bookmarks, merge_duplicates, DuplicateGroup, DuplicateMatch};
 pub use public_node::PublicNode;
+pub use reorder::{move_items, reorder_children};
+pub use repair::{check_and_repair, RepairReport};
 pub use root_guid::{BookmarkRootGuid, USER_CONTENT_ROOTS};
+pub(crate) use undo::{clear_undo_log, UndoLog};
+pub use undo::{redo, undo};
 
+mod changes;
 mod conversions;
+mod duplicates;
 pub mod public_node;
+mod reorder;
+mod repair;
 mod root_guid;
+mod undo;
 
 fn create_root(
     db: &Connection,
@@ -167,6 +181,9 @@ pub struct InsertableBookmark {
     pub guid: Option<SyncGuid>,
     pub url: Url,
     pub title: Option<String>,
+    pub keyword: Option<String>,
+    /// Tags to add to the bookmark's URL. Existing tags for the URL are kept.
+    pub tags: Vec<String>,
 }
 
 impl From<InsertableBookmark> for InsertableItem {
@@ -245,13 +262,47 @@ impl InsertableItem {
 
 pub fn insert_bookmark(db: &PlacesDb, bm: &InsertableItem) -> Result<SyncGuid> {
     let tx = db.begin_transaction()?;
-    let result = insert_bookmark_in_tx(db, bm);
+    let result =
+        insert_bookmark_in_tx(db, bm).and_then(|guid| Ok((undo::inserted(db, &guid)?, guid)));
     super::delete_pending_temp_tables(db)?;
     match result {
         Ok(_) => tx.commit()?,
         Err(_) => tx.rollback()?,
     }
-    result
+    let (change, guid) = result?;
+    change.record(db);
+    notify_inserted(db, std::slice::from_ref(&guid));
+    Ok(guid)
+}
+
+/// Tells observers about newly inserted items. Called after the transaction
+/// which inserted them commits, so failing to read them back is only logged:
+/// the change itself has already been made.
+pub(crate) fn notify_inserted(db: &PlacesDb, guids: &[SyncGuid]) {
+    if db.observers.is_empty() {
+        return;
+    }
+    let mut events = Vec::with_capacity(guids.len());
+    for guid in guids {
+        let raw = match get_raw_bookmark(db, guid) {
+            Ok(raw) => raw,
+            Err(e) => {
+                log::warn!("Failed to notify observers about {}: {}", guid, e);
+                continue;
+            }
+        };
+        if let Some(raw) = raw {
+            if let Some(parent_guid) = raw.parent_guid {
+                events.push(PlacesEvent::BookmarkInserted {
+                    guid: raw.guid,
+                    parent_guid,
+                    index: raw.position,
+                    url: raw.url.map(Url::into_string),
+                });
+            }
+        }
+    }
+    db.observers.notify_all(&events);
 }
 
 pub fn maybe_truncate_title<'a>(t: &Option<&'a str>) -> Option<&'a str> {
@@ -260,7 +311,7 @@ pub fn maybe_truncate_title<'a>(t: &Option<&'a str>) -> Option<&'a str> {
     t.map(|title| slice_up_to(title, TITLE_LENGTH_MAX))
 }
 
-fn insert_bookmark_in_tx(db: &PlacesDb, bm: &InsertableItem) -> Result<SyncGuid> {
+pub(crate) fn insert_bookmark_in_tx(db: &PlacesDb, bm: &InsertableItem) -> Result<SyncGuid> {
     // find the row ID of the parent.
     if bm.parent_guid() == BookmarkRootGuid::Root {
         return Err(InvalidPlaceInfo::CannotUpdateRoot(BookmarkRootGuid::Root).into());
@@ -271,6 +322,17 @@ fn insert_bookmark_in_tx(db: &PlacesDb, bm: &InsertableItem) -> Result<SyncGuid>
     if parent.bookmark_type != BookmarkType::Folder {
         return Err(InvalidPlaceInfo::InvalidParent(parent_guid.to_string()).into());
     }
+    // Check the keyword and tags before we write anything.
+    let (keyword, tags) = match bm {
+        InsertableItem::Bookmark(ref b) => (
+            validate_keyword(&b.keyword)?,
+            b.tags
+                .iter()
+                .map(|t| validate_tag(t).ensure_valid())
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        _ => (None, Vec::new()),
+    };
     // Do the "position" dance.
     let position = resolve_pos_for_insert(db, *bm.position(), &parent)?;
 
@@ -289,9 +351,9 @@ fn insert_bookmark_in_tx(db: &PlacesDb, bm: &InsertableItem) -> Result<SyncGuid>
     };
     let sql = "INSERT INTO moz_bookmarks
               (fk, type, parent, position, title, dateAdded, lastModified,
-               guid, syncStatus, syncChangeCounter) VALUES
+               guid, syncStatus, syncChangeCounter, keyword) VALUES
               (:fk, :type, :parent, :position, :title, :dateAdded, :lastModified,
-               :guid, :syncStatus, :syncChangeCounter)";
+               :guid, :syncStatus, :syncChangeCounter, :keyword)";
 
     let guid = bm.guid().clone().unwrap_or_else(SyncGuid::random);
     let date_added = bm.date_added().unwrap_or_else(Timestamp::now);
@@ -305,6 +367,9 @@ fn insert_bookmark_in_tx(db: &PlacesDb, bm: &InsertableItem) -> Result<SyncGuid>
     match bm {
         InsertableItem::Bookmark(ref b) => {
             let title = maybe_truncate_title(&b.title.as_ref().map(String::as_str));
+            if let Some(keyword) = keyword {
+                remove_keyword(db, keyword)?;
+            }
             db.execute_named_cached(
                 sql,
                 &[
@@ -318,8 +383,12 @@ fn insert_bookmark_in_tx(db: &PlacesDb, bm: &InsertableItem) -> Result<SyncGuid>
                     (":guid", &guid),
                     (":syncStatus", &SyncStatus::New),
                     (":syncChangeCounter", &1),
+                    (":keyword", &keyword),
                 ],
             )?;
+            if !tags.is_empty() {
+                set_tags_for_url(db, &b.url, &tags, false)?;
+            }
         }
         InsertableItem::Separator(ref _s) => {
             db.execute_named_cached(
@@ -364,16 +433,91 @@ fn insert_bookmark_in_tx(db: &PlacesDb, bm: &InsertableItem) -> Result<SyncGuid>
     Ok(guid)
 }
 
+/// Keywords follow the same rules as tags. An empty keyword is treated as
+/// no keyword.
+fn validate_keyword(keyword: &Option<String>) -> Result<Option<&str>> {
+    match keyword.as_ref().map(String::as_str) {
+        None | Some("") => Ok(None),
+        Some(k) => Ok(Some(validate_tag(k).ensure_valid()?)),
+    }
+}
+
+/// A keyword can only point at one bookmark, so this takes it away from any
+/// bookmark which already has it, flagging those bookmarks for upload.
+fn remove_keyword(db: &PlacesDb, keyword: &str) -> Result<()> {
+    db.execute_named_cached(
+        "UPDATE moz_bookmarks SET
+             keyword = NULL,
+             syncChangeCounter = syncChangeCounter + 1
+         WHERE keyword = :keyword",
+        &[(":keyword", &keyword)],
+    )?;
+    Ok(())
+}
+
+/// Adds `tags` to the URL, first removing its existing tags if `replace` is
+/// true. The tag triggers flag every bookmark of the URL for upload, so this
+/// leaves the tags alone if they wouldn't change.
+fn set_tags_for_url(db: &PlacesDb, url: &Url, tags: &[&str], replace: bool) -> Result<()> {
+    let existing = get_tags_for_url(db, url)?
+        .into_iter()
+        .collect::<HashSet<String>>();
+    let wanted = tags.iter().cloned().collect::<HashSet<&str>>();
+    let adding = wanted.iter().any(|t| !existing.contains(*t));
+    let removing = replace && existing.iter().any(|t| !wanted.contains(t.as_str()));
+    if !adding && !removing {
+        return Ok(());
+    }
+    if replace {
+        remove_all_tags_from_url(db, url)?;
+    }
+    for tag in wanted {
+        tag_url_in_tx(db, url, tag)?;
+    }
+    Ok(())
+}
+
 /// Delete the specified bookmark. Returns true if a bookmark with the guid
 /// existed and was deleted, false otherwise.
 pub fn delete_bookmark(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
     let tx = db.begin_transaction()?;
-    let result = delete_bookmark_in_tx(db, guid);
+    // Grab the existing item and its descendants first, so observers can be
+    // told where it was, and so the deletion can be undone.
+    let subtree = undo::fetch_subtree(db, guid)?;
+    let result = delete_bookmark_in_tx(db, guid).and_then(|deleted| {
+        Ok(if deleted {
+            Some(undo::deleted(&subtree)?)
+        } else {
+            None
+        })
+    });
     match result {
         Ok(_) => tx.commit()?,
         Err(_) => tx.rollback()?,
     }
-    result
+    match result? {
+        Some(change) => {
+            change.record(db);
+            if let Some(raw) = subtree.into_iter().next() {
+                notify_removed(db, raw);
+            }
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Tells observers about a removed item, given its state before it was
+/// removed. Called after the transaction which removed it commits.
+fn notify_removed(db: &PlacesDb, raw: RawBookmark) {
+    if let Some(parent_guid) = raw.parent_guid {
+        db.observers.notify(&PlacesEvent::BookmarkRemoved {
+            guid: raw.guid,
+            parent_guid,
+            index: raw.position,
+            url: raw.url.map(Url::into_string),
+        });
+    }
 }
 
 fn delete_bookmark_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
@@ -431,6 +575,10 @@ pub struct UpdatableBookmark {
     pub location: UpdateTreeLocation,
     pub url: Option<Url>,
     pub title: Option<String>,
+    /// As for `title`, an empty string removes the keyword.
+    pub keyword: Option<String>,
+    /// If specified, replaces all tags for the bookmark's URL.
+    pub tags: Option<Vec<String>>,
 }
 
 impl From<UpdatableBookmark> for UpdatableItem {
@@ -491,10 +639,64 @@ pub fn update_bookmark(db: &PlacesDb, guid: &SyncGuid, item: &UpdatableItem) ->
     let tx = db.begin_transaction()?;
     let existing = get_raw_bookmark(db, guid)?
         .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
-    let result = update_bookmark_in_tx(db, guid, item, existing);
-    // Note: `tx` automatically rolls back on drop if we don't commit
-    tx.commit()?;
-    result
+    // If the tags are being replaced, remember the old ones so that the
+    // update can be undone.
+    let old_tags = match item {
+        UpdatableItem::Bookmark(UpdatableBookmark {
+            url, tags: Some(_), ..
+        }) => match url.as_ref().or(existing.url.as_ref()) {
+            Some(url) => Some((url.clone(), get_tags_for_url(db, url)?)),
+            None => None,
+        },
+        _ => None,
+    };
+    let result = update_bookmark_in_tx(db, guid, item, existing.clone())
+        .and_then(|_| undo::updated(db, &existing, old_tags));
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result?.record(db);
+    notify_updated(db, &existing);
+    Ok(())
+}
+
+/// Tells observers how an item changed, given its state before the change.
+/// Called after the transaction which changed it commits, so, like
+/// `notify_inserted`, it only logs failures.
+fn notify_updated(db: &PlacesDb, old: &RawBookmark) {
+    if db.observers.is_empty() {
+        return;
+    }
+    let raw = match get_raw_bookmark(db, &old.guid) {
+        Ok(raw) => raw,
+        Err(e) => {
+            log::warn!("Failed to notify observers about {}: {}", old.guid, e);
+            return;
+        }
+    };
+    if let Some(raw) = raw {
+        let mut events = Vec::new();
+        if raw.title != old.title || raw.url != old.url || raw.keyword != old.keyword {
+            events.push(PlacesEvent::BookmarkChanged {
+                guid: old.guid.clone(),
+            });
+        }
+        if let (Some(old_parent_guid), Some(new_parent_guid)) =
+            (old.parent_guid.clone(), raw.parent_guid)
+        {
+            if old_parent_guid != new_parent_guid || old.position != raw.position {
+                events.push(PlacesEvent::BookmarkMoved {
+                    guid: old.guid.clone(),
+                    old_parent_guid,
+                    old_index: old.position,
+                    new_parent_guid,
+                    new_index: raw.position,
+                });
+            }
+        }
+        db.observers.notify_all(&events);
+    }
 }
 
 fn update_bookmark_in_tx(
@@ -524,6 +726,25 @@ fn update_bookmark_in_tx(
         .into());
     }
 
+    // Check the keyword and tags before we write anything.
+    let (keyword, tags) = match item {
+        UpdatableItem::Bookmark(b) => (
+            match &b.keyword {
+                None => raw.keyword.clone(),
+                Some(_) => validate_keyword(&b.keyword)?.map(str::to_string),
+            },
+            match &b.tags {
+                None => None,
+                Some(tags) => Some(
+                    tags.iter()
+                        .map(|t| validate_tag(t).ensure_valid())
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+            },
+        ),
+        _ => (None, None),
+    };
+
     let update_old_parent_status;
     let update_new_parent_status;
     // to make our life easier we update every field, using existing when
@@ -606,7 +827,12 @@ fn update_bookmark_in_tx(
         }
     };
 
-    let change_incr = title != raw.title || place_id != raw.place_id;
+    let change_incr = title != raw.title || place_id != raw.place_id || keyword != raw.keyword;
+    if keyword != raw.keyword {
+        if let Some(keyword) = &keyword {
+            remove_keyword(db, keyword)?;
+        }
+    }
 
     let now = Timestamp::now();
 
@@ -616,6 +842,7 @@ fn update_bookmark_in_tx(
             parent = :parent,
             position = :position,
             title = :title,
+            keyword = :keyword,
             lastModified = :now,
             syncChangeCounter = syncChangeCounter + :change_incr
         WHERE id = :id";
@@ -630,12 +857,25 @@ fn update_bookmark_in_tx(
                 ":title",
                 &maybe_truncate_title(&title.as_ref().map(String::as_str)),
             ),
+            (":keyword", &keyword),
             (":now", &now),
             (":change_incr", &(change_incr as u32)),
             (":id", &raw.row_id),
         ],
     )?;
 
+    if let Some(tags) = tags {
+        // Tags belong to the URL, so they follow the bookmark if it was
+        // changed to point somewhere else.
+        let url = match item {
+            UpdatableItem::Bookmark(UpdatableBookmark { url: Some(url), .. }) => Some(url),
+            _ => raw.url.as_ref(),
+        };
+        if let Some(url) = url {
+            set_tags_for_url(db, url, &tags, true)?;
+        }
+    }
+
     let sql_counter = "
         UPDATE moz_bookmarks SET syncChangeCounter = syncChangeCounter + 1
         WHERE id = :parent_id";
@@ -906,12 +1146,21 @@ impl<'de> Deserialize<'de> for BookmarkTreeNode {
     }
 }
 
-/// Get the URL of the bookmark matching a keyword
+/// Get the URL of the bookmark matching a keyword. Local keywords win over
+/// ones which are only in the mirror.
 pub fn bookmarks_get_url_for_keyword(db: &PlacesDb, keyword: &str) -> Result<Option<Url>> {
     let bookmark_url = db.try_query_row(
-        "SELECT url FROM moz_places p
-        JOIN moz_bookmarks_synced b ON b.placeId = p.id
-        WHERE b.keyword = :keyword",
+        "SELECT url FROM (
+           SELECT p.url, 0 AS isSynced FROM moz_places p
+           JOIN moz_bookmarks b ON b.fk = p.id
+           WHERE b.keyword = :keyword
+           UNION ALL
+           SELECT p.url, 1 AS isSynced FROM moz_places p
+           JOIN moz_bookmarks_synced b ON b.placeId = p.id
+           WHERE b.keyword = :keyword
+         )
+         ORDER BY isSynced
+         LIMIT 1",
         &[(":keyword", &keyword)],
         |row| row.get::<_, String>("url"),
         true,
@@ -1040,6 +1289,8 @@ fn add_subtree_infos(parent: &SyncGuid, tree: &FolderNode, insert_infos: &mut Ve
                     guid: b.guid.clone(),
                     url: b.url.clone(),
                     title: b.title.clone(),
+                    keyword: None,
+                    tags: Vec::new(),
                 }
                 .into(),
             ),
@@ -1078,6 +1329,9 @@ pub fn delete_everything(db: &PlacesDb) -> Result<()> {
     let tx = db.begin_transaction()?;
     delete_everything_in_tx(db)?;
     tx.commit()?;
+    // None of the logged changes can be undone now.
+    clear_undo_log(db);
+    db.observers.notify(&PlacesEvent::BookmarksCleared);
     Ok(())
 }
 
@@ -1118,11 +1372,13 @@ pub fn insert_tree(db: &PlacesDb, tree: &FolderNode) -> Result<()> {
     log::info!("insert_tree inserting {} records", insert_infos.len());
     let tx = db.begin_transaction()?;
 
+    let mut guids = Vec::with_capacity(insert_infos.len());
     for insertable in insert_infos {
-        insert_bookmark_in_tx(db, &insertable)?;
+        guids.push(insert_bookmark_in_tx(db, &insertable)?);
     }
     super::delete_pending_temp_tables(db)?;
     tx.commit()?;
+    notify_inserted(db, &guids);
     Ok(())
 }
 
@@ -1348,7 +1604,7 @@ pub fn fetch_tree(
 }
 
 /// A "raw" bookmark - a representation of the row and some summary fields.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct RawBookmark {
     pub place_id: Option<RowId>,
     pub row_id: RowId,
@@ -1358,6 +1614,7 @@ pub(crate) struct RawBookmark {
     pub position: u32,
     pub title: Option<String>,
     pub url: Option<Url>,
+    pub keyword: Option<String>,
     pub date_added: Timestamp,
     pub date_modified: Timestamp,
     pub guid: SyncGuid,
@@ -1384,6 +1641,7 @@ impl RawBookmark {
                 Some(s) => Some(Url::parse(&s)?),
                 None => None,
             },
+            keyword: row.get::<_, Option<String>>("keyword")?,
             date_added: row.get("dateAdded")?,
             date_modified: row.get("lastModified")?,
             guid: row.get::<_, String>("guid")?.into(),
@@ -1410,6 +1668,7 @@ const RAW_BOOKMARK_SQL: &str = "
         -- Note we return null for titles with an empty string.
         NULLIF(b.title, '') AS title,
         h.url AS url,
+        b.keyword,
         b.id AS _id,
         b.parent AS _parentId,
         (SELECT count(*) FROM moz_bookmarks WHERE parent = b.id) AS _childCount,
@@ -1514,6 +1773,136 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_keyword_and_tags() -> Result<()> {
+        use public_node::{fetch_bookmark, fetch_bookmarks_by_url};
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        let url = Url::parse("https://www.example.com")?;
+
+        let guid = insert_bookmark(
+            &conn,
+            &InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: url.clone(),
+                title: None,
+                keyword: Some("ex".into()),
+                tags: vec!["foo".into(), " bar ".into()],
+            }
+            .into(),
+        )?;
+        let node = fetch_bookmark(&conn, &guid, false)?.unwrap();
+        assert_eq!(node.keyword, Some("ex".into()));
+        let mut tags = node.tags.clone();
+        tags.sort();
+        assert_eq!(tags, vec!["bar".to_string(), "foo".to_string()]);
+        assert_eq!(
+            bookmarks_get_url_for_keyword(&conn, "ex")?,
+            Some(url.clone())
+        );
+
+        // Invalid tags and keywords are rejected without inserting anything.
+        let mut invalid = InsertableBookmark {
+            parent_guid: BookmarkRootGuid::Unfiled.into(),
+            position: BookmarkPosition::Append,
+            date_added: None,
+            last_modified: None,
+            guid: None,
+            url: Url::parse("https://www.example.com/invalid")?,
+            title: None,
+            keyword: None,
+            tags: vec!["  ".into()],
+        };
+        assert!(insert_bookmark(&conn, &invalid.clone().into()).is_err());
+        invalid.tags = vec![];
+        invalid.keyword = Some(" ".into());
+        assert!(insert_bookmark(&conn, &invalid.into()).is_err());
+        assert!(
+            fetch_bookmarks_by_url(&conn, &Url::parse("https://www.example.com/invalid")?)?
+                .is_empty()
+        );
+
+        // A second bookmark with the same keyword takes it away from the
+        // first, and flags the first for upload.
+        let counter = get_raw_bookmark(&conn, &guid)?.unwrap().sync_change_counter;
+        let other_url = Url::parse("https://www.example.com/other")?;
+        let other_guid = insert_bookmark(
+            &conn,
+            &InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: other_url.clone(),
+                title: None,
+                keyword: Some("ex".into()),
+                tags: vec![],
+            }
+            .into(),
+        )?;
+        assert_eq!(bookmarks_get_url_for_keyword(&conn, "ex")?, Some(other_url));
+        let raw = get_raw_bookmark(&conn, &guid)?.unwrap();
+        assert_eq!(raw.keyword, None);
+        assert_eq!(raw.sync_change_counter, counter + 1);
+
+        // Updating the tags replaces them, and leaves the keyword alone.
+        update_bookmark(
+            &conn,
+            &guid,
+            &UpdatableBookmark {
+                keyword: Some("ex2".into()),
+                tags: Some(vec!["baz".into()]),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        let node = fetch_bookmark(&conn, &guid, false)?.unwrap();
+        assert_eq!(node.keyword, Some("ex2".into()));
+        assert_eq!(node.tags, vec!["baz".to_string()]);
+
+        update_bookmark(
+            &conn,
+            &guid,
+            &UpdatableBookmark {
+                title: Some("new title".into()),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        let node = fetch_bookmark(&conn, &guid, false)?.unwrap();
+        assert_eq!(node.keyword, Some("ex2".into()));
+        assert_eq!(node.tags, vec!["baz".to_string()]);
+
+        // An empty keyword and empty tags remove them.
+        update_bookmark(
+            &conn,
+            &guid,
+            &UpdatableBookmark {
+                keyword: Some("".into()),
+                tags: Some(vec![]),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        let node = fetch_bookmark(&conn, &guid, false)?.unwrap();
+        assert_eq!(node.keyword, None);
+        assert!(node.tags.is_empty());
+
+        // The keyword belongs to the other bookmark, and shows up when
+        // fetching its parent's children too.
+        let unfiled = fetch_bookmark(&conn, &BookmarkRootGuid::Unfiled.into(), true)?.unwrap();
+        let children = unfiled.child_nodes.unwrap();
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[1].guid, other_guid);
+        assert_eq!(children[1].keyword, Some("ex".into()));
+        Ok(())
+    }
+
     #[test]
     fn test_insert() -> Result<()> {
         let _ = env_logger::try_init();
@@ -1531,6 +1920,8 @@ mod tests {
             guid: None,
             url: url.clone(),
             title: Some("the title".into()),
+            keyword: None,
+            tags: Vec::new(),
         });
         let guid = insert_bookmark(&conn, &bm)?;
 
@@ -1568,6 +1959,8 @@ mod tests {
             guid: None,
             url: url.clone(),
             title: Some("".into()),
+            keyword: None,
+            tags: Vec::new(),
         });
         let guid = insert_bookmark(&conn, &bm)?;
         let rb = get_raw_bookmark(&conn, &guid)?.expect("should get the bookmark");
@@ -1581,6 +1974,8 @@ mod tests {
             guid: None,
             url: url.clone(),
             title: None,
+            keyword: None,
+            tags: Vec::new(),
         });
         let guid2 = insert_bookmark(&conn, &bm2)?;
         let rb2 = get_raw_bookmark(&conn, &guid2)?.expect("should get the bookmark");
@@ -1670,6 +2065,8 @@ mod tests {
             guid: None,
             url: url.clone(),
             title: Some("the title".into()),
+            keyword: None,
+            tags: Vec::new(),
         });
         let guid = insert_bookmark(&conn, &bm)?;
 
@@ -2363,6 +2760,8 @@ mod tests {
                 guid: Some("bookmarkBBBB".into()),
                 url: Url::parse("http://example.com/b")?,
                 title: Some("B".into()),
+                keyword: None,
+                tags: Vec::new(),
             }
             .into(),
         )?;
@@ -2376,6 +2775,8 @@ mod tests {
                 guid: Some("bookmarkCCCC".into()),
                 url: Url::parse("http://example.com/c")?,
                 title: Some("C".into()),
+                keyword: None,
+                tags: Vec::new(),
             }
             .into(),
         )?;
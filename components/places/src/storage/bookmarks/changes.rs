@@ -0,0 +1,361 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Asking which bookmarks changed since a consumer last looked, so it can
+//! refresh just those, instead of reloading the whole tree after every sync.
+//! Changes are recorded by triggers; see `moz_bookmarks_changes` in the
+//! schema.
+
+use super::*;
+use crate::msg_types;
+use crate::storage::get_meta;
+
+/// The newest change token when maintenance last ran. Deletions recorded
+/// before then are forgotten at the next run, so consumers have at least one
+/// maintenance interval to catch up on them.
+const MAINTENANCE_TOKEN_META_KEY: &str = "bookmarks_changes_maintenance_token";
+
+/// The newest deletion we've forgotten. Consumers with an older token may
+/// have missed it.
+const PRUNED_TOKEN_META_KEY: &str = "bookmarks_changes_pruned_token";
+
+/// Items which were deleted, and not inserted again since.
+const TOMBSTONE_CONDITION: &str = "deletedToken > IFNULL(insertedToken, 0)";
+
+/// The bookmarks changed since a token, and the token to pass next time.
+/// A bookmark which was both updated and moved is in both lists.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BookmarkChanges {
+    pub token: i64,
+    pub inserted: Vec<SyncGuid>,
+    pub updated: Vec<SyncGuid>,
+    pub moved: Vec<SyncGuid>,
+    pub deleted: Vec<SyncGuid>,
+    /// If set, the token was so old that some deletions since have been
+    /// forgotten, and the consumer should reload the whole tree.
+    pub token_expired: bool,
+}
+
+impl From<BookmarkChanges> for msg_types::BookmarkChanges {
+    fn from(changes: BookmarkChanges) -> Self {
+        fn strings(guids: Vec<SyncGuid>) -> Vec<String> {
+            guids.into_iter().map(SyncGuid::into_string).collect()
+        }
+        msg_types::BookmarkChanges {
+            token: changes.token,
+            inserted: strings(changes.inserted),
+            updated: strings(changes.updated),
+            moved: strings(changes.moved),
+            deleted: strings(changes.deleted),
+            token_expired: changes.token_expired,
+        }
+    }
+}
+
+/// Returns the token for the most recent change, which consumers should
+/// fetch along with the tree.
+pub fn get_bookmark_change_token(db: &PlacesDb) -> Result<i64> {
+    Ok(db.query_one("SELECT IFNULL(MAX(changeToken), 0) FROM moz_bookmarks_changes")?)
+}
+
+/// Returns the bookmarks which were inserted, updated, moved to another
+/// folder, or deleted after `token`. Items which were inserted are only in
+/// `inserted`, even if they've changed since, and items which were deleted
+/// are only in `deleted`. A folder whose children were reordered, or shifted
+/// by an insertion, move, or deletion, counts as updated.
+pub fn get_bookmark_changes_since(db: &PlacesDb, token: i64) -> Result<BookmarkChanges> {
+    let rows = db.query_rows_and_then_named(
+        "SELECT guid, insertedToken, updatedToken, movedToken, deletedToken,
+                changeToken
+         FROM moz_bookmarks_changes
+         WHERE changeToken > :token
+         ORDER BY changeToken",
+        &[(":token", &token)],
+        |row| -> Result<_> {
+            Ok((
+                row.get::<_, SyncGuid>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, Option<i64>>(2)?,
+                row.get::<_, Option<i64>>(3)?,
+                row.get::<_, Option<i64>>(4)?,
+                row.get::<_, i64>(5)?,
+            ))
+        },
+    )?;
+    // This is read after the changes, so that if maintenance pruned any of
+    // them in between, we say the token expired.
+    let pruned_token = get_meta::<i64>(db, PRUNED_TOKEN_META_KEY)?.unwrap_or_default();
+    let mut changes = BookmarkChanges {
+        token,
+        token_expired: token < pruned_token,
+        ..BookmarkChanges::default()
+    };
+    let since = |change: Option<i64>| change > Some(token);
+    for (guid, inserted, updated, moved, deleted, change) in rows {
+        changes.token = change;
+        // Items can be inserted again after they're deleted, for example,
+        // by undo.
+        if deleted > inserted {
+            changes.deleted.push(guid);
+        } else if since(inserted) {
+            changes.inserted.push(guid);
+        } else {
+            if since(updated) {
+                changes.updated.push(guid.clone());
+            }
+            if since(moved) {
+                changes.moved.push(guid);
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// Forgets deleted items which were recorded before maintenance last ran.
+/// Changes for items which still exist are kept, since there's only one row
+/// for each item anyway.
+pub(crate) fn prune_bookmark_changes(db: &PlacesDb) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    if let Some(maintenance_token) = get_meta::<i64>(db, MAINTENANCE_TOKEN_META_KEY)? {
+        let pruned_token = db.try_query_one::<Option<i64>>(
+            &format!(
+                "SELECT MAX(changeToken) FROM moz_bookmarks_changes
+                 WHERE {} AND changeToken <= :token",
+                TOMBSTONE_CONDITION
+            ),
+            &[(":token", &maintenance_token)],
+            false,
+        )?;
+        // `MAX` returns a row with `NULL` if nothing matched.
+        if let Some(Some(pruned_token)) = pruned_token {
+            let pruned = db.execute_named(
+                &format!(
+                    "DELETE FROM moz_bookmarks_changes
+                     WHERE {} AND changeToken <= :token",
+                    TOMBSTONE_CONDITION
+                ),
+                &[(":token", &pruned_token)],
+            )?;
+            log::debug!("Pruned {} deleted bookmark changes", pruned);
+            put_meta(db, PRUNED_TOKEN_META_KEY, &pruned_token)?;
+        }
+    }
+    put_meta(
+        db,
+        MAINTENANCE_TOKEN_META_KEY,
+        &get_bookmark_change_token(db)?,
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::{test::new_mem_api, ConnectionType};
+    use crate::bookmark_sync::store::BookmarksStore;
+    use crate::tests::insert_json_tree;
+    use serde_json::json;
+    use sync15::{telemetry, IncomingChangeset, Payload, ServerTimestamp, Store};
+
+    fn guids(guids: &[SyncGuid]) -> Vec<&str> {
+        guids.iter().map(SyncGuid::as_str).collect()
+    }
+
+    #[test]
+    fn test_local_changes() -> Result<()> {
+        let _ = env_logger::try_init();
+        let api = new_mem_api();
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        assert_eq!(get_bookmark_change_token(&conn)?, 0);
+
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    { "guid": "bookmarkAAAA", "url": "https://example.com/a" },
+                    { "guid": "bookmarkBBBB", "url": "https://example.com/b" },
+                    { "guid": "folderCCCCCC", "children": [] },
+                ]
+            }),
+        );
+        let changes = get_bookmark_changes_since(&conn, 0)?;
+        assert_eq!(
+            guids(&changes.inserted),
+            vec!["bookmarkAAAA", "bookmarkBBBB", "folderCCCCCC"]
+        );
+        assert!(changes.updated.is_empty());
+        assert!(changes.moved.is_empty());
+        let token = changes.token;
+        assert_eq!(get_bookmark_changes_since(&conn, token)?.inserted.len(), 0);
+
+        // Only changes to titles, URLs, and keywords are updates.
+        update_bookmark(
+            &conn,
+            &"bookmarkBBBB".into(),
+            &UpdatableBookmark {
+                title: Some("B".into()),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        conn.execute_batch("UPDATE moz_bookmarks SET syncChangeCounter = 0")?;
+        move_items(
+            &conn,
+            &["bookmarkAAAA".into()],
+            &"folderCCCCCC".into(),
+            BookmarkPosition::Append,
+        )?;
+        delete_bookmark(&conn, &"bookmarkBBBB".into())?;
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": "folderCCCCCC",
+                "children": [{ "guid": "bookmarkDDDD", "url": "https://example.com/d" }]
+            }),
+        );
+
+        let changes = get_bookmark_changes_since(&conn, token)?;
+        assert_eq!(guids(&changes.inserted), vec!["bookmarkDDDD"]);
+        // Moving A and deleting B moved C up. That's not a move to another
+        // folder, but it changed the positions of the unfiled folder's
+        // children, so the folder is updated.
+        assert_eq!(
+            guids(&changes.updated),
+            vec![BookmarkRootGuid::Unfiled.as_str()]
+        );
+        assert_eq!(guids(&changes.moved), vec!["bookmarkAAAA"]);
+        assert_eq!(guids(&changes.deleted), vec!["bookmarkBBBB"]);
+        assert!(changes.token > token);
+
+        // Undoing the deletion inserts B again.
+        undo(&conn)?;
+        let changes = get_bookmark_changes_since(&conn, changes.token)?;
+        assert_eq!(guids(&changes.inserted), vec!["bookmarkBBBB"]);
+        assert!(changes.deleted.is_empty());
+
+        // Reordering a folder updates the folder, without moving its
+        // children.
+        reorder_children(
+            &conn,
+            &"folderCCCCCC".into(),
+            &["bookmarkDDDD".into(), "bookmarkAAAA".into()],
+        )?;
+        let changes = get_bookmark_changes_since(&conn, changes.token)?;
+        assert_eq!(guids(&changes.updated), vec!["folderCCCCCC"]);
+        assert!(changes.moved.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_synced_changes() -> Result<()> {
+        let _ = env_logger::try_init();
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        insert_json_tree(
+            &writer,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    { "guid": "bookmarkAAAA", "url": "https://example.com/a", "title": "A" },
+                ]
+            }),
+        );
+        let token = get_bookmark_change_token(&writer)?;
+
+        let syncer = api.open_sync_connection()?;
+        let interrupt_scope = syncer.begin_interrupt_scope();
+        let store = BookmarksStore::new(&syncer, &interrupt_scope);
+        let records = vec![
+            json!({
+                "id": "unfiled",
+                "type": "folder",
+                "parentid": "places",
+                "parentName": "",
+                "dateAdded": 0,
+                "title": "Unfiled",
+                "children": ["bookmarkAAAA", "bookmarkEEEE"],
+            }),
+            json!({
+                "id": "bookmarkAAAA",
+                "type": "bookmark",
+                "parentid": "unfiled",
+                "parentName": "Unfiled",
+                "dateAdded": 0,
+                "title": "Remote A",
+                "bmkUri": "https://example.com/a",
+            }),
+            json!({
+                "id": "bookmarkEEEE",
+                "type": "bookmark",
+                "parentid": "unfiled",
+                "parentName": "Unfiled",
+                "dateAdded": 0,
+                "title": "E",
+                "bmkUri": "https://example.com/e",
+            }),
+        ];
+        let mut incoming =
+            IncomingChangeset::new(store.collection_name().to_string(), ServerTimestamp(0));
+        for record in records {
+            let payload = Payload::from_json(record).unwrap();
+            incoming.changes.push((payload, ServerTimestamp(0)));
+        }
+        store
+            .apply_incoming(incoming, &mut telemetry::Engine::new("bookmarks"))
+            .expect("Should apply incoming records");
+
+        let changes = get_bookmark_changes_since(&writer, token)?;
+        assert_eq!(guids(&changes.inserted), vec!["bookmarkEEEE"]);
+        assert_eq!(guids(&changes.updated), vec!["bookmarkAAAA"]);
+        assert!(changes.deleted.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_changes() -> Result<()> {
+        let _ = env_logger::try_init();
+        let api = new_mem_api();
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    { "guid": "bookmarkAAAA", "url": "https://example.com/a" },
+                    { "guid": "bookmarkBBBB", "url": "https://example.com/b" },
+                ]
+            }),
+        );
+        let old_token = get_bookmark_change_token(&conn)?;
+        delete_bookmark(&conn, &"bookmarkAAAA".into())?;
+
+        // The first run only remembers the newest change, so consumers have
+        // until the next run to see the deletion.
+        prune_bookmark_changes(&conn)?;
+        let changes = get_bookmark_changes_since(&conn, old_token)?;
+        assert_eq!(guids(&changes.deleted), vec!["bookmarkAAAA"]);
+        assert!(!changes.token_expired);
+        let token = changes.token;
+
+        delete_bookmark(&conn, &"bookmarkBBBB".into())?;
+        prune_bookmark_changes(&conn)?;
+        let changes = get_bookmark_changes_since(&conn, token)?;
+        assert_eq!(guids(&changes.deleted), vec!["bookmarkBBBB"]);
+        assert!(!changes.token_expired);
+
+        // A consumer which missed A's deletion should reload everything.
+        let changes = get_bookmark_changes_since(&conn, old_token)?;
+        assert_eq!(guids(&changes.deleted), vec!["bookmarkBBBB"]);
+        assert!(changes.token_expired);
+
+        let count: i64 = conn.query_one(
+            "SELECT COUNT(*) FROM moz_bookmarks_changes
+             WHERE guid IN ('bookmarkAAAA', 'bookmarkBBBB')",
+        )?;
+        assert_eq!(count, 1);
+        Ok(())
+    }
+}
@@ -65,6 +65,11 @@ impl From<PublicNode> for msg_types::BookmarkNode {
         } else {
             None
         };
+        let have_tags = if n.node_type == BookmarkType::Bookmark {
+            Some(true)
+        } else {
+            None
+        };
         Self {
             node_type: Some(n.node_type as i32),
             guid: Some(n.guid.into_string()),
@@ -87,6 +92,9 @@ impl From<PublicNode> for msg_types::BookmarkNode {
                     .collect()
             }),
             have_child_nodes,
+            keyword: n.keyword,
+            tags: n.tags,
+            have_tags,
         }
     }
 }
@@ -103,6 +111,10 @@ impl From<RawBookmark> for PublicNode {
             last_modified: rb.date_modified,
             url: rb.url,
             title: rb.title,
+            keyword: rb.keyword,
+            // Tags aren't stored with the bookmark, so callers need to fetch
+            // them separately.
+            tags: Vec::new(),
             child_guids: None,
             child_nodes: None,
         }
@@ -153,6 +165,8 @@ impl msg_types::BookmarkNode {
                 // This will fail if Url is empty, but with a url parse error,
                 // which is what we want.
                 url: Url::parse(&self.url.unwrap_or_default())?,
+                keyword: self.keyword,
+                tags: self.tags,
                 guid: None,
                 date_added: None,
                 last_modified: None,
@@ -197,6 +211,8 @@ pub(crate) struct BookmarkUpdateInfo {
     pub url: Option<String>,
     pub parent_guid: Option<SyncGuid>,
     pub position: Option<u32>,
+    pub keyword: Option<String>,
+    pub tags: Option<Vec<String>>,
 }
 
 impl BookmarkUpdateInfo {
@@ -213,6 +229,14 @@ impl BookmarkUpdateInfo {
             return Err(InvalidPlaceInfo::IllegalChange("url", ty).into());
         }
 
+        if self.keyword.is_some() && ty != BookmarkType::Bookmark {
+            return Err(InvalidPlaceInfo::IllegalChange("keyword", ty).into());
+        }
+
+        if self.tags.is_some() && ty != BookmarkType::Bookmark {
+            return Err(InvalidPlaceInfo::IllegalChange("tags", ty).into());
+        }
+
         let location = match (self.parent_guid, self.position) {
             (None, None) => UpdateTreeLocation::None,
             (None, Some(pos)) => UpdateTreeLocation::Position(BookmarkPosition::Specific(pos)),
@@ -227,6 +251,8 @@ impl BookmarkUpdateInfo {
                 location,
                 title: self.title,
                 url: self.url.map(|u| Url::parse(&u)).transpose()?,
+                keyword: self.keyword,
+                tags: self.tags,
             }),
             BookmarkType::Separator => UpdatableItem::Separator(UpdatableSeparator { location }),
             BookmarkType::Folder => UpdatableItem::Folder(UpdatableFolder {
@@ -249,6 +275,12 @@ impl From<msg_types::BookmarkNode> for BookmarkUpdateInfo {
             url: n.url,
             parent_guid: n.parent_guid.map(SyncGuid::from),
             position: n.position,
+            keyword: n.keyword,
+            tags: if n.have_tags.unwrap_or(false) {
+                Some(n.tags)
+            } else {
+                None
+            },
         }
     }
 }
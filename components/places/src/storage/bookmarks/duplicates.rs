@@ -0,0 +1,404 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Finding and merging duplicate bookmarks.
+//!
+//! Importing from several browsers and then syncing tends to leave the same
+//! URL bookmarked more than once, often in different folders.
+
+use super::public_node::{bookmark_node, fill_keywords_and_tags, PublicNode};
+use super::*;
+
+/// What, besides the URL, bookmarks need to have in common to be considered
+/// duplicates.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DuplicateMatch {
+    pub same_title: bool,
+    pub same_parent: bool,
+}
+
+/// Bookmarks for the same URL, oldest first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateGroup {
+    pub url: Url,
+    pub bookmarks: Vec<PublicNode>,
+}
+
+/// Returns every group of two or more bookmarks which are duplicates of each
+/// other, ordered by URL.
+pub fn find_duplicate_bookmarks(
+    db: &PlacesDb,
+    matching: DuplicateMatch,
+) -> Result<Vec<DuplicateGroup>> {
+    // Every bookmark whose URL is bookmarked more than once, grouped by URL
+    // and oldest first.
+    let mut stmt = db.prepare(&format!(
+        "{}
+         WHERE b.type = :bookmark AND b.fk IN (
+           SELECT fk FROM moz_bookmarks
+           WHERE type = :bookmark
+           GROUP BY fk
+           HAVING COUNT(*) > 1
+         )
+         ORDER BY h.url, b.dateAdded, b.guid",
+        RAW_BOOKMARK_SQL
+    ))?;
+    let rows = stmt.query_and_then_named(
+        &[(":bookmark", &(BookmarkType::Bookmark as u8))],
+        RawBookmark::from_row,
+    )?;
+    let mut nodes = Vec::new();
+    for row in rows {
+        match row {
+            Ok(raw) => nodes.push(bookmark_node(raw)),
+            Err(e) => log::warn!("Skipping a bookmark with an invalid URL: {}", e),
+        }
+    }
+    fill_keywords_and_tags(db, &mut nodes)?;
+
+    let mut by_url: Vec<(Url, Vec<PublicNode>)> = Vec::new();
+    for node in nodes {
+        let url = match node.url.clone() {
+            Some(url) => url,
+            None => continue,
+        };
+        match by_url.last_mut() {
+            Some((last_url, bookmarks)) if *last_url == url => bookmarks.push(node),
+            _ => by_url.push((url, vec![node])),
+        }
+    }
+
+    let mut groups = Vec::new();
+    for (url, bookmarks) in by_url {
+        // Split the bookmarks up by whatever else needs to match, keeping
+        // the groups in the order their oldest bookmark was added.
+        let mut split: Vec<Vec<PublicNode>> = Vec::new();
+        for bookmark in bookmarks {
+            let group = split.iter_mut().find(|group| {
+                let first = &group[0];
+                (!matching.same_title || first.title == bookmark.title)
+                    && (!matching.same_parent || first.parent_guid == bookmark.parent_guid)
+            });
+            match group {
+                Some(group) => group.push(bookmark),
+                None => split.push(vec![bookmark]),
+            }
+        }
+        groups.extend(
+            split
+                .into_iter()
+                .filter(|bookmarks| bookmarks.len() > 1)
+                .map(|bookmarks| DuplicateGroup {
+                    url: url.clone(),
+                    bookmarks,
+                }),
+        );
+    }
+    Ok(groups)
+}
+
+/// Merges `duplicates` into `keep`, which must all be bookmarks for the same
+/// URL. The duplicates are deleted, leaving tombstones for any which were
+/// synced. If `keep` has no title or keyword, it takes the first one the
+/// duplicates have, so nothing the user typed is lost; tags belong to the
+/// URL, so they're kept anyway. Merging can't be undone, and clears the undo
+/// log, since the changes in it may no longer apply.
+pub fn merge_duplicates(db: &PlacesDb, keep: &SyncGuid, duplicates: &[SyncGuid]) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    let result = merge_duplicates_in_tx(db, keep, duplicates);
+    let (kept, deleted) = match result {
+        Ok(result) => {
+            tx.commit()?;
+            result
+        }
+        Err(e) => {
+            tx.rollback()?;
+            return Err(e);
+        }
+    };
+    clear_undo_log(db);
+    if let Some(kept) = kept {
+        notify_updated(db, &kept);
+    }
+    for raw in deleted {
+        notify_removed(db, raw);
+    }
+    Ok(())
+}
+
+/// Returns the kept bookmark as it was before, if it was changed, and the
+/// deleted bookmarks. The kept bookmark is read after the duplicates are
+/// deleted, so that moving up when a sibling is deleted isn't reported as a
+/// move.
+fn merge_duplicates_in_tx(
+    db: &PlacesDb,
+    keep: &SyncGuid,
+    duplicates: &[SyncGuid],
+) -> Result<(Option<RawBookmark>, Vec<RawBookmark>)> {
+    let kept = get_bookmark(db, keep)?;
+    let mut deleted = Vec::with_capacity(duplicates.len());
+    for guid in duplicates {
+        if guid == keep || deleted.iter().any(|d: &RawBookmark| &d.guid == guid) {
+            continue;
+        }
+        let duplicate = get_bookmark(db, guid)?;
+        if duplicate.url != kept.url {
+            return Err(InvalidPlaceInfo::NotADuplicate(guid.to_string()).into());
+        }
+        deleted.push(duplicate);
+    }
+
+    let title = match kept.title {
+        Some(_) => None,
+        None => deleted.iter().find_map(|d| d.title.clone()),
+    };
+    let keyword = match kept.keyword {
+        Some(_) => None,
+        None => deleted.iter().find_map(|d| d.keyword.clone()),
+    };
+    // The duplicates need to go first, so that their keyword is free.
+    for duplicate in &deleted {
+        delete_bookmark_in_tx(db, &duplicate.guid)?;
+    }
+    if title.is_none() && keyword.is_none() {
+        return Ok((None, deleted));
+    }
+    // Positions may have changed if duplicates in the same folder were
+    // deleted.
+    let current = get_bookmark(db, keep)?;
+    let update = UpdatableBookmark {
+        title,
+        keyword,
+        ..UpdatableBookmark::default()
+    };
+    update_bookmark_in_tx(db, keep, &update.into(), current.clone())?;
+    Ok((Some(current), deleted))
+}
+
+fn get_bookmark(db: &PlacesDb, guid: &SyncGuid) -> Result<RawBookmark> {
+    let raw = get_raw_bookmark(db, guid)?
+        .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
+    if raw.bookmark_type != BookmarkType::Bookmark {
+        return Err(InvalidPlaceInfo::MismatchedBookmarkType(
+            raw.bookmark_type as u8,
+            BookmarkType::Bookmark as u8,
+        )
+        .into());
+    }
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::tests::{assert_json_tree, insert_json_tree};
+    use serde_json::json;
+
+    fn guids(group: &DuplicateGroup) -> Vec<&str> {
+        group.bookmarks.iter().map(|b| b.guid.as_str()).collect()
+    }
+
+    #[test]
+    fn test_find_duplicates() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    {
+                        "guid": "bookmarkAAAA",
+                        "url": "https://example.com/a",
+                        "title": "A",
+                        "date_added": 3_000,
+                    },
+                    {
+                        "guid": "bookmarkBBBB",
+                        "url": "https://example.com/a",
+                        "title": "A",
+                        "date_added": 1_000,
+                    },
+                    {
+                        "guid": "folderCCCCCC",
+                        "children": [
+                            {
+                                "guid": "bookmarkDDDD",
+                                "url": "https://example.com/a",
+                                "title": "A",
+                                "date_added": 2_000,
+                            },
+                            {
+                                "guid": "bookmarkEEEE",
+                                "url": "https://example.com/a",
+                                "title": "Other",
+                                "date_added": 4_000,
+                            },
+                        ],
+                    },
+                    { "guid": "bookmarkFFFF", "url": "https://example.com/f" },
+                ]
+            }),
+        );
+
+        let groups = find_duplicate_bookmarks(&conn, DuplicateMatch::default())?;
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].url.as_str(), "https://example.com/a");
+        assert_eq!(
+            guids(&groups[0]),
+            vec![
+                "bookmarkBBBB",
+                "bookmarkDDDD",
+                "bookmarkAAAA",
+                "bookmarkEEEE"
+            ]
+        );
+
+        let groups = find_duplicate_bookmarks(
+            &conn,
+            DuplicateMatch {
+                same_title: true,
+                ..DuplicateMatch::default()
+            },
+        )?;
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            guids(&groups[0]),
+            vec!["bookmarkBBBB", "bookmarkDDDD", "bookmarkAAAA"]
+        );
+
+        let groups = find_duplicate_bookmarks(
+            &conn,
+            DuplicateMatch {
+                same_title: true,
+                same_parent: true,
+            },
+        )?;
+        assert_eq!(groups.len(), 1);
+        assert_eq!(guids(&groups[0]), vec!["bookmarkBBBB", "bookmarkAAAA"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_duplicates() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    { "guid": "bookmarkAAAA", "url": "https://example.com/a" },
+                    {
+                        "guid": "bookmarkBBBB",
+                        "url": "https://example.com/a",
+                        "title": "B",
+                    },
+                    {
+                        "guid": "folderCCCCCC",
+                        "children": [
+                            {
+                                "guid": "bookmarkDDDD",
+                                "url": "https://example.com/a",
+                                "title": "D",
+                            },
+                        ],
+                    },
+                    { "guid": "bookmarkEEEE", "url": "https://example.com/e" },
+                ]
+            }),
+        );
+        update_bookmark(
+            &conn,
+            &"bookmarkDDDD".into(),
+            &UpdatableBookmark {
+                keyword: Some("d".into()),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        // Pretend everything has been synced, so deleting writes tombstones.
+        conn.execute_batch("UPDATE moz_bookmarks SET syncStatus = 2, syncChangeCounter = 0")?;
+
+        // Only bookmarks for the same URL can be merged.
+        assert!(merge_duplicates(&conn, &"bookmarkAAAA".into(), &["bookmarkEEEE".into()]).is_err());
+        assert!(merge_duplicates(&conn, &"bookmarkAAAA".into(), &["folderCCCCCC".into()]).is_err());
+        assert!(merge_duplicates(&conn, &"bookmarkAAAA".into(), &["bookmarkXXXX".into()]).is_err());
+
+        merge_duplicates(
+            &conn,
+            &"bookmarkAAAA".into(),
+            &["bookmarkBBBB".into(), "bookmarkDDDD".into()],
+        )?;
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "children": [
+                    { "guid": "bookmarkAAAA", "url": "https://example.com/a", "title": "B" },
+                    { "guid": "folderCCCCCC", "children": [] },
+                    { "guid": "bookmarkEEEE", "url": "https://example.com/e" },
+                ]
+            }),
+        );
+        let kept = get_raw_bookmark(&conn, &"bookmarkAAAA".into())?.unwrap();
+        assert_eq!(kept.keyword, Some("d".into()));
+        assert_eq!(kept.sync_change_counter, 1);
+
+        let tombstones: Vec<String> = conn.query_rows_into(
+            "SELECT guid FROM moz_bookmarks_deleted ORDER BY guid",
+            &[],
+            |row| row.get(0),
+        )?;
+        assert_eq!(tombstones, vec!["bookmarkBBBB", "bookmarkDDDD"]);
+        assert!(find_duplicate_bookmarks(&conn, DuplicateMatch::default())?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_duplicates_notifies() -> Result<()> {
+        use crate::observer::{EventQueue, PlacesEvent};
+        use std::sync::Arc;
+
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    {
+                        "guid": "bookmarkBBBB",
+                        "url": "https://example.com/a",
+                        "title": "B",
+                    },
+                    { "guid": "bookmarkAAAA", "url": "https://example.com/a" },
+                ]
+            }),
+        );
+        let queue = Arc::new(EventQueue::default());
+        conn.observers.register(queue.clone());
+
+        merge_duplicates(&conn, &"bookmarkAAAA".into(), &["bookmarkBBBB".into()])?;
+        // Deleting B moves A up, but that's not a move.
+        assert_eq!(
+            queue.drain(),
+            vec![
+                PlacesEvent::BookmarkChanged {
+                    guid: "bookmarkAAAA".into(),
+                },
+                PlacesEvent::BookmarkRemoved {
+                    guid: "bookmarkBBBB".into(),
+                    parent_guid: BookmarkRootGuid::Unfiled.into(),
+                    index: 0,
+                    url: Some("https://example.com/a".into()),
+                },
+            ]
+        );
+        Ok(())
+    }
+}
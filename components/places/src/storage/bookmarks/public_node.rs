@@ -19,6 +19,8 @@ pub struct PublicNode {
     pub last_modified: Timestamp,
     pub url: Option<Url>,
     pub title: Option<String>,
+    pub keyword: Option<String>,
+    pub tags: Vec<String>,
     pub child_guids: Option<Vec<SyncGuid>>,
     pub child_nodes: Option<Vec<PublicNode>>,
 }
@@ -36,6 +38,8 @@ impl Default for PublicNode {
             last_modified: Timestamp(0),
             url: None,
             title: None,
+            keyword: None,
+            tags: Vec::new(),
             child_guids: None,
             child_nodes: None,
         }
@@ -49,37 +53,123 @@ impl PartialEq for PublicNode {
             && self.guid == other.guid
             && self.parent_guid == other.parent_guid
             && self.url == other.url
+            && self.keyword == other.keyword
+            && self.tags == other.tags
             && self.child_guids == other.child_guids
             && self.child_nodes == other.child_nodes
     }
 }
 
 pub fn fetch_bookmarks_by_url(db: &PlacesDb, url: &Url) -> Result<Vec<PublicNode>> {
-    let nodes = get_raw_bookmarks_for_url(db, url)?
+    let mut nodes = get_raw_bookmarks_for_url(db, url)?
         .into_iter()
         .map(|rb| {
-            // Cause tests to fail, but we'd rather not panic here
-            // for real.
-            debug_assert_eq!(rb.child_count, 0);
-            debug_assert_eq!(rb.bookmark_type, BookmarkType::Bookmark);
             debug_assert_eq!(rb.url.as_ref(), Some(url));
-            PublicNode {
-                node_type: rb.bookmark_type,
-                guid: rb.guid,
-                parent_guid: rb.parent_guid,
-                position: rb.position,
-                date_added: rb.date_added,
-                last_modified: rb.date_modified,
-                url: rb.url,
-                title: rb.title,
-                child_guids: None,
-                child_nodes: None,
-            }
+            bookmark_node(rb)
         })
         .collect::<Vec<_>>();
+    fill_keywords_and_tags(db, &mut nodes)?;
     Ok(nodes)
 }
 
+/// Makes a node for a bookmark, without its keyword and tags.
+pub(super) fn bookmark_node(rb: RawBookmark) -> PublicNode {
+    // Cause tests to fail, but we'd rather not panic here
+    // for real.
+    debug_assert_eq!(rb.child_count, 0);
+    debug_assert_eq!(rb.bookmark_type, BookmarkType::Bookmark);
+    PublicNode {
+        node_type: rb.bookmark_type,
+        guid: rb.guid,
+        parent_guid: rb.parent_guid,
+        position: rb.position,
+        date_added: rb.date_added,
+        last_modified: rb.date_modified,
+        url: rb.url,
+        title: rb.title,
+        keyword: rb.keyword,
+        tags: Vec::new(),
+        child_guids: None,
+        child_nodes: None,
+    }
+}
+
+/// Keywords aren't part of the bookmark tree, and tags belong to URLs rather
+/// than bookmarks, so the functions which return `PublicNode`s look them up
+/// separately and fill them in with this.
+pub(super) fn fill_keywords_and_tags(db: &PlacesDb, nodes: &mut [PublicNode]) -> Result<()> {
+    // Only bookmarks have keywords and tags.
+    fn collect_guids(nodes: &[PublicNode], guids: &mut Vec<SyncGuid>) {
+        for node in nodes {
+            if node.url.is_some() {
+                guids.push(node.guid.clone());
+            }
+            if let Some(children) = node.child_nodes.as_ref() {
+                collect_guids(children, guids);
+            }
+        }
+    }
+    let mut guids = Vec::new();
+    collect_guids(nodes, &mut guids);
+
+    let mut keywords: HashMap<SyncGuid, String> = HashMap::new();
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    sql_support::each_chunk(&guids, |chunk, _| -> Result<()> {
+        let vars = sql_support::repeat_sql_vars(chunk.len());
+        let mut stmt = db.prepare(&format!(
+            "SELECT guid, keyword FROM moz_bookmarks
+             WHERE keyword NOT NULL AND guid IN ({})",
+            vars
+        ))?;
+        let mut rows = stmt.query(chunk)?;
+        while let Some(row) = rows.next()? {
+            keywords.insert(row.get("guid")?, row.get("keyword")?);
+        }
+
+        // Several bookmarks can share a URL, so skip the URLs whose tags
+        // we already have from an earlier chunk.
+        let mut stmt = db.prepare(&format!(
+            "SELECT h.url, t.tag
+             FROM moz_tags t
+             JOIN moz_tags_relation r ON r.tag_id = t.id
+             JOIN moz_places h ON h.id = r.place_id
+             WHERE h.id IN (SELECT fk FROM moz_bookmarks WHERE guid IN ({}))
+             ORDER BY t.lastModified DESC",
+            vars
+        ))?;
+        let mut chunk_tags: HashMap<String, Vec<String>> = HashMap::new();
+        let mut rows = stmt.query(chunk)?;
+        while let Some(row) = rows.next()? {
+            chunk_tags
+                .entry(row.get("url")?)
+                .or_default()
+                .push(row.get("tag")?);
+        }
+        for (url, url_tags) in chunk_tags {
+            tags.entry(url).or_insert(url_tags);
+        }
+        Ok(())
+    })?;
+
+    fn fill(
+        nodes: &mut [PublicNode],
+        keywords: &HashMap<SyncGuid, String>,
+        tags: &HashMap<String, Vec<String>>,
+    ) {
+        for node in nodes {
+            node.keyword = keywords.get(&node.guid).cloned();
+            if let Some(url) = &node.url {
+                node.tags = tags.get(url.as_str()).cloned().unwrap_or_default();
+            }
+            if let Some(children) = node.child_nodes.as_mut() {
+                fill(children, keywords, tags);
+            }
+        }
+    }
+    fill(nodes, &keywords, &tags);
+    Ok(())
+}
+
 /// This is similar to fetch_tree, but does not recursively fetch children of
 /// folders.
 ///
@@ -163,12 +253,13 @@ pub fn fetch_public_tree_with_depth(
         proto.parent_guid = parent_guid;
         proto.position = position;
     }
+    fill_keywords_and_tags(db, std::slice::from_mut(&mut proto))?;
     Ok(Some(proto))
 }
 
 pub fn search_bookmarks(db: &PlacesDb, search: &str, limit: u32) -> Result<Vec<PublicNode>> {
     let scope = db.begin_interrupt_scope();
-    Ok(db.query_rows_into_cached(
+    let mut nodes: Vec<PublicNode> = db.query_rows_into_cached(
         &SEARCH_QUERY,
         &[(":search", &search), (":limit", &limit)],
         |row| -> Result<_> {
@@ -185,11 +276,15 @@ pub fn search_bookmarks(db: &PlacesDb, search: &str, limit: u32) -> Result<Vec<P
                     .get::<_, Option<String>>("url")?
                     .map(|href| url::Url::parse(&href))
                     .transpose()?,
+                keyword: None,
+                tags: Vec::new(),
                 child_guids: None,
                 child_nodes: None,
             })
         },
-    )?)
+    )?;
+    fill_keywords_and_tags(db, &mut nodes)?;
+    Ok(nodes)
 }
 
 pub fn recent_bookmarks(db: &PlacesDb, limit: u32) -> Result<Vec<PublicNode>> {
@@ -211,7 +306,7 @@ pub fn recent_bookmarks(db: &PlacesDb, limit: u32) -> Result<Vec<PublicNode>> {
         LIMIT :limit",
         bookmark_type = BookmarkType::Bookmark as u8,
     );
-    Ok(
+    let mut nodes: Vec<PublicNode> =
         db.query_rows_into_cached(&sql, &[(":limit", &limit)], |row| -> Result<_> {
             scope.err_if_interrupted()?;
             Ok(PublicNode {
@@ -226,11 +321,140 @@ pub fn recent_bookmarks(db: &PlacesDb, limit: u32) -> Result<Vec<PublicNode>> {
                     .get::<_, Option<String>>("url")?
                     .map(|href| url::Url::parse(&href))
                     .transpose()?,
+                keyword: None,
+                tags: Vec::new(),
                 child_guids: None,
                 child_nodes: None,
             })
-        })?,
-    )
+        })?;
+    fill_keywords_and_tags(db, &mut nodes)?;
+    Ok(nodes)
+}
+
+/// How `list_folder_children` sorts a folder's children. Ties are broken by
+/// position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FolderChildrenOrder {
+    /// The order the children are stored in.
+    Position = 1,
+    /// By title, A to Z, ignoring case. Untitled items come last.
+    Title = 2,
+    /// Most recently added first.
+    DateAdded = 3,
+    /// Most recently modified first.
+    LastModified = 4,
+    /// Bookmarks for the most recently visited pages first. Folders,
+    /// separators and bookmarks for pages which haven't been visited come
+    /// last.
+    LastVisited = 5,
+}
+
+impl FolderChildrenOrder {
+    pub fn from_primitive(p: u8) -> Option<Self> {
+        match p {
+            1 => Some(FolderChildrenOrder::Position),
+            2 => Some(FolderChildrenOrder::Title),
+            3 => Some(FolderChildrenOrder::DateAdded),
+            4 => Some(FolderChildrenOrder::LastModified),
+            5 => Some(FolderChildrenOrder::LastVisited),
+            _ => None,
+        }
+    }
+
+    fn order_by(self) -> &'static str {
+        match self {
+            FolderChildrenOrder::Position => "b.position",
+            FolderChildrenOrder::Title => {
+                "NULLIF(b.title, '') IS NULL, b.title COLLATE NOCASE, b.position"
+            }
+            FolderChildrenOrder::DateAdded => "b.dateAdded DESC, b.position",
+            FolderChildrenOrder::LastModified => "b.lastModified DESC, b.position",
+            FolderChildrenOrder::LastVisited => {
+                "IFNULL(h.last_visit_date_local, 0) DESC, b.position"
+            }
+        }
+    }
+}
+
+/// Returns a page of a folder's children, sorted by `order`, without their
+/// own children. Folders have their `child_guids` filled in, so that callers
+/// can tell how many children they have.
+pub fn list_folder_children(
+    db: &PlacesDb,
+    folder_guid: &SyncGuid,
+    order: FolderChildrenOrder,
+    offset: u32,
+    limit: u32,
+) -> Result<Vec<PublicNode>> {
+    let scope = db.begin_interrupt_scope();
+    let folder = get_raw_bookmark(db, folder_guid)?
+        .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(folder_guid.to_string()))?;
+    if folder.bookmark_type != BookmarkType::Folder {
+        return Err(InvalidPlaceInfo::InvalidParent(folder_guid.to_string()).into());
+    }
+    let sql = format!(
+        "{}
+         WHERE b.parent = :parent
+         ORDER BY {}
+         LIMIT :limit OFFSET :offset",
+        RAW_BOOKMARK_SQL,
+        order.order_by()
+    );
+    let children: Vec<RawBookmark> = db.query_rows_into_cached(
+        &sql,
+        &[
+            (":parent", &folder.row_id),
+            (":limit", &limit),
+            (":offset", &offset),
+        ],
+        |row| -> Result<_> {
+            scope.err_if_interrupted()?;
+            RawBookmark::from_row(row)
+        },
+    )?;
+    // Fetch the child guids for all the folders in the page at once.
+    let folder_ids: Vec<RowId> = children
+        .iter()
+        .filter(|rb| rb.bookmark_type == BookmarkType::Folder)
+        .map(|rb| rb.row_id)
+        .collect();
+    let mut child_guids: HashMap<RowId, Vec<SyncGuid>> =
+        folder_ids.iter().map(|id| (*id, Vec::new())).collect();
+    sql_support::each_chunk(&folder_ids, |chunk, _| -> Result<()> {
+        let mut stmt = db.prepare(&format!(
+            "SELECT parent, guid FROM moz_bookmarks
+             WHERE parent IN ({})
+             ORDER BY parent, position",
+            sql_support::repeat_sql_vars(chunk.len())
+        ))?;
+        let mut rows = stmt.query(chunk)?;
+        while let Some(row) = rows.next()? {
+            scope.err_if_interrupted()?;
+            if let Some(guids) = child_guids.get_mut(&row.get::<_, RowId>(0)?) {
+                guids.push(row.get(1)?);
+            }
+        }
+        Ok(())
+    })?;
+    let mut nodes = Vec::with_capacity(children.len());
+    for rb in children {
+        nodes.push(PublicNode {
+            node_type: rb.bookmark_type,
+            child_guids: child_guids.remove(&rb.row_id),
+            guid: rb.guid,
+            parent_guid: rb.parent_guid,
+            position: rb.position,
+            date_added: rb.date_added,
+            last_modified: rb.date_modified,
+            url: rb.url,
+            title: rb.title,
+            keyword: rb.keyword,
+            tags: Vec::new(),
+            child_nodes: None,
+        });
+    }
+    fill_keywords_and_tags(db, &mut nodes)?;
+    Ok(nodes)
 }
 
 lazy_static::lazy_static! {
@@ -323,6 +547,8 @@ mod test {
                 position: 1,
                 child_guids: None,
                 child_nodes: None,
+                keyword: None,
+                tags: Vec::new(),
                 // Ignored by our PartialEq
                 date_added: Timestamp(0),
                 last_modified: Timestamp(0),
@@ -339,6 +565,8 @@ mod test {
                 position: 3,
                 child_guids: None,
                 child_nodes: None,
+                keyword: None,
+                tags: Vec::new(),
                 // Ignored by our PartialEq
                 date_added: Timestamp(0),
                 last_modified: Timestamp(0),
@@ -500,6 +728,80 @@ mod test {
         Ok(())
     }
     #[test]
+    fn test_keyword_and_tags_from_message() -> Result<()> {
+        let conns = new_mem_connections();
+        let _ = env_logger::try_init();
+
+        let insertable = ProtoBookmark {
+            node_type: Some(BookmarkType::Bookmark as i32),
+            parent_guid: Some(BookmarkRootGuid::Mobile.as_guid().into_string()),
+            url: Some("https://www.example.com/".into()),
+            keyword: Some("ex".into()),
+            tags: vec!["foo".into()],
+            ..ProtoBookmark::default()
+        };
+        let guid = insert_bookmark(&conns.write, &insertable.into_insertable()?)?;
+        let node = ProtoBookmark::from(fetch_bookmark(&conns.read, &guid, false)?.unwrap());
+        assert_eq!(node.keyword, Some("ex".into()));
+        assert_eq!(node.tags, vec!["foo".to_string()]);
+        assert_eq!(node.have_tags, Some(true));
+
+        // Tags belong to the URL, so a second bookmark for it has them too,
+        // but keywords belong to the bookmark.
+        let other = ProtoBookmark {
+            node_type: Some(BookmarkType::Bookmark as i32),
+            parent_guid: Some(BookmarkRootGuid::Mobile.as_guid().into_string()),
+            url: Some("https://www.example.com/".into()),
+            ..ProtoBookmark::default()
+        };
+        let other = insert_bookmark(&conns.write, &other.into_insertable()?)?;
+        let mobile = fetch_bookmark(&conns.read, BookmarkRootGuid::Mobile.guid(), true)?.unwrap();
+        let children = mobile.child_nodes.unwrap();
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[0].keyword, Some("ex".into()));
+        assert_eq!(children[1].guid, other);
+        assert_eq!(children[1].keyword, None);
+        for child in &children {
+            assert_eq!(child.tags, vec!["foo".to_string()]);
+        }
+
+        // Tags are only replaced if `have_tags` is set.
+        update_bookmark_from_message(
+            &conns.write,
+            ProtoBookmark {
+                guid: Some(guid.clone().into_string()),
+                keyword: Some("".into()),
+                ..ProtoBookmark::default()
+            },
+        )?;
+        let node = fetch_bookmark(&conns.read, &guid, false)?.unwrap();
+        assert_eq!(node.keyword, None);
+        assert_eq!(node.tags, vec!["foo".to_string()]);
+
+        update_bookmark_from_message(
+            &conns.write,
+            ProtoBookmark {
+                guid: Some(guid.clone().into_string()),
+                have_tags: Some(true),
+                ..ProtoBookmark::default()
+            },
+        )?;
+        let node = fetch_bookmark(&conns.read, &guid, false)?.unwrap();
+        assert!(node.tags.is_empty());
+
+        // Folders can't have keywords.
+        assert!(update_bookmark_from_message(
+            &conns.write,
+            ProtoBookmark {
+                guid: Some(BookmarkRootGuid::Mobile.as_guid().into_string()),
+                keyword: Some("mobile".into()),
+                ..ProtoBookmark::default()
+            },
+        )
+        .is_err());
+        Ok(())
+    }
+    #[test]
     fn test_fetch_tree() -> Result<()> {
         let conns = new_mem_connections();
         let _ = env_logger::try_init();
@@ -613,6 +915,8 @@ mod test {
                 position: 5,
                 child_guids: None,
                 child_nodes: None,
+                keyword: None,
+                tags: Vec::new(),
                 // Ignored by our PartialEq
                 date_added: Timestamp(0),
                 last_modified: Timestamp(0),
@@ -629,6 +933,8 @@ mod test {
                 position: 3,
                 child_guids: None,
                 child_nodes: None,
+                keyword: None,
+                tags: Vec::new(),
                 // Ignored by our PartialEq
                 date_added: Timestamp(0),
                 last_modified: Timestamp(0),
@@ -645,6 +951,8 @@ mod test {
                 position: 2,
                 child_guids: None,
                 child_nodes: None,
+                keyword: None,
+                tags: Vec::new(),
                 // Ignored by our PartialEq
                 date_added: Timestamp(0),
                 last_modified: Timestamp(0),
@@ -652,4 +960,179 @@ mod test {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_list_folder_children() -> Result<()> {
+        use crate::observation::VisitObservation;
+        use crate::storage::history::apply_observation;
+        use crate::types::VisitTransition;
+
+        let conns = new_mem_connections();
+        let _ = env_logger::try_init();
+        insert_json_tree(
+            &conns.write,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    {
+                        "guid": "bookmarkAAAA",
+                        "url": "https://example.com/a",
+                        "title": "b",
+                        "date_added": 2_000,
+                        "last_modified": 5_000,
+                    },
+                    {
+                        "guid": "folderBBBBBB",
+                        "title": "A",
+                        "date_added": 3_000,
+                        "last_modified": 3_000,
+                        "children": [
+                            { "guid": "bookmarkCCCC", "url": "https://example.com/c" },
+                        ],
+                    },
+                    {
+                        "guid": "separatorDDD",
+                        "type": BookmarkType::Separator as u8,
+                        "date_added": 1_000,
+                        "last_modified": 1_000,
+                    },
+                    {
+                        "guid": "bookmarkEEEE",
+                        "url": "https://example.com/e",
+                        "title": "c",
+                        "date_added": 4_000,
+                        "last_modified": 4_000,
+                    },
+                ]
+            }),
+        );
+        for (url, when) in &[
+            ("https://example.com/a", 10_000),
+            ("https://example.com/e", 20_000),
+        ] {
+            apply_observation(
+                &conns.write,
+                VisitObservation::new(Url::parse(url)?)
+                    .with_at(Timestamp(*when))
+                    .with_visit_type(VisitTransition::Link),
+            )?;
+        }
+
+        let list = |order, offset, limit| -> Result<Vec<String>> {
+            Ok(list_folder_children(
+                &conns.read,
+                &BookmarkRootGuid::Unfiled.into(),
+                order,
+                offset,
+                limit,
+            )?
+            .into_iter()
+            .map(|node| node.guid.into_string())
+            .collect())
+        };
+        assert_eq!(
+            list(FolderChildrenOrder::Position, 0, 10)?,
+            vec![
+                "bookmarkAAAA",
+                "folderBBBBBB",
+                "separatorDDD",
+                "bookmarkEEEE"
+            ]
+        );
+        assert_eq!(
+            list(FolderChildrenOrder::Position, 1, 2)?,
+            vec!["folderBBBBBB", "separatorDDD"]
+        );
+        assert_eq!(
+            list(FolderChildrenOrder::Title, 0, 10)?,
+            vec![
+                "folderBBBBBB",
+                "bookmarkAAAA",
+                "bookmarkEEEE",
+                "separatorDDD"
+            ]
+        );
+        assert_eq!(
+            list(FolderChildrenOrder::DateAdded, 0, 10)?,
+            vec![
+                "bookmarkEEEE",
+                "folderBBBBBB",
+                "bookmarkAAAA",
+                "separatorDDD"
+            ]
+        );
+        assert_eq!(
+            list(FolderChildrenOrder::LastModified, 0, 2)?,
+            vec!["bookmarkAAAA", "bookmarkEEEE"]
+        );
+        assert_eq!(
+            list(FolderChildrenOrder::LastVisited, 0, 10)?,
+            vec![
+                "bookmarkEEEE",
+                "bookmarkAAAA",
+                "folderBBBBBB",
+                "separatorDDD"
+            ]
+        );
+        assert!(list(FolderChildrenOrder::Position, 4, 10)?.is_empty());
+
+        // Folders come with their children's GUIDs.
+        let nodes = list_folder_children(
+            &conns.read,
+            &BookmarkRootGuid::Unfiled.into(),
+            FolderChildrenOrder::Position,
+            1,
+            1,
+        )?;
+        assert_eq!(nodes[0].child_guids, Some(vec!["bookmarkCCCC".into()]));
+        assert_eq!(nodes[0].child_nodes, None);
+
+        insert_json_tree(
+            &conns.write,
+            json!({
+                "guid": "folderBBBBBB",
+                "children": [{ "guid": "bookmarkFFFF", "url": "https://example.com/f" }],
+            }),
+        );
+        insert_json_tree(
+            &conns.write,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [{ "guid": "folderGGGGGG", "children": [] }],
+            }),
+        );
+        let child_guids = list_folder_children(
+            &conns.read,
+            &BookmarkRootGuid::Unfiled.into(),
+            FolderChildrenOrder::Position,
+            0,
+            10,
+        )?
+        .into_iter()
+        .map(|node| (node.guid.into_string(), node.child_guids))
+        .collect::<Vec<_>>();
+        assert_eq!(
+            child_guids,
+            vec![
+                ("bookmarkAAAA".into(), None),
+                (
+                    "folderBBBBBB".into(),
+                    Some(vec!["bookmarkCCCC".into(), "bookmarkFFFF".into()])
+                ),
+                ("separatorDDD".into(), None),
+                ("bookmarkEEEE".into(), None),
+                ("folderGGGGGG".into(), Some(vec![])),
+            ]
+        );
+
+        assert!(list_folder_children(
+            &conns.read,
+            &"bookmarkAAAA".into(),
+            FolderChildrenOrder::Position,
+            0,
+            10
+        )
+        .is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,497 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Reordering and moving many bookmarks at once.
+//!
+//! `update_bookmark` moves one item at a time, and rewrites the positions of
+//! its siblings each time. The functions here work out the new children of
+//! every folder involved first, and then write them in one transaction,
+//! bumping the change counter of each folder which changed once. This is
+//! like desktop's `PlacesUtils.bookmarks.reorder` and `moveToFolder`.
+
+use super::*;
+use crate::db::PlacesTransaction;
+use std::iter;
+
+/// A folder, and the GUIDs of all its children in order.
+#[derive(Debug, Clone)]
+pub(super) struct FolderChildren {
+    pub(super) guid: SyncGuid,
+    pub(super) children: Vec<SyncGuid>,
+}
+
+impl FolderChildren {
+    pub(super) fn fetch(db: &PlacesDb, guid: &SyncGuid) -> Result<Self> {
+        let children = db.query_rows_into_cached(
+            "SELECT b.guid FROM moz_bookmarks b
+             JOIN moz_bookmarks p ON p.id = b.parent
+             WHERE p.guid = :guid
+             ORDER BY b.position",
+            &[(":guid", guid)],
+            |row| row.get::<_, SyncGuid>(0),
+        )?;
+        Ok(Self {
+            guid: guid.clone(),
+            children,
+        })
+    }
+}
+
+/// Fetches a folder whose children can be changed.
+fn get_folder(db: &PlacesDb, guid: &SyncGuid) -> Result<RawBookmark> {
+    if guid == BookmarkRootGuid::Root {
+        return Err(InvalidPlaceInfo::CannotUpdateRoot(BookmarkRootGuid::Root).into());
+    }
+    let folder = get_raw_bookmark(db, guid)?
+        .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
+    if folder.bookmark_type != BookmarkType::Folder {
+        return Err(InvalidPlaceInfo::InvalidParent(guid.to_string()).into());
+    }
+    Ok(folder)
+}
+
+/// Gives each folder the children listed for it, in that order. Between
+/// them, the lists must hold exactly the items which are currently children
+/// of those folders, so nothing is orphaned or in two places. Returns the
+/// old state of every item which moved.
+pub(super) fn set_children_in_tx(
+    db: &PlacesDb,
+    folders: &[FolderChildren],
+) -> Result<Vec<RawBookmark>> {
+    let mut parents = Vec::with_capacity(folders.len());
+    let mut current = HashMap::new();
+    for folder in folders {
+        let parent = get_folder(db, &folder.guid)?;
+        let old = FolderChildren::fetch(db, &folder.guid)?;
+        for guid in &old.children {
+            let child = get_raw_bookmark(db, guid)?
+                .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
+            current.insert(guid.clone(), child);
+        }
+        parents.push((parent, old));
+    }
+
+    let now = Timestamp::now();
+    let mut moved = Vec::new();
+    for (folder, (parent, old)) in folders.iter().zip(parents) {
+        for (position, guid) in folder.children.iter().enumerate() {
+            let child = current
+                .remove(guid)
+                .ok_or(InvalidPlaceInfo::InvalidChildGuid)?;
+            let position = position as u32;
+            if child.parent_id == Some(parent.row_id) && child.position == position {
+                continue;
+            }
+            // Items which changed folders are modified, like they are when
+            // moved with `update_bookmark`; items which only changed
+            // position aren't.
+            db.execute_named_cached(
+                "UPDATE moz_bookmarks SET
+                     lastModified = CASE WHEN parent = :parent
+                                         THEN lastModified
+                                         ELSE :now END,
+                     parent = :parent,
+                     position = :position
+                 WHERE id = :id",
+                &[
+                    (":parent", &parent.row_id),
+                    (":position", &position),
+                    (":now", &now),
+                    (":id", &child.row_id),
+                ],
+            )?;
+            moved.push(child);
+        }
+        if old.children != folder.children {
+            set_ancestors_last_modified(db, parent.row_id, now)?;
+            db.execute_named_cached(
+                "UPDATE moz_bookmarks SET syncChangeCounter = syncChangeCounter + 1
+                 WHERE id = :id",
+                &[(":id", &parent.row_id)],
+            )?;
+        }
+    }
+    if !current.is_empty() {
+        return Err(InvalidPlaceInfo::InvalidChildGuid.into());
+    }
+    Ok(moved)
+}
+
+/// The children of the folders a batch change touched, before and after,
+/// and the old state of the items which moved.
+struct Moves {
+    before: Vec<FolderChildren>,
+    after: Vec<FolderChildren>,
+    moved: Vec<RawBookmark>,
+}
+
+/// Commits or rolls back `tx`, and if the change worked, records it in the
+/// undo log and tells observers about the items which moved.
+fn finish(db: &PlacesDb, tx: PlacesTransaction<'_>, result: Result<Moves>) -> Result<()> {
+    let Moves {
+        before,
+        after,
+        moved,
+    } = match result {
+        Ok(result) => {
+            tx.commit()?;
+            result
+        }
+        Err(e) => {
+            tx.rollback()?;
+            return Err(e);
+        }
+    };
+    if !moved.is_empty() {
+        undo::record_move(db, before, after);
+        for old in &moved {
+            notify_updated(db, old);
+        }
+    }
+    Ok(())
+}
+
+/// Reorders the children of a folder. The children in `ordered_child_guids`
+/// come first, in that order, followed by any which weren't listed, in their
+/// current order. GUIDs which aren't children of the folder are ignored.
+pub fn reorder_children(
+    db: &PlacesDb,
+    parent_guid: &SyncGuid,
+    ordered_child_guids: &[SyncGuid],
+) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    let result = reorder_children_in_tx(db, parent_guid, ordered_child_guids);
+    finish(db, tx, result)
+}
+
+fn reorder_children_in_tx(
+    db: &PlacesDb,
+    parent_guid: &SyncGuid,
+    ordered_child_guids: &[SyncGuid],
+) -> Result<Moves> {
+    get_folder(db, parent_guid)?;
+    let old = FolderChildren::fetch(db, parent_guid)?;
+    let mut listed = HashSet::new();
+    let mut children = ordered_child_guids
+        .iter()
+        .filter(|guid| old.children.contains(guid) && listed.insert(*guid))
+        .cloned()
+        .collect::<Vec<_>>();
+    children.extend(
+        old.children
+            .iter()
+            .filter(|guid| !listed.contains(guid))
+            .cloned(),
+    );
+    let new = FolderChildren {
+        guid: parent_guid.clone(),
+        children,
+    };
+    let moved = set_children_in_tx(db, std::slice::from_ref(&new))?;
+    Ok(Moves {
+        before: vec![old],
+        after: vec![new],
+        moved,
+    })
+}
+
+/// Moves the items with the given GUIDs into a folder, keeping them together
+/// in the order they're listed. `BookmarkPosition::Specific(index)` puts
+/// them before the child currently at `index`, so that dropping items at a
+/// position in the same folder they came from does the expected thing.
+pub fn move_items(
+    db: &PlacesDb,
+    guids: &[SyncGuid],
+    new_parent_guid: &SyncGuid,
+    position: BookmarkPosition,
+) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    let result = move_items_in_tx(db, guids, new_parent_guid, position);
+    finish(db, tx, result)
+}
+
+fn move_items_in_tx(
+    db: &PlacesDb,
+    guids: &[SyncGuid],
+    new_parent_guid: &SyncGuid,
+    position: BookmarkPosition,
+) -> Result<Moves> {
+    get_folder(db, new_parent_guid)?;
+    // A folder can't be moved into itself, or into one of its descendants.
+    let ancestors: HashSet<SyncGuid> = db.query_rows_into_cached(
+        "WITH RECURSIVE
+         ancestors(id, guid, parent) AS (
+           SELECT id, guid, parent FROM moz_bookmarks
+           WHERE guid = :guid
+           UNION ALL
+           SELECT b.id, b.guid, b.parent FROM moz_bookmarks b
+           JOIN ancestors a ON b.id = a.parent
+         )
+         SELECT guid FROM ancestors",
+        &[(":guid", new_parent_guid)],
+        |row| row.get::<_, SyncGuid>(0),
+    )?;
+
+    let mut moving = HashSet::new();
+    let mut items = Vec::new();
+    let mut old_parents = Vec::new();
+    for guid in guids {
+        if let Some(root) = BookmarkRootGuid::well_known(guid.as_str()) {
+            return Err(InvalidPlaceInfo::CannotUpdateRoot(root).into());
+        }
+        if ancestors.contains(guid) {
+            return Err(InvalidPlaceInfo::InvalidParent(new_parent_guid.to_string()).into());
+        }
+        if !moving.insert(guid) {
+            continue;
+        }
+        let item = get_raw_bookmark(db, guid)?
+            .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
+        let parent_guid = item
+            .parent_guid
+            .ok_or_else(|| Corruption::NonRootWithoutParent(guid.to_string()))?;
+        if !old_parents.contains(&parent_guid) {
+            old_parents.push(parent_guid);
+        }
+        items.push(item.guid);
+    }
+
+    let mut before = Vec::new();
+    let mut after = Vec::new();
+    for guid in old_parents.iter().chain(iter::once(new_parent_guid)) {
+        if before.iter().any(|f: &FolderChildren| &f.guid == guid) {
+            continue;
+        }
+        let old = FolderChildren::fetch(db, guid)?;
+        let mut children = old
+            .children
+            .iter()
+            .filter(|guid| !moving.contains(guid))
+            .cloned()
+            .collect::<Vec<_>>();
+        if guid == new_parent_guid {
+            let index = match position {
+                BookmarkPosition::Specific(index) => old
+                    .children
+                    .iter()
+                    .take(index as usize)
+                    .filter(|guid| !moving.contains(guid))
+                    .count(),
+                BookmarkPosition::Append => children.len(),
+            };
+            children.splice(index..index, items.iter().cloned());
+        }
+        after.push(FolderChildren {
+            guid: guid.clone(),
+            children,
+        });
+        before.push(old);
+    }
+    let moved = set_children_in_tx(db, &after)?;
+    Ok(Moves {
+        before,
+        after,
+        moved,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::tests::{assert_json_tree, insert_json_tree};
+    use serde_json::json;
+
+    fn counter(db: &PlacesDb, guid: &str) -> Result<u32> {
+        Ok(get_raw_bookmark(db, &guid.into())?
+            .unwrap()
+            .sync_change_counter)
+    }
+
+    fn insert_test_tree(db: &PlacesDb) {
+        insert_json_tree(
+            db,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    { "guid": "bookmarkAAAA", "url": "https://example.com/a" },
+                    { "guid": "bookmarkBBBB", "url": "https://example.com/b" },
+                    {
+                        "guid": "folderCCCCCC",
+                        "children": [
+                            { "guid": "bookmarkDDDD", "url": "https://example.com/d" },
+                        ],
+                    },
+                    { "guid": "bookmarkEEEE", "url": "https://example.com/e" },
+                ]
+            }),
+        );
+        db.execute_batch("UPDATE moz_bookmarks SET syncChangeCounter = 0")
+            .expect("should work");
+    }
+
+    #[test]
+    fn test_reorder_children() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        insert_test_tree(&conn);
+
+        reorder_children(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            &[
+                "bookmarkEEEE".into(),
+                "bookmarkDDDD".into(),
+                "bookmarkBBBB".into(),
+                "bookmarkEEEE".into(),
+            ],
+        )?;
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "children": [
+                    { "guid": "bookmarkEEEE", "url": "https://example.com/e" },
+                    { "guid": "bookmarkBBBB", "url": "https://example.com/b" },
+                    { "guid": "bookmarkAAAA", "url": "https://example.com/a" },
+                    {
+                        "guid": "folderCCCCCC",
+                        "children": [
+                            { "guid": "bookmarkDDDD", "url": "https://example.com/d" },
+                        ],
+                    },
+                ]
+            }),
+        );
+        // Only the folder's counter changes, and only once.
+        assert_eq!(counter(&conn, BookmarkRootGuid::Unfiled.as_str())?, 1);
+        assert_eq!(counter(&conn, "bookmarkEEEE")?, 0);
+        assert_eq!(counter(&conn, "bookmarkDDDD")?, 0);
+
+        // Reordering into the same order doesn't change anything.
+        reorder_children(&conn, &BookmarkRootGuid::Unfiled.into(), &[])?;
+        assert_eq!(counter(&conn, BookmarkRootGuid::Unfiled.as_str())?, 1);
+
+        assert!(reorder_children(&conn, &"bookmarkAAAA".into(), &[]).is_err());
+        assert!(reorder_children(&conn, &BookmarkRootGuid::Root.into(), &[]).is_err());
+
+        assert!(undo(&conn)?);
+        assert_eq!(
+            FolderChildren::fetch(&conn, &BookmarkRootGuid::Unfiled.into())?.children,
+            vec![
+                SyncGuid::from("bookmarkAAAA"),
+                "bookmarkBBBB".into(),
+                "folderCCCCCC".into(),
+                "bookmarkEEEE".into(),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_items() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        insert_test_tree(&conn);
+
+        // Move items from two folders into the middle of one of them.
+        move_items(
+            &conn,
+            &[
+                "bookmarkEEEE".into(),
+                "bookmarkDDDD".into(),
+                "bookmarkAAAA".into(),
+            ],
+            &BookmarkRootGuid::Unfiled.into(),
+            BookmarkPosition::Specific(2),
+        )?;
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "children": [
+                    { "guid": "bookmarkBBBB", "url": "https://example.com/b" },
+                    { "guid": "bookmarkEEEE", "url": "https://example.com/e" },
+                    { "guid": "bookmarkDDDD", "url": "https://example.com/d" },
+                    { "guid": "bookmarkAAAA", "url": "https://example.com/a" },
+                    { "guid": "folderCCCCCC", "children": [] },
+                ]
+            }),
+        );
+        assert_eq!(counter(&conn, BookmarkRootGuid::Unfiled.as_str())?, 1);
+        assert_eq!(counter(&conn, "folderCCCCCC")?, 1);
+        assert_eq!(counter(&conn, "bookmarkDDDD")?, 0);
+
+        move_items(
+            &conn,
+            &["bookmarkBBBB".into(), "bookmarkAAAA".into()],
+            &"folderCCCCCC".into(),
+            BookmarkPosition::Append,
+        )?;
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "children": [
+                    { "guid": "bookmarkEEEE", "url": "https://example.com/e" },
+                    { "guid": "bookmarkDDDD", "url": "https://example.com/d" },
+                    {
+                        "guid": "folderCCCCCC",
+                        "children": [
+                            { "guid": "bookmarkBBBB", "url": "https://example.com/b" },
+                            { "guid": "bookmarkAAAA", "url": "https://example.com/a" },
+                        ],
+                    },
+                ]
+            }),
+        );
+        assert_eq!(counter(&conn, BookmarkRootGuid::Unfiled.as_str())?, 2);
+        assert_eq!(counter(&conn, "folderCCCCCC")?, 2);
+
+        // Can't move a folder into itself, or move roots.
+        assert!(move_items(
+            &conn,
+            &["folderCCCCCC".into()],
+            &"folderCCCCCC".into(),
+            BookmarkPosition::Append,
+        )
+        .is_err());
+        assert!(move_items(
+            &conn,
+            &[BookmarkRootGuid::Toolbar.into()],
+            &"folderCCCCCC".into(),
+            BookmarkPosition::Append,
+        )
+        .is_err());
+        assert!(move_items(
+            &conn,
+            &["bookmarkXXXX".into()],
+            &"folderCCCCCC".into(),
+            BookmarkPosition::Append,
+        )
+        .is_err());
+
+        // Undoing puts everything back where it was.
+        assert!(undo(&conn)?);
+        assert!(undo(&conn)?);
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "children": [
+                    { "guid": "bookmarkAAAA", "url": "https://example.com/a" },
+                    { "guid": "bookmarkBBBB", "url": "https://example.com/b" },
+                    {
+                        "guid": "folderCCCCCC",
+                        "children": [
+                            { "guid": "bookmarkDDDD", "url": "https://example.com/d" },
+                        ],
+                    },
+                    { "guid": "bookmarkEEEE", "url": "https://example.com/e" },
+                ]
+            }),
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,485 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Finds and fixes problems with the local bookmark tree.
+//!
+//! The schema and triggers keep the tree consistent for changes made through
+//! our APIs, but databases written by older versions, or with foreign key or
+//! check constraints turned off, can still end up with broken trees. This is
+//! a subset of what desktop's `PlacesDBUtils.maintenanceOnIdle` checks.
+
+use super::*;
+use std::collections::{HashMap, HashSet};
+
+/// What `check_and_repair` found and fixed. Everything which was changed has
+/// its change counter bumped, so that the fixes are uploaded on the next
+/// sync.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RepairReport {
+    /// Items whose parent didn't exist, or wasn't a folder, which were moved
+    /// to the end of the unfiled folder. For a loop of folders which wasn't
+    /// connected to the root, one folder in the loop is moved there, taking
+    /// the rest with it.
+    pub orphans: Vec<SyncGuid>,
+    /// Folders whose children had gaps or duplicates in their positions, and
+    /// were renumbered, keeping their order.
+    pub renumbered_folders: Vec<SyncGuid>,
+    /// Folders and separators which had a URL, which was removed.
+    pub items_with_urls: Vec<SyncGuid>,
+    /// The number of pages whose `foreign_count` didn't match the number of
+    /// bookmarks, synced bookmarks and tags which reference them. URLs are
+    /// private, so we don't report which ones they were.
+    pub wrong_foreign_counts: usize,
+}
+
+impl RepairReport {
+    /// Returns true if no problems were found.
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+            && self.renumbered_folders.is_empty()
+            && self.items_with_urls.is_empty()
+            && self.wrong_foreign_counts == 0
+    }
+}
+
+/// Checks the bookmark tree for orphans, bad positions, folders and
+/// separators with URLs, and pages with the wrong `foreign_count`, and fixes
+/// any it finds in one transaction. If anything was fixed, the undo log is
+/// cleared, and observers are sent a `BookmarksRepaired` event.
+pub fn check_and_repair(db: &PlacesDb) -> Result<RepairReport> {
+    let tx = db.begin_transaction()?;
+    let report = check_and_repair_in_tx(db)?;
+    tx.commit()?;
+    if !report.is_empty() {
+        log::warn!("Repaired the bookmark tree: {:?}", report);
+        clear_undo_log(db);
+        db.observers.notify(&PlacesEvent::BookmarksRepaired);
+    }
+    Ok(report)
+}
+
+fn check_and_repair_in_tx(db: &PlacesDb) -> Result<RepairReport> {
+    // Orphans are fixed first, so that the items are numbered along with the
+    // rest of the unfiled folder, and URLs are removed before the foreign
+    // counts are recalculated, because the triggers don't handle `fk`
+    // becoming NULL.
+    let now = Timestamp::now();
+    Ok(RepairReport {
+        orphans: reparent_orphans(db, now)?,
+        renumbered_folders: renumber_children(db, now)?,
+        items_with_urls: remove_non_bookmark_urls(db, now)?,
+        wrong_foreign_counts: fix_foreign_counts(db, now)?,
+    })
+}
+
+/// Moves items whose parent is missing, isn't a folder, or is the item
+/// itself, to the unfiled folder, then breaks any loops of folders which
+/// aren't connected to the root.
+fn reparent_orphans(db: &PlacesDb, now: Timestamp) -> Result<Vec<SyncGuid>> {
+    let mut orphans = reparent_missing_parents(db, now)?;
+    orphans.extend(break_detached_loops(db, now)?);
+    Ok(orphans)
+}
+
+/// Moves items whose parent is missing, isn't a folder, or is the item
+/// itself, to the unfiled folder. The roots are left alone.
+fn reparent_missing_parents(db: &PlacesDb, now: Timestamp) -> Result<Vec<SyncGuid>> {
+    let orphans: Vec<(RowId, SyncGuid)> = db.query_rows_into(
+        "SELECT b.id, b.guid FROM moz_bookmarks b
+         LEFT JOIN moz_bookmarks p ON p.id = b.parent
+         WHERE p.id IS NULL OR p.type <> :folder OR p.id = b.id
+         ORDER BY b.id",
+        &[(":folder", &(BookmarkType::Folder as u8))],
+        |row| -> Result<_> { Ok((row.get(0)?, row.get(1)?)) },
+    )?;
+    let orphans = orphans
+        .into_iter()
+        .filter(|(_, guid)| BookmarkRootGuid::well_known(guid.as_str()).is_none())
+        .collect::<Vec<_>>();
+    if orphans.is_empty() {
+        return Ok(Vec::new());
+    }
+    let unfiled = root_id(db, BookmarkRootGuid::Unfiled)?;
+    for (id, _) in &orphans {
+        move_to_end(db, *id, unfiled, now)?;
+    }
+    bump_folder(db, unfiled, now)?;
+    Ok(orphans.into_iter().map(|(_, guid)| guid).collect())
+}
+
+/// Finds items which can't be reached from the root, even though they all
+/// have a parent, which means they're in (or under) a loop of folders, like a
+/// folder which is its own grandparent. Each loop is broken by moving one of
+/// its folders to the unfiled folder, or, if a root is in the loop, moving
+/// that root back under the root folder.
+fn break_detached_loops(db: &PlacesDb, now: Timestamp) -> Result<Vec<SyncGuid>> {
+    let mut moved = Vec::new();
+    loop {
+        let detached: HashMap<RowId, (RowId, SyncGuid)> = db
+            .query_rows_and_then_named(
+                &format!(
+                    "WITH RECURSIVE
+                     reachable(id) AS (
+                         SELECT id FROM moz_bookmarks WHERE guid = '{}'
+                         UNION
+                         SELECT b.id FROM moz_bookmarks b
+                         JOIN reachable r ON b.parent = r.id
+                     )
+                     SELECT id, parent, guid FROM moz_bookmarks
+                     WHERE id NOT IN reachable",
+                    BookmarkRootGuid::Root.as_str()
+                ),
+                &[],
+                |row| -> Result<_> { Ok((row.get(0)?, (row.get(1)?, row.get(2)?))) },
+            )?
+            .into_iter()
+            .collect();
+        // Move any detached roots first, so that the unfiled folder is
+        // connected before we move other items into it.
+        let root = detached
+            .iter()
+            .filter(|(_, (_, guid))| BookmarkRootGuid::well_known(guid.as_str()).is_some())
+            .map(|(id, _)| *id)
+            .min();
+        let (id, parent) = match root {
+            Some(id) => (id, root_id(db, BookmarkRootGuid::Root)?),
+            None => {
+                let mut id = match detached.keys().min() {
+                    Some(id) => *id,
+                    None => break,
+                };
+                // Walk up from any detached item until we come back to one
+                // we've already seen, which must be in the loop.
+                let mut seen = HashSet::new();
+                while seen.insert(id) {
+                    match detached.get(&id) {
+                        Some((parent, _)) if detached.contains_key(parent) => id = *parent,
+                        _ => break,
+                    }
+                }
+                (id, root_id(db, BookmarkRootGuid::Unfiled)?)
+            }
+        };
+        move_to_end(db, id, parent, now)?;
+        bump_folder(db, parent, now)?;
+        moved.push(detached[&id].1.clone());
+    }
+    Ok(moved)
+}
+
+fn root_id(db: &PlacesDb, root: BookmarkRootGuid) -> Result<RowId> {
+    Ok(db.query_one(&format!(
+        "SELECT id FROM moz_bookmarks WHERE guid = '{}'",
+        root.as_str()
+    ))?)
+}
+
+/// Moves an item to the end of `parent`, and marks it as changed.
+fn move_to_end(db: &PlacesDb, id: RowId, parent: RowId, now: Timestamp) -> Result<()> {
+    db.execute_named_cached(
+        "UPDATE moz_bookmarks SET
+             parent = :parent,
+             position = (SELECT IFNULL(MAX(position) + 1, 0)
+                         FROM moz_bookmarks
+                         WHERE parent = :parent),
+             lastModified = :now,
+             syncChangeCounter = syncChangeCounter + 1
+         WHERE id = :id",
+        &[(":parent", &parent), (":now", &now), (":id", &id)],
+    )?;
+    Ok(())
+}
+
+/// Renumbers the children of folders whose positions aren't 0 to the number
+/// of children minus one. Children with the same position are kept in the
+/// order they were added.
+fn renumber_children(db: &PlacesDb, now: Timestamp) -> Result<Vec<SyncGuid>> {
+    let folders: Vec<(RowId, SyncGuid)> = db.query_rows_into(
+        "SELECT p.id, p.guid FROM moz_bookmarks p
+         JOIN moz_bookmarks b ON b.parent = p.id
+         GROUP BY p.id
+         HAVING COUNT(DISTINCT b.position) <> COUNT(*)
+             OR MIN(b.position) <> 0
+             OR MAX(b.position) <> COUNT(*) - 1
+         ORDER BY p.id",
+        &[],
+        |row| -> Result<_> { Ok((row.get(0)?, row.get(1)?)) },
+    )?;
+    for (folder_id, _) in &folders {
+        let children: Vec<RowId> = db.query_rows_into(
+            "SELECT id FROM moz_bookmarks
+             WHERE parent = :parent
+             ORDER BY position, id",
+            &[(":parent", folder_id)],
+            |row| row.get(0),
+        )?;
+        for (position, id) in children.iter().enumerate() {
+            db.execute_named_cached(
+                "UPDATE moz_bookmarks SET position = :position WHERE id = :id",
+                &[(":position", &(position as u32)), (":id", id)],
+            )?;
+        }
+        bump_folder(db, *folder_id, now)?;
+    }
+    Ok(folders.into_iter().map(|(_, guid)| guid).collect())
+}
+
+/// Removes the URLs from folders and separators. Only bookmarks should have
+/// them.
+fn remove_non_bookmark_urls(db: &PlacesDb, now: Timestamp) -> Result<Vec<SyncGuid>> {
+    let items: Vec<SyncGuid> = db.query_rows_into(
+        "SELECT guid FROM moz_bookmarks
+         WHERE type <> :bookmark AND fk NOT NULL
+         ORDER BY id",
+        &[(":bookmark", &(BookmarkType::Bookmark as u8))],
+        |row| row.get(0),
+    )?;
+    if !items.is_empty() {
+        db.execute_named(
+            "UPDATE moz_bookmarks SET
+                 fk = NULL,
+                 lastModified = :now,
+                 syncChangeCounter = syncChangeCounter + 1
+             WHERE type <> :bookmark AND fk NOT NULL",
+            &[
+                (":bookmark", &(BookmarkType::Bookmark as u8)),
+                (":now", &now),
+            ],
+        )?;
+    }
+    Ok(items)
+}
+
+// Selects the pages whose `foreign_count` doesn't match the number of rows
+// which reference them, and the count they should have. The references are
+// counted in one pass over each table, rather than once per page. This must
+// be kept in sync with the triggers which maintain `foreign_count`.
+const WRONG_FOREIGN_COUNTS: &str = "
+    SELECT h.id, IFNULL(c.count, 0) FROM moz_places h
+    LEFT JOIN (
+        SELECT place_id, COUNT(*) AS count FROM (
+            SELECT fk AS place_id FROM moz_bookmarks WHERE fk NOT NULL
+            UNION ALL
+            SELECT placeId FROM moz_bookmarks_synced WHERE placeId NOT NULL
+            UNION ALL
+            SELECT place_id FROM moz_tags_relation
+        )
+        GROUP BY place_id
+    ) c ON c.place_id = h.id
+    WHERE h.foreign_count <> IFNULL(c.count, 0)";
+
+/// Recalculates `foreign_count` for pages where it's wrong, and marks their
+/// frecencies as stale, since bookmarked pages are scored differently.
+fn fix_foreign_counts(db: &PlacesDb, now: Timestamp) -> Result<usize> {
+    let wrong: Vec<(RowId, i64)> =
+        db.query_rows_into(WRONG_FOREIGN_COUNTS, &[], |row| -> Result<_> {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    for (place_id, count) in &wrong {
+        db.execute_named_cached(
+            "UPDATE moz_places SET foreign_count = :count WHERE id = :id",
+            &[(":count", count), (":id", place_id)],
+        )?;
+        db.execute_named_cached(
+            "INSERT OR REPLACE INTO moz_places_stale_frecencies(place_id, stale_at)
+             VALUES(:id, :now)",
+            &[(":id", place_id), (":now", &now)],
+        )?;
+    }
+    Ok(wrong.len())
+}
+
+/// Marks a folder whose children were changed as modified.
+fn bump_folder(db: &PlacesDb, folder_id: RowId, now: Timestamp) -> Result<()> {
+    set_ancestors_last_modified(db, folder_id, now)?;
+    db.execute_named_cached(
+        "UPDATE moz_bookmarks SET syncChangeCounter = syncChangeCounter + 1
+         WHERE id = :id",
+        &[(":id", &folder_id)],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::tags;
+    use crate::tests::{assert_json_tree, insert_json_tree};
+    use rusqlite::NO_PARAMS;
+    use serde_json::json;
+
+    fn counter(db: &PlacesDb, guid: &str) -> Result<u32> {
+        Ok(get_raw_bookmark(db, &guid.into())?
+            .unwrap()
+            .sync_change_counter)
+    }
+
+    #[test]
+    fn test_check_and_repair() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        let url = Url::parse("https://example.com/a")?;
+
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    { "guid": "bookmarkAAAA", "url": url.as_str() },
+                    {
+                        "guid": "folderBBBBBB",
+                        "children": [
+                            { "guid": "bookmarkCCCC", "url": "https://example.com/c" },
+                            { "guid": "bookmarkDDDD", "url": "https://example.com/d" },
+                            { "guid": "bookmarkEEEE", "url": "https://example.com/e" },
+                        ],
+                    },
+                    { "guid": "separatorFFF", "type": BookmarkType::Separator as u8 },
+                ]
+            }),
+        );
+        tags::tag_url(&conn, &url, "tag")?;
+
+        // A healthy tree has nothing to fix.
+        assert!(check_and_repair(&conn)?.is_empty());
+
+        conn.execute_batch(&format!(
+            "PRAGMA foreign_keys = OFF;
+             PRAGMA ignore_check_constraints = ON;
+             UPDATE moz_bookmarks SET parent = 9999 WHERE guid = 'bookmarkAAAA';
+             UPDATE moz_bookmarks SET position = 5 WHERE guid = 'bookmarkCCCC';
+             UPDATE moz_bookmarks SET position = 1 WHERE guid = 'bookmarkEEEE';
+             UPDATE moz_bookmarks
+             SET fk = (SELECT fk FROM moz_bookmarks WHERE guid = 'bookmarkCCCC')
+             WHERE guid = 'separatorFFF';
+             UPDATE moz_places SET foreign_count = 0 WHERE url = '{}';
+             UPDATE moz_bookmarks SET syncChangeCounter = 0;
+             PRAGMA ignore_check_constraints = OFF;
+             PRAGMA foreign_keys = ON;",
+            url.as_str()
+        ))?;
+
+        let report = check_and_repair(&conn)?;
+        assert_eq!(report.orphans, vec![SyncGuid::from("bookmarkAAAA")]);
+        // Unfiled is renumbered because the orphan left a gap.
+        assert_eq!(
+            report.renumbered_folders,
+            vec![
+                SyncGuid::from(BookmarkRootGuid::Unfiled.as_str()),
+                "folderBBBBBB".into()
+            ]
+        );
+        assert_eq!(report.items_with_urls, vec![SyncGuid::from("separatorFFF")]);
+        assert_eq!(report.wrong_foreign_counts, 1);
+
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "children": [
+                    {
+                        "guid": "folderBBBBBB",
+                        "children": [
+                            { "guid": "bookmarkDDDD", "url": "https://example.com/d" },
+                            { "guid": "bookmarkEEEE", "url": "https://example.com/e" },
+                            { "guid": "bookmarkCCCC", "url": "https://example.com/c" },
+                        ],
+                    },
+                    { "guid": "separatorFFF", "type": BookmarkType::Separator as u8 },
+                    { "guid": "bookmarkAAAA", "url": url.as_str() },
+                ]
+            }),
+        );
+        // One for the bookmark, and one for the tag.
+        let foreign_count: u32 = conn.query_one(&format!(
+            "SELECT foreign_count FROM moz_places WHERE url = '{}'",
+            url.as_str()
+        ))?;
+        assert_eq!(foreign_count, 2);
+        let wrong: u32 =
+            conn.query_one(&format!("SELECT COUNT(*) FROM ({})", WRONG_FOREIGN_COUNTS))?;
+        assert_eq!(wrong, 0);
+
+        // Everything we fixed will be uploaded.
+        assert_eq!(counter(&conn, "bookmarkAAAA")?, 1);
+        assert_eq!(counter(&conn, "separatorFFF")?, 1);
+        assert_eq!(counter(&conn, "folderBBBBBB")?, 1);
+        assert_eq!(counter(&conn, "bookmarkCCCC")?, 0);
+        assert_eq!(counter(&conn, BookmarkRootGuid::Unfiled.as_str())?, 2);
+
+        assert!(check_and_repair(&conn)?.is_empty());
+        let violations = conn
+            .prepare("PRAGMA foreign_key_check")?
+            .query_map(NO_PARAMS, |_| Ok(()))?
+            .count();
+        assert_eq!(violations, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_repair_detached_loop() -> Result<()> {
+        use crate::observer::EventQueue;
+        use std::sync::Arc;
+
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    {
+                        "guid": "folderBBBBBB",
+                        "children": [
+                            {
+                                "guid": "folderCCCCCC",
+                                "children": [
+                                    { "guid": "bookmarkDDDD", "url": "https://example.com/d" },
+                                ],
+                            },
+                        ],
+                    },
+                ]
+            }),
+        );
+        // B and C are each other's parents, so neither can be reached from
+        // the root, even though both have a parent which is a folder.
+        conn.execute_batch(
+            "UPDATE moz_bookmarks SET
+                 parent = (SELECT id FROM moz_bookmarks WHERE guid = 'folderCCCCCC'),
+                 position = 1
+             WHERE guid = 'folderBBBBBB'",
+        )?;
+        let queue = Arc::new(EventQueue::default());
+        conn.observers.register(queue.clone());
+
+        let report = check_and_repair(&conn)?;
+        assert_eq!(report.orphans, vec![SyncGuid::from("folderBBBBBB")]);
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "children": [
+                    {
+                        "guid": "folderBBBBBB",
+                        "children": [
+                            {
+                                "guid": "folderCCCCCC",
+                                "children": [
+                                    { "guid": "bookmarkDDDD", "url": "https://example.com/d" },
+                                ],
+                            },
+                        ],
+                    },
+                ]
+            }),
+        );
+        assert_eq!(queue.drain(), vec![PlacesEvent::BookmarksRepaired]);
+
+        assert!(check_and_repair(&conn)?.is_empty());
+        assert_eq!(queue.drain(), vec![]);
+        Ok(())
+    }
+}
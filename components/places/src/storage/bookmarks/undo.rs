@@ -0,0 +1,878 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An undo log for local bookmark changes.
+//!
+//! `insert_bookmark`, `update_bookmark`, `delete_bookmark`,
+//! `reorder_children` and `move_items` record the changes they make in a log
+//! owned by the connection. `undo` reverts the
+//! most recent change, and `redo` makes it again. Items are put back with
+//! their original GUIDs, positions and dates added, so Sync sees the same
+//! items rather than new ones, and change counters are bumped by the usual
+//! insert, update and delete code.
+//!
+//! The log is shared by all connections to the same database. Changes which
+//! don't go through it, like syncing, merging duplicates and repairs, clear
+//! it; undo and redo also check that the items they touch haven't changed
+//! since, in case something else changed them anyway.
+
+use super::reorder::{set_children_in_tx, FolderChildren};
+use super::*;
+use std::collections::VecDeque;
+
+/// How many changes we remember. The oldest change is dropped when a new one
+/// would go over this.
+const MAX_UNDO_ENTRIES: usize = 100;
+
+#[derive(Debug, Default)]
+pub(crate) struct UndoLog {
+    undo: VecDeque<Change>,
+    redo: Vec<Change>,
+}
+
+impl UndoLog {
+    fn record(&mut self, change: Change) {
+        if self.undo.len() == MAX_UNDO_ENTRIES {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(change);
+        // Making a new change means the undone ones can't be redone.
+        self.redo.clear();
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+/// Forgets all the changes in the log, after changing the tree without
+/// going through it.
+pub(crate) fn clear_undo_log(db: &PlacesDb) {
+    db.undo_log.lock().unwrap().clear();
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+enum Change {
+    Insert(ItemState),
+    Update {
+        before: ItemState,
+        after: ItemState,
+        tags: Option<TagsChange>,
+    },
+    /// The deleted item, followed by all its descendants, parents first.
+    Delete(Vec<ItemState>),
+    /// The children of every folder a batch move or reorder touched.
+    Move {
+        before: Vec<FolderChildren>,
+        after: Vec<FolderChildren>,
+    },
+}
+
+/// Tags belong to a URL rather than a bookmark, so an update which changed
+/// them remembers the URL too.
+#[derive(Debug, Clone)]
+struct TagsChange {
+    url: Url,
+    before: Vec<String>,
+    after: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+enum ItemKind {
+    Bookmark {
+        url: Url,
+        title: Option<String>,
+        keyword: Option<String>,
+    },
+    Separator,
+    Folder {
+        title: Option<String>,
+    },
+}
+
+/// Everything we need to put an item back the way it was.
+#[derive(Debug, Clone)]
+struct ItemState {
+    guid: SyncGuid,
+    parent_guid: SyncGuid,
+    position: u32,
+    date_added: Timestamp,
+    kind: ItemKind,
+}
+
+impl ItemState {
+    fn from_raw(raw: &RawBookmark) -> Result<Self> {
+        let parent_guid = raw
+            .parent_guid
+            .clone()
+            .ok_or_else(|| Corruption::NonRootWithoutParent(raw.guid.to_string()))?;
+        let kind = match raw.bookmark_type {
+            BookmarkType::Bookmark => ItemKind::Bookmark {
+                url: raw.url.clone().ok_or(InvalidPlaceInfo::NoUrl)?,
+                title: raw.title.clone(),
+                keyword: raw.keyword.clone(),
+            },
+            BookmarkType::Separator => ItemKind::Separator,
+            BookmarkType::Folder => ItemKind::Folder {
+                title: raw.title.clone(),
+            },
+        };
+        Ok(Self {
+            guid: raw.guid.clone(),
+            parent_guid,
+            position: raw.position,
+            date_added: raw.date_added,
+            kind,
+        })
+    }
+
+    /// Returns true if `current` is still in this state. Positions aren't
+    /// compared, since they change when siblings are inserted or removed.
+    /// Missing titles and keywords are the same as empty ones, since undo
+    /// and redo use empty ones to remove them.
+    fn matches(&self, current: &RawBookmark) -> bool {
+        fn same(a: &Option<String>, b: &Option<String>) -> bool {
+            a.as_ref().map_or("", String::as_str) == b.as_ref().map_or("", String::as_str)
+        }
+        if current.parent_guid.as_ref() != Some(&self.parent_guid) {
+            return false;
+        }
+        match (&self.kind, current.bookmark_type) {
+            (
+                ItemKind::Bookmark {
+                    url,
+                    title,
+                    keyword,
+                },
+                BookmarkType::Bookmark,
+            ) => {
+                current.url.as_ref() == Some(url)
+                    && same(title, &current.title)
+                    && same(keyword, &current.keyword)
+            }
+            (ItemKind::Separator, BookmarkType::Separator) => true,
+            (ItemKind::Folder { title }, BookmarkType::Folder) => same(title, &current.title),
+            _ => false,
+        }
+    }
+
+    fn fetch(db: &PlacesDb, guid: &SyncGuid) -> Result<Self> {
+        let raw = get_raw_bookmark(db, guid)?
+            .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
+        Self::from_raw(&raw)
+    }
+
+    fn insertable(&self) -> InsertableItem {
+        let parent_guid = self.parent_guid.clone();
+        let position = BookmarkPosition::Specific(self.position);
+        let guid = Some(self.guid.clone());
+        let date_added = Some(self.date_added);
+        match &self.kind {
+            ItemKind::Bookmark {
+                url,
+                title,
+                keyword,
+            } => InsertableBookmark {
+                parent_guid,
+                position,
+                date_added,
+                last_modified: None,
+                guid,
+                url: url.clone(),
+                title: title.clone(),
+                keyword: keyword.clone(),
+                tags: Vec::new(),
+            }
+            .into(),
+            ItemKind::Separator => InsertableSeparator {
+                parent_guid,
+                position,
+                date_added,
+                last_modified: None,
+                guid,
+            }
+            .into(),
+            ItemKind::Folder { title } => InsertableFolder {
+                parent_guid,
+                position,
+                date_added,
+                last_modified: None,
+                guid,
+                title: title.clone(),
+            }
+            .into(),
+        }
+    }
+
+    /// Builds an update which moves `current` back to this state. Empty
+    /// strings are used for missing titles and keywords, so that they're
+    /// removed instead of left alone.
+    fn updatable(&self, current: &RawBookmark) -> UpdatableItem {
+        let location = if current.parent_guid.as_ref() != Some(&self.parent_guid) {
+            UpdateTreeLocation::Parent(
+                self.parent_guid.clone(),
+                BookmarkPosition::Specific(self.position),
+            )
+        } else if current.position != self.position {
+            UpdateTreeLocation::Position(BookmarkPosition::Specific(self.position))
+        } else {
+            UpdateTreeLocation::None
+        };
+        match &self.kind {
+            ItemKind::Bookmark {
+                url,
+                title,
+                keyword,
+            } => UpdatableBookmark {
+                location,
+                url: Some(url.clone()),
+                title: Some(title.clone().unwrap_or_default()),
+                keyword: Some(keyword.clone().unwrap_or_default()),
+                tags: None,
+            }
+            .into(),
+            ItemKind::Separator => UpdatableSeparator { location }.into(),
+            ItemKind::Folder { title } => UpdatableFolder {
+                location,
+                title: Some(title.clone().unwrap_or_default()),
+            }
+            .into(),
+        }
+    }
+}
+
+/// A change which has been made but not recorded yet. These are built inside
+/// the transaction which makes the change, and recorded once it commits, so
+/// that recording can't fail after the change is already in the database.
+pub(super) struct PendingChange(Change);
+
+impl PendingChange {
+    pub(super) fn record(self, db: &PlacesDb) {
+        db.undo_log.lock().unwrap().record(self.0);
+    }
+}
+
+/// Remembers an item which was just inserted.
+pub(super) fn inserted(db: &PlacesDb, guid: &SyncGuid) -> Result<PendingChange> {
+    let state = ItemState::fetch(db, guid)?;
+    Ok(PendingChange(Change::Insert(state)))
+}
+
+/// Remembers an item which was just updated, given its state before the
+/// update. `tags` is the URL whose tags were replaced, if any, with its tags
+/// before the update.
+pub(super) fn updated(
+    db: &PlacesDb,
+    before: &RawBookmark,
+    tags: Option<(Url, Vec<String>)>,
+) -> Result<PendingChange> {
+    let change = Change::Update {
+        before: ItemState::from_raw(before)?,
+        after: ItemState::fetch(db, &before.guid)?,
+        tags: match tags {
+            Some((url, before)) => Some(TagsChange {
+                after: get_tags_for_url(db, &url)?,
+                url,
+                before,
+            }),
+            None => None,
+        },
+    };
+    Ok(PendingChange(change))
+}
+
+/// Fetches the state of an item and its descendants, so that they can be
+/// remembered with `deleted` once it's been deleted.
+pub(super) fn fetch_subtree(db: &PlacesDb, guid: &SyncGuid) -> Result<Vec<RawBookmark>> {
+    db.query_rows_into_cached(
+        &format!(
+            "WITH RECURSIVE
+             descendants(id, level) AS (
+               SELECT id, 0 FROM moz_bookmarks
+               WHERE guid = :guid
+               UNION ALL
+               SELECT b.id, d.level + 1 FROM moz_bookmarks b
+               JOIN descendants d ON b.parent = d.id
+             )
+             {}
+             JOIN descendants d ON d.id = b.id
+             ORDER BY d.level, b.parent, b.position",
+            RAW_BOOKMARK_SQL
+        ),
+        &[(":guid", guid)],
+        RawBookmark::from_row,
+    )
+}
+
+/// Remembers a deleted item, given the result of `fetch_subtree`.
+pub(super) fn deleted(subtree: &[RawBookmark]) -> Result<PendingChange> {
+    let states = subtree
+        .iter()
+        .map(ItemState::from_raw)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(PendingChange(Change::Delete(states)))
+}
+
+/// Records a batch move or reorder, given the children of the folders it
+/// touched before and after.
+pub(super) fn record_move(db: &PlacesDb, before: Vec<FolderChildren>, after: Vec<FolderChildren>) {
+    db.undo_log
+        .lock()
+        .unwrap()
+        .record(Change::Move { before, after });
+}
+
+/// What a change did to the tree, so that we can tell observers once its
+/// transaction commits.
+#[derive(Default)]
+struct Applied {
+    inserted: Vec<SyncGuid>,
+    updated: Vec<RawBookmark>,
+    deleted: Vec<RawBookmark>,
+}
+
+impl Applied {
+    fn notify(self, db: &PlacesDb) {
+        notify_inserted(db, &self.inserted);
+        for old in &self.updated {
+            notify_updated(db, old);
+        }
+        for old in self.deleted {
+            notify_removed(db, old);
+        }
+    }
+}
+
+/// Returns the item with `state`'s GUID, or an error if it's missing or
+/// doesn't match `expected`.
+fn fetch_expected(db: &PlacesDb, state: &ItemState, expected: &ItemState) -> Result<RawBookmark> {
+    let existing = get_raw_bookmark(db, &state.guid)?
+        .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(state.guid.to_string()))?;
+    if !expected.matches(&existing) {
+        return Err(ErrorKind::UndoConflict(state.guid.to_string()).into());
+    }
+    Ok(existing)
+}
+
+fn insert_states(db: &PlacesDb, states: &[ItemState], applied: &mut Applied) -> Result<()> {
+    for state in states {
+        // Something else might have put the item back already.
+        if get_raw_bookmark(db, &state.guid)?.is_some() {
+            return Err(ErrorKind::UndoConflict(state.guid.to_string()).into());
+        }
+        applied
+            .inserted
+            .push(insert_bookmark_in_tx(db, &state.insertable())?);
+    }
+    Ok(())
+}
+
+fn delete_state(db: &PlacesDb, state: &ItemState, applied: &mut Applied) -> Result<()> {
+    let existing = fetch_expected(db, state, state)?;
+    delete_bookmark_in_tx(db, &state.guid)?;
+    applied.deleted.push(existing);
+    Ok(())
+}
+
+/// Moves an item from the `expected` state to `state`. `tags` is the URL
+/// whose tags changed, with its tags in the expected state and in `state`.
+fn update_to_state(
+    db: &PlacesDb,
+    state: &ItemState,
+    expected: &ItemState,
+    tags: Option<(&Url, &[String], &[String])>,
+    applied: &mut Applied,
+) -> Result<()> {
+    let existing = fetch_expected(db, state, expected)?;
+    if let Some((url, expected_tags, _)) = tags {
+        let mut current_tags = get_tags_for_url(db, url)?;
+        let mut expected_tags = expected_tags.to_vec();
+        current_tags.sort();
+        expected_tags.sort();
+        if current_tags != expected_tags {
+            return Err(ErrorKind::UndoConflict(state.guid.to_string()).into());
+        }
+    }
+    let updatable = state.updatable(&existing);
+    update_bookmark_in_tx(db, &state.guid, &updatable, existing.clone())?;
+    if let Some((url, _, tags)) = tags {
+        let tags = tags.iter().map(String::as_str).collect::<Vec<_>>();
+        set_tags_for_url(db, url, &tags, true)?;
+    }
+    applied.updated.push(existing);
+    Ok(())
+}
+
+/// Moves the children of each folder from the `expected` order to the order
+/// in `folders`.
+fn set_children(
+    db: &PlacesDb,
+    folders: &[FolderChildren],
+    expected: &[FolderChildren],
+    applied: &mut Applied,
+) -> Result<()> {
+    for folder in expected {
+        if FolderChildren::fetch(db, &folder.guid)?.children != folder.children {
+            return Err(ErrorKind::UndoConflict(folder.guid.to_string()).into());
+        }
+    }
+    applied.updated.extend(set_children_in_tx(db, folders)?);
+    Ok(())
+}
+
+fn apply(db: &PlacesDb, change: &Change, reverse: bool) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    let mut applied = Applied::default();
+    let result = match (change, reverse) {
+        (Change::Insert(state), false) => {
+            insert_states(db, std::slice::from_ref(state), &mut applied)
+        }
+        (Change::Insert(state), true) => delete_state(db, state, &mut applied),
+        (
+            Change::Update {
+                before,
+                after,
+                tags,
+            },
+            reverse,
+        ) => {
+            let (from, to) = if reverse {
+                (after, before)
+            } else {
+                (before, after)
+            };
+            let tags = tags.as_ref().map(|t| {
+                if reverse {
+                    (&t.url, t.after.as_slice(), t.before.as_slice())
+                } else {
+                    (&t.url, t.before.as_slice(), t.after.as_slice())
+                }
+            });
+            update_to_state(db, to, from, tags, &mut applied)
+        }
+        (Change::Delete(states), false) => delete_state(db, &states[0], &mut applied),
+        (Change::Delete(states), true) => insert_states(db, states, &mut applied),
+        (Change::Move { before, after }, false) => set_children(db, after, before, &mut applied),
+        (Change::Move { before, after }, true) => set_children(db, before, after, &mut applied),
+    };
+    super::super::delete_pending_temp_tables(db)?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result?;
+    applied.notify(db);
+    Ok(())
+}
+
+/// Reverts the most recent bookmark change made on this connection. Returns
+/// false if there's nothing to undo.
+///
+/// If the change can't be reverted, because something else has changed the
+/// items it touched since, an `UndoConflict` error is returned and the change
+/// is dropped from the log. Items which only shifted within their folder,
+/// because their siblings changed, don't count as changed.
+pub fn undo(db: &PlacesDb) -> Result<bool> {
+    let change = match db.undo_log.lock().unwrap().undo.pop_back() {
+        Some(change) => change,
+        None => return Ok(false),
+    };
+    apply(db, &change, true)?;
+    db.undo_log.lock().unwrap().redo.push(change);
+    Ok(true)
+}
+
+/// Makes the most recently undone change again. Returns false if there's
+/// nothing to redo.
+pub fn redo(db: &PlacesDb) -> Result<bool> {
+    let change = match db.undo_log.lock().unwrap().redo.pop() {
+        Some(change) => change,
+        None => return Ok(false),
+    };
+    apply(db, &change, false)?;
+    db.undo_log.lock().unwrap().undo.push_back(change);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::tags;
+    use crate::tests::{assert_json_tree, insert_json_tree};
+    use serde_json::json;
+
+    fn insert_bookmark_at(db: &PlacesDb, guid: &str, url: &str) -> Result<SyncGuid> {
+        insert_bookmark(
+            db,
+            &InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: Some(Timestamp(1_000)),
+                last_modified: None,
+                guid: Some(guid.into()),
+                url: Url::parse(url)?,
+                title: Some(guid.into()),
+                keyword: None,
+                tags: Vec::new(),
+            }
+            .into(),
+        )
+    }
+
+    #[test]
+    fn test_undo_insert() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+
+        assert!(!undo(&conn)?);
+        assert!(!redo(&conn)?);
+
+        insert_bookmark_at(&conn, "bookmarkAAAA", "https://example.com/a")?;
+        insert_bookmark_at(&conn, "bookmarkBBBB", "https://example.com/b")?;
+
+        assert!(undo(&conn)?);
+        assert!(undo(&conn)?);
+        assert!(!undo(&conn)?);
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({ "children": [] }),
+        );
+
+        assert!(redo(&conn)?);
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "children": [
+                    { "guid": "bookmarkAAAA", "url": "https://example.com/a" },
+                ]
+            }),
+        );
+        let raw = get_raw_bookmark(&conn, &"bookmarkAAAA".into())?.unwrap();
+        assert_eq!(raw.date_added, Timestamp(1_000));
+
+        // A new change means the other insert can't be redone.
+        insert_bookmark_at(&conn, "bookmarkCCCC", "https://example.com/c")?;
+        assert!(!redo(&conn)?);
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "children": [
+                    { "guid": "bookmarkAAAA", "url": "https://example.com/a" },
+                    { "guid": "bookmarkCCCC", "url": "https://example.com/c" },
+                ]
+            }),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_delete() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    {
+                        "guid": "bookmarkAAAA",
+                        "url": "https://example.com/a",
+                    },
+                    {
+                        "guid": "folderBBBBBB",
+                        "title": "B",
+                        "date_added": 1_000,
+                        "children": [
+                            {
+                                "guid": "bookmarkCCCC",
+                                "url": "https://example.com/c",
+                                "date_added": 2_000,
+                            },
+                            {
+                                "guid": "separatorDDD",
+                                "type": BookmarkType::Separator as u8,
+                            },
+                        ],
+                    },
+                    {
+                        "guid": "bookmarkEEEE",
+                        "url": "https://example.com/e",
+                    },
+                ]
+            }),
+        );
+        update_bookmark(
+            &conn,
+            &"bookmarkCCCC".into(),
+            &UpdatableBookmark {
+                keyword: Some("c".into()),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        // Pretend everything has been synced, so that deleting writes
+        // tombstones.
+        conn.execute_batch("UPDATE moz_bookmarks SET syncStatus = 2, syncChangeCounter = 0")?;
+
+        assert!(delete_bookmark(&conn, &"folderBBBBBB".into())?);
+        let tombstones: u32 = conn.query_one("SELECT COUNT(*) FROM moz_bookmarks_deleted")?;
+        assert_eq!(tombstones, 3);
+
+        assert!(undo(&conn)?);
+        let expected = json!({
+            "children": [
+                { "guid": "bookmarkAAAA", "url": "https://example.com/a" },
+                {
+                    "guid": "folderBBBBBB",
+                    "title": "B",
+                    "children": [
+                        { "guid": "bookmarkCCCC", "url": "https://example.com/c" },
+                        { "guid": "separatorDDD", "type": BookmarkType::Separator as u8 },
+                    ],
+                },
+                { "guid": "bookmarkEEEE", "url": "https://example.com/e" },
+            ]
+        });
+        assert_json_tree(&conn, &BookmarkRootGuid::Unfiled.into(), expected.clone());
+        let tombstones: u32 = conn.query_one("SELECT COUNT(*) FROM moz_bookmarks_deleted")?;
+        assert_eq!(tombstones, 0);
+
+        let folder = get_raw_bookmark(&conn, &"folderBBBBBB".into())?.unwrap();
+        assert_eq!(folder.date_added, Timestamp(1_000));
+        assert!(folder.sync_change_counter > 0);
+        let bookmark = get_raw_bookmark(&conn, &"bookmarkCCCC".into())?.unwrap();
+        assert_eq!(bookmark.date_added, Timestamp(2_000));
+        assert_eq!(bookmark.keyword, Some("c".into()));
+        assert!(bookmark.sync_change_counter > 0);
+        let unfiled = get_raw_bookmark(&conn, &BookmarkRootGuid::Unfiled.into())?.unwrap();
+        assert!(unfiled.sync_change_counter > 0);
+
+        assert!(redo(&conn)?);
+        assert!(get_raw_bookmark(&conn, &"folderBBBBBB".into())?.is_none());
+        assert!(get_raw_bookmark(&conn, &"bookmarkCCCC".into())?.is_none());
+
+        assert!(undo(&conn)?);
+        assert_json_tree(&conn, &BookmarkRootGuid::Unfiled.into(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_update() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        let url_a = Url::parse("https://example.com/a")?;
+
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    {
+                        "guid": "bookmarkAAAA",
+                        "title": "A",
+                        "url": url_a.as_str(),
+                    },
+                    {
+                        "guid": "bookmarkBBBB",
+                        "url": "https://example.com/b",
+                    },
+                    {
+                        "guid": "folderCCCCCC",
+                        "children": [],
+                    },
+                ]
+            }),
+        );
+        tags::tag_url(&conn, &url_a, "old")?;
+
+        update_bookmark(
+            &conn,
+            &"bookmarkAAAA".into(),
+            &UpdatableBookmark {
+                location: UpdateTreeLocation::Parent(
+                    "folderCCCCCC".into(),
+                    BookmarkPosition::Append,
+                ),
+                title: Some("".into()),
+                keyword: Some("a".into()),
+                tags: Some(vec!["new".into()]),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        update_bookmark(
+            &conn,
+            &"bookmarkBBBB".into(),
+            &UpdatableBookmark {
+                location: UpdateTreeLocation::Position(BookmarkPosition::Append),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+
+        assert!(undo(&conn)?);
+        assert!(undo(&conn)?);
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "children": [
+                    { "guid": "bookmarkAAAA", "title": "A", "url": url_a.as_str() },
+                    { "guid": "bookmarkBBBB", "url": "https://example.com/b" },
+                    { "guid": "folderCCCCCC", "children": [] },
+                ]
+            }),
+        );
+        let raw = get_raw_bookmark(&conn, &"bookmarkAAAA".into())?.unwrap();
+        assert_eq!(raw.keyword, None);
+        assert_eq!(
+            tags::get_tags_for_url(&conn, &url_a)?,
+            vec!["old".to_string()]
+        );
+
+        assert!(redo(&conn)?);
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "children": [
+                    { "guid": "bookmarkBBBB", "url": "https://example.com/b" },
+                    {
+                        "guid": "folderCCCCCC",
+                        "children": [
+                            { "guid": "bookmarkAAAA", "url": url_a.as_str() },
+                        ],
+                    },
+                ]
+            }),
+        );
+        let raw = get_raw_bookmark(&conn, &"bookmarkAAAA".into())?.unwrap();
+        assert_eq!(raw.title, None);
+        assert_eq!(raw.keyword, Some("a".into()));
+        assert_eq!(
+            tags::get_tags_for_url(&conn, &url_a)?,
+            vec!["new".to_string()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_missing_item() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+
+        insert_bookmark_at(&conn, "bookmarkAAAA", "https://example.com/a")?;
+        // Something which doesn't go through the log removes the item.
+        delete_bookmark_in_tx(&conn, &"bookmarkAAAA".into())?;
+        assert!(undo(&conn).is_err());
+        // The change is dropped.
+        assert!(!undo(&conn)?);
+        assert!(!redo(&conn)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_conflict() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+
+        insert_bookmark_at(&conn, "bookmarkAAAA", "https://example.com/a")?;
+        insert_bookmark_at(&conn, "bookmarkBBBB", "https://example.com/b")?;
+        update_bookmark(
+            &conn,
+            &"bookmarkAAAA".into(),
+            &UpdatableBookmark {
+                title: Some("Local A".into()),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        // Something which doesn't go through the log changes the title.
+        conn.execute_batch(
+            "UPDATE moz_bookmarks SET title = 'Other A' WHERE guid = 'bookmarkAAAA'",
+        )?;
+        match undo(&conn)
+            .expect_err("Should refuse to overwrite the title")
+            .kind()
+        {
+            ErrorKind::UndoConflict(guid) => assert_eq!(guid, "bookmarkAAAA"),
+            kind => panic!("Expected undo conflict; got {}", kind),
+        }
+        let raw = get_raw_bookmark(&conn, &"bookmarkAAAA".into())?.unwrap();
+        assert_eq!(raw.title, Some("Other A".into()));
+
+        // Moving B before A, then swapping them back behind the log's back,
+        // means the move can't be undone either.
+        reorder_children(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            &["bookmarkBBBB".into(), "bookmarkAAAA".into()],
+        )?;
+        conn.execute_batch(
+            "UPDATE moz_bookmarks SET position = 1 - position
+             WHERE guid IN ('bookmarkAAAA', 'bookmarkBBBB')",
+        )?;
+        assert!(undo(&conn).is_err());
+
+        // Undoing the insert of B still works, since nothing changed B.
+        assert!(undo(&conn)?);
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "children": [{ "guid": "bookmarkAAAA", "url": "https://example.com/a" }]
+            }),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_log_cleared() -> Result<()> {
+        use crate::api::places_api::{test::new_mem_api, ConnectionType};
+        use crate::bookmark_sync::store::BookmarksStore;
+        use sync15::{telemetry, IncomingChangeset, Payload, ServerTimestamp, Store};
+
+        let _ = env_logger::try_init();
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+
+        // Merging duplicates clears the log.
+        insert_bookmark_at(&writer, "bookmarkAAAA", "https://example.com/a")?;
+        insert_bookmark_at(&writer, "bookmarkBBBB", "https://example.com/a")?;
+        merge_duplicates(&writer, &"bookmarkAAAA".into(), &["bookmarkBBBB".into()])?;
+        assert!(!undo(&writer)?);
+
+        // So does syncing, even though it's on another connection.
+        insert_bookmark_at(&writer, "bookmarkCCCC", "https://example.com/c")?;
+        let syncer = api.open_sync_connection()?;
+        let interrupt_scope = syncer.begin_interrupt_scope();
+        let store = BookmarksStore::new(&syncer, &interrupt_scope);
+        let mut incoming =
+            IncomingChangeset::new(store.collection_name().to_string(), ServerTimestamp(0));
+        let record = json!({
+            "id": "bookmarkDDDD",
+            "type": "bookmark",
+            "parentid": "unfiled",
+            "parentName": "Unfiled",
+            "dateAdded": 0,
+            "title": "D",
+            "bmkUri": "https://example.com/d",
+        });
+        incoming
+            .changes
+            .push((Payload::from_json(record).unwrap(), ServerTimestamp(0)));
+        store
+            .apply_incoming(incoming, &mut telemetry::Engine::new("bookmarks"))
+            .expect("Should apply incoming records");
+        assert!(!undo(&writer)?);
+        Ok(())
+    }
+}
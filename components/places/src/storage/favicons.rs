@@ -0,0 +1,332 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::RowId;
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::msg_types;
+use rusqlite::Row;
+use sql_support::{self, ConnExt};
+use url::Url;
+
+/// One size of a favicon.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Favicon {
+    pub url: Url,
+    /// The width of the icon, in pixels. Icons are assumed to be square.
+    pub width: u32,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl Favicon {
+    pub fn from_message(msg: msg_types::FaviconInfo) -> Result<Self> {
+        Ok(Self {
+            url: Url::parse(&msg.url)?,
+            width: msg.width,
+            mime_type: msg.mime_type,
+            data: msg.data,
+        })
+    }
+
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        let url: String = row.get("icon_url")?;
+        Ok(Self {
+            url: Url::parse(&url)?,
+            width: row.get("width")?,
+            mime_type: row.get("mime_type")?,
+            data: row.get("data")?,
+        })
+    }
+}
+
+impl From<Favicon> for msg_types::FaviconInfo {
+    fn from(icon: Favicon) -> Self {
+        msg_types::FaviconInfo {
+            url: icon.url.into_string(),
+            width: icon.width,
+            mime_type: icon.mime_type,
+            data: icon.data,
+        }
+    }
+}
+
+/// Sets the icon for a page, which must already be in history or bookmarked.
+///
+/// A page uses one icon URL at a time, so setting an icon with a different URL
+/// replaces the page's old icon. Setting more sizes of the same icon URL adds
+/// them to the page, and replaces any size that was already stored. Icons at
+/// the root of the page's site, like `/favicon.ico`, are also used for other
+/// pages on the site which don't have their own icon.
+pub fn set_favicon_for_page(db: &PlacesDb, page_url: &Url, icon: &Favicon) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    set_favicon_for_page_in_tx(db, page_url, icon)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn set_favicon_for_page_in_tx(db: &PlacesDb, page_url: &Url, icon: &Favicon) -> Result<()> {
+    let (page_id, origin_id) = db
+        .try_query_row(
+            "SELECT id, origin_id FROM moz_places
+             WHERE url_hash = hash(:url) AND url = :url",
+            &[(":url", &page_url.as_str())],
+            |row| -> Result<(RowId, Option<RowId>)> { Ok((row.get(0)?, row.get(1)?)) },
+            true,
+        )?
+        .ok_or(InvalidPlaceInfo::NoSuchUrl)?;
+
+    let icon_url = icon.url.as_str();
+    let existing_id = db.try_query_one::<RowId>(
+        "SELECT id FROM moz_icons
+         WHERE icon_url_hash = hash(:icon_url) AND icon_url = :icon_url
+           AND width = :width",
+        &[(":icon_url", &icon_url), (":width", &icon.width)],
+        true,
+    )?;
+    match existing_id {
+        Some(id) => {
+            db.execute_named_cached(
+                "UPDATE moz_icons SET
+                   mime_type = :mime_type,
+                   data = :data
+                 WHERE id = :id",
+                &[
+                    (":mime_type", &icon.mime_type),
+                    (":data", &icon.data),
+                    (":id", &id),
+                ],
+            )?;
+        }
+        None => {
+            db.execute_named_cached(
+                "INSERT INTO moz_icons(icon_url, icon_url_hash, width, mime_type, data)
+                 VALUES(:icon_url, hash(:icon_url), :width, :mime_type, :data)",
+                &[
+                    (":icon_url", &icon_url),
+                    (":width", &icon.width),
+                    (":mime_type", &icon.mime_type),
+                    (":data", &icon.data),
+                ],
+            )?;
+        }
+    }
+
+    db.execute_named_cached(
+        "DELETE FROM moz_icons_to_pages
+         WHERE page_id = :page_id
+           AND icon_id IN (SELECT id FROM moz_icons WHERE icon_url <> :icon_url)",
+        &[(":page_id", &page_id), (":icon_url", &icon_url)],
+    )?;
+    db.execute_named_cached(
+        "INSERT OR IGNORE INTO moz_icons_to_pages(page_id, icon_id)
+         SELECT :page_id, id FROM moz_icons
+         WHERE icon_url_hash = hash(:icon_url) AND icon_url = :icon_url",
+        &[(":page_id", &page_id), (":icon_url", &icon_url)],
+    )?;
+    match origin_id {
+        Some(origin_id) if is_root_icon(page_url, &icon.url) => {
+            db.execute_named_cached(
+                "INSERT OR IGNORE INTO moz_icons_to_origins(origin_id, icon_id)
+                 SELECT :origin_id, id FROM moz_icons
+                 WHERE icon_url_hash = hash(:icon_url) AND icon_url = :icon_url",
+                &[(":origin_id", &origin_id), (":icon_url", &icon_url)],
+            )?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// As on desktop, an icon at `/favicon.ico` is the icon for the whole site.
+fn is_root_icon(page_url: &Url, icon_url: &Url) -> bool {
+    icon_url.path() == "/favicon.ico" && icon_url.origin() == page_url.origin()
+}
+
+// Prefers the smallest icon that's at least the preferred size, then the
+// largest icon that's smaller.
+const BEST_SIZE_ORDER: &str = "i.width < :size,
+                               CASE WHEN i.width < :size THEN -i.width ELSE i.width END";
+
+/// Returns the size of the page's icon closest to `preferred_size`, or the
+/// icon for the page's site, if the page doesn't have one.
+pub fn get_favicon_for_page(
+    db: &PlacesDb,
+    page_url: &Url,
+    preferred_size: u32,
+) -> Result<Option<Favicon>> {
+    let icon = db.try_query_row(
+        &format!(
+            "SELECT i.icon_url, i.width, i.mime_type, i.data
+             FROM moz_icons i
+             JOIN moz_icons_to_pages p ON p.icon_id = i.id
+             JOIN moz_places h ON h.id = p.page_id
+             WHERE h.url_hash = hash(:url) AND h.url = :url
+             ORDER BY {}
+             LIMIT 1",
+            BEST_SIZE_ORDER
+        ),
+        &[(":url", &page_url.as_str()), (":size", &preferred_size)],
+        Favicon::from_row,
+        true,
+    )?;
+    if icon.is_some() {
+        return Ok(icon);
+    }
+    db.try_query_row(
+        &format!(
+            "SELECT i.icon_url, i.width, i.mime_type, i.data
+             FROM moz_icons i
+             JOIN moz_icons_to_origins r ON r.icon_id = i.id
+             JOIN moz_origins o ON o.id = r.origin_id
+             WHERE o.prefix = get_prefix(:url)
+               AND o.host = get_host_and_port(:url)
+             ORDER BY {}
+             LIMIT 1",
+            BEST_SIZE_ORDER
+        ),
+        &[(":url", &page_url.as_str()), (":size", &preferred_size)],
+        Favicon::from_row,
+        true,
+    )
+}
+
+/// Removes icons which aren't used by any pages or origins. Deleting pages and
+/// origins removes their icon mappings, so this is run when history is
+/// expired or cleared, and during maintenance.
+pub(crate) fn delete_orphaned_icons(db: &PlacesDb) -> Result<()> {
+    db.execute_cached(
+        "DELETE FROM moz_icons
+         WHERE NOT EXISTS(SELECT 1 FROM moz_icons_to_pages
+                          WHERE icon_id = moz_icons.id)
+           AND NOT EXISTS(SELECT 1 FROM moz_icons_to_origins
+                          WHERE icon_id = moz_icons.id)",
+        rusqlite::NO_PARAMS,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::{apply_observation, delete_place_by_guid};
+    use crate::storage::{fetch_page_info, run_maintenance};
+    use crate::types::VisitTransition;
+
+    fn visit(conn: &PlacesDb, url: &str) -> Result<Url> {
+        let url = Url::parse(url)?;
+        apply_observation(
+            conn,
+            VisitObservation::new(url.clone()).with_visit_type(VisitTransition::Link),
+        )?;
+        Ok(url)
+    }
+
+    fn icon(url: &str, width: u32) -> Favicon {
+        Favicon {
+            url: Url::parse(url).unwrap(),
+            width,
+            mime_type: "image/png".into(),
+            data: vec![width as u8; 4],
+        }
+    }
+
+    fn icon_count(conn: &PlacesDb) -> Result<u32> {
+        Ok(conn.query_one("SELECT COUNT(*) FROM moz_icons")?)
+    }
+
+    #[test]
+    fn test_sizes() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        let page = visit(&conn, "https://www.example.com/page")?;
+
+        // Pages must be known.
+        assert!(set_favicon_for_page(
+            &conn,
+            &Url::parse("https://www.example.com/unknown")?,
+            &icon("https://www.example.com/icon.png", 16)
+        )
+        .is_err());
+        assert_eq!(get_favicon_for_page(&conn, &page, 16)?, None);
+
+        for width in &[16, 32, 64] {
+            set_favicon_for_page(
+                &conn,
+                &page,
+                &icon("https://www.example.com/icon.png", *width),
+            )?;
+        }
+        let width =
+            |size| -> Result<u32> { Ok(get_favicon_for_page(&conn, &page, size)?.unwrap().width) };
+        assert_eq!(width(16)?, 16);
+        assert_eq!(width(20)?, 32);
+        assert_eq!(width(64)?, 64);
+        assert_eq!(width(128)?, 64);
+        assert_eq!(width(0)?, 16);
+
+        // Setting the same size again replaces it.
+        let mut replacement = icon("https://www.example.com/icon.png", 32);
+        replacement.data = vec![1, 2, 3];
+        set_favicon_for_page(&conn, &page, &replacement)?;
+        assert_eq!(get_favicon_for_page(&conn, &page, 32)?, Some(replacement));
+        assert_eq!(icon_count(&conn)?, 3);
+
+        // A different icon URL replaces the page's old icon, which is removed
+        // during maintenance.
+        set_favicon_for_page(&conn, &page, &icon("https://www.example.com/new.png", 16))?;
+        let new_icon = get_favicon_for_page(&conn, &page, 64)?.unwrap();
+        assert_eq!(new_icon.url.as_str(), "https://www.example.com/new.png");
+        assert_eq!(new_icon.width, 16);
+        run_maintenance(&conn)?;
+        assert_eq!(icon_count(&conn)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_icons() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        let a = visit(&conn, "https://www.example.com/a")?;
+        let b = visit(&conn, "https://www.example.com/b")?;
+        let other = visit(&conn, "http://www.example.com/other")?;
+        let unvisited = Url::parse("https://www.example.com/unvisited")?;
+
+        set_favicon_for_page(&conn, &a, &icon("https://www.example.com/favicon.ico", 16))?;
+        set_favicon_for_page(&conn, &b, &icon("https://www.example.com/b.png", 16))?;
+
+        // Pages with their own icon use it; other pages on the same site,
+        // even unvisited ones, use the site's icon.
+        let url_for = |page| -> Result<Option<String>> {
+            Ok(get_favicon_for_page(&conn, page, 16)?.map(|icon| icon.url.into_string()))
+        };
+        assert_eq!(
+            url_for(&a)?,
+            Some("https://www.example.com/favicon.ico".to_string())
+        );
+        assert_eq!(
+            url_for(&b)?,
+            Some("https://www.example.com/b.png".to_string())
+        );
+        assert_eq!(
+            url_for(&unvisited)?,
+            Some("https://www.example.com/favicon.ico".to_string())
+        );
+        // Different scheme, different origin.
+        assert_eq!(url_for(&other)?, None);
+
+        // Icons go away with the pages and origins that use them.
+        let guid = |url| -> Result<_> { Ok(fetch_page_info(&conn, url)?.unwrap().page.guid) };
+        delete_place_by_guid(&conn, &guid(&b)?)?;
+        run_maintenance(&conn)?;
+        assert_eq!(icon_count(&conn)?, 1);
+        delete_place_by_guid(&conn, &guid(&a)?)?;
+        run_maintenance(&conn)?;
+        assert_eq!(icon_count(&conn)?, 0);
+        assert_eq!(url_for(&unvisited)?, None);
+        Ok(())
+    }
+}
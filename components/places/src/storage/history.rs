@@ -4,20 +4,26 @@
 
 use super::{fetch_page_info, new_page_info, PageInfo, RowId};
 use crate::db::PlacesDb;
-use crate::error::Result;
+use crate::error::{InvalidPlaceInfo, Result};
 use crate::frecency;
 use crate::hash;
 use crate::history_sync::store::{
     COLLECTION_SYNCID_META_KEY, GLOBAL_SYNCID_META_KEY, LAST_SYNC_META_KEY,
 };
-use crate::msg_types::{HistoryVisitInfo, HistoryVisitInfos};
+use crate::msg_types::{
+    HistoryVisitInfo, HistoryVisitInfos, TopFrecentSiteInfo, TopFrecentSiteInfos,
+};
 use crate::observation::VisitObservation;
+use crate::observer::PlacesEvent;
 use crate::storage::{delete_meta, delete_pending_temp_tables, get_meta, put_meta};
 use crate::types::{SyncStatus, Timestamp, VisitTransition, VisitTransitionSet};
 use rusqlite::types::ToSql;
 use rusqlite::Result as RusqliteResult;
 use rusqlite::{Row, NO_PARAMS};
-use sql_support::{self, ConnExt};
+use sql_support::{self, ConnExt, SqlInterruptScope};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 use sync_guid::Guid as SyncGuid;
 use url::Url;
 
@@ -30,10 +36,44 @@ use url::Url;
 static DELETION_HIGH_WATER_MARK_META_KEY: &str = "history_deleted_hwm";
 
 /// Returns the RowId of a new visit in moz_historyvisits, or None if no new visit was added.
-pub fn apply_observation(db: &PlacesDb, visit_ob: VisitObservation) -> Result<Option<RowId>> {
+pub fn apply_observation(db: &PlacesDb, mut visit_ob: VisitObservation) -> Result<Option<RowId>> {
+    // Fix the visit date now, so we can report it to observers.
+    let visit_date = *visit_ob.at.get_or_insert_with(Timestamp::now);
+    let url = visit_ob.url.clone();
+    let title = visit_ob.title.clone();
+    let visit_type = visit_ob.visit_type;
+    let is_remote = visit_ob.is_remote.unwrap_or(false);
+
     let tx = db.begin_transaction()?;
+    // Only report a title change if the stored title is actually different.
+    let old_title = match (&title, Url::parse(&url)) {
+        (Some(_), Ok(parsed)) => fetch_page_info(db, &parsed)?.map(|info| info.page.title),
+        _ => None,
+    };
     let result = apply_observation_direct(db, visit_ob)?;
+    // Update the origin frecencies too.
+    delete_pending_temp_tables(db)?;
     tx.commit()?;
+
+    let mut events = Vec::new();
+    if let (Some(_), Some(visit_type)) = (result, visit_type) {
+        events.push(PlacesEvent::PageVisited {
+            url: url.clone(),
+            visit_date,
+            visit_type,
+            is_remote,
+        });
+    }
+    // `apply_observation_direct` ignores observations for overlong URLs.
+    if url.len() <= super::URL_LENGTH_MAX {
+        if let Some(title) = title {
+            let title: String = crate::util::slice_up_to(&title, super::TITLE_LENGTH_MAX).into();
+            if old_title.unwrap_or_default() != title {
+                events.push(PlacesEvent::TitleChanged { url, title });
+            }
+        }
+    }
+    db.observers.notify_all(&events);
     Ok(result)
 }
 
@@ -75,7 +115,11 @@ pub fn apply_observation_direct(
 
             let at = visit_ob.at.unwrap_or_else(Timestamp::now);
             let is_remote = visit_ob.is_remote.unwrap_or(false);
-            let row_id = add_visit(db, page_info.row_id, None, at, visit_type, !is_remote)?;
+            let from_visit = match visit_ob.referrer.as_ref().and_then(|r| Url::parse(r).ok()) {
+                Some(referrer) => find_referring_visit(db, &referrer, at)?,
+                None => None,
+            };
+            let row_id = add_visit(db, page_info.row_id, from_visit, at, visit_type, !is_remote)?;
             // a new visit implies new frecency except in error cases.
             if !visit_ob.is_error.unwrap_or(false) {
                 update_frec = true;
@@ -122,10 +166,42 @@ pub fn apply_observation_direct(
     Ok(visit_row_id)
 }
 
+/// Sets the description and preview image URL for a page, typically taken from
+/// its `og:description` and `og:image` metadata. Passing `None` for either clears
+/// the stored value. Like desktop's `History.update`, this does nothing if we
+/// don't know about the page.
+pub fn note_page_metadata(
+    db: &PlacesDb,
+    url: &Url,
+    description: Option<&str>,
+    preview_image_url: Option<&Url>,
+) -> Result<()> {
+    if let Some(preview_image_url) = preview_image_url {
+        if preview_image_url.as_str().len() > super::URL_LENGTH_MAX {
+            return Err(InvalidPlaceInfo::UrlTooLong.into());
+        }
+    }
+    let description = description
+        .map(|d| crate::util::slice_up_to(d.trim(), super::DESCRIPTION_LENGTH_MAX))
+        .filter(|d| !d.is_empty());
+    db.execute_named_cached(
+        "UPDATE moz_places
+         SET description = :description,
+             preview_image_url = :preview_image_url
+         WHERE url_hash = hash(:url) AND url = :url",
+        &[
+            (":url", &url.as_str()),
+            (":description", &description),
+            (":preview_image_url", &preview_image_url.map(Url::as_str)),
+        ],
+    )?;
+    Ok(())
+}
+
 pub fn update_frecency(db: &PlacesDb, id: RowId, redirect_boost: Option<bool>) -> Result<()> {
     let score = frecency::calculate_frecency(
         db.conn(),
-        &frecency::DEFAULT_FRECENCY_SETTINGS,
+        &db.frecency_settings(),
         id.0, // TODO: calculate_frecency should take a RowId here.
         redirect_boost,
     )?;
@@ -155,6 +231,207 @@ pub fn frecency_stale_at(db: &PlacesDb, url: &Url) -> Result<Option<Timestamp>>
     Ok(result)
 }
 
+/// The maximum number of URLs for which to recalculate frecencies at once.
+/// This is a trade-off between write efficiency and transaction time: higher
+/// maximums mean fewer write statements, but longer transactions, possibly
+/// blocking writes from other connections.
+const MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK: usize = 400;
+
+/// Recalculates frecencies for all pages in `moz_places_stale_frecencies`,
+/// most recently marked first. This commits after each chunk, so if it's
+/// interrupted, the pages it already rescored stay rescored, and calling it
+/// again picks up where it left off.
+pub fn update_stale_frecencies(db: &PlacesDb, scope: &SqlInterruptScope) -> Result<()> {
+    let settings = db.frecency_settings();
+    let mut tx = db.begin_transaction()?;
+
+    let mut frecencies = Vec::with_capacity(MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK);
+    loop {
+        let sql = format!(
+            "SELECT place_id FROM moz_places_stale_frecencies
+             ORDER BY stale_at DESC
+             LIMIT {}",
+            MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK
+        );
+        let mut stmt = db.prepare_maybe_cached(&sql, true)?;
+        let mut results = stmt.query(NO_PARAMS)?;
+        while let Some(row) = results.next()? {
+            let place_id = row.get("place_id")?;
+            // Frecency recalculation runs several statements, so check to
+            // make sure we aren't interrupted before each calculation.
+            scope.err_if_interrupted()?;
+            let frecency = frecency::calculate_frecency(db, &settings, place_id, Some(false))?;
+            frecencies.push((place_id, frecency));
+        }
+        if frecencies.is_empty() {
+            break;
+        }
+
+        // Update all frecencies in one fell swoop...
+        db.execute_batch(&format!(
+            "WITH frecencies(id, frecency) AS (
+               VALUES {}
+             )
+             UPDATE moz_places SET
+               frecency = (SELECT frecency FROM frecencies f
+                           WHERE f.id = moz_places.id)
+             WHERE id IN (SELECT f.id FROM frecencies f)",
+            sql_support::repeat_display(frecencies.len(), ",", |index, f| {
+                let (id, frecency) = frecencies[index];
+                write!(f, "({}, {})", id, frecency)
+            })
+        ))?;
+        // ...Including the frecencies of their origins...
+        delete_pending_temp_tables(db)?;
+
+        // ...And remove them from the stale table.
+        db.execute_batch(&format!(
+            "DELETE FROM moz_places_stale_frecencies
+             WHERE place_id IN ({})",
+            sql_support::repeat_display(frecencies.len(), ",", |index, f| {
+                let (id, _) = frecencies[index];
+                write!(f, "{}", id)
+            })
+        ))?;
+        // Commit each chunk, so that other connections can write in between,
+        // and we don't lose our progress if we're interrupted.
+        tx.commit()?;
+        scope.err_if_interrupted()?;
+        tx = db.begin_transaction()?;
+
+        // If the query returned fewer URLs than the maximum, we're done.
+        // Otherwise, we might have more, so clear the ones we just
+        // recalculated and fetch the next chunk.
+        if frecencies.len() < MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK {
+            break;
+        }
+        frecencies.clear();
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Recalculates the frecency of every page, for example, after changing the
+/// frecency settings. Like `update_stale_frecencies`, this can be interrupted,
+/// and calling `update_stale_frecencies` afterward finishes the job.
+pub fn recalculate_all_frecencies(db: &PlacesDb, scope: &SqlInterruptScope) -> Result<()> {
+    mark_all_frecencies_stale(db)?;
+    update_stale_frecencies(db, scope)
+}
+
+/// Marks the frecency of every page as stale, so that the next call to
+/// `update_stale_frecencies` rescores them all. We do this when the frecency
+/// settings change.
+pub(crate) fn mark_all_frecencies_stale(db: &PlacesDb) -> Result<()> {
+    db.execute_named_cached(
+        "INSERT OR REPLACE INTO moz_places_stale_frecencies(place_id, stale_at)
+         SELECT id, :now FROM moz_places",
+        &[(":now", &Timestamp::now())],
+    )?;
+    Ok(())
+}
+
+/// The meta key for when frecencies were last decayed.
+static LAST_FRECENCY_DECAY_META_KEY: &str = "frecency_last_decayed";
+
+/// How often to decay frecencies. `decay_rate` in the frecency settings is
+/// per day, so this should stay at a day.
+const FRECENCY_DECAY_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How much to multiply adaptive history use counts by each day. This is
+/// Desktop's default decay rate, but, unlike frecencies, doesn't follow the
+/// settings, so that turning off frecency decay doesn't keep adaptive history
+/// around forever.
+const ADAPTIVE_HISTORY_DECAY_RATE: f64 = 0.975;
+
+/// Multiplies the frecency of every page and origin by the `decay_rate` in
+/// the frecency settings, and the use count of every adaptive history entry
+/// by `ADAPTIVE_HISTORY_DECAY_RATE`, if we haven't done so in the last day.
+/// This is run as part of maintenance, and means that pages which haven't
+/// been visited in a while slowly rank lower than the ones visited recently,
+/// even if nothing recalculates their frecencies.
+pub fn decay_frecencies_if_needed(db: &PlacesDb, now: Timestamp) -> Result<()> {
+    if let Some(last_decayed) = get_meta::<Timestamp>(db, LAST_FRECENCY_DECAY_META_KEY)? {
+        // `duration_since` returns `None` if the clock went backwards, in
+        // which case we decay anyway.
+        match now.duration_since(last_decayed) {
+            Some(elapsed) if elapsed < FRECENCY_DECAY_INTERVAL => return Ok(()),
+            _ => {}
+        }
+    }
+    let tx = db.begin_transaction()?;
+    let decay_rate = db.frecency_settings().decay_rate;
+    if decay_rate > 0.0 && decay_rate < 1.0 {
+        // Like Desktop, we skip the frecency triggers while decaying, and
+        // decay origins and fix up the origin stats ourselves. Make sure we
+        // unset the flag even if decaying fails.
+        db.frecency_decaying.store(true, Ordering::SeqCst);
+        let result = decay_frecencies(db, decay_rate);
+        db.frecency_decaying.store(false, Ordering::SeqCst);
+        result?;
+    }
+    decay_adaptive_history(db)?;
+    put_meta(db, LAST_FRECENCY_DECAY_META_KEY, &now)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn decay_frecencies(db: &PlacesDb, decay_rate: f64) -> Result<()> {
+    db.execute_named_cached(
+        "UPDATE moz_places SET
+           frecency = CAST(ROUND(frecency * :decay_rate) AS INTEGER)
+         WHERE frecency > 0",
+        &[(":decay_rate", &decay_rate)],
+    )?;
+    db.execute_named_cached(
+        "UPDATE moz_origins SET
+           frecency = CAST(ROUND(frecency * :decay_rate) AS INTEGER)
+         WHERE frecency > 0",
+        &[(":decay_rate", &decay_rate)],
+    )?;
+    crate::db::recalculate_origin_frecency_stats(db)?;
+    Ok(())
+}
+
+fn decay_adaptive_history(db: &PlacesDb) -> Result<()> {
+    db.execute_named_cached(
+        "UPDATE moz_inputhistory SET use_count = use_count * :decay_rate",
+        &[(":decay_rate", &ADAPTIVE_HISTORY_DECAY_RATE)],
+    )?;
+    // Like Desktop, we forget entries which haven't been used in a long time.
+    db.execute_batch("DELETE FROM moz_inputhistory WHERE use_count < .01")?;
+    Ok(())
+}
+
+/// Like desktop, we only link a visit to the most recent visit to its referrer
+/// if that happened in the last 15 minutes.
+const REFERRING_VISIT_THRESHOLD: Duration = Duration::from_secs(15 * 60);
+
+/// Finds the visit to `referrer` which led to a visit at `at`, if any.
+fn find_referring_visit(db: &PlacesDb, referrer: &Url, at: Timestamp) -> Result<Option<RowId>> {
+    let earliest = at
+        .checked_sub(REFERRING_VISIT_THRESHOLD)
+        .unwrap_or(Timestamp(0));
+    Ok(db.try_query_row(
+        "SELECT v.id
+         FROM moz_historyvisits v
+         JOIN moz_places h ON h.id = v.place_id
+         WHERE h.url_hash = hash(:url) AND h.url = :url
+           AND v.visit_date BETWEEN :earliest AND :at
+         ORDER BY v.visit_date DESC
+         LIMIT 1",
+        &[
+            (":url", &referrer.as_str()),
+            (":earliest", &earliest),
+            (":at", &at),
+        ],
+        |row| row.get::<_, RowId>(0),
+        true,
+    )?)
+}
+
 // Add a single visit - you must know the page rowid. Does not update the
 // page info - if you are calling this, you will also need to update the
 // parent page with an updated change counter etc.
@@ -210,19 +487,21 @@ pub fn href_to_guid(db: &PlacesDb, url: &str) -> Result<Option<SyncGuid>> {
 }
 
 /// Internal function for deleting a place, creating a tombstone if necessary.
-/// Assumes a transaction is already set up by the caller.
-fn do_delete_place_by_guid(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
+/// Assumes a transaction is already set up by the caller. Returns whether the
+/// place existed.
+fn do_delete_place_by_guid(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
     // We only create tombstones for history which exists and with sync_status
     // == SyncStatus::Normal
     let sql = "INSERT OR IGNORE INTO moz_places_tombstones (guid)
                SELECT guid FROM moz_places
                WHERE guid = :guid AND sync_status = :status";
     db.execute_named_cached(sql, &[(":guid", guid), (":status", &SyncStatus::Normal)])?;
-    // and try the delete - it might not exist, but that's ok.
+    // and try the delete - it might not exist, but that's ok. This also
+    // removes the page's adaptive history, through the foreign key.
     let delete_sql = "DELETE FROM moz_places WHERE guid = :guid";
-    db.execute_named_cached(delete_sql, &[(":guid", guid)])?;
+    let deleted = db.execute_named_cached(delete_sql, &[(":guid", guid)])? > 0;
     delete_pending_temp_tables(db)?;
-    Ok(())
+    Ok(deleted)
 }
 
 /// Delete a place given its guid, creating a tombstone if necessary.
@@ -230,7 +509,11 @@ pub fn delete_place_by_guid(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
     let tx = db.begin_transaction()?;
     let result = do_delete_place_by_guid(db, guid);
     tx.commit()?;
-    result
+    if result? {
+        db.observers
+            .notify(&PlacesEvent::PageRemoved { guid: guid.clone() });
+    }
+    Ok(())
 }
 
 /// Delete all visits in a date range.
@@ -238,6 +521,11 @@ pub fn delete_visits_between(db: &PlacesDb, start: Timestamp, end: Timestamp) ->
     let tx = db.begin_transaction()?;
     delete_visits_between_in_tx(db, start, end)?;
     tx.commit()?;
+    db.observers.notify(&PlacesEvent::VisitsRemoved {
+        url: None,
+        start,
+        end,
+    });
     Ok(())
 }
 
@@ -253,25 +541,312 @@ pub fn delete_place_visit_at_time_by_href(
     let tx = db.begin_transaction()?;
     delete_place_visit_at_time_in_tx(db, place, visit)?;
     tx.commit()?;
+    db.observers.notify(&PlacesEvent::VisitsRemoved {
+        url: Some(place.to_string()),
+        start: visit,
+        end: visit,
+    });
+    Ok(())
+}
+
+/// Delete all visits to pages on `host`, and to pages on its subdomains if
+/// `include_subdomains` is true. Pages which are left without visits are
+/// removed too, unless they're bookmarked or tagged. Tombstones are written
+/// for everything removed, so the deletions are synced.
+///
+/// Pages on any port of the host are removed.
+pub fn delete_visits_for_host(db: &PlacesDb, host: &str, include_subdomains: bool) -> Result<()> {
+    // Let the URL parser normalize the host for us - this lowercases it and
+    // converts IDNs to punycode, to match what's in moz_origins.
+    let parsed = Url::parse(&format!("http://{}/", host.trim().trim_end_matches('.')))?;
+    let host = match parsed.host_str() {
+        Some(host) if !host.is_empty() => host,
+        _ => return Err(url::ParseError::EmptyHost.into()),
+    };
+    let tx = db.begin_transaction()?;
+    delete_visits_for_host_in_tx(db, host, include_subdomains)?;
+    tx.commit()?;
+    db.observers.notify(&PlacesEvent::HostVisitsRemoved {
+        host: host.to_string(),
+        include_subdomains,
+    });
+    Ok(())
+}
+
+fn delete_visits_for_host_in_tx(db: &PlacesDb, host: &str, include_subdomains: bool) -> Result<()> {
+    // moz_origins stores the host with its port, if it has one, reversed
+    // and with a trailing dot. So, "example.com" is "moc.elpmaxe.",
+    // "www.example.com" is "moc.elpmaxe.www.", and "example.com:8080" is
+    // "0808:moc.elpmaxe.". Ports are rare enough that it's fine for them to
+    // take the slower GLOB path.
+    let rev_host: String = host.chars().rev().chain(std::iter::once('.')).collect();
+    // Make sure every page has its origin_id set.
+    delete_pending_temp_tables(db)?;
+    // Like desktop, we find subdomains using the fact that '/' sorts just
+    // after '.', so they're all between "moc.elpmaxe." and "moc.elpmaxe/".
+    let rev_host_end = format!("{}/", &rev_host[..rev_host.len() - 1]);
+    let origin_filter = if include_subdomains {
+        "(o.rev_host >= :rev_host AND o.rev_host < :rev_host_end)
+         OR o.rev_host GLOB '*:' || :rev_host_glob || '*'"
+    } else {
+        "o.rev_host = :rev_host
+         OR o.rev_host GLOB '*:' || :rev_host_glob"
+    };
+    let rev_host_glob = escape_glob(&rev_host);
+    let mut params: Vec<(&str, &dyn ToSql)> =
+        vec![(":rev_host", &rev_host), (":rev_host_glob", &rev_host_glob)];
+    if include_subdomains {
+        params.push((":rev_host_end", &rev_host_end));
+    }
+    let place_ids = db.query_rows_and_then_named(
+        &format!(
+            "SELECT h.id FROM moz_places h
+             JOIN moz_origins o ON o.id = h.origin_id
+             WHERE {}",
+            origin_filter
+        ),
+        &params,
+        |row| row.get::<_, RowId>(0),
+    )?;
+    log::debug!("Deleting visits to {} pages for a host", place_ids.len());
+
+    let mut visits = Vec::new();
+    sql_support::each_chunk(&place_ids, |chunk, _| -> Result<()> {
+        let mut stmt = db.conn().prepare(&format!(
+            "SELECT id, place_id, visit_date
+             FROM moz_historyvisits
+             WHERE place_id IN ({})",
+            sql_support::repeat_sql_vars(chunk.len()),
+        ))?;
+        let rows = stmt.query_and_then(chunk, VisitToDelete::from_row)?;
+        for visit in rows {
+            visits.push(visit?);
+        }
+        Ok(())
+    })?;
+    delete_visits_in_tx(db, &visits)?;
+
+    // Forget the adaptive history for all the host's pages. Pages which we
+    // remove lose theirs through the foreign key anyway, but bookmarked pages
+    // are kept, so we remove theirs here.
+    sql_support::each_chunk(&place_ids, |chunk, _| -> Result<()> {
+        db.execute(
+            &format!(
+                "DELETE FROM moz_inputhistory WHERE place_id IN ({})",
+                sql_support::repeat_sql_vars(chunk.len()),
+            ),
+            chunk,
+        )?;
+        Ok(())
+    })?;
+
+    // Pages which had no visits to start with weren't cleaned up above.
+    let visited: std::collections::HashSet<RowId> =
+        visits.iter().map(|visit| visit.place_id).collect();
+    let unvisited: Vec<RowId> = place_ids
+        .into_iter()
+        .filter(|id| !visited.contains(id))
+        .collect();
+    clean_up_places_in_tx(db, &unvisited)?;
+    delete_pending_temp_tables(db)?;
     Ok(())
 }
 
+/// Escapes the characters in `s` which are special in a GLOB pattern.
+fn escape_glob(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '*' | '?' | '[' => {
+                escaped.push('[');
+                escaped.push(c);
+                escaped.push(']');
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The limits used by `prune_destructively_with_limits`. Pages are expired,
+/// oldest and least frecent first, until the database is within all of the
+/// limits which are set. Pages which are bookmarked or tagged (that is, pages
+/// with a non-zero `foreign_count`) are never expired.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PruneLimits {
+    /// The maximum size of the database, in bytes.
+    pub max_db_size: Option<u64>,
+    /// The maximum number of pages the database should contain.
+    pub max_pages: Option<u64>,
+    /// Visits older than this are expired regardless of the other limits.
+    pub max_visit_age: Option<Duration>,
+}
+
+/// Matches `DATABASE_MAX_SIZE` in desktop's PlacesExpiration.jsm.
+const DEFAULT_MAX_DB_SIZE: u64 = 60 * 1024 * 1024;
+
+/// How many pages (or visits) we expire in each transaction. Between chunks we
+/// commit and check for interruption, so this shouldn't be too large.
+const PRUNE_CHUNK_SIZE: i64 = 200;
+
+impl Default for PruneLimits {
+    fn default() -> Self {
+        Self {
+            max_db_size: Some(DEFAULT_MAX_DB_SIZE),
+            max_pages: None,
+            max_visit_age: None,
+        }
+    }
+}
+
+/// Expire history using the default `PruneLimits`.
 pub fn prune_destructively(db: &PlacesDb) -> Result<()> {
-    // For now, just fall back to wipe_local until we decide how this should work.
-    wipe_local(db)
+    prune_destructively_with_limits(db, &PruneLimits::default())
+}
+
+/// Expire history until the database is within `limits`. The work is done in
+/// chunks, each in its own transaction, so an interruption leaves whatever
+/// was already expired in place. The database is only vacuumed if something
+/// was expired, since vacuuming rewrites the whole file.
+pub fn prune_destructively_with_limits(db: &PlacesDb, limits: &PruneLimits) -> Result<()> {
+    let scope = db.begin_interrupt_scope();
+    let mut expired = false;
+    if let Some(max_age) = limits.max_visit_age {
+        let cutoff = Timestamp::now()
+            .checked_sub(max_age)
+            .unwrap_or(Timestamp(0));
+        expired |= expire_visits_before(db, cutoff, &scope)?;
+    }
+    let num_to_expire = count_pages_to_expire(db, limits)?;
+    if num_to_expire > 0 {
+        expired |= expire_pages(db, num_to_expire, &scope)?;
+    }
+    super::favicons::delete_orphaned_icons(db)?;
+    if expired {
+        db.observers.notify(&PlacesEvent::HistoryExpired);
+        // Note: SQLite cannot VACUUM within a transaction, and we need to
+        // VACUUM to actually give the space back.
+        db.execute_batch("VACUUM")?;
+    }
+    Ok(())
+}
+
+/// Works out how many pages need to be removed for the database to be within
+/// `limits`. For the size limit, we assume the space used is roughly
+/// proportional to the number of pages.
+fn count_pages_to_expire(db: &PlacesDb, limits: &PruneLimits) -> Result<i64> {
+    let num_pages = db.query_one::<i64>("SELECT COUNT(*) FROM moz_places")?;
+    let mut num_to_expire = 0;
+    if let Some(max_pages) = limits.max_pages {
+        num_to_expire = num_pages - (max_pages as i64);
+    }
+    if let Some(max_db_size) = limits.max_db_size {
+        let page_size = db.query_one::<i64>("PRAGMA page_size")?;
+        let used_db_pages = db.query_one::<i64>("PRAGMA page_count")?
+            - db.query_one::<i64>("PRAGMA freelist_count")?;
+        let used_size = (used_db_pages * page_size) as u64;
+        if used_size > max_db_size {
+            let num_to_keep = (num_pages as f64 * (max_db_size as f64 / used_size as f64)) as i64;
+            num_to_expire = num_to_expire.max(num_pages - num_to_keep);
+        }
+    }
+    Ok(num_to_expire.max(0))
+}
+
+/// Expire up to `num_to_expire` pages which aren't bookmarked or tagged,
+/// starting with the least recently visited, and the least frecent of those.
+/// Returns whether any were expired.
+fn expire_pages(
+    db: &PlacesDb,
+    mut num_to_expire: i64,
+    scope: &sql_support::SqlInterruptScope,
+) -> Result<bool> {
+    let mut expired = false;
+    while num_to_expire > 0 {
+        scope.err_if_interrupted()?;
+        let tx = db.begin_transaction()?;
+        let place_ids = db.query_rows_and_then_named(
+            "SELECT id FROM moz_places
+             WHERE foreign_count = 0
+             ORDER BY MAX(last_visit_date_local, last_visit_date_remote), frecency
+             LIMIT :limit",
+            &[(":limit", &num_to_expire.min(PRUNE_CHUNK_SIZE))],
+            |row| row.get::<_, RowId>(0),
+        )?;
+        if place_ids.is_empty() {
+            // Everything left is bookmarked or tagged.
+            tx.commit()?;
+            break;
+        }
+        num_to_expire -= place_ids.len() as i64;
+        expired = true;
+        let visits = db.query_rows_and_then_named(
+            &format!(
+                "SELECT id, place_id, visit_date
+                 FROM moz_historyvisits
+                 WHERE place_id IN ({})",
+                sql_support::repeat_display(place_ids.len(), ",", |i, f| write!(
+                    f,
+                    "{}",
+                    place_ids[i].0
+                )),
+            ),
+            &[],
+            VisitToDelete::from_row,
+        )?;
+        delete_visits_in_tx(db, &visits)?;
+        // Pages without any visits won't have been cleaned up above.
+        clean_up_places_in_tx(db, &place_ids)?;
+        delete_pending_temp_tables(db)?;
+        tx.commit()?;
+    }
+    Ok(expired)
+}
+
+/// Expire visits older than `cutoff` to pages which aren't bookmarked or
+/// tagged, oldest and least frecent first. Returns whether any were expired.
+fn expire_visits_before(
+    db: &PlacesDb,
+    cutoff: Timestamp,
+    scope: &sql_support::SqlInterruptScope,
+) -> Result<bool> {
+    let mut expired = false;
+    loop {
+        scope.err_if_interrupted()?;
+        let tx = db.begin_transaction()?;
+        let visits = db.query_rows_and_then_named(
+            "SELECT v.id, v.place_id, v.visit_date
+             FROM moz_historyvisits v
+             JOIN moz_places h ON h.id = v.place_id
+             WHERE v.visit_date < :cutoff
+               AND h.foreign_count = 0
+             ORDER BY v.visit_date, h.frecency
+             LIMIT :limit",
+            &[(":cutoff", &cutoff), (":limit", &PRUNE_CHUNK_SIZE)],
+            VisitToDelete::from_row,
+        )?;
+        let done = (visits.len() as i64) < PRUNE_CHUNK_SIZE;
+        expired |= !visits.is_empty();
+        delete_visits_in_tx(db, &visits)?;
+        delete_pending_temp_tables(db)?;
+        tx.commit()?;
+        if done {
+            return Ok(expired);
+        }
+    }
 }
 
 pub fn wipe_local(db: &PlacesDb) -> Result<()> {
     let tx = db.begin_transaction()?;
     wipe_local_in_tx(db)?;
     tx.commit()?;
+    db.observers.notify(&PlacesEvent::HistoryCleared);
     // Note: SQLite cannot VACUUM within a transaction.
     db.execute_batch("VACUUM")?;
     Ok(())
 }
 
 fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
-    use crate::frecency::DEFAULT_FRECENCY_SETTINGS;
     db.execute_all(&[
         "DELETE FROM moz_places WHERE foreign_count == 0",
         "DELETE FROM moz_historyvisits",
@@ -290,7 +865,7 @@ fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
                                  ELSE {unvisited_bookmark_frec}
                             END),
                 sync_change_counter = 0"#,
-            unvisited_bookmark_frec = DEFAULT_FRECENCY_SETTINGS.unvisited_bookmark_bonus
+            unvisited_bookmark_frec = db.frecency_settings().unvisited_bookmark_bonus
         ),
     ])?;
 
@@ -302,6 +877,7 @@ fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
         update_frecency(db, row_id, None)?;
     }
     delete_pending_temp_tables(db)?;
+    super::favicons::delete_orphaned_icons(db)?;
     Ok(())
 }
 
@@ -331,6 +907,7 @@ pub fn delete_everything(db: &PlacesDb) -> Result<()> {
     delete_meta(db, COLLECTION_SYNCID_META_KEY)?;
 
     tx.commit()?;
+    db.observers.notify(&PlacesEvent::HistoryCleared);
 
     // Note: SQLite cannot VACUUM within a transaction.
     db.execute_batch("VACUUM")?;
@@ -402,18 +979,37 @@ pub fn delete_visits_between_in_tx(db: &PlacesDb, start: Timestamp, end: Timesta
     let visits = db.query_rows_and_then_named(
         sql,
         &[(":start", &start), (":end", &end)],
-        |row| -> rusqlite::Result<_> {
-            Ok((
-                row.get::<_, RowId>(0)?,
-                row.get::<_, RowId>(1)?,
-                row.get::<_, Timestamp>(2)?,
-            ))
-        },
+        VisitToDelete::from_row,
     )?;
+    delete_visits_in_tx(db, &visits)?;
+    delete_pending_temp_tables(db)?;
+    Ok(())
+}
+
+#[derive(Debug)]
+struct VisitToDelete {
+    id: RowId,
+    place_id: RowId,
+    visit_date: Timestamp,
+}
+
+impl VisitToDelete {
+    pub fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            place_id: row.get("place_id")?,
+            visit_date: row.get("visit_date")?,
+        })
+    }
+}
 
+/// Deletes the specified visits, inserting tombstones for them, and cleans up
+/// the pages they belonged to. The caller is expected to have set up a
+/// transaction, and to call `delete_pending_temp_tables` afterwards.
+fn delete_visits_in_tx(db: &PlacesDb, visits: &[VisitToDelete]) -> Result<()> {
     sql_support::each_chunk_mapped(
-        &visits,
-        |(visit_id, _, _)| visit_id,
+        visits,
+        |visit| visit.id,
         |chunk, _| -> Result<()> {
             db.conn().execute(
                 &format!(
@@ -431,35 +1027,35 @@ pub fn delete_visits_between_in_tx(db: &PlacesDb, start: Timestamp, end: Timesta
         let sql = format!(
             "INSERT OR IGNORE INTO moz_historyvisit_tombstones(place_id, visit_date) VALUES {}",
             sql_support::repeat_display(visits.len(), ",", |i, f| {
-                let (_, place_id, visit_date) = visits[i];
-                write!(f, "({},{})", place_id.0, visit_date.0)
+                let visit = &visits[i];
+                write!(f, "({},{})", visit.place_id.0, visit.visit_date.0)
             })
         );
         db.conn().execute(&sql, NO_PARAMS)?;
     }
 
     // Find out which pages have been possibly orphaned and clean them up.
-    sql_support::each_chunk_mapped(
-        &visits,
-        |(_, place_id, _)| place_id.0,
-        |chunk, _| -> Result<()> {
-            let query = format!(
-                "SELECT id, -- url, url_hash, guid
-                    (foreign_count != 0) AS has_foreign,
-                    ((last_visit_date_local + last_visit_date_remote) != 0) as has_visits
-                FROM moz_places
-                WHERE id IN ({})",
-                sql_support::repeat_sql_vars(chunk.len()),
-            );
+    let place_ids: Vec<RowId> = visits.iter().map(|visit| visit.place_id).collect();
+    clean_up_places_in_tx(db, &place_ids)
+}
 
-            let mut stmt = db.conn().prepare(&query)?;
-            let page_results = stmt.query_and_then(chunk, PageToClean::from_row)?;
-            let pages: Vec<PageToClean> = page_results.collect::<Result<_>>()?;
-            cleanup_pages(db, &pages)
-        },
-    )?;
-    delete_pending_temp_tables(db)?;
-    Ok(())
+/// Cleans up the specified pages, using `cleanup_pages`.
+fn clean_up_places_in_tx(db: &PlacesDb, place_ids: &[RowId]) -> Result<()> {
+    sql_support::each_chunk(place_ids, |chunk, _| -> Result<()> {
+        let query = format!(
+            "SELECT id, -- url, url_hash, guid
+                (foreign_count != 0) AS has_foreign,
+                ((last_visit_date_local + last_visit_date_remote) != 0) as has_visits
+            FROM moz_places
+            WHERE id IN ({})",
+            sql_support::repeat_sql_vars(chunk.len()),
+        );
+
+        let mut stmt = db.conn().prepare(&query)?;
+        let page_results = stmt.query_and_then(chunk, PageToClean::from_row)?;
+        let pages: Vec<PageToClean> = page_results.collect::<Result<_>>()?;
+        cleanup_pages(db, &pages)
+    })
 }
 
 #[derive(Debug)]
@@ -1080,7 +1676,8 @@ pub fn get_visit_infos(
 ) -> Result<HistoryVisitInfos> {
     let allowed_types = exclude_types.complement();
     let infos = db.query_rows_and_then_named_cached(
-        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden
+        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden,
+                h.description, h.preview_image_url
          FROM moz_places h
          JOIN moz_historyvisits v
            ON h.id = v.place_id
@@ -1125,7 +1722,8 @@ pub fn get_visit_page(
 ) -> Result<HistoryVisitInfos> {
     let allowed_types = exclude_types.complement();
     let infos = db.query_rows_and_then_named_cached(
-        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden
+        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden,
+                h.description, h.preview_image_url
          FROM moz_places h
          JOIN moz_historyvisits v
            ON h.id = v.place_id
@@ -1144,76 +1742,438 @@ pub fn get_visit_page(
     Ok(HistoryVisitInfos { infos })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::history_sync::*;
-    use super::*;
-    use crate::api::places_api::ConnectionType;
-    use crate::history_sync::record::{HistoryRecord, HistoryRecordVisit};
-    use crate::types::Timestamp;
-    use pretty_assertions::assert_eq;
-    use std::time::{Duration, SystemTime};
+/// Returns the most frecent sites, for showing as "top sites". Sites are ranked
+/// by the frecency of their origin (that is, the sum of the frecencies of its
+/// pages), and each is represented by its most frecent page. Only origins with
+/// a frecency of at least `frecency_threshold` are included.
+///
+/// Hidden pages (including redirect sources and pages only visited in frames),
+/// pages which have redirected elsewhere, and pages which were only visited
+/// with errors (these never have a positive frecency) are excluded.
+pub fn get_top_frecent_sites(
+    db: &PlacesDb,
+    num_items: i64,
+    frecency_threshold: i64,
+) -> Result<TopFrecentSiteInfos> {
+    let infos = db.query_rows_and_then_named_cached(
+        "SELECT h.url, h.title, h.frecency,
+                h.visit_count_local + h.visit_count_remote AS visit_count,
+                MAX(h.last_visit_date_local, h.last_visit_date_remote) AS last_visit_date
+         FROM moz_origins o
+         JOIN moz_places h ON h.id = (
+             SELECT p.id FROM moz_places p
+             WHERE p.origin_id = o.id
+               AND p.frecency > 0
+               AND NOT p.hidden
+               AND NOT EXISTS(
+                   SELECT 1 FROM moz_historyvisits src
+                   JOIN moz_historyvisits dst ON dst.from_visit = src.id
+                   WHERE src.place_id = p.id
+                     AND dst.visit_type IN (:redirect_permanent, :redirect_temporary)
+               )
+             ORDER BY p.frecency DESC, p.id DESC
+             LIMIT 1
+         )
+         WHERE o.frecency >= :frecency_threshold
+         ORDER BY o.frecency DESC, o.id DESC
+         LIMIT :limit",
+        rusqlite::named_params! {
+            ":frecency_threshold": frecency_threshold,
+            ":limit": num_items,
+            ":redirect_permanent": VisitTransition::RedirectPermanent,
+            ":redirect_temporary": VisitTransition::RedirectTemporary,
+        },
+        TopFrecentSiteInfo::from_row,
+    )?;
+    Ok(TopFrecentSiteInfos { infos })
+}
 
-    #[test]
-    fn test_get_visited_urls() {
-        use std::collections::HashSet;
-        use std::time::SystemTime;
-        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("no memory db");
-        let now: Timestamp = SystemTime::now().into();
-        let now_u64 = now.0;
-        // (url, when, is_remote, (expected_always, expected_only_local)
-        let to_add = [
-            (
-                "https://www.example.com/1",
-                now_u64 - 200_100,
-                false,
-                (false, false),
-            ),
-            (
-                "https://www.example.com/12",
-                now_u64 - 200_000,
-                false,
-                (true, true),
-            ),
-            (
-                "https://www.example.com/123",
-                now_u64 - 10_000,
-                true,
-                (true, false),
-            ),
-            (
-                "https://www.example.com/1234",
-                now_u64 - 1000,
-                false,
-                (true, true),
-            ),
-            (
-                "https://www.mozilla.com",
-                now_u64 - 500,
-                false,
-                (false, false),
-            ),
-        ];
+/// How `search_history` sorts its results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistorySearchOrder {
+    /// Best matches first, using the full-text index's ranking. Titles count
+    /// for more than URLs, and ties are broken by frecency. If the query has no
+    /// search terms, this is the same as `Date`.
+    Relevance = 1,
+    /// Most recently visited first.
+    Date = 2,
+}
 
-        for &(url, when, remote, _) in &to_add {
-            apply_observation(
-                &conn,
-                VisitObservation::new(Url::parse(url).unwrap())
-                    .with_at(Timestamp(when))
-                    .with_is_remote(remote)
-                    .with_visit_type(VisitTransition::Link),
-            )
-            .expect("Should apply visit");
+impl HistorySearchOrder {
+    pub fn from_primitive(p: u8) -> Option<Self> {
+        match p {
+            1 => Some(HistorySearchOrder::Relevance),
+            2 => Some(HistorySearchOrder::Date),
+            _ => None,
         }
+    }
+}
 
-        let visited_all = get_visited_urls(
-            &conn,
-            Timestamp(now_u64 - 200_000),
-            Timestamp(now_u64 - 1000),
-            true,
-        )
-        .unwrap()
-        .into_iter()
+#[derive(Debug, Clone)]
+pub struct HistorySearchParams {
+    /// The terms to search for. Pages match if their title or URL contains
+    /// every term, as a prefix of a word. If there are no terms, all pages
+    /// visited in the date range match.
+    pub query: String,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub exclude_types: VisitTransitionSet,
+    pub order: HistorySearchOrder,
+    pub offset: i64,
+    pub count: i64,
+}
+
+/// Converts a search string into an FTS5 query which matches all of its
+/// terms, as prefixes. Each term is quoted, so that punctuation and FTS5
+/// operators in the search string are treated as text.
+fn to_fts_query(search: &str) -> Option<String> {
+    let terms = search
+        .split_whitespace()
+        .filter(|term| term.chars().any(char::is_alphanumeric))
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Searches all of history, using the full-text index over page titles and
+/// URLs. Unlike `search_frecent`, which is tuned for the awesomebar, this
+/// returns one result per matching page: its most recent visit in the date
+/// range which isn't excluded by `exclude_types`. Hidden pages are never
+/// returned.
+pub fn search_history(db: &PlacesDb, params: &HistorySearchParams) -> Result<HistoryVisitInfos> {
+    let fts_query = to_fts_query(&params.query);
+    let allowed_types = params.exclude_types.complement();
+    let mut sql_params: Vec<(&str, &dyn ToSql)> = vec![
+        (":start", &params.start),
+        (":end", &params.end),
+        (":allowed_types", &allowed_types),
+        (":count", &params.count),
+        (":offset", &params.offset),
+    ];
+    let (from, filter, order) = match fts_query {
+        Some(ref fts_query) => {
+            sql_params.push((":query", fts_query));
+            (
+                "moz_places_fts JOIN moz_places h ON h.id = moz_places_fts.rowid",
+                "moz_places_fts MATCH :query AND",
+                match params.order {
+                    HistorySearchOrder::Relevance => {
+                        "bm25(moz_places_fts, 2.0, 1.0), h.frecency DESC, h.id"
+                    }
+                    HistorySearchOrder::Date => "v.visit_date DESC, h.id",
+                },
+            )
+        }
+        None => ("moz_places h", "", "v.visit_date DESC, h.id"),
+    };
+    let infos = db.query_rows_and_then_named_cached(
+        &format!(
+            "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden,
+                    h.description, h.preview_image_url
+             FROM {from}
+             JOIN moz_historyvisits v ON v.id = (
+                 SELECT id FROM moz_historyvisits
+                 WHERE place_id = h.id
+                   AND visit_date BETWEEN :start AND :end
+                   AND ((1 << visit_type) & :allowed_types) != 0
+                 ORDER BY visit_date DESC
+                 LIMIT 1
+             )
+             WHERE {filter} NOT h.hidden
+             ORDER BY {order}
+             LIMIT :count
+             OFFSET :offset",
+            from = from,
+            filter = filter,
+            order = order,
+        ),
+        &sql_params,
+        HistoryVisitInfo::from_row,
+    )?;
+    Ok(HistoryVisitInfos { infos })
+}
+
+/// The maximum number of links we follow when walking the visit graph. The
+/// `from_visit` links always point to earlier visits, so there shouldn't be
+/// cycles, but this also bounds the work we do for a pathological history.
+const MAX_VISIT_GRAPH_DEPTH: i64 = 100;
+
+/// A visit, along with the visit it came from. Returned by the visit graph
+/// functions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkedVisit {
+    pub id: RowId,
+    /// The visit which led to this one, by following a link or a redirect.
+    pub from_visit: Option<RowId>,
+    pub url: Url,
+    pub title: Option<String>,
+    pub visit_date: Timestamp,
+    pub visit_type: VisitTransition,
+}
+
+impl LinkedVisit {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            from_visit: row.get("from_visit")?,
+            url: Url::parse(&row.get::<_, String>("url")?)?,
+            title: row.get("title")?,
+            visit_date: row.get("visit_date")?,
+            visit_type: VisitTransition::from_primitive(row.get("visit_type")?)
+                .unwrap_or(VisitTransition::Link),
+        })
+    }
+}
+
+// Desktop uses 0 for "no from_visit", and so might imported history.
+const LINKED_VISIT_COLUMNS: &str = "v.id, NULLIF(v.from_visit, 0) AS from_visit, h.url, h.title,
+                                    v.visit_date, v.visit_type";
+
+/// Returns the chain of visits which led to `visit_id`, following referrers and
+/// redirects back to the first visit, which is returned first. The last item
+/// is the visit itself. Returns an empty vec if the visit doesn't exist.
+pub fn get_visit_chain(db: &PlacesDb, visit_id: RowId) -> Result<Vec<LinkedVisit>> {
+    db.query_rows_and_then_named_cached(
+        &format!(
+            "WITH RECURSIVE chain(id, depth) AS (
+                 SELECT :visit_id, 0
+                 UNION ALL
+                 SELECT v.from_visit, chain.depth + 1
+                 FROM moz_historyvisits v
+                 JOIN chain ON v.id = chain.id
+                 WHERE v.from_visit > 0 AND chain.depth < :max_depth
+             )
+             SELECT {columns}
+             FROM chain
+             JOIN moz_historyvisits v ON v.id = chain.id
+             JOIN moz_places h ON h.id = v.place_id
+             ORDER BY chain.depth DESC",
+            columns = LINKED_VISIT_COLUMNS
+        ),
+        rusqlite::named_params! {
+            ":visit_id": visit_id,
+            ":max_depth": MAX_VISIT_GRAPH_DEPTH,
+        },
+        LinkedVisit::from_row,
+    )
+}
+
+/// A group of visits which happened close together, or which are linked by
+/// referrers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisitSession {
+    /// The visits in this session, oldest first.
+    pub visits: Vec<LinkedVisit>,
+}
+
+impl VisitSession {
+    pub fn start(&self) -> Timestamp {
+        self.visits.first().map_or(Timestamp(0), |v| v.visit_date)
+    }
+
+    pub fn end(&self) -> Timestamp {
+        self.visits.last().map_or(Timestamp(0), |v| v.visit_date)
+    }
+}
+
+/// Groups the visits between `start` and `end` into browsing sessions. A visit
+/// joins the session of the visit it came from if we know it, otherwise it
+/// joins the most recent session if no more than `max_gap` has passed since
+/// that session's last visit, and otherwise starts a new session. Sessions
+/// are returned in the order they started.
+pub fn get_visit_sessions(
+    db: &PlacesDb,
+    start: Timestamp,
+    end: Timestamp,
+    max_gap: Duration,
+    exclude_types: VisitTransitionSet,
+) -> Result<Vec<VisitSession>> {
+    let allowed_types = exclude_types.complement();
+    let visits = db.query_rows_and_then_named_cached(
+        &format!(
+            "SELECT {columns}
+             FROM moz_historyvisits v
+             JOIN moz_places h ON h.id = v.place_id
+             WHERE v.visit_date BETWEEN :start AND :end
+               AND ((1 << v.visit_type) & :allowed_types) != 0
+             ORDER BY v.visit_date, v.id",
+            columns = LINKED_VISIT_COLUMNS
+        ),
+        rusqlite::named_params! {
+            ":start": start,
+            ":end": end,
+            ":allowed_types": allowed_types,
+        },
+        LinkedVisit::from_row,
+    )?;
+    let max_gap = max_gap.as_millis() as u64;
+    let mut sessions: Vec<VisitSession> = Vec::new();
+    // Maps visit ids to the index of their session.
+    let mut session_indices: HashMap<RowId, usize> = HashMap::new();
+    // The index of the session with the most recent visit.
+    let mut latest: Option<usize> = None;
+    for visit in visits {
+        let index = match visit
+            .from_visit
+            .and_then(|from| session_indices.get(&from).cloned())
+        {
+            Some(index) => index,
+            None => match latest {
+                Some(index) if visit.visit_date.0 <= sessions[index].end().0 + max_gap => index,
+                _ => {
+                    sessions.push(VisitSession { visits: Vec::new() });
+                    sessions.len() - 1
+                }
+            },
+        };
+        session_indices.insert(visit.id, index);
+        sessions[index].visits.push(visit);
+        latest = Some(index);
+    }
+    Ok(sessions)
+}
+
+/// A node in a tree of visits, where the children of a visit are the visits
+/// which came from it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisitTreeNode {
+    pub visit: LinkedVisit,
+    /// The visits which came from this one, oldest first.
+    pub children: Vec<VisitTreeNode>,
+}
+
+/// Returns the tree of visits that `visit_id` is part of: rooted at the first
+/// visit in its chain (see `get_visit_chain`), and including every visit
+/// reached from there. This shows how the user got to the page, and where
+/// they went afterwards. Returns None if the visit doesn't exist.
+pub fn get_visit_tree(db: &PlacesDb, visit_id: RowId) -> Result<Option<VisitTreeNode>> {
+    let root = match get_visit_chain(db, visit_id)?.into_iter().next() {
+        Some(root) => root,
+        None => return Ok(None),
+    };
+    let descendants = db.query_rows_and_then_named_cached(
+        &format!(
+            "WITH RECURSIVE descendants(id, depth) AS (
+                 SELECT v.id, 1
+                 FROM moz_historyvisits v
+                 WHERE v.from_visit = :root_id
+                 UNION ALL
+                 SELECT v.id, descendants.depth + 1
+                 FROM moz_historyvisits v
+                 JOIN descendants ON v.from_visit = descendants.id
+                 WHERE descendants.depth < :max_depth
+             )
+             SELECT {columns}
+             FROM descendants
+             JOIN moz_historyvisits v ON v.id = descendants.id
+             JOIN moz_places h ON h.id = v.place_id
+             ORDER BY v.visit_date, v.id",
+            columns = LINKED_VISIT_COLUMNS
+        ),
+        rusqlite::named_params! {
+            ":root_id": root.id,
+            ":max_depth": MAX_VISIT_GRAPH_DEPTH,
+        },
+        LinkedVisit::from_row,
+    )?;
+    let mut children_by_parent: HashMap<RowId, Vec<LinkedVisit>> = HashMap::new();
+    for visit in descendants {
+        if let Some(from_visit) = visit.from_visit {
+            children_by_parent
+                .entry(from_visit)
+                .or_default()
+                .push(visit);
+        }
+    }
+    fn build_node(
+        visit: LinkedVisit,
+        children_by_parent: &mut HashMap<RowId, Vec<LinkedVisit>>,
+    ) -> VisitTreeNode {
+        let children = children_by_parent
+            .remove(&visit.id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|child| build_node(child, children_by_parent))
+            .collect();
+        VisitTreeNode { visit, children }
+    }
+    Ok(Some(build_node(root, &mut children_by_parent)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::history_sync::*;
+    use super::*;
+    use crate::api::places_api::ConnectionType;
+    use crate::history_sync::record::{HistoryRecord, HistoryRecordVisit};
+    use crate::types::Timestamp;
+    use pretty_assertions::assert_eq;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_get_visited_urls() {
+        use std::collections::HashSet;
+        use std::time::SystemTime;
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("no memory db");
+        let now: Timestamp = SystemTime::now().into();
+        let now_u64 = now.0;
+        // (url, when, is_remote, (expected_always, expected_only_local)
+        let to_add = [
+            (
+                "https://www.example.com/1",
+                now_u64 - 200_100,
+                false,
+                (false, false),
+            ),
+            (
+                "https://www.example.com/12",
+                now_u64 - 200_000,
+                false,
+                (true, true),
+            ),
+            (
+                "https://www.example.com/123",
+                now_u64 - 10_000,
+                true,
+                (true, false),
+            ),
+            (
+                "https://www.example.com/1234",
+                now_u64 - 1000,
+                false,
+                (true, true),
+            ),
+            (
+                "https://www.mozilla.com",
+                now_u64 - 500,
+                false,
+                (false, false),
+            ),
+        ];
+
+        for &(url, when, remote, _) in &to_add {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_at(Timestamp(when))
+                    .with_is_remote(remote)
+                    .with_visit_type(VisitTransition::Link),
+            )
+            .expect("Should apply visit");
+        }
+
+        let visited_all = get_visited_urls(
+            &conn,
+            Timestamp(now_u64 - 200_000),
+            Timestamp(now_u64 - 1000),
+            true,
+        )
+        .unwrap()
+        .into_iter()
         .collect::<HashSet<_>>();
 
         let visited_local = get_visited_urls(
@@ -1687,11 +2647,17 @@ mod tests {
             .with_at(Some(SystemTime::now().into()));
         apply_observation(&db, obs)?;
         let guid = url_to_guid(&db, &url)?.expect("should exist");
+        crate::api::matcher::accept_result(&db, "ex", &url)?;
 
         delete_place_by_guid(&db, &guid)?;
 
         // status was "New", so expect no tombstone.
         assert_eq!(get_tombstone_count(&db), 0);
+        // The page's adaptive history should be gone too.
+        assert_eq!(
+            db.query_one::<i64>("SELECT COUNT(*) FROM moz_inputhistory")?,
+            0
+        );
 
         let obs = VisitObservation::new(url.clone())
             .with_visit_type(VisitTransition::Link)
@@ -1975,6 +2941,8 @@ mod tests {
                     guid: Some(guid.clone()),
                     url: url.clone(),
                     title: None,
+                    keyword: None,
+                    tags: Vec::new(),
                 }),
             )
             .unwrap();
@@ -2090,6 +3058,8 @@ mod tests {
                 guid: Some("bookmarkAAAA".into()),
                 url: urls[2].clone(),
                 title: Some("A".into()),
+                keyword: None,
+                tags: Vec::new(),
             }
             .into(),
         )
@@ -2244,4 +3214,881 @@ mod tests {
         assert_eq!(db_title.len(), crate::storage::TITLE_LENGTH_MAX);
         assert!(title.starts_with(&db_title));
     }
+
+    #[test]
+    fn test_note_page_metadata() {
+        let _ = env_logger::try_init();
+        let mut conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        let url = Url::parse("https://www.example.com/article").unwrap();
+        let image_url = Url::parse("https://www.example.com/image.png").unwrap();
+        let now = Timestamp::now();
+        get_custom_observed_page(&mut conn, url.as_str(), |obs| obs.with_at(now)).unwrap();
+
+        note_page_metadata(
+            &conn,
+            &url,
+            Some("  An article about examples  "),
+            Some(&image_url),
+        )
+        .unwrap();
+        let infos = get_visit_infos(
+            &conn,
+            Timestamp(now.0 - 1000),
+            Timestamp(now.0 + 1000),
+            VisitTransitionSet::empty(),
+        )
+        .unwrap()
+        .infos;
+        assert_eq!(infos.len(), 1);
+        assert_eq!(
+            infos[0].description,
+            Some("An article about examples".to_string())
+        );
+        assert_eq!(
+            infos[0].preview_image_url,
+            Some(image_url.clone().into_string())
+        );
+
+        // Long descriptions are truncated.
+        let description = "x".repeat(crate::storage::DESCRIPTION_LENGTH_MAX + 10);
+        note_page_metadata(&conn, &url, Some(&description), None).unwrap();
+        let infos = get_visit_page(&conn, 0, 1, VisitTransitionSet::empty())
+            .unwrap()
+            .infos;
+        assert_eq!(
+            infos[0].description.as_ref().map(String::len),
+            Some(crate::storage::DESCRIPTION_LENGTH_MAX)
+        );
+        assert_eq!(infos[0].preview_image_url, None);
+
+        // Clearing both.
+        note_page_metadata(&conn, &url, None, None).unwrap();
+        let infos = get_visit_page(&conn, 0, 1, VisitTransitionSet::empty())
+            .unwrap()
+            .infos;
+        assert_eq!(infos[0].description, None);
+        assert_eq!(infos[0].preview_image_url, None);
+
+        // Unknown pages are ignored, and not created.
+        let unknown = Url::parse("https://www.example.com/unknown").unwrap();
+        note_page_metadata(&conn, &unknown, Some("Unknown"), None).unwrap();
+        assert!(fetch_page_info(&conn, &unknown).unwrap().is_none());
+
+        // Overlong image URLs are rejected.
+        let mut long_image_url = "https://www.example.com/".to_string();
+        long_image_url += &"x".repeat(crate::storage::URL_LENGTH_MAX);
+        note_page_metadata(
+            &conn,
+            &url,
+            None,
+            Some(&Url::parse(&long_image_url).unwrap()),
+        )
+        .expect_err("should reject overlong image URLs");
+    }
+
+    fn add_linked_visit(
+        conn: &PlacesDb,
+        url: &str,
+        referrer: Option<&str>,
+        visit_type: VisitTransition,
+        at: Timestamp,
+    ) -> RowId {
+        apply_observation(
+            conn,
+            VisitObservation::new(Url::parse(url).unwrap())
+                .with_visit_type(visit_type)
+                .with_referrer(referrer.map(|r| Url::parse(r).unwrap()))
+                .with_at(at),
+        )
+        .unwrap()
+        .expect("should add a visit")
+    }
+
+    #[test]
+    fn test_visit_graph() {
+        let _ = env_logger::try_init();
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        let now = Timestamp::now();
+        let minute = 60 * 1000;
+        let at = |minutes: u64| Timestamp(now.0 - 120 * minute + minutes * minute);
+
+        // A search, which redirects to a result we follow two links from.
+        let search = add_linked_visit(
+            &conn,
+            "https://search.example.com/?q=foo",
+            None,
+            VisitTransition::Typed,
+            at(0),
+        );
+        let redirect = add_linked_visit(
+            &conn,
+            "https://search.example.com/r?u=foo",
+            Some("https://search.example.com/?q=foo"),
+            VisitTransition::Link,
+            at(1),
+        );
+        let result = add_linked_visit(
+            &conn,
+            "https://foo.example.com/",
+            Some("https://search.example.com/r?u=foo"),
+            VisitTransition::RedirectTemporary,
+            at(1),
+        );
+        let link1 = add_linked_visit(
+            &conn,
+            "https://foo.example.com/1",
+            Some("https://foo.example.com/"),
+            VisitTransition::Link,
+            at(3),
+        );
+        let link2 = add_linked_visit(
+            &conn,
+            "https://foo.example.com/2",
+            Some("https://foo.example.com/"),
+            VisitTransition::Link,
+            at(5),
+        );
+        // An unrelated typed visit shortly after, which is in the same session
+        // because of the time.
+        let typed = add_linked_visit(
+            &conn,
+            "https://bar.example.com/",
+            None,
+            VisitTransition::Typed,
+            at(6),
+        );
+        // A link from the result, long after the referrer visit, isn't linked to it.
+        let late = add_linked_visit(
+            &conn,
+            "https://foo.example.com/3",
+            Some("https://foo.example.com/"),
+            VisitTransition::Link,
+            at(60),
+        );
+        // A link from the late visit, 12 minutes later, is linked to it, since
+        // that's within 15 minutes. That's longer than the 10 minute gap we
+        // use for sessions below, so only the link keeps them together.
+        let late_link = add_linked_visit(
+            &conn,
+            "https://foo.example.com/4",
+            Some("https://foo.example.com/3"),
+            VisitTransition::Link,
+            at(72),
+        );
+
+        let chain = get_visit_chain(&conn, link2).unwrap();
+        assert_eq!(
+            chain.iter().map(|v| v.id).collect::<Vec<_>>(),
+            vec![search, redirect, result, link2]
+        );
+        assert_eq!(chain[0].from_visit, None);
+        assert_eq!(chain[2].from_visit, Some(redirect));
+        assert_eq!(chain[2].visit_type, VisitTransition::RedirectTemporary);
+        assert_eq!(chain[3].url.as_str(), "https://foo.example.com/2");
+        assert_eq!(
+            get_visit_chain(&conn, late).unwrap()[0].from_visit,
+            None,
+            "Referrer visits more than 15 minutes old shouldn't be linked"
+        );
+        assert_eq!(
+            get_visit_chain(&conn, late_link)
+                .unwrap()
+                .iter()
+                .map(|v| v.id)
+                .collect::<Vec<_>>(),
+            vec![late, late_link]
+        );
+        assert!(get_visit_chain(&conn, RowId(1000)).unwrap().is_empty());
+
+        let get_session_ids = |max_gap: Duration| {
+            get_visit_sessions(&conn, at(0), at(120), max_gap, VisitTransitionSet::empty())
+                .unwrap()
+                .iter()
+                .map(|s| s.visits.iter().map(|v| v.id).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            get_session_ids(Duration::from_secs(10 * 60)),
+            vec![
+                vec![search, redirect, result, link1, link2, typed],
+                vec![late, late_link],
+            ]
+        );
+        // Without a gap, only visits linked by referrers are grouped.
+        assert_eq!(
+            get_session_ids(Duration::from_secs(0)),
+            vec![
+                vec![search, redirect, result, link1, link2],
+                vec![typed],
+                vec![late, late_link],
+            ]
+        );
+        let sessions = get_visit_sessions(
+            &conn,
+            at(0),
+            at(120),
+            Duration::from_secs(10 * 60),
+            VisitTransitionSet::empty(),
+        )
+        .unwrap();
+        assert_eq!(sessions[0].start(), at(0));
+        assert_eq!(sessions[0].end(), at(6));
+
+        let tree = get_visit_tree(&conn, link1)
+            .unwrap()
+            .expect("should have a tree");
+        assert_eq!(tree.visit.id, search);
+        assert_eq!(tree.children.len(), 1);
+        assert_eq!(tree.children[0].visit.id, redirect);
+        let result_node = &tree.children[0].children[0];
+        assert_eq!(result_node.visit.id, result);
+        assert_eq!(
+            result_node
+                .children
+                .iter()
+                .map(|n| n.visit.id)
+                .collect::<Vec<_>>(),
+            vec![link1, link2]
+        );
+        assert!(result_node.children.iter().all(|n| n.children.is_empty()));
+        assert!(get_visit_tree(&conn, RowId(1000)).unwrap().is_none());
+
+        // Deleting the redirect and result unlinks the visits which came
+        // from them.
+        delete_visits_between(&conn, at(1), at(1)).unwrap();
+        let chain = get_visit_chain(&conn, link2).unwrap();
+        assert_eq!(chain.iter().map(|v| v.id).collect::<Vec<_>>(), vec![link2]);
+        assert_eq!(chain[0].from_visit, None);
+        let dangling: u32 = conn
+            .query_one(
+                "SELECT COUNT(*) FROM moz_historyvisits
+                 WHERE from_visit NOT IN (SELECT id FROM moz_historyvisits)",
+            )
+            .unwrap();
+        assert_eq!(dangling, 0);
+    }
+
+    #[test]
+    fn test_get_top_frecent_sites() {
+        let _ = env_logger::try_init();
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        let now = Timestamp::now();
+        let visit = |url: &str, obs: &dyn Fn(VisitObservation) -> VisitObservation| {
+            apply_observation(
+                &conn,
+                obs(VisitObservation::new(Url::parse(url).unwrap())
+                    .with_visit_type(VisitTransition::Link)
+                    .with_at(now)),
+            )
+            .unwrap();
+        };
+        // Lots of visits to two pages on example.com, and one to example.org.
+        for _ in 0..3 {
+            visit("https://example.com/a", &|o| o.with_title("A".to_string()));
+            visit("https://example.com/b", &|o| o);
+        }
+        visit("https://example.com/a", &|o| o.with_is_remote(true));
+        visit("https://example.org/", &|o| o);
+        // A page which redirects to another, both on example.net, which is
+        // more frecent than example.org.
+        for _ in 0..2 {
+            visit("https://example.net/redirect", &|o| o);
+            visit("https://example.net/final", &|o| {
+                o.with_visit_type(VisitTransition::RedirectTemporary)
+                    .with_referrer(Url::parse("https://example.net/redirect").unwrap())
+            });
+        }
+        // Many visits to an error page, and to a page in a frame.
+        for _ in 0..10 {
+            visit("https://example.info/", &|o| o.with_is_error(true));
+            visit("https://example.edu/", &|o| {
+                o.with_visit_type(VisitTransition::FramedLink)
+            });
+        }
+
+        let infos = get_top_frecent_sites(&conn, 10, 1).unwrap().infos;
+        assert_eq!(
+            infos.iter().map(|i| i.url.as_str()).collect::<Vec<_>>(),
+            vec![
+                "https://example.com/a",
+                "https://example.net/final",
+                "https://example.org/",
+            ]
+        );
+        assert_eq!(infos[0].title, Some("A".to_string()));
+        assert_eq!(infos[0].visit_count, 4);
+        assert_eq!(infos[0].last_visit_date, now.0 as i64);
+
+        assert_eq!(get_top_frecent_sites(&conn, 1, 1).unwrap().infos.len(), 1);
+        let org_frecency = infos[2].frecency;
+        assert_eq!(
+            get_top_frecent_sites(&conn, 10, org_frecency + 1)
+                .unwrap()
+                .infos
+                .len(),
+            2
+        );
+    }
+
+    fn search_urls(conn: &PlacesDb, query: &str, order: HistorySearchOrder) -> Vec<String> {
+        search_history(
+            conn,
+            &HistorySearchParams {
+                query: query.into(),
+                start: Timestamp(0),
+                end: Timestamp::now(),
+                exclude_types: VisitTransitionSet::empty(),
+                order,
+                offset: 0,
+                count: 10,
+            },
+        )
+        .unwrap()
+        .infos
+        .into_iter()
+        .map(|info| info.url)
+        .collect()
+    }
+
+    #[test]
+    fn test_search_history() {
+        let _ = env_logger::try_init();
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        let now = Timestamp::now();
+        let at = |seconds: u64| Timestamp(now.0 - 100_000 + seconds * 1000);
+        let pages = [
+            ("https://www.example.com/fox", "The quick brown fox"),
+            ("https://www.example.com/dog", "The lazy dog"),
+            ("https://foxes.example.org/", "All about foxes"),
+            ("https://www.example.com/about", "About us"),
+        ];
+        let visit_types = [
+            VisitTransition::Link,
+            VisitTransition::Typed,
+            VisitTransition::Link,
+            VisitTransition::Download,
+        ];
+        for (i, ((url, title), visit_type)) in pages.iter().zip(&visit_types).enumerate() {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_title(title.to_string())
+                    .with_visit_type(*visit_type)
+                    .with_at(at((i as u64 + 1) * 10)),
+            )
+            .unwrap();
+        }
+        // A second, later visit to the first page.
+        apply_observation(
+            &conn,
+            VisitObservation::new(Url::parse(pages[0].0).unwrap())
+                .with_visit_type(VisitTransition::Link)
+                .with_at(at(50)),
+        )
+        .unwrap();
+
+        // Matches on prefixes of words in the title, and returns the most
+        // recent visit.
+        let infos = search_history(
+            &conn,
+            &HistorySearchParams {
+                query: "fox".into(),
+                start: at(0),
+                end: at(100),
+                exclude_types: VisitTransitionSet::empty(),
+                order: HistorySearchOrder::Date,
+                offset: 0,
+                count: 10,
+            },
+        )
+        .unwrap()
+        .infos;
+        assert_eq!(
+            infos
+                .iter()
+                .map(|info| (info.url.as_str(), info.timestamp as u64))
+                .collect::<Vec<_>>(),
+            vec![
+                ("https://www.example.com/fox", at(50).0),
+                ("https://foxes.example.org/", at(30).0),
+            ]
+        );
+        // Every term needs to match, in the title or URL.
+        assert_eq!(
+            search_urls(&conn, "quick example.com", HistorySearchOrder::Relevance),
+            vec!["https://www.example.com/fox"]
+        );
+        assert!(search_urls(&conn, "brown cat", HistorySearchOrder::Relevance).is_empty());
+        // Pages matching in both their title and URL rank higher.
+        assert_eq!(
+            search_urls(&conn, "about", HistorySearchOrder::Relevance),
+            vec![pages[3].0, pages[2].0]
+        );
+        // FTS5 syntax is treated as text.
+        assert!(search_urls(&conn, "fox NOT dog -", HistorySearchOrder::Date).is_empty());
+        assert_eq!(
+            search_urls(&conn, "\"lazy", HistorySearchOrder::Date),
+            vec![pages[1].0]
+        );
+        // An empty query returns everything.
+        assert_eq!(
+            search_urls(&conn, "  ", HistorySearchOrder::Relevance).len(),
+            4
+        );
+
+        // Date ranges, excluded types and paging.
+        let infos = search_history(
+            &conn,
+            &HistorySearchParams {
+                query: "example".into(),
+                start: at(15),
+                end: at(45),
+                exclude_types: VisitTransitionSet::for_specific(&[VisitTransition::Download]),
+                order: HistorySearchOrder::Date,
+                offset: 1,
+                count: 10,
+            },
+        )
+        .unwrap()
+        .infos;
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].url, "https://www.example.com/dog");
+
+        // The index follows title changes and deletions.
+        apply_observation(
+            &conn,
+            VisitObservation::new(Url::parse(pages[1].0).unwrap())
+                .with_title("A sleepy hound".to_string()),
+        )
+        .unwrap();
+        assert!(search_urls(&conn, "lazy", HistorySearchOrder::Relevance).is_empty());
+        assert_eq!(
+            search_urls(&conn, "hound", HistorySearchOrder::Relevance),
+            vec![pages[1].0]
+        );
+        delete_place_visit_at_time(&conn, &Url::parse(pages[2].0).unwrap(), at(30)).unwrap();
+        assert!(search_urls(&conn, "foxes", HistorySearchOrder::Relevance).is_empty());
+        conn.execute_batch("INSERT INTO moz_places_fts(moz_places_fts) VALUES('integrity-check')")
+            .expect("full-text index should be consistent");
+    }
+
+    fn insert_prune_test_data(conn: &mut PlacesDb) -> Timestamp {
+        use crate::storage::bookmarks::{
+            self, BookmarkPosition, BookmarkRootGuid, InsertableBookmark, InsertableItem,
+        };
+        let now = Timestamp::now();
+        // Add 10 pages, with the oldest visits going to the lowest numbered pages.
+        for i in 0..10 {
+            get_custom_observed_page(conn, &format!("http://example.com/{}", i), |obs| {
+                obs.with_at(Timestamp(now.0 - (10 - i) * 1_000_000))
+            })
+            .unwrap();
+        }
+        // Page 1 is bookmarked, and so should never be expired.
+        bookmarks::insert_bookmark(
+            conn,
+            &InsertableItem::Bookmark(InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("http://example.com/1").unwrap(),
+                title: None,
+                keyword: None,
+                tags: Vec::new(),
+            }),
+        )
+        .unwrap();
+        conn.execute_all(&[&format!(
+            "UPDATE moz_places set sync_status = {}",
+            (SyncStatus::Normal as u8)
+        )])
+        .unwrap();
+        now
+    }
+
+    fn get_place_urls(conn: &PlacesDb) -> Vec<String> {
+        conn.query_rows_and_then_named(
+            "SELECT url FROM moz_places ORDER BY id",
+            &[],
+            |row| -> RusqliteResult<String> { row.get(0) },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_prune_destructively_max_pages() {
+        let _ = env_logger::try_init();
+        let mut conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        insert_prune_test_data(&mut conn);
+
+        prune_destructively_with_limits(
+            &conn,
+            &PruneLimits {
+                max_db_size: None,
+                max_pages: Some(6),
+                max_visit_age: None,
+            },
+        )
+        .unwrap();
+
+        // The 4 least recently visited pages which aren't bookmarked are gone.
+        assert_eq!(
+            get_place_urls(&conn),
+            vec![
+                "http://example.com/1",
+                "http://example.com/5",
+                "http://example.com/6",
+                "http://example.com/7",
+                "http://example.com/8",
+                "http://example.com/9",
+            ]
+        );
+        assert_eq!(get_tombstone_count(&conn), 4);
+        assert_eq!(
+            get_visit_count(&conn, VisitTransitionSet::empty()).unwrap(),
+            6
+        );
+
+        // Pruning again with the same limits shouldn't remove anything else.
+        prune_destructively_with_limits(
+            &conn,
+            &PruneLimits {
+                max_db_size: None,
+                max_pages: Some(6),
+                max_visit_age: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(get_place_urls(&conn).len(), 6);
+    }
+
+    #[test]
+    fn test_prune_destructively_max_db_size() {
+        let _ = env_logger::try_init();
+        let mut conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        insert_prune_test_data(&mut conn);
+
+        prune_destructively_with_limits(
+            &conn,
+            &PruneLimits {
+                max_db_size: Some(0),
+                max_pages: None,
+                max_visit_age: None,
+            },
+        )
+        .unwrap();
+
+        // Everything but the bookmarked page should be gone, and it keeps its
+        // visits.
+        assert_eq!(get_place_urls(&conn), vec!["http://example.com/1"]);
+        assert_eq!(get_tombstone_count(&conn), 9);
+        assert_eq!(
+            get_visit_count(&conn, VisitTransitionSet::empty()).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_prune_destructively_max_visit_age() {
+        let _ = env_logger::try_init();
+        let mut conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        let now = insert_prune_test_data(&mut conn);
+        // Add a recent visit to the oldest page, so only its old visit is expired.
+        get_custom_observed_page(&mut conn, "http://example.com/0", |obs| obs.with_at(now))
+            .unwrap();
+
+        prune_destructively_with_limits(
+            &conn,
+            &PruneLimits {
+                max_db_size: None,
+                max_pages: None,
+                max_visit_age: Some(Duration::from_millis(5_500_000)),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_place_urls(&conn),
+            vec![
+                "http://example.com/0",
+                "http://example.com/1",
+                "http://example.com/5",
+                "http://example.com/6",
+                "http://example.com/7",
+                "http://example.com/8",
+                "http://example.com/9",
+            ]
+        );
+        assert_eq!(get_tombstone_count(&conn), 3);
+        let page = fetch_page_info(&conn, &Url::parse("http://example.com/0").unwrap())
+            .unwrap()
+            .unwrap()
+            .page;
+        assert_tombstones(&conn, &[(page.row_id, Timestamp(now.0 - 10 * 1_000_000))]);
+        assert_eq!(
+            get_visit_count(&conn, VisitTransitionSet::empty()).unwrap(),
+            7
+        );
+    }
+
+    #[test]
+    fn test_delete_visits_for_host() {
+        use crate::storage::bookmarks::{
+            self, BookmarkPosition, BookmarkRootGuid, InsertableBookmark, InsertableItem,
+        };
+        let _ = env_logger::try_init();
+        let mut conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        for url in &[
+            "http://example.com/a",
+            "https://www.example.com/b",
+            "http://example.com:8080/c",
+            "http://sub.www.example.com:8080/d",
+            "http://www.example.com/bookmarked",
+            "http://notexample.com/",
+            "http://example.com.au/",
+        ] {
+            get_observed_page(&mut conn, url).unwrap();
+        }
+        bookmarks::insert_bookmark(
+            &conn,
+            &InsertableItem::Bookmark(InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("http://www.example.com/bookmarked").unwrap(),
+                title: None,
+                keyword: None,
+                tags: Vec::new(),
+            }),
+        )
+        .unwrap();
+        // An unvisited page, which should still be removed.
+        new_page_info(
+            &conn,
+            &Url::parse("http://example.com/unvisited").unwrap(),
+            None,
+        )
+        .unwrap();
+        conn.execute_all(&[&format!(
+            "UPDATE moz_places set sync_status = {}",
+            (SyncStatus::Normal as u8)
+        )])
+        .unwrap();
+
+        let bookmarked_url = Url::parse("http://www.example.com/bookmarked").unwrap();
+        let not_example_url = Url::parse("http://notexample.com/").unwrap();
+        for url in &[&bookmarked_url, &not_example_url] {
+            crate::api::matcher::accept_result(&conn, "ex", url).unwrap();
+        }
+        let bookmarked_visit_date = fetch_page_info(&conn, &bookmarked_url)
+            .unwrap()
+            .unwrap()
+            .page
+            .last_visit_date_local;
+
+        // Without subdomains, pages on any port of the host itself go.
+        delete_visits_for_host(&conn, "EXAMPLE.com.", false).unwrap();
+        assert_eq!(
+            get_place_urls(&conn),
+            vec![
+                "https://www.example.com/b",
+                "http://sub.www.example.com:8080/d",
+                "http://www.example.com/bookmarked",
+                "http://notexample.com/",
+                "http://example.com.au/",
+            ]
+        );
+        assert_eq!(get_tombstone_count(&conn), 3);
+        assert_eq!(
+            get_visit_count(&conn, VisitTransitionSet::empty()).unwrap(),
+            5
+        );
+
+        // With subdomains, the bookmarked page stays, but loses its visit.
+        delete_visits_for_host(&conn, "www.example.com", true).unwrap();
+        assert_eq!(
+            get_place_urls(&conn),
+            vec![
+                "http://www.example.com/bookmarked",
+                "http://notexample.com/",
+                "http://example.com.au/",
+            ]
+        );
+        assert_eq!(get_tombstone_count(&conn), 5);
+        assert_eq!(
+            get_visit_count(&conn, VisitTransitionSet::empty()).unwrap(),
+            2
+        );
+        let bookmarked = fetch_page_info(&conn, &bookmarked_url).unwrap().unwrap();
+        assert_eq!(bookmarked.last_visit_id, None);
+        // The bookmarked page loses its adaptive history, too.
+        let adaptive_place_ids = conn
+            .query_rows_and_then_named(
+                "SELECT place_id FROM moz_inputhistory",
+                &[],
+                |row| -> RusqliteResult<RowId> { row.get(0) },
+            )
+            .unwrap();
+        assert_eq!(
+            adaptive_place_ids,
+            vec![
+                fetch_page_info(&conn, &not_example_url)
+                    .unwrap()
+                    .unwrap()
+                    .page
+                    .row_id
+            ]
+        );
+        assert_tombstones(&conn, &[(bookmarked.page.row_id, bookmarked_visit_date)]);
+
+        // The origins have been cleaned up too.
+        let hosts = conn
+            .query_rows_and_then_named(
+                "SELECT host FROM moz_origins ORDER BY host",
+                &[],
+                |row| -> RusqliteResult<String> { row.get(0) },
+            )
+            .unwrap();
+        assert_eq!(
+            hosts,
+            vec!["example.com.au", "notexample.com", "www.example.com"]
+        );
+
+        delete_visits_for_host(&conn, "", true).expect_err("should reject an empty host");
+    }
+
+    #[test]
+    fn test_recalculate_all_frecencies() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let now = Timestamp::now();
+        for (url, visit_type) in &[
+            ("https://example.com/link", VisitTransition::Link),
+            ("https://example.com/typed", VisitTransition::Typed),
+            ("https://example.org/bookmark", VisitTransition::Bookmark),
+        ] {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url)?)
+                    .with_at(now)
+                    .with_visit_type(*visit_type),
+            )?;
+        }
+        let get_frecencies = || -> Result<Vec<(String, i64)>> {
+            conn.query_rows_and_then_named(
+                "SELECT url, frecency FROM moz_places ORDER BY id",
+                &[],
+                |row| -> Result<_> { Ok((row.get(0)?, row.get(1)?)) },
+            )
+        };
+        let frecencies = get_frecencies()?;
+        assert_eq!(frecencies.len(), 3);
+        assert!(frecencies[0].1 != frecencies[1].1 && frecencies[1].1 != frecencies[2].1);
+
+        // Each page gets its own frecency back, not the first one's.
+        conn.execute_batch("UPDATE moz_places SET frecency = 1")?;
+        recalculate_all_frecencies(&conn, &conn.begin_interrupt_scope())?;
+        assert_eq!(get_frecencies()?, frecencies);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decay_frecencies() {
+        let _ = env_logger::try_init();
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("no memory db");
+        let now = Timestamp::now();
+        for url in &["https://example.com/1", "https://example.com/2"] {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_at(now)
+                    .with_visit_type(VisitTransition::Typed),
+            )
+            .expect("Should apply visit");
+        }
+        let get_frecencies = || -> (Vec<i64>, i64) {
+            let places = conn
+                .query_rows_and_then_named(
+                    "SELECT frecency FROM moz_places ORDER BY id",
+                    &[],
+                    |row| -> RusqliteResult<i64> { row.get(0) },
+                )
+                .unwrap();
+            let origin = conn.query_one("SELECT frecency FROM moz_origins").unwrap();
+            (places, origin)
+        };
+        let get_stats = || -> (i64, i64, i64) {
+            let get = |key| get_meta::<i64>(&conn, key).unwrap().unwrap_or_default();
+            (
+                get("origin_frecency_count"),
+                get("origin_frecency_sum"),
+                get("origin_frecency_sum_of_squares"),
+            )
+        };
+        crate::api::matcher::accept_result(
+            &conn,
+            "ex",
+            &Url::parse("https://example.com/1").unwrap(),
+        )
+        .expect("should accept result");
+        let get_use_count = || -> Option<f64> {
+            conn.try_query_one("SELECT use_count FROM moz_inputhistory", &[], false)
+                .unwrap()
+        };
+        let (old_places, old_origin) = get_frecencies();
+        assert!(old_places.iter().all(|&f| f > 0));
+        assert_eq!(get_stats(), (1, old_origin, old_origin * old_origin));
+
+        decay_frecencies_if_needed(&conn, now).expect("should decay");
+        let (places, origin) = get_frecencies();
+        let decayed = |f: i64| (f as f64 * 0.975).round() as i64;
+        assert_eq!(
+            places,
+            old_places.iter().map(|&f| decayed(f)).collect::<Vec<_>>()
+        );
+        assert_eq!(origin, decayed(old_origin));
+        assert_eq!(get_stats(), (1, origin, origin * origin));
+        assert!(!conn.frecency_decaying.load(Ordering::SeqCst));
+        assert_eq!(get_use_count(), Some(0.975));
+
+        // We only decay once a day.
+        decay_frecencies_if_needed(&conn, Timestamp(now.0 + 60 * 60 * 1000))
+            .expect("should skip decay");
+        assert_eq!(get_frecencies(), (places.clone(), origin));
+        decay_frecencies_if_needed(&conn, Timestamp(now.0 + 24 * 60 * 60 * 1000))
+            .expect("should decay again");
+        assert_eq!(get_frecencies().1, decayed(origin));
+
+        // Recalculating a frecency updates its origin as usual.
+        update_frecency(&conn, RowId(1), None).expect("should update frecency");
+        delete_pending_temp_tables(&conn).expect("should update origins");
+        let (new_places, new_origin) = get_frecencies();
+        assert_eq!(
+            new_origin - decayed(origin),
+            new_places[0] - decayed(places[0])
+        );
+
+        // Turning off frecency decay still decays adaptive history.
+        *conn.frecency_settings.write().unwrap() = crate::frecency::FrecencySettings {
+            decay_rate: 1.0,
+            ..crate::frecency::FrecencySettings::default()
+        };
+        let frecencies = get_frecencies();
+        let use_count = get_use_count().expect("should have adaptive history");
+        decay_frecencies_if_needed(&conn, Timestamp(now.0 + 2 * 24 * 60 * 60 * 1000))
+            .expect("should decay adaptive history");
+        assert_eq!(get_frecencies(), frecencies);
+        assert_eq!(get_use_count(), Some(use_count * 0.975));
+
+        // Adaptive history which hasn't been used in a long time is removed.
+        conn.execute_batch("UPDATE moz_inputhistory SET use_count = .01")
+            .expect("should update use count");
+        decay_frecencies_if_needed(&conn, Timestamp(now.0 + 3 * 24 * 60 * 60 * 1000))
+            .expect("should decay adaptive history");
+        assert_eq!(get_use_count(), None);
+    }
 }
@@ -6,12 +6,14 @@
 // API and the database.
 
 pub mod bookmarks;
+pub mod favicons;
 pub mod history;
+pub mod reading_list;
 pub mod tags;
 
 use crate::db::PlacesDb;
 use crate::error::{ErrorKind, InvalidPlaceInfo, Result};
-use crate::msg_types::HistoryVisitInfo;
+use crate::msg_types::{HistoryVisitInfo, TopFrecentSiteInfo};
 use crate::types::{SyncStatus, Timestamp, VisitTransition};
 use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use rusqlite::Result as RusqliteResult;
@@ -26,10 +28,12 @@ use url::Url;
 pub const URL_LENGTH_MAX: usize = 65536;
 pub const TITLE_LENGTH_MAX: usize = 4096;
 pub const TAG_LENGTH_MAX: usize = 100;
-// pub const DESCRIPTION_LENGTH_MAX: usize = 256;
+pub const DESCRIPTION_LENGTH_MAX: usize = 256;
 
 // Typesafe way to manage RowIds. Does it make sense? A better way?
-#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Deserialize, Serialize, Default)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash, Deserialize, Serialize, Default,
+)]
 pub struct RowId(pub i64);
 
 impl From<RowId> for i64 {
@@ -188,11 +192,30 @@ impl HistoryVisitInfo {
             timestamp: visit_date.0 as i64,
             visit_type: visit_type as i32,
             is_hidden: row.get("hidden")?,
+            description: row.get("description")?,
+            preview_image_url: row.get("preview_image_url")?,
+        })
+    }
+}
+
+impl TopFrecentSiteInfo {
+    pub(crate) fn from_row(row: &rusqlite::Row<'_>) -> Result<Self> {
+        let last_visit_date: Timestamp = row.get("last_visit_date")?;
+        Ok(Self {
+            url: row.get("url")?,
+            title: row.get("title")?,
+            frecency: row.get("frecency")?,
+            visit_count: row.get("visit_count")?,
+            last_visit_date: last_visit_date.0 as i64,
         })
     }
 }
 
 pub fn run_maintenance(conn: &PlacesDb) -> Result<()> {
+    bookmarks::check_and_repair(conn)?;
+    bookmarks::prune_bookmark_changes(conn)?;
+    favicons::delete_orphaned_icons(conn)?;
+    history::decay_frecencies_if_needed(conn, Timestamp::now())?;
     conn.execute_all(&[
         "VACUUM",
         "PRAGMA optimize",
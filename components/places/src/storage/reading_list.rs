@@ -0,0 +1,341 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The reading list: pages the user saved to read later.
+//!
+//! Items are kept in their own tables, apart from history and bookmarks, and
+//! synced in their own collection by `reading_list_sync`. Like bookmarks,
+//! local changes bump the item's change counter, and removing a synced item
+//! leaves a tombstone until the next sync.
+
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::msg_types;
+use crate::types::{SyncStatus, Timestamp};
+use rusqlite::Row;
+use sql_support::{self, ConnExt};
+use sync_guid::Guid as SyncGuid;
+use url::Url;
+
+/// An item in the reading list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadingListItem {
+    pub guid: SyncGuid,
+    pub url: Url,
+    pub title: Option<String>,
+    /// A short extract of the page, to show in the list.
+    pub excerpt: Option<String>,
+    pub date_added: Timestamp,
+    pub last_modified: Timestamp,
+    pub unread: bool,
+    pub favorite: bool,
+}
+
+impl ReadingListItem {
+    pub(crate) fn from_row(row: &Row<'_>) -> Result<Self> {
+        let url: String = row.get("url")?;
+        Ok(Self {
+            guid: row.get("guid")?,
+            url: Url::parse(&url)?,
+            title: row.get("title")?,
+            excerpt: row.get("excerpt")?,
+            date_added: row.get("dateAdded")?,
+            last_modified: row.get("lastModified")?,
+            unread: row.get("unread")?,
+            favorite: row.get("favorite")?,
+        })
+    }
+}
+
+impl From<ReadingListItem> for msg_types::ReadingListItem {
+    fn from(item: ReadingListItem) -> Self {
+        msg_types::ReadingListItem {
+            guid: item.guid.into_string(),
+            url: item.url.into_string(),
+            title: item.title,
+            excerpt: item.excerpt,
+            date_added: item.date_added.0 as i64,
+            last_modified: item.last_modified.0 as i64,
+            unread: item.unread,
+            favorite: item.favorite,
+        }
+    }
+}
+
+impl From<Vec<ReadingListItem>> for msg_types::ReadingListItems {
+    fn from(items: Vec<ReadingListItem>) -> Self {
+        msg_types::ReadingListItems {
+            items: items.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Changes to make to a reading list item. Fields which are `None` are left
+/// as they are.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReadingListItemUpdate {
+    pub title: Option<String>,
+    pub excerpt: Option<String>,
+    pub unread: Option<bool>,
+    pub favorite: Option<bool>,
+}
+
+impl ReadingListItemUpdate {
+    pub fn from_message(msg: msg_types::ReadingListItemUpdate) -> Self {
+        Self {
+            title: msg.title,
+            excerpt: msg.excerpt,
+            unread: msg.unread,
+            favorite: msg.favorite,
+        }
+    }
+}
+
+const ITEM_COLUMNS: &str = "guid, url, title, excerpt, dateAdded, lastModified, unread, favorite";
+
+/// Adds a page to the reading list, and returns the new item. Saving a page
+/// which is already in the list marks it as unread again, and updates its
+/// title and excerpt, if given.
+pub fn add_reading_list_item(
+    db: &PlacesDb,
+    url: &Url,
+    title: Option<&str>,
+    excerpt: Option<&str>,
+) -> Result<ReadingListItem> {
+    if url.as_str().len() > super::URL_LENGTH_MAX {
+        return Err(InvalidPlaceInfo::UrlTooLong.into());
+    }
+    let tx = db.begin_transaction()?;
+    let now = Timestamp::now();
+    let updated = db.execute_named_cached(
+        "UPDATE moz_reading_list SET
+             title = IFNULL(:title, title),
+             excerpt = IFNULL(:excerpt, excerpt),
+             unread = 1,
+             lastModified = :now,
+             syncChangeCounter = syncChangeCounter + 1
+         WHERE url = :url",
+        &[
+            (":url", &url.as_str()),
+            (":title", &title),
+            (":excerpt", &excerpt),
+            (":now", &now),
+        ],
+    )?;
+    if updated == 0 {
+        db.execute_named_cached(
+            "INSERT INTO moz_reading_list(guid, url, title, excerpt, dateAdded,
+                                          lastModified, syncStatus, syncChangeCounter)
+             VALUES(:guid, :url, :title, :excerpt, :now, :now, :syncStatus, 1)",
+            &[
+                (":guid", &SyncGuid::random()),
+                (":url", &url.as_str()),
+                (":title", &title),
+                (":excerpt", &excerpt),
+                (":now", &now),
+                (":syncStatus", &SyncStatus::New),
+            ],
+        )?;
+    }
+    // We just added or updated the item, so this returns an error instead of
+    // `None` if it's somehow missing.
+    let item = db.query_row_and_then_named(
+        &format!(
+            "SELECT {} FROM moz_reading_list WHERE url = :url",
+            ITEM_COLUMNS
+        ),
+        &[(":url", &url.as_str())],
+        ReadingListItem::from_row,
+        true,
+    )?;
+    tx.commit()?;
+    Ok(item)
+}
+
+/// Returns every item in the reading list, most recently added first.
+pub fn get_reading_list(db: &PlacesDb) -> Result<Vec<ReadingListItem>> {
+    db.query_rows_and_then_named_cached(
+        &format!(
+            "SELECT {} FROM moz_reading_list
+             ORDER BY dateAdded DESC, id DESC",
+            ITEM_COLUMNS
+        ),
+        &[],
+        ReadingListItem::from_row,
+    )
+}
+
+pub fn get_reading_list_item(db: &PlacesDb, guid: &SyncGuid) -> Result<Option<ReadingListItem>> {
+    db.try_query_row(
+        &format!(
+            "SELECT {} FROM moz_reading_list WHERE guid = :guid",
+            ITEM_COLUMNS
+        ),
+        &[(":guid", guid)],
+        ReadingListItem::from_row,
+        true,
+    )
+}
+
+pub fn get_reading_list_item_for_url(db: &PlacesDb, url: &Url) -> Result<Option<ReadingListItem>> {
+    db.try_query_row(
+        &format!(
+            "SELECT {} FROM moz_reading_list WHERE url = :url",
+            ITEM_COLUMNS
+        ),
+        &[(":url", &url.as_str())],
+        ReadingListItem::from_row,
+        true,
+    )
+}
+
+/// Updates a reading list item. Returns an error if there's no item with the
+/// GUID.
+pub fn update_reading_list_item(
+    db: &PlacesDb,
+    guid: &SyncGuid,
+    update: &ReadingListItemUpdate,
+) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    let item = get_reading_list_item(db, guid)?
+        .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
+    let title = update.title.clone().or_else(|| item.title.clone());
+    let excerpt = update.excerpt.clone().or_else(|| item.excerpt.clone());
+    let unread = update.unread.unwrap_or(item.unread);
+    let favorite = update.favorite.unwrap_or(item.favorite);
+    if title == item.title
+        && excerpt == item.excerpt
+        && unread == item.unread
+        && favorite == item.favorite
+    {
+        return Ok(());
+    }
+    db.execute_named_cached(
+        "UPDATE moz_reading_list SET
+             title = :title,
+             excerpt = :excerpt,
+             unread = :unread,
+             favorite = :favorite,
+             lastModified = :now,
+             syncChangeCounter = syncChangeCounter + 1
+         WHERE guid = :guid",
+        &[
+            (":guid", guid),
+            (":title", &title),
+            (":excerpt", &excerpt),
+            (":unread", &unread),
+            (":favorite", &favorite),
+            (":now", &Timestamp::now()),
+        ],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Removes an item from the reading list. Returns true if it was in the list,
+/// false otherwise.
+pub fn delete_reading_list_item(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
+    let tx = db.begin_transaction()?;
+    db.execute_named_cached(
+        "INSERT OR IGNORE INTO moz_reading_list_deleted(guid, dateRemoved)
+         SELECT guid, :now FROM moz_reading_list
+         WHERE guid = :guid AND syncStatus = :syncStatus",
+        &[
+            (":guid", guid),
+            (":now", &Timestamp::now()),
+            (":syncStatus", &SyncStatus::Normal),
+        ],
+    )?;
+    let deleted = db.execute_named_cached(
+        "DELETE FROM moz_reading_list WHERE guid = :guid",
+        &[(":guid", guid)],
+    )?;
+    tx.commit()?;
+    Ok(deleted > 0)
+}
+
+/// Removes every item, and every tombstone, from the reading list. This is
+/// used by Sync to wipe the collection, so it doesn't write tombstones.
+pub(crate) fn delete_everything(db: &PlacesDb) -> Result<()> {
+    db.execute_batch(
+        "DELETE FROM moz_reading_list;
+         DELETE FROM moz_reading_list_deleted;",
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+
+    #[test]
+    fn test_reading_list() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+
+        let a = add_reading_list_item(
+            &conn,
+            &Url::parse("https://example.com/a")?,
+            Some("A"),
+            Some("Once upon a time"),
+        )?;
+        assert!(a.unread);
+        assert!(!a.favorite);
+        let b = add_reading_list_item(&conn, &Url::parse("https://example.com/b")?, None, None)?;
+        conn.execute_named(
+            "UPDATE moz_reading_list SET dateAdded = :dateAdded WHERE guid = :guid",
+            &[(":dateAdded", &Timestamp(1)), (":guid", &a.guid)],
+        )?;
+        let guids: Vec<SyncGuid> = get_reading_list(&conn)?
+            .into_iter()
+            .map(|item| item.guid)
+            .collect();
+        assert_eq!(guids, vec![b.guid.clone(), a.guid.clone()]);
+
+        update_reading_list_item(
+            &conn,
+            &a.guid,
+            &ReadingListItemUpdate {
+                unread: Some(false),
+                favorite: Some(true),
+                ..ReadingListItemUpdate::default()
+            },
+        )?;
+        let updated = get_reading_list_item(&conn, &a.guid)?.unwrap();
+        assert!(!updated.unread);
+        assert!(updated.favorite);
+        assert_eq!(updated.title, Some("A".to_string()));
+        assert!(update_reading_list_item(
+            &conn,
+            &SyncGuid::from("itemXXXXXXXX"),
+            &ReadingListItemUpdate::default()
+        )
+        .is_err());
+
+        // Saving the same page again keeps the item, but marks it unread.
+        let again = add_reading_list_item(&conn, &a.url, Some("New A"), None)?;
+        assert_eq!(again.guid, a.guid);
+        assert!(again.unread);
+        assert!(again.favorite);
+        assert_eq!(again.title, Some("New A".to_string()));
+        assert_eq!(again.excerpt, Some("Once upon a time".to_string()));
+
+        // Only synced items leave tombstones.
+        conn.execute_named(
+            "UPDATE moz_reading_list SET syncStatus = :syncStatus WHERE guid = :guid",
+            &[(":syncStatus", &SyncStatus::Normal), (":guid", &a.guid)],
+        )?;
+        assert!(delete_reading_list_item(&conn, &a.guid)?);
+        assert!(delete_reading_list_item(&conn, &b.guid)?);
+        assert!(!delete_reading_list_item(&conn, &b.guid)?);
+        assert!(get_reading_list(&conn)?.is_empty());
+        let tombstones: Vec<SyncGuid> =
+            conn.query_rows_into("SELECT guid FROM moz_reading_list_deleted", &[], |row| {
+                row.get(0)
+            })?;
+        assert_eq!(tombstones, vec![a.guid]);
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use places::{
+    api::places_api::{ConnectionType, PlacesApi},
+    storage::bookmarks::{public_node::fetch_bookmark, BookmarkRootGuid},
+    BookmarkType, Result, Timestamp,
+};
+use serde_json::json;
+use tempfile::tempdir;
+
+#[test]
+fn test_import() -> Result<()> {
+    let tmpdir = tempdir().unwrap();
+    let chromium_path = tmpdir.path().join("Bookmarks");
+    let bookmarks = json!({
+        "checksum": "5a5e2fb1d95c3ce14e8a1a4f2e1b2c27",
+        "roots": {
+            "bookmark_bar": {
+                "children": [{
+                    "date_added": "13212158400000000",
+                    "guid": "00000000-0000-4000-a000-000000000004",
+                    "id": "4",
+                    "name": "Example",
+                    "type": "url",
+                    "url": "https://example.com/"
+                }, {
+                    "children": [{
+                        "date_added": "0",
+                        "guid": "00000000-0000-4000-a000-000000000006",
+                        "id": "6",
+                        "name": "",
+                        "type": "url",
+                        "url": "https://mozilla.org/"
+                    }, {
+                        "date_added": "13212158400000000",
+                        "guid": "00000000-0000-4000-a000-000000000007",
+                        "id": "7",
+                        "name": "Invalid",
+                        "type": "url",
+                        "url": "I'm a super invalid URL, yo"
+                    }],
+                    "date_added": "13212158400000000",
+                    "date_modified": "13212158500000000",
+                    "guid": "00000000-0000-4000-a000-000000000005",
+                    "id": "5",
+                    "name": "Some folder",
+                    "type": "folder"
+                }],
+                "date_added": "13212158400000000",
+                "date_modified": "0",
+                "guid": "00000000-0000-4000-a000-000000000001",
+                "id": "1",
+                "name": "Bookmarks bar",
+                "type": "folder"
+            },
+            "other": {
+                "children": [],
+                "date_added": "13212158400000000",
+                "date_modified": "0",
+                "guid": "00000000-0000-4000-a000-000000000002",
+                "id": "2",
+                "name": "Other bookmarks",
+                "type": "folder"
+            },
+            "synced": {
+                "children": [{
+                    "date_added": "13212158400000000",
+                    "guid": "00000000-0000-4000-a000-000000000008",
+                    "id": "8",
+                    "name": "On my phone",
+                    "type": "url",
+                    "url": "https://example.org/"
+                }],
+                "date_added": "13212158400000000",
+                "date_modified": "0",
+                "guid": "00000000-0000-4000-a000-000000000003",
+                "id": "3",
+                "name": "Mobile bookmarks",
+                "type": "folder"
+            }
+        },
+        "version": 1
+    });
+    std::fs::write(&chromium_path, bookmarks.to_string()).unwrap();
+
+    let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
+    let report = places::import::import_chromium_bookmarks(&places_api, &chromium_path)?;
+    assert_eq!(report.rows_seen, 5);
+    assert_eq!(report.rows_imported, 4);
+    assert_eq!(report.skipped_invalid_url, 1);
+    assert_eq!(report.skipped_unsupported, 0);
+    assert_eq!(report.invalid_timestamps, 1);
+    assert_eq!(
+        report.phases.iter().map(|p| p.name).collect::<Vec<_>>(),
+        vec!["parse", "insert"]
+    );
+
+    let conn = places_api.open_connection(ConnectionType::ReadOnly)?;
+    let toolbar = fetch_bookmark(&conn, BookmarkRootGuid::Toolbar.guid(), true)?.unwrap();
+    let children = toolbar.child_nodes.unwrap();
+    assert_eq!(children.len(), 2);
+
+    assert_eq!(children[0].node_type, BookmarkType::Bookmark);
+    assert_eq!(children[0].title, Some("Example".to_owned()));
+    assert_eq!(
+        children[0].url.as_ref().map(|u| u.as_str()),
+        Some("https://example.com/")
+    );
+    assert_eq!(children[0].date_added, Timestamp(1_567_684_800_000));
+
+    assert_eq!(children[1].node_type, BookmarkType::Folder);
+    assert_eq!(children[1].title, Some("Some folder".to_owned()));
+    assert_eq!(children[1].last_modified, Timestamp(1_567_684_900_000));
+    let folder = fetch_bookmark(&conn, &children[1].guid, true)?.unwrap();
+    let folder_children = folder.child_nodes.unwrap();
+    assert_eq!(folder_children.len(), 1);
+    assert_eq!(folder_children[0].title, None);
+    assert_eq!(
+        folder_children[0].url.as_ref().map(|u| u.as_str()),
+        Some("https://mozilla.org/")
+    );
+    // "0" means Chromium doesn't know when it was added.
+    assert!(folder_children[0].date_added > Timestamp(1_567_684_800_000));
+
+    let mobile = fetch_bookmark(&conn, BookmarkRootGuid::Mobile.guid(), true)?.unwrap();
+    let mobile_children = mobile.child_nodes.unwrap();
+    assert_eq!(mobile_children.len(), 1);
+    assert_eq!(mobile_children[0].title, Some("On my phone".to_owned()));
+
+    let unfiled = fetch_bookmark(&conn, BookmarkRootGuid::Unfiled.guid(), true)?.unwrap();
+    assert_eq!(unfiled.child_nodes.map(|c| c.len()), Some(0));
+
+    Ok(())
+}
+
+#[test]
+fn test_import_invalid_json() -> Result<()> {
+    let tmpdir = tempdir().unwrap();
+    let chromium_path = tmpdir.path().join("Bookmarks");
+    std::fs::write(&chromium_path, "{\"roots\": 5}").unwrap();
+    let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
+    assert!(places::import::import_chromium_bookmarks(&places_api, &chromium_path).is_err());
+    Ok(())
+}
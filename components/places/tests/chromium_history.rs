@@ -0,0 +1,175 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use places::{
+    api::places_api::{ConnectionType, PlacesApi},
+    ErrorKind, Result,
+};
+use rusqlite::{Connection, NO_PARAMS};
+use sql_support::ConnExt;
+use std::path::Path;
+use tempfile::tempdir;
+
+// Microseconds between 1601-01-01 and the Unix epoch.
+const WINDOWS_EPOCH_OFFSET_US: i64 = 11_644_473_600_000_000;
+
+fn empty_chromium_db(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(include_str!("./chromium_history_schema.sql"))?;
+    Ok(conn)
+}
+
+fn insert_url(conn: &Connection, url: &str, title: Option<&str>, typed_count: u32) -> Result<i64> {
+    conn.execute_named(
+        "INSERT INTO urls(url, title, typed_count, last_visit_time)
+         VALUES (:url, :title, :typed_count, 0)",
+        rusqlite::named_params! {
+            ":url": url,
+            ":title": title,
+            ":typed_count": typed_count,
+        },
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn insert_visit(conn: &Connection, url_id: i64, time_ms: i64, transition: i32) -> Result<()> {
+    conn.execute_named(
+        "INSERT INTO visits(url, visit_time, transition) VALUES (:url, :visit_time, :transition)",
+        rusqlite::named_params! {
+            ":url": url_id,
+            ":visit_time": time_ms * 1000 + WINDOWS_EPOCH_OFFSET_US,
+            ":transition": transition,
+        },
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_import_unsupported_db_version() -> Result<()> {
+    let tmpdir = tempdir().unwrap();
+    let chromium_path = tmpdir.path().join("History");
+    let chromium_db = empty_chromium_db(&chromium_path)?;
+    chromium_db.execute(
+        "UPDATE meta SET value = '3' WHERE key = 'version'",
+        NO_PARAMS,
+    )?;
+    let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
+    match places::import::import_chromium_history(&places_api, chromium_path)
+        .unwrap_err()
+        .kind()
+    {
+        ErrorKind::UnsupportedDatabaseVersion(3) => {}
+        _ => unreachable!("Should fail with UnsupportedDatabaseVersion!"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_import() -> Result<()> {
+    let tmpdir = tempdir().unwrap();
+    let chromium_path = tmpdir.path().join("History");
+    let chromium_db = empty_chromium_db(&chromium_path)?;
+
+    let example = insert_url(&chromium_db, "https://example.com/", Some("Example"), 1)?;
+    // Typed, then a link with the CHAIN_START and CHAIN_END qualifiers.
+    insert_visit(&chromium_db, example, 1_565_117_389_897, 0x3000_0001)?;
+    insert_visit(&chromium_db, example, 1_565_117_389_898, 0x3000_0000)?;
+    let redirected = insert_url(&chromium_db, "https://www.example.com/", None, 0)?;
+    // A server redirect, stored like Chromium does as a negative number.
+    insert_visit(
+        &chromium_db,
+        redirected,
+        1_565_117_389_899,
+        0xA000_0000u32 as i32,
+    )?;
+    // An automatic subframe, which we skip.
+    let frame = insert_url(&chromium_db, "https://ads.example.com/", None, 0)?;
+    insert_visit(&chromium_db, frame, 1_565_117_389_899, 3)?;
+    // A visit with a time that doesn't make sense gets the current time.
+    let mozilla = insert_url(&chromium_db, "https://mozilla.org/", Some("Mozilla"), 0)?;
+    insert_visit(&chromium_db, mozilla, -1, 8)?;
+    let invalid = insert_url(&chromium_db, "I'm a super invalid URL, yo", None, 0)?;
+    insert_visit(&chromium_db, invalid, 1_565_117_389_899, 1)?;
+
+    let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
+    let report = places::import::import_chromium_history(&places_api, &chromium_path)?;
+    assert_eq!(report.rows_seen, 6);
+    assert_eq!(report.rows_imported, 4);
+    assert_eq!(report.skipped_invalid_url, 1);
+    assert_eq!(report.skipped_unsupported, 1);
+    assert_eq!(report.invalid_timestamps, 1);
+
+    let conn = places_api.open_connection(ConnectionType::ReadOnly)?;
+    let visits = conn.query_rows_and_then_named(
+        "SELECT h.url, v.visit_date, v.visit_type, v.is_local
+         FROM moz_historyvisits v
+         JOIN moz_places h ON h.id = v.place_id
+         ORDER BY v.visit_date, h.url",
+        &[],
+        |row| -> rusqlite::Result<(String, i64, u8, bool)> {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        },
+    )?;
+    assert_eq!(
+        &visits[..3],
+        &[
+            (
+                "https://example.com/".to_owned(),
+                1_565_117_389_897,
+                2,
+                true
+            ),
+            (
+                "https://example.com/".to_owned(),
+                1_565_117_389_898,
+                1,
+                true
+            ),
+            (
+                "https://www.example.com/".to_owned(),
+                1_565_117_389_899,
+                6,
+                true
+            ),
+        ]
+    );
+    assert_eq!(visits.len(), 4);
+    assert_eq!(visits[3].0, "https://mozilla.org/");
+    assert_eq!(visits[3].2, 9);
+
+    let (title, typed, frecency) = conn.query_row_and_then_named(
+        "SELECT title, typed, frecency FROM moz_places WHERE url = 'https://example.com/'",
+        &[],
+        |row| -> rusqlite::Result<(String, u32, i64)> {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        },
+        false,
+    )?;
+    assert_eq!(title, "Example");
+    assert_eq!(typed, 1);
+    assert!(frecency > 0);
+    assert_eq!(
+        conn.query_one::<i64>(
+            "SELECT COUNT(*) FROM moz_places WHERE url = 'https://ads.example.com/'"
+        )?,
+        0
+    );
+    drop(conn);
+
+    // Importing again shouldn't duplicate any visits.
+    let report = places::import::import_chromium_history(&places_api, &chromium_path)?;
+    // ...except the one with the invalid time, which gets a new one.
+    assert_eq!(report.rows_imported, 1);
+    let conn = places_api.open_connection(ConnectionType::ReadOnly)?;
+    assert_eq!(
+        conn.query_one::<i64>(
+            "SELECT COUNT(*) FROM moz_historyvisits v
+             JOIN moz_places h ON h.id = v.place_id
+             WHERE h.url LIKE '%example.com/'"
+        )?,
+        3
+    );
+
+    Ok(())
+}
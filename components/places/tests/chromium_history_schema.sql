@@ -0,0 +1,36 @@
+-- A trimmed down version of the tables in Chromium's `History` database,
+-- with just the parts the importer reads.
+
+CREATE TABLE meta (
+    key LONGVARCHAR NOT NULL UNIQUE PRIMARY KEY,
+    value LONGVARCHAR
+);
+
+INSERT INTO meta(key, value) VALUES ('version', '42'), ('last_compatible_version', '16');
+
+CREATE TABLE urls (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url LONGVARCHAR,
+    title LONGVARCHAR,
+    visit_count INTEGER DEFAULT 0 NOT NULL,
+    typed_count INTEGER DEFAULT 0 NOT NULL,
+    last_visit_time INTEGER NOT NULL,
+    hidden INTEGER DEFAULT 0 NOT NULL
+);
+
+CREATE INDEX urls_url_index ON urls (url);
+
+CREATE TABLE visits (
+    id INTEGER PRIMARY KEY,
+    url INTEGER NOT NULL,
+    visit_time INTEGER NOT NULL,
+    from_visit INTEGER,
+    transition INTEGER DEFAULT 0 NOT NULL,
+    segment_id INTEGER,
+    visit_duration INTEGER DEFAULT 0 NOT NULL,
+    incremented_omnibox_typed_score BOOLEAN DEFAULT FALSE NOT NULL
+);
+
+CREATE INDEX visits_url_index ON visits (url);
+CREATE INDEX visits_from_index ON visits (from_visit);
+CREATE INDEX visits_time_index ON visits (visit_time);
@@ -253,9 +253,21 @@ fn test_import() -> Result<()> {
 
     let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
 
-    let pinned = places::import::import_fennec_bookmarks(&places_api, fennec_path)?;
-    assert_eq!(pinned.len(), 1);
-    assert_eq!(pinned[0].title, Some("Pinned Bookmark".to_owned()));
+    let import = places::import::import_fennec_bookmarks(&places_api, &fennec_path)?;
+    assert_eq!(import.pinned.len(), 1);
+    assert_eq!(import.pinned[0].title, Some("Pinned Bookmark".to_owned()));
+    assert_eq!(import.report.rows_seen, 5);
+    assert_eq!(import.report.rows_imported, 4);
+    assert_eq!(import.report.skipped_invalid_url, 1);
+    assert_eq!(import.report.skipped_bad_parent, 0);
+    assert_eq!(import.report.skipped_unsupported, 0);
+
+    // Importing again doesn't count the bookmarks we already have.
+    let import = places::import::import_fennec_bookmarks(&places_api, fennec_path)?;
+    assert_eq!(import.report.rows_seen, 1);
+    assert_eq!(import.report.rows_imported, 0);
+    assert_eq!(import.report.skipped_invalid_url, 1);
+    assert_eq!(import.report.skipped_bad_parent, 0);
 
     // Uncomment the following to debug with cargo test -- --nocapture.
     // println!(
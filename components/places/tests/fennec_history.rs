@@ -250,7 +250,21 @@ fn test_import() -> Result<()> {
     )
     .expect("should insert");
 
-    places::import::import_fennec_history(&places_api, fennec_path)?;
+    let report = places::import::import_fennec_history(&places_api, fennec_path)?;
+    assert_eq!(report.rows_seen, 7);
+    assert_eq!(report.rows_imported, 7);
+    assert_eq!(report.skipped_invalid_url, 0);
+    assert_eq!(report.invalid_timestamps, 1);
+    assert_eq!(
+        report.phases.iter().map(|p| p.name).collect::<Vec<_>>(),
+        vec![
+            "attach",
+            "count",
+            "fill_moz_places",
+            "insert_history_visits",
+            "update_frecencies"
+        ]
+    );
 
     // Uncomment the following to debug with cargo test -- --nocapture.
     // println!(
@@ -374,7 +374,16 @@ fn test_import_basic() -> Result<()> {
 
     nodes.populate(&ios_db)?;
     let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
-    places::import::import_ios_bookmarks(&places_api, ios_path)?;
+    let report = places::import::import_ios_bookmarks(&places_api, &ios_path)?;
+    assert_eq!(report.rows_seen, 3);
+    assert_eq!(report.rows_imported, 3);
+    assert_eq!(report.skipped_bad_parent, 0);
+
+    // Importing again doesn't count the bookmarks we already have.
+    let report = places::import::import_ios_bookmarks(&places_api, &ios_path)?;
+    assert_eq!(report.rows_seen, 0);
+    assert_eq!(report.rows_imported, 0);
+    assert_eq!(report.skipped_bad_parent, 0);
 
     let places_db = places_api.open_connection(ConnectionType::ReadOnly)?;
 
@@ -2,6 +2,8 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+mod chromium_bookmarks;
+mod chromium_history;
 mod fennec_bookmarks;
 mod fennec_history;
 mod ios_bookmarks;